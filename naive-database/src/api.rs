@@ -0,0 +1,114 @@
+use std::{
+    marker::PhantomData,
+    mem,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use naive_sql_parser::SqlStmtsParser;
+
+use crate::{
+    config::set_data_dir,
+    dbms::exec::{Exec, ExecResult},
+    error::DBResult,
+    init,
+    record::ColumnVal,
+};
+
+/// What a statement gives back
+#[derive(Debug, Default)]
+pub struct QueryResult {
+    /// Names of the columns of `rows`, empty if the statement returns no rows
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<Option<ColumnVal>>>,
+    /// Rows of the query left out of `rows`, if `max_rows` is set
+    pub omitted: usize,
+    /// Rows inserted, updated or deleted
+    pub affected: usize,
+    /// What the statement reports besides rows, e.g. the number of tables dropped
+    pub message: Option<String>,
+}
+
+impl From<ExecResult> for QueryResult {
+    fn from(result: ExecResult) -> Self {
+        match result {
+            ExecResult::Rows(rows, header, omitted) => Self {
+                header,
+                rows,
+                omitted,
+                ..Self::default()
+            },
+            ExecResult::Affected(affected) => Self {
+                affected,
+                ..Self::default()
+            },
+            ExecResult::Message(message) => Self {
+                message: Some(message),
+                ..Self::default()
+            },
+            ExecResult::Empty => Self::default(),
+        }
+    }
+}
+
+/// Set while a `Database` handle is open
+static OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Handle to the databases kept in a directory
+///
+/// The engine is global to the process, so only one handle can be open at a time, and
+/// no other directory can be opened afterwards. The engine is not thread-safe either,
+/// so the handle is neither `Send` nor `Sync`:
+///
+/// ```compile_fail
+/// fn send<T: Send>() {}
+/// send::<naive_database::Database>();
+/// ```
+///
+/// Everything is written back by `close`, or when the handle is dropped.
+pub struct Database {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Database {
+    /// Open the databases kept in `path`, creating the directory if needed
+    pub fn open(path: impl AsRef<Path>) -> DBResult<Self> {
+        set_data_dir(path.as_ref())?;
+        if OPEN.swap(true, Ordering::SeqCst) {
+            return Err("a database handle is already open".into());
+        }
+        init::init();
+        Ok(Self {
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Run one or more statements, returning the result of the last one
+    ///
+    /// Statements after the first failing one are not run.
+    pub fn execute(&self, sql: &str) -> DBResult<QueryResult> {
+        let stmts = SqlStmtsParser::new()
+            .parse(sql)
+            .map_err(|e| format!("Error while parsing sql: {:?}", e))?;
+        let mut result = QueryResult::default();
+        for stmt in &stmts {
+            result = stmt.exec()?.into();
+        }
+        Ok(result)
+    }
+
+    /// Write everything back, reporting the error that dropping the handle would ignore
+    pub fn close(self) -> DBResult<()> {
+        let result = init::write_back();
+        mem::forget(self);
+        OPEN.store(false, Ordering::SeqCst);
+        result
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = init::write_back();
+        OPEN.store(false, Ordering::SeqCst);
+    }
+}
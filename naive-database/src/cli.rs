@@ -1,18 +1,22 @@
 use std::{
+    error::Error,
     fs::File,
-    io::{BufReader, Read},
+    io::{stdout, BufReader, Read, Write},
+    iter,
     path::{Path, PathBuf},
 };
 
-use naive_sql_parser::SqlStmtsParser;
+use naive_sql_parser::{SpannedSqlStmtsParser, SpannedStmt};
 use structopt::StructOpt;
 
 use crate::{
+    config::{ConstraintMode, LOAD_BATCH_SIZE, SETTINGS},
     dbms::{
-        database::{change_database, ensure_table, get_table_id, modify_table},
-        exec::Exec,
+        database::{change_database, ensure_table, get_table, get_table_id, modify_table},
+        exec::{batch_duplicates, check_deferred, check_record, check_row},
     },
     error::DBResult,
+    init::begin_statement,
     repl,
     utils::table::parse_colval,
 };
@@ -30,6 +34,13 @@ enum Sub {
         /// Table name to insert data into
         #[structopt(long, name = "table_name")]
         table: String,
+        /// Number of rows to read and reserve space for at a time
+        #[structopt(long, name = "rows")]
+        batch_size: Option<usize>,
+        /// Check unique and foreign keys once for each batch, rejecting all of its rows
+        /// if one violates them, like `SET CONSTRAINTS DEFERRED`
+        #[structopt(long)]
+        deferred: bool,
     },
     /// Exec all statements in an SQL file
     Exec {
@@ -46,78 +57,202 @@ enum Sub {
 struct Opt {
     #[structopt(subcommand)]
     cmd: Option<Sub>,
+    /// Directory where sorts too large for memory write their runs
+    #[structopt(long, parse(from_os_str), global = true)]
+    temp_dir: Option<PathBuf>,
 }
 
-fn load_csv(from: &Path, database: &str, table: &str) -> DBResult<()> {
-    change_database(database);
-    let id = match get_table_id(table) {
-        Some(id) => id,
-        None => {
-            return Err(format!("table {} does not exist in database {}", table, database).into())
+/// Rows violating constraints are reported and skipped, the rest are still loaded.
+/// With deferred constraints, a batch with a row violating a unique or foreign key
+/// is rejected as a whole
+pub fn load_csv(from: &Path, database: &str, table: &str, batch_size: usize) -> DBResult<()> {
+    if batch_size == 0 {
+        return Err("batch size must be positive".into());
+    }
+    let (id, coltype) = {
+        // like every batch, opening the table keeps a termination signal from writing back
+        let _executing = begin_statement()?;
+        if !change_database(database)? {
+            return Err(format!("database {} does not exist", database).into());
+        }
+        let id = match get_table_id(table) {
+            Some(id) => id,
+            None => {
+                return Err(
+                    format!("table {} does not exist in database {}", table, database).into(),
+                )
+            }
+        };
+
+        let (coltype, ftables) = ensure_table(id, move |table| {
+            (
+                table
+                    .meta
+                    .columns
+                    .iter()
+                    .map(|col| col.coltype)
+                    .collect::<Vec<_>>(),
+                table
+                    .meta
+                    .foreign_key
+                    .values()
+                    .map(|(ftable, _)| *ftable)
+                    .collect::<Vec<_>>(),
+            )
+        });
+        // referenced tables cannot be loaded while the table is borrowed
+        for ftable in ftables {
+            ensure_table(ftable, |_| {});
         }
+        (id, coltype)
     };
 
-    let (coltype, slot_size) = ensure_table(id, move |table| {
-        (
-            table
-                .meta
-                .columns
-                .iter()
-                .map(|col| col.coltype)
-                .collect::<Vec<_>>(),
-            table.meta.slot_size() as u64,
-        )
-    });
-
-    let file = File::open(from)?;
-    let n_slots = (file.metadata()?.len() as f32 * 1.3) as u64 / slot_size;
-    let mut rdr = csv::Reader::from_reader(BufReader::new(file));
-
-    modify_table(id, |table| -> DBResult<()> {
-        table.reserve_for(n_slots as _)?;
-        let mut row = Vec::with_capacity(15);
-        let headers = rdr.headers();
-        row.extend(
-            headers?
+    let deferred = SETTINGS.borrow().constraints == ConstraintMode::Deferred;
+    let mut rdr = csv::Reader::from_reader(BufReader::new(File::open(from)?));
+    let headers = rdr.headers()?.clone();
+    let mut records = iter::once(Ok(headers)).chain(rdr.records()).enumerate();
+
+    let (mut loaded, mut rejected) = (0, 0);
+    let mut batch = Vec::with_capacity(batch_size);
+    loop {
+        batch.clear();
+        for (i, record) in records.by_ref().take(batch_size) {
+            let row = record?
                 .into_iter()
                 .enumerate()
-                .map(|(i, val)| parse_colval(coltype[i], val).unwrap()),
-        );
-        table.insert(&row)?;
-        row.clear();
-        for val in rdr.records() {
-            row.extend(
-                val?.into_iter()
-                    .enumerate()
-                    .map(|(i, val)| parse_colval(coltype[i], val).unwrap()),
+                .map(|(i, val)| parse_colval(coltype[i], val))
+                .collect::<DBResult<Vec<_>>>()?;
+            batch.push((i, row));
+        }
+        if batch.is_empty() {
+            break;
+        }
+        // a termination signal stops loading between batches
+        let _executing = begin_statement()?;
+
+        let (mut lines, mut rows) = (vec![], vec![]);
+        for (i, row) in batch.drain(..) {
+            let checked = ensure_table(id, |table| {
+                if deferred {
+                    check_row(table, &row, i)
+                } else {
+                    check_record(table, &row, i)
+                }
+            });
+            if let Err(e) = checked {
+                eprintln!("Rejected line {} of {}: {}", i + 1, from.display(), e);
+                rejected += 1;
+                continue;
+            }
+            lines.push(i);
+            rows.push(row);
+        }
+        if deferred {
+            if let Err(e) = ensure_table(id, |table| check_deferred(table, &rows)) {
+                eprintln!(
+                    "Rejected {} line(s) from line {} of {}: {}",
+                    rows.len(),
+                    lines.first().map_or(0, |i| i + 1),
+                    from.display(),
+                    e
+                );
+                rejected += rows.len();
+                continue;
+            }
+        }
+        // rows of a batch are added to indices together, so they are not checked against
+        // each other by `check_record`, nor against unique indices before the pages are written
+        let duplicates = ensure_table(id, |table| batch_duplicates(table, &rows));
+        for &pos in &duplicates {
+            eprintln!(
+                "Rejected line {} of {}: unique key is in the table or on a line before",
+                lines[pos] + 1,
+                from.display()
             );
-            table.insert(&row)?;
-            row.clear();
         }
-        Ok(())
-    })?;
+        rejected += duplicates.len();
+        let rows = rows
+            .into_iter()
+            .enumerate()
+            .filter(|(pos, _)| !duplicates.contains(pos))
+            .map(|(_, row)| row)
+            .collect::<Vec<_>>();
+
+        let rids = modify_table(id, |table| table.bulk_insert(&rows))?;
+        get_table(id, |table| table.extend_indices(&rids, &rows))?;
+        loaded += rows.len();
+    }
+    println!("{} row(s) loaded, {} row(s) rejected", loaded, rejected);
     Ok(())
 }
 
 fn exec_sql(path: &Path) -> DBResult<()> {
     let mut sqls = String::new();
     File::open(path)?.read_to_string(&mut sqls)?;
+    exec_script(&sqls, &mut stdout())
+}
 
-    let parser = SqlStmtsParser::new();
-    match parser.parse(&sqls) {
-        Ok(stmts) => stmts.exec()?,
+/// Run statements in order, printing their results to `out`, stopping at the first
+/// failing one, whose error tells which statement it is and where it starts
+pub fn exec_script(sqls: &str, out: &mut dyn Write) -> DBResult<()> {
+    let parser = SpannedSqlStmtsParser::new();
+    match parser.parse(sqls) {
+        Ok(stmts) => {
+            for (i, stmt) in stmts.iter().enumerate() {
+                repl::exec_stmt(&stmt.stmt, out).map_err(|e| locate_error(sqls, i, stmt, e))?;
+            }
+        }
         Err(e) => eprintln!("Error while parsing sql: {:?}", e),
     }
     Ok(())
 }
 
+fn locate_error(sqls: &str, i: usize, stmt: &SpannedStmt, err: Box<dyn Error>) -> Box<dyn Error> {
+    const NEAR_CHARS: usize = 40;
+    let line = sqls[..stmt.start].matches('\n').count() + 1;
+    let text = sqls[stmt.start..stmt.end]
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim_end();
+    let mut near = text.chars().take(NEAR_CHARS).collect::<String>();
+    if near.len() < text.len() {
+        near.push_str("...");
+    }
+    format!(
+        "error in statement {} near '{}' at line {}: {}",
+        i + 1,
+        near,
+        line,
+        err
+    )
+    .into()
+}
+
 pub fn run_cli() -> DBResult<()> {
     let cli = Opt::from_args();
+    if let Some(dir) = cli.temp_dir {
+        if !dir.is_dir() {
+            return Err(format!("temp dir {} is not a directory", dir.display()).into());
+        }
+        SETTINGS.borrow_mut().temp_dir = dir;
+    }
 
     match cli.cmd {
         Some(cmd) => match cmd {
             Sub::Exec { sql_path } => exec_sql(&sql_path)?,
-            Sub::Load { from, to, table } => load_csv(&from, &to, &table)?,
+            Sub::Load {
+                from,
+                to,
+                table,
+                batch_size,
+                deferred,
+            } => {
+                if deferred {
+                    SETTINGS.borrow_mut().constraints = ConstraintMode::Deferred;
+                }
+                load_csv(&from, &to, &table, batch_size.unwrap_or(LOAD_BATCH_SIZE))?
+            }
             Sub::Repl => repl::main_loop(),
         },
         None => repl::main_loop(),
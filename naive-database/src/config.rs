@@ -1,6 +1,14 @@
-use std::{fs::OpenOptions, path::PathBuf};
+use std::{
+    env,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::DBResult, utils::serial_cell::SerialCell};
 
 pub const PAGE_SIZE_IDX: u64 = 13;
 pub const PAGE_SIZE: usize = 8192; // bytes, 1 << PAGE_SIZE_IDX
@@ -22,8 +30,38 @@ pub const MAX_JOIN_TABLE: usize = 2;
 
 pub const MAX_CHAR_LEN: usize = 255;
 
+pub const MAX_NAME_LEN: usize = 64; // of databases and tables, which name their files
+
+pub const LOAD_BATCH_SIZE: usize = 4096; // rows reserved at a time when loading csv
+
+pub const REPL_MAX_ROWS: usize = 1000; // `max_rows` in the REPL
+
+/// Set by `set_data_dir`, or to the default on the first use of `BASE_DIR`
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+#[cfg(not(test))]
+fn default_data_dir() -> PathBuf {
+    "data".into()
+}
+
+#[cfg(test)]
+fn default_data_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("naive-db-test-{}", std::process::id()))
+}
+
 lazy_static! {
-    pub static ref BASE_DIR: PathBuf = "data".into();
+    /// Directory of all databases
+    pub static ref BASE_DIR: PathBuf = DATA_DIR.get_or_init(default_data_dir).clone();
+}
+
+/// Keep the databases in `dir`, which cannot be changed once a directory is in use
+pub fn set_data_dir(dir: &Path) -> DBResult<()> {
+    let current = DATA_DIR.get_or_init(|| dir.to_owned());
+    if current == dir {
+        Ok(())
+    } else {
+        Err(format!("data directory {} is already in use", current.display()).into())
+    }
 }
 
 lazy_static! {
@@ -39,3 +77,204 @@ lazy_static! {
         path
     };
 }
+
+/// Runtime settings of the current session, changed through `SET name = value`
+#[derive(Debug)]
+pub struct Settings {
+    /// How NULL is displayed in output tables
+    pub null_string: String,
+    /// Result of dividing an integer by another integer
+    pub int_division: IntDivision,
+    /// Largest estimated fraction of rows a predicate may match for an index to be used,
+    /// a full scan reads pages in order and is cheaper for less selective predicates
+    pub index_selectivity: f32,
+    /// When unique and foreign key constraints of inserted rows are checked
+    pub constraints: ConstraintMode,
+    /// Most rows ORDER BY keeps in memory, more are sorted in runs written to `temp_dir`
+    pub sort_buffer_rows: usize,
+    /// Where sorts spill their runs, set by `--temp-dir`
+    pub temp_dir: PathBuf,
+    /// Where tables created from now on keep the null bits of their rows
+    pub null_layout: NullLayout,
+    /// Most tables kept loaded with their indices after a statement,
+    /// the least recently used are written back and loaded again when needed
+    pub max_loaded_tables: usize,
+    /// Errors list every offending value instead of the first one
+    pub verbose_errors: bool,
+    /// Most rows a SELECT gives, the rest are only counted, 0 for no limit
+    pub max_rows: usize,
+}
+
+/// Policy of `int_division`, `SET int_division = truncate` or `SET int_division = float`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntDivision {
+    /// Truncate towards zero as in standard SQL, `5 / 2` gives 2
+    Truncate,
+    /// Promote to float, `5 / 2` gives 2.5
+    Float,
+}
+
+/// Policy of `constraints`, `SET CONSTRAINTS IMMEDIATE` or `SET CONSTRAINTS DEFERRED`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstraintMode {
+    /// Look up the table for each row as it is checked
+    Immediate,
+    /// Check all rows of a statement or a batch of a CSV load at once, which is faster
+    /// for large imports, the whole batch is rejected if any row violates a constraint
+    Deferred,
+}
+
+/// Policy of `null_layout`, `SET null_layout = inline` or `SET null_layout = separate`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum NullLayout {
+    /// The null bits of a row start its slot
+    Inline,
+    /// The null bits of all rows of a page are stored together before their data,
+    /// so nullability is scanned without touching the data
+    Separate,
+}
+
+impl Settings {
+    /// Each setting with its value as `SET` takes it
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        let lowercase = |value: &dyn std::fmt::Debug| format!("{:?}", value).to_lowercase();
+        vec![
+            ("null_string", self.null_string.clone()),
+            ("int_division", lowercase(&self.int_division)),
+            ("index_selectivity", self.index_selectivity.to_string()),
+            ("constraints", lowercase(&self.constraints)),
+            ("sort_buffer_rows", self.sort_buffer_rows.to_string()),
+            ("temp_dir", self.temp_dir.display().to_string()),
+            ("null_layout", lowercase(&self.null_layout)),
+            ("max_loaded_tables", self.max_loaded_tables.to_string()),
+            (
+                "verbose_errors",
+                if self.verbose_errors { "on" } else { "off" }.to_owned(),
+            ),
+            ("max_rows", self.max_rows.to_string()),
+        ]
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> DBResult<()> {
+        match name.to_lowercase().as_str() {
+            "null_string" => self.null_string = value.to_owned(),
+            "int_division" => {
+                self.int_division = match value.to_lowercase().as_str() {
+                    "truncate" => IntDivision::Truncate,
+                    "float" => IntDivision::Float,
+                    _ => {
+                        return Err(format!(
+                            "int_division must be truncate or float, got {}",
+                            value
+                        )
+                        .into())
+                    }
+                }
+            }
+            "index_selectivity" => match value.parse() {
+                Ok(fraction) if (0.0..=1.0).contains(&fraction) => {
+                    self.index_selectivity = fraction
+                }
+                _ => {
+                    return Err(
+                        format!("index_selectivity must be between 0 and 1, got {}", value).into(),
+                    )
+                }
+            },
+            "constraints" => {
+                self.constraints = match value.to_lowercase().as_str() {
+                    "immediate" => ConstraintMode::Immediate,
+                    "deferred" => ConstraintMode::Deferred,
+                    _ => {
+                        return Err(format!(
+                            "constraints must be immediate or deferred, got {}",
+                            value
+                        )
+                        .into())
+                    }
+                }
+            }
+            "sort_buffer_rows" => match value.parse() {
+                Ok(rows) if rows > 0 => self.sort_buffer_rows = rows,
+                _ => {
+                    return Err(format!(
+                        "sort_buffer_rows must be a positive integer, got {}",
+                        value
+                    )
+                    .into())
+                }
+            },
+            "temp_dir" => {
+                if !Path::new(value).is_dir() {
+                    return Err(format!("temp_dir {} is not a directory", value).into());
+                }
+                self.temp_dir = value.into();
+            }
+            "null_layout" => {
+                self.null_layout = match value.to_lowercase().as_str() {
+                    "inline" => NullLayout::Inline,
+                    "separate" => NullLayout::Separate,
+                    _ => {
+                        return Err(format!(
+                            "null_layout must be inline or separate, got {}",
+                            value
+                        )
+                        .into())
+                    }
+                }
+            }
+            "max_loaded_tables" => match value.parse() {
+                Ok(tables) if tables > 0 => self.max_loaded_tables = tables,
+                _ => {
+                    return Err(format!(
+                        "max_loaded_tables must be a positive integer, got {}",
+                        value
+                    )
+                    .into())
+                }
+            },
+            "verbose_errors" => {
+                self.verbose_errors = match value.to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => {
+                        return Err(
+                            format!("verbose_errors must be on or off, got {}", value).into()
+                        )
+                    }
+                }
+            }
+            "max_rows" => match value.parse() {
+                Ok(rows) => self.max_rows = rows,
+                _ => {
+                    return Err(
+                        format!("max_rows must be a non-negative integer, got {}", value).into(),
+                    )
+                }
+            },
+            _ => return Err(format!("unknown setting {}", name).into()),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            null_string: "NULL".to_owned(),
+            int_division: IntDivision::Truncate,
+            index_selectivity: 0.3,
+            constraints: ConstraintMode::Immediate,
+            sort_buffer_rows: 1 << 20,
+            temp_dir: env::temp_dir(),
+            null_layout: NullLayout::Inline,
+            max_loaded_tables: 64,
+            verbose_errors: false,
+            max_rows: 0,
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref SETTINGS: SerialCell<Settings> = SerialCell::new(Settings::default());
+}
@@ -1,107 +1,91 @@
-use num_bigint::BigInt;
+use std::{cmp::Ordering, convert::TryFrom};
+
+use naive_sql_parser::Aggregator;
 
 use crate::{
-    defines::{ColID, RowID, TableID},
     error::DBResult,
     record::{ColumnType, ColumnVal},
 };
 
-use super::database::get_table;
-
-pub fn count(rows: impl Iterator<Item = RowID>, table: TableID, col: ColID) -> DBResult<u32> {
-    let count = get_table(table, |table| -> DBResult<_> {
-        let count = rows
-            .filter_map(|rid| table.select(rid, col).unwrap())
-            .count();
-        Ok(count)
-    })?;
-    Ok(count as _)
-}
-
-pub fn count_all(rows: impl Iterator<Item = RowID>) -> DBResult<u32> {
-    Ok(rows.count() as _)
-}
-
-pub fn avg(rows: impl Iterator<Item = RowID>, table: TableID, col: ColID) -> DBResult<f64> {
-    let avg = get_table(table, |table| -> DBResult<_> {
-        match table.meta.columns[col as usize].coltype {
-            ColumnType::Int | ColumnType::Float => {}
-            _ => return Err("column referenced in `AVG` must be of `INT` or `FLOAT` type".into()),
+/// Aggregate values not read from a table, NULLs are ignored
+pub fn aggregate(
+    aggr: &Aggregator,
+    vals: impl Iterator<Item = Option<ColumnVal>>,
+) -> DBResult<Option<ColumnVal>> {
+    use ColumnVal::*;
+    let mut vals = vals.flatten().peekable();
+    if let Some(val) = vals.peek() {
+        if matches!(aggr, Aggregator::AVG | Aggregator::SUM)
+            && !matches!(val.coltype(), ColumnType::Int | ColumnType::Float)
+        {
+            return Err(format!(
+                "values aggregated by `{:?}` must be of `INT` or `FLOAT` type",
+                aggr
+            )
+            .into());
         }
+    }
 
-        use ColumnVal::*;
-        let vals = rows.filter_map(|rid| {
-            table.select(rid, col).unwrap().map(|val| match val {
-                Int(i) => i as f64,
-                Float(f) => f as _,
+    let ret = match aggr {
+        Aggregator::COUNT => Some(Int(vals.count() as _)),
+        Aggregator::MIN => vals.min_by(|x, y| x.partial_cmp(y).unwrap_or(Ordering::Equal)),
+        Aggregator::MAX => vals.max_by(|x, y| x.partial_cmp(y).unwrap_or(Ordering::Equal)),
+        Aggregator::AVG => {
+            let (sum, count) = vals.fold((0f64, 0), |(sum, count), val| match val {
+                Int(i) => (sum + i as f64, count + 1),
+                Float(f) => (sum + f as f64, count + 1),
                 _ => unreachable!(),
-            })
-        });
-        let mut sum = 0f64;
-        let mut count = 0;
-        for (i, val) in vals.enumerate() {
-            count = i;
-            sum += val;
+            });
+            (count != 0).then(|| Float((sum / count as f64) as _))
+        }
+        Aggregator::SUM => {
+            // integers are added as i64, so only a total out of the range of `INT` overflows,
+            // not a partial sum
+            let (mut int_sum, mut float_sum) = (None::<i64>, None::<f32>);
+            for val in vals {
+                match val {
+                    Int(i) => int_sum = Some(int_sum.unwrap_or(0) + i as i64),
+                    Float(f) => float_sum = Some(float_sum.unwrap_or(0.0) + f),
+                    _ => unreachable!(),
+                }
+            }
+            match (int_sum, float_sum) {
+                (None, None) => None,
+                (Some(sum), None) => {
+                    let sum = i32::try_from(sum).map_err(|_| "integer overflow in `SUM`")?;
+                    Some(Int(sum))
+                }
+                (sum, Some(f)) => Some(Float(sum.unwrap_or(0) as f32 + f)),
+            }
         }
-        Ok(sum / (count + 1) as f64)
-    })?;
-    Ok(avg)
+    };
+    Ok(ret)
 }
 
-pub fn min(
-    rows: impl Iterator<Item = RowID>,
-    table: TableID,
-    col: ColID,
-) -> DBResult<Option<ColumnVal>> {
-    let min = get_table(table, |table| -> DBResult<_> {
-        let min = rows
-            .filter_map(|rid| table.select(rid, col).unwrap())
-            .min_by(|x, y| x.partial_cmp(y).unwrap());
-        Ok(min)
-    })?;
-    Ok(min)
-}
+#[cfg(test)]
+mod tests {
+    use naive_sql_parser::Aggregator;
 
-pub fn max(
-    rows: impl Iterator<Item = RowID>,
-    table: TableID,
-    col: ColID,
-) -> DBResult<Option<ColumnVal>> {
-    let min = get_table(table, |table| -> DBResult<_> {
-        let min = rows
-            .filter_map(|rid| table.select(rid, col).unwrap())
-            .max_by(|x, y| x.partial_cmp(y).unwrap());
-        Ok(min)
-    })?;
-    Ok(min)
-}
+    use super::aggregate;
+    use crate::{error::DBResult, record::ColumnVal::*};
 
-pub fn sum_float(rows: impl Iterator<Item = RowID>, table: TableID, col: ColID) -> DBResult<f64> {
-    let sum = get_table(table, |table| -> DBResult<_> {
-        let sum = rows
-            .filter_map(|rid| {
-                table.select(rid, col).unwrap().map(|val| match val {
-                    ColumnVal::Float(f) => f as f64,
-                    _ => unreachable!(),
-                })
-            })
-            .sum::<f64>();
-        Ok(sum)
-    })?;
-    Ok(sum)
-}
+    #[test]
+    fn int_sum_overflows_only_at_the_end() -> DBResult<()> {
+        let near_max = i32::MAX - 10;
+        let vals = [
+            Some(Int(near_max)),
+            Some(Int(near_max)),
+            None,
+            Some(Int(-near_max)),
+        ];
+        let sum = aggregate(&Aggregator::SUM, vals.iter().cloned())?;
+        assert_eq!(sum, Some(Int(near_max)));
 
-pub fn sum_int(rows: impl Iterator<Item = RowID>, table: TableID, col: ColID) -> DBResult<BigInt> {
-    let sum = get_table(table, |table| -> DBResult<_> {
-        let sum = rows
-            .filter_map(|rid| {
-                table.select(rid, col).unwrap().map(|val| match val {
-                    ColumnVal::Int(i) => i,
-                    _ => unreachable!(),
-                })
-            })
-            .sum::<BigInt>();
-        Ok(sum)
-    })?;
-    Ok(sum)
+        let vals = [Some(Int(near_max)), Some(Int(near_max))];
+        let err = aggregate(&Aggregator::SUM, vals.iter().cloned()).unwrap_err();
+        assert_eq!(err.to_string(), "integer overflow in `SUM`");
+        let vals = [Some(Int(near_max)), Some(Int(near_max)), Some(Float(0.5))];
+        assert!(aggregate(&Aggregator::SUM, vals.iter().cloned())?.is_some());
+        Ok(())
+    }
 }
@@ -2,28 +2,66 @@ use std::{
     cell::RefCell,
     collections::{HashMap, HashSet},
     convert::TryInto,
-    fs,
-    path::PathBuf,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
 };
 
 use bimap::BiHashMap;
 use lazy_static::lazy_static;
-use naive_sql_parser::{CreateTBField, NamedTBConstraint, TBConstraint::*};
+use naive_sql_parser::{CompareOp, CreateTBField, Expr, NamedTBConstraint, TBConstraint::*};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config::{BASE_DIR, PAGE_NUM_ON_CREATE},
-    defines::{ColID, TableID},
+    config::{BASE_DIR, MAX_NAME_LEN, PAGE_NUM_ON_CREATE, SETTINGS},
+    defines::{ColID, PageNum, TableID},
     error::DBResult,
-    record::{Constraints, Table, TableMeta},
-    utils::{iter_dir_by, persistence::Persistence, serial_cell::SerialCell},
+    filesystem::{page_manager, storage},
+    record::{ColumnType, ColumnVal, Constraints, ForeignPolicy, Table, TableMeta},
+    utils::{like_match, persistence::Persistence, serial_cell::SerialCell},
 };
 
+use super::{
+    expr::{cast, eval_expr, Row},
+    system::is_system_table,
+};
+
+/// Names of databases and tables are those of their directories and files, so they take
+/// what the SQL grammar does, letters, digits, `_`, `$` and, in quoted table names, spaces
+pub fn validate_identifier(kind: &str, name: &str) -> DBResult<()> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == ' ';
+    if name.trim().is_empty() {
+        return Err(format!("{} name cannot be empty", kind).into());
+    }
+    if name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "{} name {} is longer than {} characters",
+            kind, name, MAX_NAME_LEN
+        )
+        .into());
+    }
+    if !name.chars().all(valid_char) || name.ends_with(' ') {
+        return Err(format!(
+            "invalid {} name {:?}, only letters, digits, `_`, `$` and inner spaces are allowed",
+            kind, name
+        )
+        .into());
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct Database {
     current: PathBuf,
     current_tables: RefCell<HashMap<TableID, RefCell<Table>>>,
+    /// Loaded tables from the least to the most recently used
+    recently_used: RefCell<Vec<TableID>>,
     id_record: BiHashMap<String, TableID>,
+    /// Temporary tables, which shadow the tables of the same names and are dropped
+    /// when the database is closed. They are neither recorded in the metadata of the
+    /// database nor stored in its directory, but in `temp_dir`
+    temporary: BiHashMap<String, TableID>,
+    temp_dir: PathBuf,
 }
 
 impl Database {
@@ -32,12 +70,16 @@ impl Database {
         Self {
             current: PathBuf::new(),
             current_tables: RefCell::new(HashMap::new()),
+            recently_used: RefCell::new(vec![]),
             id_record: BiHashMap::new(),
+            temporary: BiHashMap::new(),
+            temp_dir: PathBuf::new(),
         }
     }
 
-    pub fn current_database(&self) -> &str {
-        self.current.iter().next_back().unwrap().to_str().unwrap()
+    /// Name of the database in use, none if there is no such database
+    pub fn current_database(&self) -> Option<&str> {
+        self.current.file_name()?.to_str()
     }
 
     pub fn is_ready(&self) -> bool {
@@ -46,9 +88,10 @@ impl Database {
 
     // discuss!
     pub fn create_database(&self, name: &str) -> DBResult<()> {
+        validate_identifier("database", name)?;
         let dir = BASE_DIR.join(name);
         let path = dir.join(self.filename());
-        fs::create_dir(&dir)?;
+        storage::create_dir(&dir)?;
         Self {
             current: path,
             ..Self::new()
@@ -61,76 +104,168 @@ impl Database {
         let dir = self.current.as_path();
         if self.current.as_os_str() != "" {
             self.store(dir)?;
+            self.drop_temporary()?;
             for (_, table) in self.current_tables.take() {
                 let table = table.into_inner();
                 table.write_back()?;
             }
+            self.recently_used.take();
             self.current = "".into();
         }
         Ok(())
     }
 
-    pub fn change_database(&mut self, name: &str) -> bool {
+    /// `false` if there is no such database, an error if it exists but cannot be loaded
+    pub fn change_database(&mut self, name: &str) -> DBResult<bool> {
+        validate_identifier("database", name)?;
         let path = BASE_DIR.join(name);
-        if path.is_dir() {
-            let mut new_db = match Self::load(&path.join(self.filename())) {
-                Ok(db) => db,
-                Err(_e) => return false,
-            };
-            new_db.current = path;
+        if storage::is_dir(&path) {
+            // write back first, `name` might be the database currently in use
             self.write_back().expect("serious error when writing back");
+            let mut new_db = Self::load(&path.join(self.filename()))
+                .map_err(|e| format!("failed to load database {}: {}", name, e))?;
+            new_db.current = path;
             *self = new_db;
-            true
+            Ok(true)
         } else {
-            false
+            Ok(false)
         }
     }
 
     pub fn drop_database(&self, name: &str) -> DBResult<()> {
-        if name == self.current_database() {
+        if self.current_database() == Some(name) {
             return Err("database already opened, try closing it before drop".into());
         }
+        validate_identifier("database", name)?;
         let path = BASE_DIR.join(name);
-        fs::remove_dir_all(path)?;
+        storage::remove_dir_all(&path)?;
+        Ok(())
+    }
+
+    /// Rename the directory of a database, its metadata and tables do not record the name
+    pub fn rename_database(&self, old: &str, new: &str) -> DBResult<()> {
+        if [Some(old), Some(new)].contains(&self.current_database()) {
+            return Err("database already opened, try closing it before rename".into());
+        }
+        validate_identifier("database", old)?;
+        validate_identifier("database", new)?;
+        let (from, to) = (BASE_DIR.join(old), BASE_DIR.join(new));
+        if !storage::is_dir(&from) {
+            return Err(format!("database {} does not exist", old).into());
+        }
+        if storage::exists(&to) {
+            return Err(format!("database {} already exists", new).into());
+        }
+        storage::rename(&from, &to)?;
+        Ok(())
+    }
+
+    /// Drop the temporary tables, with the directory their files are kept in
+    fn drop_temporary(&mut self) -> DBResult<()> {
+        let mut current_tables = self.current_tables.borrow_mut();
+        for (name, id) in std::mem::take(&mut self.temporary) {
+            match current_tables.remove(&id) {
+                Some(table) => table.into_inner().delete_self()?,
+                None => Table::load_no_index(&self.temp_dir, &name)?.delete_self()?,
+            }
+        }
+        if self.temp_dir.as_os_str() != "" {
+            match storage::remove_dir_all(&self.temp_dir) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => self.temp_dir = PathBuf::new(),
+            }
+        }
         Ok(())
     }
 
+    /// Whether a table of the name exists, only temporary tables count for a temporary one
+    /// as it may shadow a table of the database
+    pub fn table_exists(&self, name: &str, temporary: bool) -> bool {
+        self.temporary.contains_left(name) || !temporary && self.id_record.contains_left(name)
+    }
+
+    pub fn is_temporary(&self, id: TableID) -> bool {
+        self.temporary.contains_right(&id)
+    }
+
+    fn table_name(&self, id: TableID) -> Option<&str> {
+        let name = self.temporary.get_by_right(&id);
+        name.or_else(|| self.id_record.get_by_right(&id))
+            .map(String::as_str)
+    }
+
+    /// Directory of the files of a table
+    fn table_dir(&self, id: TableID) -> &Path {
+        if self.is_temporary(id) {
+            &self.temp_dir
+        } else {
+            &self.current
+        }
+    }
+
     pub fn new_table(
         &mut self,
         name: &str,
+        temporary: bool,
         init: impl FnOnce(&mut TableMeta) -> DBResult<()>,
     ) -> DBResult<()> {
         if !self.is_ready() {
             return Err("no database in use".into());
         }
-        let idr = &mut self.id_record;
-        if idr.contains_left(name) {
+        validate_identifier("table", name)?;
+        let exists = if temporary {
+            self.temporary.contains_left(name)
+        } else {
+            self.id_record.contains_left(name)
+        };
+        if exists {
             return Err("table already exists".into());
         }
+        if temporary && self.temp_dir.as_os_str() == "" {
+            let dir_name = format!("naive-db-temporary-{}", process::id());
+            let temp_dir = SETTINGS.borrow().temp_dir.join(dir_name);
+            // left by a process of the same id that did not exit cleanly
+            if storage::exists(&temp_dir) {
+                storage::remove_dir_all(&temp_dir)?;
+            }
+            storage::create_dir(&temp_dir)?;
+            self.temp_dir = temp_dir;
+        }
         for i in 0..TableID::MAX {
-            if !idr.contains_right(&i) {
+            if !self.id_record.contains_right(&i) && !self.temporary.contains_right(&i) {
                 let mut meta = TableMeta::new(i, name.to_owned());
                 init(&mut meta)?;
-                let table = Table::from_meta(meta, &self.current)?;
-                idr.insert(name.to_owned(), i);
+                let dir = if temporary {
+                    &self.temp_dir
+                } else {
+                    &self.current
+                };
+                let table = Table::from_meta(meta, dir)?;
+                if temporary {
+                    self.temporary.insert(name.to_owned(), i);
+                } else {
+                    self.id_record.insert(name.to_owned(), i);
+                }
                 let mut current_tables = self.current_tables.borrow_mut();
                 current_tables.insert(i, RefCell::new(table));
+                self.touch(i);
                 return Ok(());
             }
         }
         Err("you've used up all available table ids, try delete some tables or recompile with a larger `TableID` type".into())
     }
 
+    /// Load a table with its indices, a table already loaded is kept as it is
     pub fn load_table(&self, id: TableID) -> DBResult<()> {
         if !self.is_ready() {
             return Err("no database in use".into());
         }
-        let name = self
-            .id_record
-            .get_by_right(&id)
-            .ok_or("no such table")?
-            .to_owned();
-        let table = Table::load_no_index(&self.current, &name)?;
+        self.touch(id);
+        if self.check_loaded(id) {
+            return Ok(());
+        }
+        let name = self.table_name(id).ok_or("no such table")?;
+        let table = Table::load_no_index(self.table_dir(id), name)?;
         let id = table.id();
 
         let mut current_tables = self.current_tables.borrow_mut();
@@ -151,6 +286,27 @@ impl Database {
         self.current_tables.borrow().contains_key(&id)
     }
 
+    /// Mark a table as the most recently used
+    fn touch(&self, id: TableID) {
+        let mut recently_used = self.recently_used.borrow_mut();
+        recently_used.retain(|&used| used != id);
+        recently_used.push(id);
+    }
+
+    /// Write back the least recently used tables until at most `keep` stay loaded
+    fn evict_tables(&self, keep: usize) -> DBResult<()> {
+        let mut current_tables = self.current_tables.borrow_mut();
+        let mut recently_used = self.recently_used.borrow_mut();
+        // dropped tables may still be listed
+        recently_used.retain(|id| current_tables.contains_key(id));
+        while current_tables.len() > keep && !recently_used.is_empty() {
+            let id = recently_used.remove(0);
+            let table = current_tables.remove(&id).unwrap();
+            table.into_inner().write_back()?;
+        }
+        Ok(())
+    }
+
     pub fn delete_table(&mut self, name: &str) -> DBResult<()> {
         if !self.is_ready() {
             return Err("no database in use".into());
@@ -166,15 +322,7 @@ impl Database {
     }
 
     pub fn list_databases(&self) -> DBResult<Vec<String>> {
-        let databases = iter_dir_by(BASE_DIR.as_path(), |d| {
-            if d.metadata().ok()?.is_dir() {
-                d.file_name().to_str().map(ToOwned::to_owned)
-            } else {
-                None
-            }
-        })?
-        .collect();
-        Ok(databases)
+        Ok(storage::list_dirs(BASE_DIR.as_path())?)
     }
 
     pub fn list_tables(&self) -> DBResult<Vec<&str>> {
@@ -182,14 +330,60 @@ impl Database {
             return Err("no database in use".into());
         }
 
-        Ok(self.id_record.left_values().map(|s| s.as_str()).collect())
+        let shadowed = |name: &&String| !self.temporary.contains_left(*name);
+        let tables = self.id_record.left_values().filter(shadowed);
+        Ok(tables
+            .chain(self.temporary.left_values())
+            .map(|s| s.as_str())
+            .collect())
     }
 
     pub fn get_table_id(&self, name: &str) -> Option<TableID> {
-        self.id_record.get_by_left(name).cloned()
+        let id = self.temporary.get_by_left(name);
+        id.or_else(|| self.id_record.get_by_left(name)).cloned()
+    }
+
+    /// The metadata of a table not loaded is read from its file,
+    /// neither its pages nor its indices are loaded
+    pub fn table_status(&self, name: &str) -> DBResult<TableStatus> {
+        let id = self.get_table_id(name).ok_or("no such table")?;
+        let dir = self.table_dir(id);
+        let data_file = dir.join(TableMeta::format_data_filename(name));
+        let status = |meta: &TableMeta| -> DBResult<_> {
+            Ok(TableStatus {
+                name: name.to_owned(),
+                rows: meta.row_count(),
+                pages: meta.page_count(),
+                file_pages: storage::page_count(&data_file)?,
+                indices: meta.index_record.len(),
+            })
+        };
+        if !self.check_loaded(id) {
+            let meta = TableMeta::load(&dir.join(TableMeta::format_meta_filename(name)))?;
+            // the rows of a legacy table are only counted when it is loaded
+            if !meta.is_legacy() {
+                return status(&meta);
+            }
+            self.load_table(id)?;
+        }
+        let current_tables = self.current_tables.borrow();
+        let table = current_tables[&id].borrow();
+        status(&table.meta)
     }
 }
 
+/// What `SHOW TABLE STATUS` reports of a table
+#[derive(Debug)]
+pub struct TableStatus {
+    pub name: String,
+    pub rows: usize,
+    /// Pages holding rows, or having held them
+    pub pages: PageNum,
+    /// Pages of the data file, including those reserved for rows to come
+    pub file_pages: u64,
+    pub indices: usize,
+}
+
 impl Serialize for Database {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -232,20 +426,28 @@ pub fn get_table_id(name: &str) -> Option<TableID> {
     DATABASE.borrow().get_table_id(name)
 }
 
+/// Names and ids of all tables in the current database, ordered by name
+pub fn table_ids() -> DBResult<Vec<(String, TableID)>> {
+    let database = DATABASE.borrow();
+    let mut tables = database
+        .list_tables()?
+        .into_iter()
+        .map(|name| (name.to_owned(), database.get_table_id(name).unwrap()))
+        .collect::<Vec<_>>();
+    tables.sort();
+    Ok(tables)
+}
+
 pub fn load_table(name: &str) -> DBResult<TableID> {
     let inner = DATABASE.borrow();
     let id = inner.get_table_id(name).ok_or("no such table")?;
-    if !inner.check_loaded(id) {
-        inner.load_table(id).expect("error when loading table");
-    }
+    inner.load_table(id).expect("error when loading table");
     Ok(id)
 }
 
 pub fn ensure_table<T>(id: TableID, action: impl FnOnce(&Table) -> T) -> T {
     let inner = DATABASE.borrow();
-    if !inner.check_loaded(id) {
-        inner.load_table(id).expect("error when loading table");
-    }
+    inner.load_table(id).expect("error when loading table");
     let current_tables = inner.current_tables.borrow();
     let table = current_tables.get(&id).unwrap();
     let table = table.borrow();
@@ -254,9 +456,7 @@ pub fn ensure_table<T>(id: TableID, action: impl FnOnce(&Table) -> T) -> T {
 
 pub fn ensure_table_mut<T>(id: TableID, action: impl FnOnce(&mut Table) -> T) -> T {
     let inner = DATABASE.borrow();
-    if !inner.check_loaded(id) {
-        inner.load_table(id).expect("error when loading table");
-    }
+    inner.load_table(id).expect("error when loading table");
     let current_tables = inner.current_tables.borrow();
     let table = current_tables.get(&id).unwrap();
     let mut table = table.borrow_mut();
@@ -265,6 +465,7 @@ pub fn ensure_table_mut<T>(id: TableID, action: impl FnOnce(&mut Table) -> T) ->
 
 pub fn get_table<T>(id: TableID, action: impl FnOnce(&Table) -> T) -> T {
     let inner = DATABASE.borrow();
+    inner.touch(id);
     let current_tables = inner.current_tables.borrow();
     let table = current_tables.get(&id).unwrap();
     let table = table.borrow();
@@ -273,32 +474,60 @@ pub fn get_table<T>(id: TableID, action: impl FnOnce(&Table) -> T) -> T {
 
 pub fn modify_table<T>(id: TableID, action: impl FnOnce(&mut Table) -> T) -> T {
     let inner = DATABASE.borrow();
+    inner.touch(id);
     let current_tables = inner.current_tables.borrow();
     let table = current_tables.get(&id).unwrap();
     let mut table = table.borrow_mut();
     action(&mut table)
 }
 
+/// Write back the least recently used tables beyond `max_loaded_tables`, called between
+/// statements as no table may be borrowed
+pub fn evict_tables() -> DBResult<()> {
+    let keep = SETTINGS.borrow().max_loaded_tables;
+    DATABASE.borrow().evict_tables(keep)
+}
+
+/// Number of tables loaded with their indices
+#[cfg(test)]
+pub fn loaded_tables() -> usize {
+    DATABASE.borrow().current_tables.borrow().len()
+}
+
 pub fn create_database(db_name: &str) -> DBResult<()> {
     DATABASE.borrow().create_database(db_name)?;
     Ok(())
 }
 
-pub fn change_database(db_name: &str) -> bool {
+pub fn change_database(db_name: &str) -> DBResult<bool> {
     DATABASE.borrow_mut().change_database(db_name)
 }
 
+/// Name of the database in use
+#[cfg(test)]
+pub fn current_database() -> Option<String> {
+    DATABASE.borrow().current_database().map(ToOwned::to_owned)
+}
+
 pub fn drop_database(db_name: &str) -> DBResult<()> {
     DATABASE.borrow().drop_database(db_name)?;
     Ok(())
 }
 
-pub fn create_table(tb_name: &str, fields: &[CreateTBField]) -> DBResult<()> {
+pub fn rename_database(old: &str, new: &str) -> DBResult<()> {
+    DATABASE.borrow().rename_database(old, new)
+}
+
+pub fn create_table(tb_name: &str, fields: &[CreateTBField], temporary: bool) -> DBResult<()> {
+    if is_system_table(tb_name) {
+        return Err(format!("table name {} is reserved for a system table", tb_name).into());
+    }
     let mut inner = DATABASE.borrow_mut();
     let mut column_record = HashSet::new();
     let mut foreign = None;
 
-    inner.new_table(tb_name, |meta| {
+    inner.new_table(tb_name, temporary, |meta| {
+        meta.null_layout = SETTINGS.borrow().null_layout;
         for field in fields {
             match field {
                 CreateTBField::Constraint(NamedTBConstraint {
@@ -334,40 +563,63 @@ pub fn create_table(tb_name: &str, fields: &[CreateTBField]) -> DBResult<()> {
                             return Err("no such column in table".into());
                         }
                     }
-                    Check { .. } => todo!(),
+                    Check { colname, exprs } => {
+                        let col = meta
+                            .get_column_id(colname)
+                            .ok_or("no such column in table")?;
+                        let allowed = check_values(exprs, meta.columns[col as usize].coltype)?;
+                        // several checks on a column allow the values all of them allow
+                        match meta.check.get_mut(&col) {
+                            Some(prev) => prev.retain(|val| allowed.contains(val)),
+                            None => {
+                                meta.check.insert(col, allowed);
+                            }
+                        }
+                    }
                     Foreign {
                         colname,
                         foreign_tb,
                         foreign_col,
+                        on_delete,
+                        on_update,
                     } => {
+                        let policy = ForeignPolicy {
+                            on_delete: (*on_delete).into(),
+                            on_update: (*on_update).into(),
+                        };
                         let id = meta.id();
                         let table_cols = meta
                             .get_columns_id(colname)
                             .ok_or("no such column in current table")?;
                         foreign = Some(move || -> DBResult<_> {
                             if let Some(ftable_id) = get_table_id(foreign_tb) {
-                                if colname.len() != foreign_col.len() {
-                                    return Err("columns number should be the same".into());
-                                }
-                                let ftable_cols = modify_table(ftable_id, |table| -> Vec<ColID> {
-                                    let ftable_cols = table
-                                        .meta
-                                        .get_columns_id(foreign_col)
-                                        .ok_or("no such column in foreign table")
-                                        .unwrap();
+                                check_foreign_temporary(id, ftable_id)?;
+                                let ftable_cols = ensure_table(ftable_id, |ftable| {
+                                    ftable.meta.get_columns_id(foreign_col)
+                                })
+                                .ok_or("no such column in foreign table")?;
+                                get_table(id, |table| {
+                                    get_table(ftable_id, |ftable| {
+                                        table.meta.check_foreign_columns(
+                                            &table_cols,
+                                            &ftable.meta,
+                                            &ftable_cols,
+                                        )
+                                    })
+                                })?;
+                                modify_table(ftable_id, |table| {
                                     if ftable_cols.len() == 1 {
                                         table
                                             .meta
                                             .columns
-                                            .get_mut(table_cols[0] as usize)
+                                            .get_mut(ftable_cols[0] as usize)
                                             .unwrap()
                                             .constraints |= Constraints::AS_FOREIGN_KEY;
                                     }
                                     table.meta.unique.insert(ftable_cols.clone());
                                     table
                                         .meta
-                                        .add_foreign_key(&ftable_cols.clone(), (id, table_cols.clone()));
-                                    ftable_cols
+                                        .add_foreign_key(&ftable_cols.clone(), (id, table_cols.clone()), policy);
                                 });
                                 modify_table(id, |table| {
                                     if table_cols.len() == 1 {
@@ -392,13 +644,27 @@ pub fn create_table(tb_name: &str, fields: &[CreateTBField]) -> DBResult<()> {
                 },
                 CreateTBField::Column(column) => {
                     if column_record.contains(column.name.as_str()) {
-                        return Err("".into());
+                        return Err(
+                            format!("column {} is defined more than once", column.name).into()
+                        );
                     }
                     column_record.insert(column.name.as_str());
-                    meta.columns.push(column.try_into()?);
+                    meta.push_column(column.try_into()?);
+                    let col = meta.columns.len() as ColID - 1;
+                    if column.primary {
+                        if !meta.primary.is_empty() {
+                            return Err("single primary key allowed".into());
+                        }
+                        meta.primary = vec![col];
+                        meta.unique.insert(vec![col]);
+                    }
+                    if column.unique {
+                        meta.unique.insert(vec![col]);
+                    }
                 }
             } // match
         } // for
+        check_conflicts(meta, fields)?;
         meta.rest_slot = meta.max_slot() as u32 * PAGE_NUM_ON_CREATE as u32;
         Ok(())
     })?;
@@ -411,42 +677,227 @@ pub fn create_table(tb_name: &str, fields: &[CreateTBField]) -> DBResult<()> {
     Ok(())
 }
 
+/// Foreign keys are only allowed between temporary tables or between tables of the database,
+/// as temporary tables are dropped without the tables of the database knowing
+pub fn check_foreign_temporary(table: TableID, ftable: TableID) -> DBResult<()> {
+    let database = DATABASE.borrow();
+    match (database.is_temporary(table), database.is_temporary(ftable)) {
+        (true, false) => Err("a temporary table cannot reference a table of the database".into()),
+        (false, true) => {
+            Err("a temporary table cannot be referenced by a table of the database".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether a table exists, see `Database::table_exists`
+pub fn table_exists(name: &str, temporary: bool) -> bool {
+    DATABASE.borrow().table_exists(name, temporary)
+}
+
+/// Values allowed by `CHECK (col IN (exprs))` on a column of `coltype`, NULL is left out
+pub fn check_values(exprs: &[Expr], coltype: ColumnType) -> DBResult<Vec<ColumnVal>> {
+    let mut allowed = vec![];
+    for expr in exprs {
+        // the values are constants, evaluated without any row
+        if let Some(val) = eval_expr(expr, Row::Constant)? {
+            allowed.push(cast(val, coltype)?);
+        }
+    }
+    Ok(allowed)
+}
+
+/// Reject constraints of a new table that contradict each other, checked once all of them
+/// are known since a constraint may refer to columns defined after it
+fn check_conflicts(meta: &TableMeta, fields: &[CreateTBField]) -> DBResult<()> {
+    for field in fields {
+        match field {
+            CreateTBField::Column(column) => {
+                let col = meta.get_column_id(&column.name).unwrap();
+                if column.nullable && meta.primary.contains(&col) {
+                    return Err(format!(
+                        "column {} is in the primary key and cannot be declared NULL",
+                        column.name
+                    )
+                    .into());
+                }
+            }
+            CreateTBField::Constraint(NamedTBConstraint { constraint, .. }) => {
+                let (kind, cols) = match constraint {
+                    Primary(cols) => ("PRIMARY KEY", cols),
+                    Unique(cols) => ("UNIQUE", cols),
+                    Foreign {
+                        colname,
+                        on_delete,
+                        on_update,
+                        ..
+                    } => {
+                        let policy = ForeignPolicy {
+                            on_delete: (*on_delete).into(),
+                            on_update: (*on_update).into(),
+                        };
+                        let cols = meta.get_columns_id(colname).unwrap();
+                        meta.check_foreign_policy(&cols, policy)?;
+                        ("FOREIGN KEY", colname)
+                    }
+                    Check { .. } => continue,
+                };
+                for (i, col) in cols.iter().enumerate() {
+                    if cols[..i].contains(col) {
+                        return Err(format!("column {} appears twice in {}", col, kind).into());
+                    }
+                }
+            }
+        }
+    }
+    for (&col, allowed) in &meta.check {
+        let column = &meta.columns[col as usize];
+        let not_null = column.constraints.is_not_null() || column.constraints.is_primary_key();
+        if allowed.is_empty() && not_null {
+            return Err(format!(
+                "CHECK constraints on column {} allow no value, and it cannot be NULL",
+                column.name
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// A temporary table is dropped before the table it shadows
 pub fn drop_table(tb_name: &str) -> DBResult<()> {
     let mut inner = DATABASE.borrow_mut();
-    let id = match inner.id_record.remove_by_left(tb_name) {
-        Some((_, table_id)) => table_id,
-        None => return Err("no such table in database".into()),
+    let (id, dir) = match inner.temporary.remove_by_left(tb_name) {
+        Some((_, table_id)) => (table_id, inner.temp_dir.clone()),
+        None => match inner.id_record.remove_by_left(tb_name) {
+            Some((_, table_id)) => (table_id, inner.current.clone()),
+            None => return Err("no such table in database".into()),
+        },
     };
     let mut current_tables = inner.current_tables.borrow_mut();
     match current_tables.remove(&id) {
         Some(table) => table.into_inner().delete_self(),
-        _ => Table::load_no_index(inner.current.as_path(), tb_name)?.delete_self(),
+        _ => Table::load_no_index(&dir, tb_name)?.delete_self(),
     }
 }
 
-pub fn show_databases() -> DBResult<()> {
-    let dbs = DATABASE.borrow().list_databases()?;
-    if dbs.is_empty() {
-        println!("No database yet.");
-    } else {
-        for db in dbs {
-            println!("{}", db);
+/// Drop tables at once, none of them if one is referenced by a foreign key of a table
+/// not dropped. Foreign keys of the dropped tables no longer restrict the tables they reference
+pub fn drop_tables(names: &[String]) -> DBResult<()> {
+    let ids = names
+        .iter()
+        .map(|name| load_table(name))
+        .collect::<DBResult<Vec<_>>>()?;
+    for (name, &id) in names.iter().zip(&ids) {
+        let ref_tables = get_table(id, |table| {
+            let refs = table.meta.as_foreign_key.values();
+            refs.flat_map(|refs| refs.keys().map(|(ref_table, _)| *ref_table))
+                .collect::<Vec<_>>()
+        });
+        if let Some(ref_table) = ref_tables.iter().find(|id| !ids.contains(id)) {
+            let database = DATABASE.borrow();
+            let ref_name = database.table_name(*ref_table).unwrap();
+            return Err(format!(
+                "table {} is referenced by a foreign key of table {}",
+                name, ref_name
+            )
+            .into());
         }
     }
+    for &id in &ids {
+        let foreign_keys = get_table(id, |table| table.meta.foreign_key.clone());
+        for (cols, (ftable, fcols)) in foreign_keys {
+            if ids.contains(&ftable) {
+                continue;
+            }
+            ensure_table_mut(ftable, |ftable| {
+                if let Some(refs) = ftable.meta.as_foreign_key.get_mut(&fcols) {
+                    refs.remove(&(id, cols));
+                    if refs.is_empty() && fcols.len() == 1 {
+                        let col = &mut ftable.meta.columns[fcols[0] as usize];
+                        col.constraints &= !Constraints::AS_FOREIGN_KEY;
+                    }
+                }
+            });
+        }
+    }
+    for name in names {
+        drop_table(name)?;
+    }
     Ok(())
 }
 
-pub fn show_tables() -> DBResult<()> {
-    let database = DATABASE.borrow();
-    let tables = database.list_tables()?;
-    if tables.is_empty() {
-        println!("No table currently in this database");
-        return Ok(());
-    }
-    for table in tables {
-        println!("{}", table);
+/// Keep the names matching a LIKE pattern, all of them if there is none
+fn matching_names(names: Vec<String>, pattern: Option<&str>) -> DBResult<Vec<String>> {
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return Ok(names),
+    };
+    let mut ret = vec![];
+    for name in names {
+        if like_match(&name, CompareOp::LIKE, pattern)? {
+            ret.push(name);
+        }
     }
-    Ok(())
+    Ok(ret)
+}
+
+/// Names of all databases, or those matching a LIKE pattern
+pub fn database_names(pattern: Option<&str>) -> DBResult<Vec<String>> {
+    matching_names(DATABASE.borrow().list_databases()?, pattern)
+}
+
+/// Names of tables in the current database, or those matching a LIKE pattern
+pub fn table_names(pattern: Option<&str>) -> DBResult<Vec<String>> {
+    let database = DATABASE.borrow();
+    let tables = database.list_tables()?.into_iter().map(ToOwned::to_owned);
+    matching_names(tables.collect(), pattern)
+}
+
+pub fn column_names(tb_name: &str) -> DBResult<Vec<String>> {
+    let id = load_table(tb_name)?;
+    Ok(ensure_table(id, |table| {
+        table
+            .meta
+            .columns
+            .iter()
+            .map(|col| col.name.clone())
+            .collect()
+    }))
+}
+
+pub fn table_status(pattern: Option<&str>) -> DBResult<Vec<TableStatus>> {
+    let tables = table_names(pattern)?;
+    let database = DATABASE.borrow();
+    tables
+        .iter()
+        .map(|name| database.table_status(name))
+        .collect()
+}
+
+/// Load a table with its indices, which are kept in memory, and read all its pages
+/// into the page cache, see `.warm`. Returns the number of pages read,
+/// none if they would not fit in the cache
+pub fn warm_table(tb_name: &str) -> DBResult<PageNum> {
+    let id = load_table(tb_name)?;
+    ensure_table(id, |table| {
+        let pages = table.meta.page_count();
+        if pages as usize > page_manager::cache_size() {
+            eprintln!(
+                "Warning: table {} has {} pages, more than the page cache holds, not warmed",
+                tb_name, pages
+            );
+            return Ok(0);
+        }
+        table.warm()?;
+        Ok(pages)
+    })
+}
+
+/// Checksum of the rows of a table, see `.checksum`
+pub fn table_checksum(tb_name: &str) -> DBResult<u64> {
+    let id = load_table(tb_name)?;
+    ensure_table(id, Table::checksum)
 }
 
 pub fn write_back() -> DBResult<()> {
@@ -1,155 +1,44 @@
-use std::collections::{HashMap, HashSet};
-use std::time::Duration;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use crate::dbms::aggregate::{avg, count, count_all, max, min, sum_float, sum_int};
+use crate::config::{ConstraintMode, SETTINGS};
 use crate::defines::TableID;
 use crate::defines::{ColID, RowID};
 use crate::error::DBResult;
 use crate::index::colindex::{data2fastcmp, ColIndex, EntryRef, IndexKey};
-use crate::record::{Constraints, Table, ColumnType, vec_to_buf};
-use crate::utils::naive_timeit;
-use crate::utils::table::{check_constraint, get_coltype, print_join_table, print_vec};
+use crate::init::{begin_statement, check_terminating};
+use crate::record::{ColumnVal, Constraints, ForeignPolicy, RefAction, Table};
+use crate::utils::table::{check_constraint, get_coltype};
 use naive_sql_parser::{
-    AddForeign, AddPrimary, Aggregator, Alter,
-    ColumnRef::{self, *},
-    CondExpr, CreateDB, CreateIdx, CreateTB, Delete, Desc, DropDB, DropForeign, DropIdx, DropTB,
-    Insert, Select,
-    Selectors::*,
-    Show, SqlStmt, Update, UseDB,
+    AddForeign, AddPrimary, Alter, CheckConstraint, ColumnRef::*, CompareOp, CreateDB, CreateIdx,
+    CreateTB, Delete, Desc, DropDB, DropForeign, DropIdx, DropTB, Expr, Insert, ProposedConstraint,
+    Select, Set, Show, SqlStmt, Union, Update, UseDB,
 };
 
 use super::database as db;
-use super::relation::{relation, Logic};
+use super::expr::{cast, eval_expr, Row};
+use super::query::{self, query, ResultSet};
+use super::relation::{matching_rows, TableRef};
+use super::system;
 
-fn print_affected(n: usize) {
-    println!("{} row(s) affected", n);
+/// What a statement gives back, printed by the REPL or the CLI
+#[derive(Debug)]
+pub enum ExecResult {
+    /// Rows of a query, show or describe statement with the names of their columns,
+    /// and the number of rows left out beyond `max_rows`
+    Rows(Vec<Vec<Option<ColumnVal>>>, Vec<String>, usize),
+    /// Number of rows inserted, updated or deleted
+    Affected(usize),
+    Message(String),
+    Empty,
 }
 
-fn print_duration(action: &str, d: Duration, after: &str) {
-    if d.as_secs() != 0 {
-        println!(
-            "Completed {} in {:.3} seconds{}",
-            action,
-            d.as_secs_f32(),
-            after
-        );
-    } else {
-        let microsec = d.as_micros();
-        if microsec >= 1000 {
-            println!(
-                "Completed {} in {:.3} milliseconds{}",
-                action,
-                microsec as f32 / 1000.,
-                after
-            );
-        } else {
-            println!(
-                "Completed {} in {:.3} microseconds{}",
-                action, d.as_nanos() as f32 / 1000., after
-            );
-        }
+impl From<ResultSet> for ExecResult {
+    fn from(result: ResultSet) -> Self {
+        ExecResult::Rows(result.rows, result.header, 0)
     }
 }
 
-fn _print_time<T>(action: &str, f: impl FnOnce() -> T) -> T {
-    let (ret, d) = naive_timeit(f);
-    print_duration(action, d, " (including possible io)");
-    ret
-}
-
-macro_rules! print_time {
-    ( $func:tt ( $args:expr ) ) => {
-        _print_time(stringify!($func), || $func($args))
-    };
-    ( $func:tt ( $args:expr ), $action:expr ) => {
-        _print_time($action, || $func($args))
-    };
-}
-
-fn check_colref(colref: &ColumnRef, table: &Table) -> DBResult<ColID> {
-    let ret = match colref {
-        Ident(ident) => table
-            .meta
-            .get_column_id(ident)
-            .ok_or("no such column in table"),
-        Attr { table_name, column } => {
-            if table_name != table.meta.name() {
-                return Err("no such column in table".into());
-            }
-            table
-                .meta
-                .get_column_id(column)
-                .ok_or("no such column in table")
-        }
-    }?;
-    Ok(ret)
-}
-
-fn check_colref_joined(
-    colref: &ColumnRef,
-    ltable: &Table,
-    rtable: &Table,
-) -> DBResult<(TableID, ColID)> {
-    let lcol = check_colref(colref, ltable);
-    let rcol = check_colref(colref, rtable);
-    match (lcol, rcol) {
-        (Ok(_), Ok(_)) => Err("column exists in both tables"),
-        (Ok(col), Err(_)) => Ok((ltable.meta.id(), col)),
-        (Err(_), Ok(col)) => Ok((rtable.meta.id(), col)),
-        (Err(_), Err(_)) => Err("column doesn't in both tables"),
-    }
-    .map_err(Into::into)
-}
-
-fn get_aggr(
-    aggr: &Aggregator,
-    colref: &ColumnRef,
-    rows: impl Iterator<Item = RowID>,
-    id: TableID,
-    col: ColID,
-) -> DBResult<String> {
-    let ret = match aggr {
-        Aggregator::COUNT => {
-            let count = count(rows, id, col)?;
-            format!("COUNT({}): {}", colref, count)
-        }
-        Aggregator::AVG => {
-            let avg = avg(rows, id, col)?;
-            format!("AVG({}): {}", colref, avg)
-        }
-        Aggregator::MIN => {
-            let min = min(rows, id, col)?;
-            match min {
-                Some(min) => format!("MIN({}): {}", colref, min),
-                None => "NULL".to_owned(),
-            }
-        }
-        Aggregator::MAX => {
-            let max = max(rows, id, col)?;
-            match max {
-                Some(max) => format!("MAX({}): {}", colref, max),
-                None => "NULL".to_owned(),
-            }
-        }
-        Aggregator::SUM => {
-            match db::get_table(id, |table| table.meta.columns[col as usize].coltype) {
-                ColumnType::Int => {
-                    let sum = sum_int(rows, id, col)?;
-                    format!("SUM({}): {}", colref, sum)
-                }
-                ColumnType::Float => {
-                    let sum = sum_float(rows, id, col)?;
-                    format!("SUM({}): {}", colref, sum)
-                }
-                _ => {
-                    return Err("column referenced in `SUM` must be of `INT` or `FLOAT` type".into())
-                }
-            }
-        }
-    };
-    Ok(ret)
-}
-
 pub trait Exec {
     type Success;
 
@@ -157,36 +46,40 @@ pub trait Exec {
 }
 
 impl Exec for SqlStmt {
-    type Success = ();
+    type Success = ExecResult;
 
     fn exec(&self) -> DBResult<Self::Success> {
-        match self {
-            SqlStmt::CreateDB(create_db_args) => print_time!(create_database(create_db_args)),
-            SqlStmt::CreateTB(create_tb_args) => print_time!(create_table(create_tb_args)),
-            SqlStmt::CreateIdx(create_idx_args) => print_time!(create_index(create_idx_args)),
-            SqlStmt::DropDB(drop_db_args) => print_time!(drop_database(drop_db_args)),
-            SqlStmt::DropTB(drop_tb_args) => print_time!(drop_table(drop_tb_args)),
-            SqlStmt::DropIdx(drop_idx_args) => print_time!(drop_index(drop_idx_args)),
-            SqlStmt::Select(select_args) => print_time!(select(select_args)),
-            SqlStmt::Insert(insert_args) => print_time!(insert(insert_args)),
-            SqlStmt::Update(update_args) => print_time!(update(update_args)),
-            SqlStmt::Delete(delete_args) => print_time!(delete(delete_args)),
-            SqlStmt::UseDB(use_db_args) => print_time!(use_database(use_db_args)),
-            SqlStmt::Show(show_args) => print_time!(show(show_args)),
-            SqlStmt::Desc(desc_args) => print_time!(describe(desc_args)),
-            SqlStmt::Alter(alter_args) => print_time!(alter_table(alter_args)),
-        }
+        let _executing = begin_statement()?;
+        let empty = |ret: DBResult<()>| ret.map(|_| ExecResult::Empty);
+        let ret = match self {
+            SqlStmt::CreateDB(create_db_args) => empty(create_database(create_db_args)),
+            SqlStmt::CreateTB(create_tb_args) => empty(create_table(create_tb_args)),
+            SqlStmt::CreateIdx(create_idx_args) => empty(create_index(create_idx_args)),
+            SqlStmt::DropDB(drop_db_args) => empty(drop_database(drop_db_args)),
+            SqlStmt::DropTB(drop_tb_args) => drop_table(drop_tb_args),
+            SqlStmt::DropIdx(drop_idx_args) => empty(drop_index(drop_idx_args)),
+            SqlStmt::Select(select_args) => select(select_args),
+            SqlStmt::Union(union_args) => union(union_args),
+            SqlStmt::Insert(insert_args) => insert(insert_args).map(ExecResult::Affected),
+            SqlStmt::Update(update_args) => update(update_args).map(ExecResult::Affected),
+            SqlStmt::Delete(delete_args) => delete(delete_args).map(ExecResult::Affected),
+            SqlStmt::UseDB(use_db_args) => empty(use_database(use_db_args)),
+            SqlStmt::Show(show_args) => show(show_args),
+            SqlStmt::Desc(desc_args) => describe(desc_args),
+            SqlStmt::Alter(alter_args) => empty(alter_table(alter_args)),
+            SqlStmt::Set(set_args) => empty(set_variable(set_args)),
+            SqlStmt::CheckConstraint(check_args) => check_violations(check_args),
+        };
+        db::evict_tables()?;
+        ret
     }
 }
 
 impl Exec for Vec<SqlStmt> {
-    type Success = ();
+    type Success = Vec<ExecResult>;
 
     fn exec(&self) -> DBResult<Self::Success> {
-        for sql in self {
-            sql.exec()?;
-        }
-        Ok(())
+        self.iter().map(Exec::exec).collect()
     }
 }
 
@@ -197,15 +90,23 @@ fn alter_table(args: &Alter) -> DBResult<()> {
         Alter::AddPrimary(args) => add_primary(args),
         Alter::AddForeign(args) => add_foreign(args),
         Alter::DropForeign(args) => drop_foreign(args),
+        Alter::RenameDB(args) => db::rename_database(&args.old, &args.new),
     }
 }
 
+fn set_variable(args: &Set) -> DBResult<()> {
+    SETTINGS.borrow_mut().set(&args.name, &args.value)
+}
+
 fn create_database(args: &CreateDB) -> DBResult<()> {
-    db::create_database(&args.0)
+    if args.if_not_exists && db::database_names(None)?.contains(&args.name) {
+        return Ok(());
+    }
+    db::create_database(&args.name)
 }
 
 fn use_database(args: &UseDB) -> DBResult<()> {
-    if db::change_database(&args.0) {
+    if db::change_database(&args.0)? {
         Ok(())
     } else {
         Err("database does not exist".into())
@@ -213,30 +114,48 @@ fn use_database(args: &UseDB) -> DBResult<()> {
 }
 
 fn drop_database(args: &DropDB) -> DBResult<()> {
-    db::drop_database(&args.0)
+    if args.if_exists && !db::database_names(None)?.contains(&args.name) {
+        return Ok(());
+    }
+    db::drop_database(&args.name)
 }
 
 fn create_table(args: &CreateTB) -> DBResult<()> {
-    db::create_table(&args.name, &args.fields)
+    if args.if_not_exists && db::table_exists(&args.name, args.temporary) {
+        return Ok(());
+    }
+    db::create_table(&args.name, &args.fields, args.temporary)
 }
 
 fn create_index(args: &CreateIdx) -> DBResult<()> {
     let id = db::load_table(&args.table_name)?;
-    let (colbuf, col_index) = db::ensure_table(id, |table| -> DBResult<_> {
+    let index = db::ensure_table(id, |table| -> DBResult<_> {
         let cols = table
             .meta
             .get_columns_id(&args.fields)
             .ok_or(format!("no such columns in table {}", args.table_name))?;
         table.create_index(&cols, false)
     })?;
-    db::modify_table(id, |table| {
-        table.indices.insert(colbuf, col_index.into());
-    });
+    // also records the index in meta, so it is reloaded with the table
+    db::modify_table(id, |table| table.insert_index(index));
     Ok(())
 }
 
-fn drop_table(args: &DropTB) -> DBResult<()> {
-    db::drop_table(&args.0)
+fn drop_table(args: &DropTB) -> DBResult<ExecResult> {
+    match args {
+        DropTB::Name { name, if_exists } => {
+            if !*if_exists || db::table_names(None)?.contains(name) {
+                db::drop_tables(&[name.to_owned()])?;
+            }
+            Ok(ExecResult::Empty)
+        }
+        DropTB::Like(pattern) => {
+            let names = db::table_names(Some(pattern))?;
+            db::drop_tables(&names)?;
+            let message = format!("{} table(s) dropped", names.len());
+            Ok(ExecResult::Message(message))
+        }
+    }
 }
 
 fn drop_index(args: &DropIdx) -> DBResult<()> {
@@ -246,6 +165,18 @@ fn drop_index(args: &DropIdx) -> DBResult<()> {
 
 fn add_primary(args: &AddPrimary) -> DBResult<()> {
     let id = db::load_table(&args.table_name)?;
+    // built before the table is borrowed mutably, comparing keys may read the table
+    let index = db::ensure_table(id, |table| -> DBResult<_> {
+        let cols = table
+            .meta
+            .get_columns_id(&args.cols)
+            .ok_or(format!("no such columns in table {}", args.table_name))?;
+        if table.meta.primary.is_empty() && !table.has_unique_index(&cols) {
+            table.create_index(&cols, true).map(Some)
+        } else {
+            Ok(None)
+        }
+    })?;
     db::ensure_table_mut(id, |table| -> DBResult<()> {
         if !table.meta.primary.is_empty() {
             return Err("a table cannot have more than one primary key".into());
@@ -254,13 +185,12 @@ fn add_primary(args: &AddPrimary) -> DBResult<()> {
                 .meta
                 .get_columns_id(&args.cols)
                 .ok_or(format!("no such columns in table {}", args.table_name))?;
-            let col_buf = vec_to_buf(&cols);
-            if table.indices.get(&(col_buf, cols.len() as u8)).is_none() {
-                let (colbuf, col_index) = table.create_index(&cols, true)?;
-                table.indices.insert(colbuf, col_index.into());
-                table.meta.primary = cols.clone();
-                table.meta.unique.insert(cols.clone());
+            // a plain index on the columns is replaced by a unique one
+            if let Some(index) = index {
+                table.insert_index(index);
             }
+            table.meta.primary = cols.clone();
+            table.meta.unique.insert(cols.clone());
             if cols.len() == 1 {
                 table.meta.columns.get_mut(cols[0] as usize).unwrap().constraints |= Constraints::PRIMARY_KEY;
             }
@@ -273,24 +203,43 @@ fn add_primary(args: &AddPrimary) -> DBResult<()> {
 fn add_foreign(args: &AddForeign) -> DBResult<()> {
     let table_id = db::load_table(&args.table_name)?;
     let ftable_id = db::load_table(&args.ftable_name)?;
+    db::check_foreign_temporary(table_id, ftable_id)?;
+    // built before the tables are borrowed mutably, comparing keys may read the table
+    let findex = db::ensure_table(ftable_id, |ftable| -> DBResult<_> {
+        let fcols = ftable
+            .meta
+            .get_columns_id(&args.fcols)
+            .ok_or(format!("no such column in table {}", args.ftable_name))?;
+        if !ftable.has_unique_index(&fcols) {
+            ftable.create_index(&fcols, true).map(Some)
+        } else {
+            Ok(None)
+        }
+    })?;
     db::modify_table(table_id, |table| -> DBResult<()> {
         let cols = table
             .meta
             .get_columns_id(&args.cols)
             .ok_or(format!("no such column in table {}", args.table_name))?;
+        let policy = ForeignPolicy {
+            on_delete: args.on_delete.into(),
+            on_update: args.on_update.into(),
+        };
+        table.meta.check_foreign_policy(&cols, policy)?;
         let fcols = db::modify_table(ftable_id, |ftable| -> DBResult<Vec<ColID>> {
             let fcols = ftable
                 .meta
                 .get_columns_id(&args.fcols)
                 .ok_or(format!("no such column in table {}", args.ftable_name))?;
+            table
+                .meta
+                .check_foreign_columns(&cols, &ftable.meta, &fcols)?;
             //check fcols is unique, maybe build a index here
             //dont repeatedly build index here
-            let col_buf = vec_to_buf(&fcols);
-            if ftable.indices.get(&(col_buf, fcols.len() as _)).is_none() {
-                let (colbuf, col_index) = ftable.create_index(&fcols, true)?;
-                ftable.indices.insert(colbuf, col_index.into());
-                ftable.meta.unique.insert(fcols.clone());
+            if let Some(index) = findex {
+                ftable.insert_index(index);
             }
+            ftable.meta.unique.insert(fcols.clone());
             //check every row exist in ftable
             for rid in table.rows() {
                 let row_data = table.select_cols(rid, cols.iter().cloned())?;
@@ -308,7 +257,7 @@ fn add_foreign(args: &AddForeign) -> DBResult<()> {
             }
             ftable
                 .meta
-                .add_foreign_key(&fcols.clone(), (table_id, cols.clone()));
+                .add_foreign_key(&fcols.clone(), (table_id, cols.clone()), policy);
             Ok(fcols)
         })?;
         if cols.len() == 1 {
@@ -339,7 +288,7 @@ fn drop_foreign(args: &DropForeign) -> DBResult<()> {
                 .get_columns_id(&args.fcols)
                 .ok_or(format!("no such column in table {}", args.ftable_name))?;
             if let Some(refs) = ftable.meta.as_foreign_key.get_mut(&fcols) {
-                if !refs.remove(&(table_id, cols.clone())) {
+                if refs.remove(&(table_id, cols.clone())).is_none() {
                     return Err(format!(
                         "no such foreign relation between table {} ans table {}",
                         args.table_name, args.ftable_name
@@ -369,244 +318,306 @@ fn drop_foreign(args: &DropForeign) -> DBResult<()> {
     Ok(())
 }
 
-fn select(args: &Select) -> DBResult<()> {
-    let mut table_ids = vec![];
-    for table in &args.from {
-        if let Some(id) = db::get_table_id(table) {
-            table_ids.push(id);
+fn select(args: &Select) -> DBResult<ExecResult> {
+    let mut result = query(args)?;
+    let omitted = limit_rows(&mut result.rows);
+    Ok(ExecResult::Rows(result.rows, result.header, omitted))
+}
+
+fn union(args: &Union) -> DBResult<ExecResult> {
+    let mut result = query::union(args)?;
+    let omitted = limit_rows(&mut result.rows);
+    Ok(ExecResult::Rows(result.rows, result.header, omitted))
+}
+
+/// Keep the first `max_rows` rows, if set, giving the number of the others
+fn limit_rows<T>(rows: &mut Vec<T>) -> usize {
+    let max_rows = SETTINGS.borrow().max_rows;
+    if max_rows == 0 || rows.len() <= max_rows {
+        return 0;
+    }
+    let omitted = rows.len() - max_rows;
+    rows.truncate(max_rows);
+    omitted
+}
+
+/// Check not null, unique and foreign key constraints of a record about to be inserted.
+/// Unique keys with a unique index are left to `batch_duplicates`
+///
+/// Tables referenced by foreign keys should be loaded before calling this
+pub fn check_record(table: &Table, record_data: &[Option<ColumnVal>], i: usize) -> DBResult<()> {
+    check_row(table, record_data, i)?;
+    for unique_cols in &table.meta.unique {
+        if table.has_unique_index(unique_cols) {
+            continue;
+        }
+        let slice_data = table.get_data_cols(record_data, unique_cols);
+        if table.check_data_exist(&slice_data, unique_cols) {
+            return Err(format!("record {} doesn't satisfy unique requirment", i).into());
+        }
+    }
+    for (table_cols, (ftable_id, ftable_cols)) in &table.meta.foreign_key {
+        let slice_data = table.get_data_cols(record_data, table_cols);
+        db::ensure_table(*ftable_id, |ftable| -> DBResult<()> {
+            if !ftable.check_data_exist(&slice_data, ftable_cols) {
+                return Err(format!("record {} doesn't satisfy foreign key requirment", i).into());
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Check constraints of a record about to be inserted which need no other row,
+/// i.e. not null constraints and types
+pub fn check_row(table: &Table, record_data: &[Option<ColumnVal>], i: usize) -> DBResult<()> {
+    table
+        .check_row_data(record_data)
+        .map_err(|e| format!("record {} is invalid: {}", i, e).into())
+}
+
+/// Check unique and foreign key constraints of records about to be inserted together,
+/// for `SET CONSTRAINTS DEFERRED`. Unique keys of the table without an index are read once
+/// and sorted, and each distinct foreign key is looked up once, rather than looking up
+/// the tables for every record as `check_record` does
+///
+/// Tables referenced by foreign keys should be loaded before calling this
+pub fn check_deferred(table: &Table, records: &[Vec<Option<ColumnVal>>]) -> DBResult<()> {
+    let cmp = |lhs: &Vec<_>, rhs: &Vec<_>| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal);
+    if let Some(i) = batch_duplicates(table, records).first() {
+        return Err(format!("record {} doesn't satisfy unique requirment", i).into());
+    }
+    for unique_cols in &table.meta.unique {
+        if table.has_unique_index(unique_cols) {
+            continue;
+        }
+        // each key is looked up in an index on the columns, or else keys of the table
+        // are read once and sorted rather than scanning the table for every record
+        let keys = if table.has_index(unique_cols) {
+            None
         } else {
-            return Err("no such table in database".into());
+            let mut keys = table
+                .rows()
+                .map(|rid| table.select_cols(rid, unique_cols.iter().copied()))
+                .collect::<DBResult<Vec<_>>>()?;
+            keys.sort_by(cmp);
+            Some(keys)
+        };
+        for (i, record) in records.iter().enumerate() {
+            let key = table.get_data_cols(record, unique_cols);
+            let exists = match &keys {
+                Some(keys) => keys.binary_search_by(|other| cmp(other, &key)).is_ok(),
+                None => table.check_data_exist(&key, unique_cols),
+            };
+            if exists {
+                return Err(format!("record {} doesn't satisfy unique requirment", i).into());
+            }
         }
     }
-    for &table_id in &table_ids {
-        db::ensure_table(table_id, |_| {});
-    }
-    let rows = match relation(
-        args.condition.as_ref().unwrap_or(&CondExpr::True),
-        &args.from,
-    )? {
-        Logic::Pos(x) => x,
-        Logic::Neg(x) => {
-            if table_ids.len() == 1 {
-                let full: HashSet<_> = db::ensure_table(table_ids[0], |table| {
-                    let mut ret = HashSet::new();
-                    for rid in table.rows() {
-                        ret.insert([rid, 0]);
-                    }
-                    ret
-                });
-                full.difference(&x).copied().collect()
-            } else if table_ids.len() == 2 {
-                let lrows: Vec<_> =
-                    db::ensure_table(table_ids[0], |ltable| ltable.rows().collect());
-                let rrows: Vec<_> =
-                    db::ensure_table(table_ids[1], |rtable| rtable.rows().collect());
-                let mut full = HashSet::new();
-                for lrow in lrows {
-                    for &rrow in &rrows {
-                        full.insert([lrow, rrow]);
-                    }
+    for (table_cols, (ftable_id, ftable_cols)) in &table.meta.foreign_key {
+        let mut keys = records
+            .iter()
+            .map(|record| table.get_data_cols(record, table_cols))
+            .enumerate()
+            .collect::<Vec<_>>();
+        // a stable sort keeps the first record with a key, which is the one reported
+        keys.sort_by(|(_, lhs), (_, rhs)| cmp(lhs, rhs));
+        keys.dedup_by(|(_, key), (_, prev)| key == prev);
+        db::ensure_table(*ftable_id, |ftable| -> DBResult<()> {
+            for (i, key) in keys {
+                if !ftable.check_data_exist(&key, ftable_cols) {
+                    return Err(
+                        format!("record {} doesn't satisfy foreign key requirment", i).into(),
+                    );
                 }
-                full.difference(&x).copied().collect()
-            } else {
-                unimplemented!();
             }
-        }
-    };
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
 
-    let mut aggregates = vec![];
-
-    // the print logic
-    if table_ids.len() == 1 {
-        let rows = rows.iter().map(|s| s[0]).collect::<Vec<_>>();
-        let mut cols = Vec::new();
-        match &args.selectors {
-            Part(columns) => {
-                db::get_table(table_ids[0], |table| -> DBResult<()> {
-                    use naive_sql_parser::SingleSelector::*;
-                    for col in columns {
-                        match col {
-                            Single(colref) => {
-                                let col_id = check_colref(colref, table)?;
-                                cols.push(col_id);
-                            }
-                            Aggregate(aggr, colref) => {
-                                let col = check_colref(colref, table)?;
-                                let id = table_ids[0];
-                                let rows = rows.iter().cloned();
-                                let aggr_str = get_aggr(aggr, colref, rows, id, col)?;
-                                aggregates.push(aggr_str);
-                            }
-                            CountAll => {
-                                let count = count_all(rows.iter().cloned())?;
-                                aggregates.push(format!("Count(*): {}", count));
-                            }
-                        }
-                    }
-                    Ok(())
-                })?;
+/// Positions of records with the same unique key as a record before them in `records`,
+/// which `check_record` cannot tell until the records before them are inserted, or with
+/// a key already in a unique index. Keys with NULL are never duplicates, as NULL equals nothing
+pub fn batch_duplicates(table: &Table, records: &[Vec<Option<ColumnVal>>]) -> BTreeSet<usize> {
+    let mut duplicates = table.unique_index_duplicates(records);
+    for unique_cols in &table.meta.unique {
+        if table.has_unique_index(unique_cols) {
+            continue;
+        }
+        let mut keys = records
+            .iter()
+            .map(|record| table.get_data_cols(record, unique_cols))
+            .enumerate()
+            .filter(|(_, key)| key.iter().all(Option::is_some))
+            .collect::<Vec<_>>();
+        // a stable sort keeps the first record with a key before the others
+        keys.sort_by(|(_, lhs), (_, rhs)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal));
+        for pair in keys.windows(2) {
+            if pair[0].1 == pair[1].1 {
+                duplicates.insert(pair[1].0);
             }
-            All => db::get_table(table_ids[0], |table| -> _ {
-                for i in 0..table.meta.columns.len() {
-                    cols.push(i as ColID);
-                }
-            }),
         }
+    }
+    duplicates
+}
 
-        db::get_table(table_ids[0], |table| {
-            table.print_val(&rows, &cols);
-        });
-        println!("{}", aggregates.join("\n"));
+fn check_violations(args: &CheckConstraint) -> DBResult<ExecResult> {
+    let violations = constraint_violations(args)?;
+    if violations.rows.is_empty() {
+        let message = "No row violates the constraint".to_owned();
+        Ok(ExecResult::Message(message))
     } else {
-        // joined
-        let mut lcols = Vec::new();
-        let mut rcols = Vec::new();
-        match &args.selectors {
-            Part(columns) => {
-                for column in columns {
-                    use naive_sql_parser::SingleSelector::*;
-                    match column {
-                        Single(colref) => match colref {
-                            Ident(ident) => {
-                                let in_ltable = db::get_table(table_ids[0], |ltable| -> bool {
-                                    if let Some(col_id) = ltable.meta.get_column_id(ident) {
-                                        lcols.push(col_id);
-                                        return true;
-                                    }
-                                    false
-                                });
-                                let in_rtable =
-                                    db::get_table(table_ids[1], |rtable| -> DBResult<_> {
-                                        if let Some(col_id) = rtable.meta.get_column_id(ident) {
-                                            if !in_ltable {
-                                                rcols.push(col_id);
-                                                return Ok(true);
-                                            } else {
-                                                return Err(format!(
-                                                    "column name {} exist in both tables",
-                                                    ident
-                                                )
-                                                .into());
-                                            }
-                                        }
-                                        Ok(false)
-                                    })?;
-                                if !in_ltable && !in_rtable {
-                                    return Err(format!(
-                                        "column name {} doesn't exist in both tables",
-                                        ident
-                                    )
-                                    .into());
-                                }
-                            }
-                            Attr { table_name, column } => {
-                                if table_name == &args.from[0] {
-                                    db::get_table(table_ids[0], |ltable| -> DBResult<()> {
-                                        if let Some(col_id) = ltable.meta.get_column_id(column) {
-                                            lcols.push(col_id);
-                                            Ok(())
-                                        } else {
-                                            return Err(format!(
-                                                "column {} doesn't exist in table {}",
-                                                column, table_name
-                                            )
-                                            .into());
-                                        }
-                                    })?;
-                                } else if table_name == &args.from[1] {
-                                    db::get_table(table_ids[1], |rtable| -> DBResult<()> {
-                                        if let Some(col_id) = rtable.meta.get_column_id(column) {
-                                            rcols.push(col_id);
-                                            Ok(())
-                                        } else {
-                                            return Err(format!(
-                                                "column {} doesn't exist in table {}",
-                                                column, table_name
-                                            )
-                                            .into());
-                                        }
-                                    })?;
-                                } else {
-                                    return Err(format!(
-                                        "select column from unrelated table {}",
-                                        table_name
-                                    )
-                                    .into());
-                                }
-                            }
-                        },
-                        Aggregate(aggr, colref) => {
-                            let (id, col) = db::get_table(table_ids[0], |ltable| {
-                                db::get_table(table_ids[1], |rtable| {
-                                    check_colref_joined(colref, ltable, rtable)
-                                })
-                            })?;
-                            let left = id == table_ids[0];
-                            let rows = rows.iter().map(|&t| if left { t[0] } else { t[1] });
-                            let aggr_str = get_aggr(aggr, colref, rows, id, col)?;
-                            aggregates.push(aggr_str);
-                        }
-                        CountAll => {
-                            aggregates.push(format!("Count(*): {}", rows.len()));
-                        }
-                    }
-                }
+        Ok(violations.into())
+    }
+}
+
+/// Rows of the table which would violate the constraint if it were added, nothing is changed.
+/// Rows sharing a key are all reported for a unique constraint, in the order of the key
+pub fn constraint_violations(args: &CheckConstraint) -> DBResult<ResultSet> {
+    let id = db::load_table(&args.table_name)?;
+    db::ensure_table(id, |table| -> DBResult<_> {
+        let get_col = |name: &String| {
+            table
+                .meta
+                .get_column_id(name)
+                .ok_or(format!("no column {} in table {}", name, args.table_name))
+        };
+        let rows = match &args.constraint {
+            ProposedConstraint::NotNull(colname) => table.null_rows(get_col(colname)?, true)?,
+            ProposedConstraint::Unique(colnames) => {
+                let cols = colnames
+                    .iter()
+                    .map(get_col)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut keys = table
+                    .rows()
+                    .map(|rid| Ok((rid, table.select_cols(rid, cols.iter().copied())?)))
+                    .collect::<DBResult<Vec<_>>>()?;
+                // a stable sort keeps rows with the same key in the order they are stored
+                keys.sort_by(|(_, lhs), (_, rhs)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal));
+                keys.iter()
+                    .enumerate()
+                    .filter(|&(i, (_, key))| {
+                        (i > 0 && keys[i - 1].1 == *key)
+                            || keys.get(i + 1).is_some_and(|next| next.1 == *key)
+                    })
+                    .map(|(_, &(rid, _))| rid)
+                    .collect()
             }
-            All => {
-                db::get_table(table_ids[0], |table| {
-                    for i in 0..table.meta.columns.len() {
-                        lcols.push(i as ColID);
-                    }
-                });
-                db::get_table(table_ids[1], |table| {
-                    for i in 0..table.meta.columns.len() {
-                        rcols.push(i as ColID);
+            ProposedConstraint::Check { colname, exprs } => {
+                let col = get_col(colname)?;
+                let allowed = db::check_values(exprs, table.meta.columns[col as usize].coltype)?;
+                let mut rows = vec![];
+                for rid in table.rows() {
+                    match &table.select_cols(rid, std::iter::once(col))?[0] {
+                        Some(val) if !allowed.contains(val) => rows.push(rid),
+                        _ => {}
                     }
-                })
+                }
+                rows
             }
-        }
-        print_join_table(rows, table_ids[0], &lcols, table_ids[1], &rcols);
-    } // joined
-    Ok(())
+        };
+        Ok(ResultSet {
+            header: table
+                .meta
+                .columns
+                .iter()
+                .map(|col| col.name.clone())
+                .collect(),
+            rows: rows
+                .into_iter()
+                .map(|rid| table.select_row(rid))
+                .collect::<DBResult<_>>()?,
+        })
+    })
 }
 
-fn insert(args: &Insert) -> DBResult<()> {
+/// Number of rows inserted
+fn insert(args: &Insert) -> DBResult<usize> {
     let id = db::get_table_id(&args.table_name).ok_or("table name not found")?;
     let records = &args.values;
-    for (i, record) in records.iter().enumerate() {
-        let record_data = db::ensure_table(id, |table| -> DBResult<_> {
-            table.check_type_insert(record)?;
-            let record_data = table.record2data(record);
-            for unique_cols in &table.meta.unique {
-                let slice_data = table.get_data_cols(&record_data, unique_cols);
-                if table.check_data_exist(&slice_data, unique_cols) {
-                    return Err(format!("record {} doesn't satisfy unique requirment", i).into());
-                }
+    let mut batch: Vec<Vec<Option<ColumnVal>>> = Vec::with_capacity(records.len());
+    for record in records {
+        check_terminating()?;
+        let record_data = db::ensure_table(id, |table| record_values(table, record))?;
+        batch.push(record_data);
+    }
+    insert_batch(id, &batch)?;
+    Ok(batch.len())
+}
+
+fn is_literal(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) | Expr::Null
+    )
+}
+
+/// Values of a record of INSERT, literals are checked against their columns,
+/// other expressions are evaluated once and converted to the types of their columns,
+/// to be checked along with the rest of the row by `insert_batch`
+fn record_values(table: &Table, record: &[Expr]) -> DBResult<Vec<Option<ColumnVal>>> {
+    if record.iter().all(is_literal) {
+        table.check_type_insert(record)?;
+        return table.record2data(record);
+    }
+    if record.len() != table.meta.columns.len() {
+        return Err("value size not equal to column size".into());
+    }
+    let mut row_data = Vec::with_capacity(record.len());
+    for (i, (expr, col)) in record.iter().zip(&table.meta.columns).enumerate() {
+        let val = if is_literal(expr) {
+            table.check_column_type(expr, i as ColID)?;
+            Table::expr2colval(expr, col.coltype)?
+        } else {
+            match eval_expr(expr, Row::Constant)? {
+                Some(val) => Some(cast(val, col.coltype)?),
+                None => None,
             }
-            for (table_cols, (ftable_id, ftable_cols)) in &table.meta.foreign_key {
-                let slice_data = table.get_data_cols(&record_data, table_cols);
-                db::ensure_table(*ftable_id, |ftable| -> DBResult<()> {
-                    if !ftable.check_data_exist(&slice_data, ftable_cols) {
-                        return Err(
-                            format!("record {} doesn't satisfy foreign key requirment", i).into(),
-                        );
-                    }
-                    Ok(())
-                })?;
+        };
+        row_data.push(val);
+    }
+    Ok(row_data)
+}
+
+/// Insert rows of values, which is what INSERT does after evaluating its records,
+/// for programs using the database without SQL. Rows are checked like INSERT checks them
+/// and none is inserted if one is rejected, then all of them are written to pages
+/// and added to indices at once
+pub fn insert_batch(id: TableID, rows: &[Vec<Option<ColumnVal>>]) -> DBResult<Vec<RowID>> {
+    let deferred = SETTINGS.borrow().constraints == ConstraintMode::Deferred;
+    // referenced tables cannot be loaded while the table is borrowed
+    let ftables = db::ensure_table(id, |table| {
+        let foreign_key = table.meta.foreign_key.values();
+        foreign_key.map(|(ftable, _)| *ftable).collect::<Vec<_>>()
+    });
+    for ftable in ftables {
+        db::ensure_table(ftable, |_| {});
+    }
+    for (i, row) in rows.iter().enumerate() {
+        check_terminating()?;
+        db::get_table(id, |table| {
+            if deferred {
+                check_row(table, row, i)
+            } else {
+                check_record(table, row, i)
             }
-            Ok(record_data)
         })?;
-        let row = db::modify_table(id, |table| -> DBResult<RowID> {
-            table.insert(&record_data)
-        })?;
-        db::get_table(id, |table| {
-            table.insert_index_at(row, &record_data);
-        })
     }
-    Ok(())
+    if deferred {
+        db::get_table(id, |table| check_deferred(table, rows))?;
+    } else if let Some(i) = db::get_table(id, |table| batch_duplicates(table, rows)).first() {
+        return Err(format!("record {} doesn't satisfy unique requirment", i).into());
+    }
+    let rids = db::modify_table(id, |table| table.bulk_insert(rows))?;
+    db::get_table(id, |table| table.extend_indices(&rids, rows))?;
+    Ok(rids)
 }
 
-fn update(args: &Update) -> DBResult<()> {
+/// Number of rows updated
+fn update(args: &Update) -> DBResult<usize> {
     let table_name = &args.table_name;
     let table_id = db::get_table_id(table_name).ok_or("table name not found")?;
     db::load_table(table_name)?;
@@ -624,43 +635,81 @@ fn update(args: &Update) -> DBResult<()> {
         }
     };
 
-    let rows = match relation(&args.condition, &[table_name.clone(); 1])? {
-        Logic::Pos(x) => x,
-        Logic::Neg(x) => {
-            let full: HashSet<_> = db::ensure_table(table_id, |table| {
-                let mut ret = HashSet::new();
-                for rid in table.rows() {
-                    ret.insert([rid, 0]);
-                }
-                ret
-            });
-            full.difference(&x).copied().collect()
-        }
-    };
+    let tables = [TableRef {
+        name: table_name.clone(),
+        id: table_id,
+        index_hint: None,
+    }];
+    let rows = matching_rows(&args.condition, &tables)?;
     let rows = rows.iter().map(|s| s[0]).collect::<Vec<_>>();
 
     let mut foreign_update: HashMap<TableID, Vec<_>> = HashMap::new();
 
-    let (col_id, new_col_val) = db::ensure_table(table_id, |table| -> DBResult<_> {
+    let literal = is_literal(&args.value);
+    let (col_id, coltype, literal_val) = db::ensure_table(table_id, |table| -> DBResult<_> {
         let col_id = table
             .meta
             .get_column_id(col_name)
             .ok_or("no such column in table")?;
+        let coltype = table.meta.columns[col_id as usize].coltype;
 
-        let val = Table::expr2colval(&args.value, table.meta.columns[col_id as usize].coltype);
-        table.check_column_type(&args.value, col_id)?;
-        Ok((col_id, val))
+        // a literal is checked and converted once, other expressions are evaluated per row
+        let val = if literal {
+            table.check_column_type(&args.value, col_id)?;
+            Some(Table::expr2colval(&args.value, coltype)?)
+        } else {
+            None
+        };
+        Ok((col_id, coltype, val))
     })?;
+    // referencing tables cannot be loaded while the table is borrowed
+    let ref_tables = db::ensure_table(table_id, |table| {
+        table
+            .meta
+            .as_foreign_key
+            .values()
+            .flat_map(|refs| refs.keys().map(|(ref_table_id, _)| *ref_table_id))
+            .collect::<Vec<_>>()
+    });
+    for ref_table_id in ref_tables {
+        db::ensure_table(ref_table_id, |_| {});
+    }
 
     for &row in &rows {
+        check_terminating()?;
+        let new_col_val = match &literal_val {
+            Some(val) => val.clone(),
+            None => match eval_expr(&args.value, Row::Stored(&tables, &[row]))? {
+                Some(val) => Some(cast(val, coltype)?),
+                None => None,
+            },
+        };
         let (row_data, new_row_data) = db::get_table(table_id, |table| -> DBResult<_> {
             let row_data = table.select_row(row)?;
             let mut new_row_data = row_data.clone();
             new_row_data[col_id as usize] = new_col_val.clone();
+            if new_col_val.is_none() {
+                let col = &table.meta.columns[col_id as usize];
+                if col.constraints.is_not_null() || table.meta.primary.contains(&col_id) {
+                    return Err(
+                        format!("row {} cannot set column {} to null", row, col.name).into(),
+                    );
+                }
+            }
+            if literal_val.is_none() {
+                table.check_row_data(&new_row_data)?;
+            } else if let Some(val) = &new_col_val {
+                table.meta.check_value(col_id, val)?;
+            }
 
             for unique_cols in &table.meta.unique {
-                if unique_cols.contains(&col_id) {
+                // a unique index is checked as the row is moved in indices
+                if unique_cols.contains(&col_id) && !table.has_unique_index(unique_cols) {
                     let slice_data = table.get_data_cols(&new_row_data, unique_cols);
+                    // the row itself is the only one with its unchanged values
+                    if slice_data == table.get_data_cols(&row_data, unique_cols) {
+                        continue;
+                    }
                     if table.check_data_exist(&slice_data, unique_cols) {
                         return Err(format!(
                             "row {} doesn't satisfy unique requirment after update",
@@ -685,26 +734,32 @@ fn update(args: &Update) -> DBResult<()> {
                     })?;
                 }
             }
-            // initial foreign_update
-            for table_ref_cols in table.meta.as_foreign_key.values() {
-                for (ref_table_id, ref_cols) in table_ref_cols.iter() {
-                    foreign_update.insert(*ref_table_id, Vec::new());
-                }
-            }
             for (ftable_cols, table_ref_cols) in &table.meta.as_foreign_key {
                 if ftable_cols.contains(&col_id) {
-                    for (ref_table_id, ref_cols) in table_ref_cols.iter() {
-                        db::ensure_table(*ref_table_id, |ref_table| -> DBResult<()> {
-                            let rows = ref_table.get_equal_rows(
-                                &table.get_data_cols(&row_data, ftable_cols),
-                                ref_cols
-                            );
-                            // maybe several cols to update?
-                            let index = ftable_cols.iter().position(|&col| col == col_id).unwrap();
-                            for row in rows {
-                                if let Some(affected) = foreign_update.get_mut(ref_table_id) {
-                                    affected.push((row, ref_cols[index]))
+                    // the referencing column paired with the updated one takes its new value
+                    let index = ftable_cols.iter().position(|&col| col == col_id).unwrap();
+                    let new_ref_val = &new_row_data[ftable_cols[index] as usize];
+                    if *new_ref_val == row_data[col_id as usize] {
+                        continue;
+                    }
+                    for ((ref_table_id, ref_cols), policy) in table_ref_cols {
+                        db::get_table(*ref_table_id, |ref_table| -> DBResult<()> {
+                            let ref_data = table.get_data_cols(&row_data, ftable_cols);
+                            if policy.on_update == RefAction::Restrict {
+                                if ref_table.any_match(ref_cols, CompareOp::EQ, &ref_data)? {
+                                    return Err(format!(
+                                        "cannot update row {}, it is referenced by table {}",
+                                        row,
+                                        ref_table.meta.name()
+                                    )
+                                    .into());
                                 }
+                                return Ok(());
+                            }
+                            let rows = ref_table.get_equal_rows(&ref_data, ref_cols);
+                            let affected = foreign_update.entry(*ref_table_id).or_default();
+                            for row in rows {
+                                affected.push((row, ref_cols[index], new_ref_val.clone()));
                             }
                             Ok(())
                         })?;
@@ -714,138 +769,320 @@ fn update(args: &Update) -> DBResult<()> {
             Ok((row_data, new_row_data))
         })?;
 
-        db::get_table(table_id, |table| -> DBResult<_> {
-            table.remove_index_at(row, &row_data);
-            Ok(())
-        })?;
-        db::modify_table(table_id, |table| -> DBResult<_> {
-            table.update(row, col_id, &new_col_val)?;
-            Ok(())
-        })?;
         db::get_table(table_id, |table| {
-            table.insert_index_at(row, &new_row_data);
-        });
+            table.update_index_at(row, &row_data, &new_row_data)
+        })
+        .map_err(|e| {
+            format!(
+                "row {} doesn't satisfy unique requirment after update: {}",
+                row, e
+            )
+        })?;
+        update_row(table_id, row, col_id, new_col_val, &row_data, &new_row_data)?;
     }
 
-    // maybe we need update record here
     for (refid, affected) in foreign_update {
-        for (row, col) in affected {
-            db::modify_table(refid, |table| -> DBResult<_> {
-                table.update(row, col, &new_col_val)?;
-                Ok(())
+        for (row, col, new_col_val) in affected {
+            let row_data = db::get_table(refid, |table| table.select_row(row))?;
+            let mut new_row_data = row_data.clone();
+            new_row_data[col as usize] = new_col_val.clone();
+            db::get_table(refid, |table| {
+                table.update_index_at(row, &row_data, &new_row_data)
             })?;
+            update_row(refid, row, col, new_col_val, &row_data, &new_row_data)?;
         }
     }
 
-    print_affected(rows.len());
+    Ok(rows.len())
+}
+
+/// Write a column of a row already moved in indices by `update_index_at`, which is moved back
+/// if the page cannot be written
+fn update_row(
+    table_id: TableID,
+    row: RowID,
+    col: ColID,
+    val: Option<ColumnVal>,
+    old: &[Option<ColumnVal>],
+    new: &[Option<ColumnVal>],
+) -> DBResult<()> {
+    if let Err(e) = db::modify_table(table_id, |table| table.update(row, col, &val)) {
+        db::get_table(table_id, |table| table.update_index_at(row, new, old))?;
+        return Err(e);
+    }
     Ok(())
 }
 
-fn delete(args: &Delete) -> DBResult<()> {
+/// Rows deleted from each table, and referencing columns set to NULL in rows kept,
+/// by deleting some rows and following foreign keys through every table referencing
+/// them in turn
+#[derive(Default)]
+struct RefEffects {
+    deletes: HashMap<TableID, BTreeSet<RowID>>,
+    nulls: HashMap<TableID, BTreeMap<RowID, BTreeSet<ColID>>>,
+}
+
+/// Effects of deleting `rows` of table `table_id`, nothing is deleted if any row
+/// is still referenced under RESTRICT
+fn ref_effects(table_id: TableID, rows: &[RowID]) -> DBResult<RefEffects> {
+    let mut effects = RefEffects::default();
+    effects
+        .deletes
+        .entry(table_id)
+        .or_default()
+        .extend(rows.iter().copied());
+    // rows losing their values, either deleted or with some columns set to NULL
+    let mut pending: Vec<(TableID, RowID, Option<BTreeSet<ColID>>)> =
+        rows.iter().map(|&rid| (table_id, rid, None)).collect();
+    while let Some((table_id, rid, nulled)) = pending.pop() {
+        check_terminating()?;
+        let as_foreign_key = db::get_table(table_id, |table| table.meta.as_foreign_key.clone());
+        // referencing tables cannot be loaded while a table is borrowed
+        for ((ref_table_id, _), _) in as_foreign_key.values().flatten() {
+            db::ensure_table(*ref_table_id, |_| {});
+        }
+        let row_data = db::get_table(table_id, |table| table.select_row(rid))?;
+        for (key_cols, refs) in &as_foreign_key {
+            // positions of the key columns losing their values
+            let lost: Vec<_> = match &nulled {
+                None => (0..key_cols.len()).collect(),
+                Some(nulled) => (0..key_cols.len())
+                    .filter(|&i| nulled.contains(&key_cols[i]))
+                    .collect(),
+            };
+            if lost.is_empty() {
+                continue;
+            }
+            let key: Vec<_> = key_cols
+                .iter()
+                .map(|&col| row_data[col as usize].clone())
+                .collect();
+            for ((ref_table_id, ref_cols), policy) in refs {
+                let (action, verb) = match nulled {
+                    None => (policy.on_delete, "delete"),
+                    Some(_) => (policy.on_update, "update"),
+                };
+                let deleted = effects.deletes.entry(*ref_table_id).or_default();
+                let rids: Vec<_> = db::get_table(*ref_table_id, |ref_table| {
+                    ref_table.get_equal_rows(&key, ref_cols)
+                })
+                .into_iter()
+                .filter(|rid| !deleted.contains(rid))
+                .collect();
+                if rids.is_empty() {
+                    continue;
+                }
+                let cols: Vec<_> = match (action, nulled.is_some()) {
+                    (RefAction::Restrict, _) => {
+                        let name = db::get_table(*ref_table_id, |t| t.meta.name().to_owned());
+                        return Err(format!(
+                            "cannot {} row {}, it is referenced by table {}",
+                            verb, rid, name
+                        )
+                        .into());
+                    }
+                    (RefAction::Cascade, false) => {
+                        for rid in rids {
+                            if deleted.insert(rid) {
+                                pending.push((*ref_table_id, rid, None));
+                            }
+                        }
+                        continue;
+                    }
+                    // referencing columns follow the referenced ones to NULL
+                    (RefAction::Cascade, true) => lost.iter().map(|&i| ref_cols[i]).collect(),
+                    (RefAction::SetNull, _) => ref_cols.clone(),
+                };
+                db::get_table(*ref_table_id, |ref_table| -> DBResult<()> {
+                    for &col in &cols {
+                        let column = &ref_table.meta.columns[col as usize];
+                        if column.constraints.is_not_null() || ref_table.meta.primary.contains(&col)
+                        {
+                            return Err(format!(
+                                "cannot {} row {}, column {} of table {} cannot be set to null",
+                                verb,
+                                rid,
+                                column.name,
+                                ref_table.meta.name()
+                            )
+                            .into());
+                        }
+                    }
+                    Ok(())
+                })?;
+                let nulls = effects.nulls.entry(*ref_table_id).or_default();
+                for ref_rid in rids {
+                    let row_nulls = nulls.entry(ref_rid).or_default();
+                    let newly: BTreeSet<_> = cols
+                        .iter()
+                        .copied()
+                        .filter(|&col| row_nulls.insert(col))
+                        .collect();
+                    if !newly.is_empty() {
+                        pending.push((*ref_table_id, ref_rid, Some(newly)));
+                    }
+                }
+            }
+        }
+    }
+    Ok(effects)
+}
+
+/// Number of rows deleted
+fn delete(args: &Delete) -> DBResult<usize> {
     let table_name = &args.table_name;
     let table_id = db::get_table_id(table_name).ok_or("table name not found")?;
     db::load_table(&table_name)?;
 
-    let rows = match relation(&args.condition, &[table_name.clone(); 1])? {
-        Logic::Pos(x) => x,
-        Logic::Neg(x) => {
-            let mut ret = HashSet::new();
-            let full: HashSet<_> = db::ensure_table(table_id, |table| {
-                for rid in table.rows() {
-                    ret.insert([rid, 0]);
-                }
-                ret
-            });
-            full.difference(&x).copied().collect()
-        }
+    let table_ref = TableRef {
+        name: table_name.clone(),
+        id: table_id,
+        index_hint: None,
     };
+    let rows = matching_rows(&args.condition, &[table_ref])?;
     let rows = rows.iter().map(|s| s[0]).collect::<Vec<_>>();
 
-    db::get_table(table_id, |table| -> DBResult<_> {
-        for row in &rows {
-            let row_data = table.select_row(*row)?;
-            table.remove_index_at(*row, &row_data)
-        }
-        Ok(())
-    })?;
-
-    let mut ref_tables = Vec::new();
-    db::get_table(table_id, |table| {
-        for (k, v) in table
-            .meta
-            .as_foreign_key
-            .iter() {
-                for (ftable, _) in v.iter() {
-                    ref_tables.push(*ftable);
-                }   
+    let RefEffects { deletes, nulls } = ref_effects(table_id, &rows)?;
+    for (ref_table_id, rids) in nulls {
+        for (rid, cols) in rids {
+            // a row both nulled and deleted is just deleted
+            if deletes
+                .get(&ref_table_id)
+                .is_some_and(|deleted| deleted.contains(&rid))
+            {
+                continue;
             }
-    }); 
-    for ftable in ref_tables {
-        db::ensure_table(ftable, |_| {});
-    }
-    db::modify_table(table_id, |table| -> DBResult<()> {
-        for row in &rows {
-            let row_data = table.select_row(*row)?;
-            for (ftable_cols, table_ref_cols) in &table.meta.as_foreign_key {
-                let slice_data = table.get_data_cols(&row_data, ftable_cols);
-                for (ref_table_id, ref_cols) in table_ref_cols.iter() {
-                    db::modify_table(*ref_table_id, |ref_table| -> DBResult<()> {
-                        let rids = ref_table.get_equal_rows(
-                            &slice_data,
-                            ref_cols
-                        );
-                        for rid in rids {
-                            ref_table.delete(rid)?;
-                        }
-                        Ok(())
-                    })?;
+            let mut row_data = db::get_table(ref_table_id, |ref_table| -> DBResult<_> {
+                let row_data = ref_table.select_row(rid)?;
+                ref_table.remove_index_at(rid, &row_data);
+                Ok(row_data)
+            })?;
+            db::modify_table(ref_table_id, |ref_table| -> DBResult<()> {
+                for &col in &cols {
+                    ref_table.update(rid, col, &None)?;
+                    row_data[col as usize] = None;
                 }
-            }
-            table.delete(*row)?;
+                Ok(())
+            })?;
+            db::get_table(ref_table_id, |ref_table| {
+                ref_table.insert_index_at(rid, &row_data)
+            })?;
         }
-        Ok(())
-    })?;
-    print_affected(rows.len());
-    Ok(())
+    }
+    for (ref_table_id, rids) in deletes {
+        for rid in rids {
+            db::get_table(ref_table_id, |ref_table| -> DBResult<_> {
+                let row_data = ref_table.select_row(rid)?;
+                ref_table.remove_index_at(rid, &row_data);
+                Ok(())
+            })?;
+            db::modify_table(ref_table_id, |ref_table| ref_table.delete(rid))?;
+        }
+    }
+    Ok(rows.len())
 }
 
-fn show(args: &Show) -> DBResult<()> {
+fn show(args: &Show) -> DBResult<ExecResult> {
+    let names = |header: &str, names: Vec<String>| {
+        let rows = names.into_iter().map(|name| vec![name]).collect();
+        text_rows(&[header], rows)
+    };
     match args {
-        Show::Databases => db::show_databases()?,
-        Show::Tables => db::show_tables()?,
-        _ => unreachable!(),
+        Show::Databases(pattern) => Ok(names("Database", db::database_names(pattern.as_deref())?)),
+        Show::Tables(pattern) => Ok(names("Table", db::table_names(pattern.as_deref())?)),
+        Show::TableStatus(pattern) => {
+            let rows = table_status_rows(pattern.as_deref())?;
+            Ok(text_rows(&TABLE_STATUS_HEADER, rows))
+        }
+        Show::Config => Ok(system::config().into()),
+        Show::Indices => Err("SHOW INDEXES is not supported".into()),
     }
-    Ok(())
 }
 
-fn describe(args: &Desc) -> DBResult<()> {
+/// Rows of text cells, as `SHOW` and `DESC` give them
+fn text_rows(header: &[&str], rows: Vec<Vec<String>>) -> ExecResult {
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|s| Some(ColumnVal::Varchar(s)))
+                .collect()
+        })
+        .collect();
+    let header = header.iter().map(|&name| name.to_owned()).collect();
+    ExecResult::Rows(rows, header, 0)
+}
+
+const TABLE_STATUS_HEADER: [&str; 5] = ["Name", "Rows", "Pages", "File Pages", "Indices"];
+
+/// Rows of `SHOW TABLE STATUS`, one for each table
+fn table_status_rows(pattern: Option<&str>) -> DBResult<Vec<Vec<String>>> {
+    let statuses = db::table_status(pattern)?;
+    let rows = statuses
+        .into_iter()
+        .map(|status| {
+            vec![
+                status.name,
+                status.rows.to_string(),
+                status.pages.to_string(),
+                status.file_pages.to_string(),
+                status.indices.to_string(),
+            ]
+        })
+        .collect();
+    Ok(rows)
+}
+
+const DESCRIBE_HEADER: [&str; 9] = [
+    "Name",
+    "Type",
+    "Not Null",
+    "Primary",
+    "Unique",
+    "Foreign",
+    "AsForeign",
+    "Default",
+    "Extra",
+];
+
+/// Rows of `DESC table`, one for each column
+fn column_descriptions(args: &Desc) -> DBResult<Vec<Vec<String>>> {
     let id = db::get_table_id(&args.0).ok_or("table name not found")?;
-    let header = [
-        "Name",
-        "Type",
-        "Not Null",
-        "Primary",
-        "Unique",
-        "Foreign",
-        "AsForeign",
-    ];
-    db::ensure_table(id, |table| {
+    let rows = db::ensure_table(id, |table| {
         let columns = &table.meta.columns;
-        let coltypes = columns
-            .iter()
-            .map(|col| get_coltype(col.coltype, col.colsize))
-            .collect::<Vec<_>>();
-        let mut body = Vec::with_capacity(columns.len() * header.len());
+        let mut rows = Vec::with_capacity(columns.len());
         for (i, col) in columns.iter().enumerate() {
-            body.push(col.name.as_str());
-            body.push(&coltypes[i]);
-            body.push(check_constraint(col.constraints.is_not_null()));
-            body.push(check_constraint(col.constraints.is_primary_key()));
-            body.push(check_constraint(col.constraints.is_unique()));
-            body.push(check_constraint(col.constraints.is_foreign_key()));
-            body.push(check_constraint(col.constraints.as_foreign_key()));
+            // values cannot be given defaults yet, the check constraint is the only
+            // extra attribute
+            let extra = match table.meta.check.get(&(i as ColID)) {
+                Some(allowed) => {
+                    let allowed = allowed
+                        .iter()
+                        .map(|val| val.to_string())
+                        .collect::<Vec<_>>();
+                    format!("CHECK IN ({})", allowed.join(", "))
+                }
+                None => String::new(),
+            };
+            let nullable = !col.constraints.is_not_null() && !col.constraints.is_primary_key();
+            rows.push(vec![
+                col.name.clone(),
+                get_coltype(col.coltype, col.colsize),
+                check_constraint(col.constraints.is_not_null()).to_owned(),
+                check_constraint(col.constraints.is_primary_key()).to_owned(),
+                check_constraint(col.constraints.is_unique()).to_owned(),
+                check_constraint(col.constraints.is_foreign_key()).to_owned(),
+                check_constraint(col.constraints.as_foreign_key()).to_owned(),
+                if nullable { "NULL" } else { "" }.to_owned(),
+                extra,
+            ]);
         }
-        print_vec(header.iter().copied(), body.chunks_exact(header.len()));
+        rows
     });
-    Ok(())
+    Ok(rows)
+}
+
+fn describe(args: &Desc) -> DBResult<ExecResult> {
+    let rows = column_descriptions(args)?;
+    Ok(text_rows(&DESCRIBE_HEADER, rows))
 }
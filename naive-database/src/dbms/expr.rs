@@ -0,0 +1,437 @@
+use std::convert::TryInto;
+
+use chrono::Datelike;
+use naive_sql_parser::{BinaryOp, CalcExpr, ColumnRef, CompareOp, CondExpr, Expr, LogicOp};
+
+use crate::{
+    config::{IntDivision, SETTINGS},
+    dbms::{
+        database::get_table,
+        relation::{comp_colval, table_by_name, table_of_column, TableRef},
+    },
+    defines::RowID,
+    error::DBResult,
+    record::{ColumnType, ColumnVal},
+    utils::{parse_date, parse_float},
+};
+
+/// A row expressions are evaluated on
+#[derive(Debug, Clone, Copy)]
+pub enum Row<'a> {
+    /// A (possibly joined) row of stored tables, with a RowID for each of them
+    ///
+    /// The tables should be loaded
+    Stored(&'a [TableRef], &'a [RowID]),
+    /// A row of a derived table, given its name, column names and values
+    Derived(&'a str, &'a [String], &'a [Option<ColumnVal>]),
+    /// No row, for expressions evaluated once such as the values of INSERT,
+    /// which cannot reference columns
+    Constant,
+}
+
+/// Read the referenced column of a row
+pub fn eval_colref(colref: &ColumnRef, row: Row) -> DBResult<Option<ColumnVal>> {
+    match row {
+        Row::Stored(tables, rids) => {
+            let (table_ref, column) = match colref {
+                ColumnRef::Ident(ident) => (table_of_column(ident, tables)?, ident),
+                ColumnRef::Attr { table_name, column } => {
+                    (table_by_name(table_name, tables)?, column)
+                }
+            };
+            let pos = tables
+                .iter()
+                .position(|table| table.name == table_ref.name)
+                .unwrap();
+            get_table(table_ref.id, |table| -> DBResult<_> {
+                let col = table.meta.get_column_id(column).ok_or(format!(
+                    "column {} doesn't exist in table {}",
+                    column, table_ref.name
+                ))?;
+                table.select(rids[pos], col)
+            })
+        }
+        Row::Derived(name, header, vals) => {
+            let column = match colref {
+                ColumnRef::Ident(column) => column,
+                ColumnRef::Attr { table_name, column } => {
+                    if table_name != name {
+                        return Err(
+                            format!("select column from unrelated table {}", table_name).into()
+                        );
+                    }
+                    column
+                }
+            };
+            let pos = header
+                .iter()
+                .position(|col| col == column)
+                .ok_or(format!("column {} doesn't exist in table {}", column, name))?;
+            Ok(vals[pos].clone())
+        }
+        Row::Constant => Err(format!("column {} cannot be referenced here", colref).into()),
+    }
+}
+
+/// Convert a value to be stored in a column of type `coltype`
+pub fn cast(val: ColumnVal, coltype: ColumnType) -> DBResult<ColumnVal> {
+    use ColumnVal::*;
+    let ret = match (val, coltype) {
+        (Int(i), ColumnType::Int) => Int(i),
+        (Int(i), ColumnType::Float) => Float(i as _),
+        (Float(f), ColumnType::Float) => Float(f),
+        (Char(s), ColumnType::Char) | (Varchar(s), ColumnType::Char) => Char(s),
+        (Char(s), ColumnType::Varchar) | (Varchar(s), ColumnType::Varchar) => Varchar(s),
+        (Char(s), ColumnType::Date) | (Varchar(s), ColumnType::Date) => {
+            Date(parse_date(&s).ok_or(format!("'{}' is not a valid date", s))?)
+        }
+        (Date(d), ColumnType::Date) => Date(d),
+        (val, coltype) => {
+            return Err(format!("cannot convert {} to type {:?}", val, coltype).into())
+        }
+    };
+    Ok(ret)
+}
+
+/// Convert a value by `CAST`, which unlike `cast` also converts between numbers and strings
+/// and rounds floats to integers
+pub fn convert(val: ColumnVal, coltype: ColumnType) -> DBResult<ColumnVal> {
+    use ColumnVal::*;
+    let ret = match (val, coltype) {
+        (Float(f), ColumnType::Int) => {
+            let rounded = f.round();
+            if !(-2147483648.0..2147483648.0).contains(&rounded) {
+                return Err(format!("{} is out of the range of INT", f).into());
+            }
+            Int(rounded as _)
+        }
+        (Int(i), ColumnType::Char) => Char(i.to_string()),
+        (Int(i), ColumnType::Varchar) => Varchar(i.to_string()),
+        (Float(f), ColumnType::Char) => Char(f.to_string()),
+        (Float(f), ColumnType::Varchar) => Varchar(f.to_string()),
+        (Date(d), ColumnType::Char) => Char(d.to_string()),
+        (Date(d), ColumnType::Varchar) => Varchar(d.to_string()),
+        (Char(s), ColumnType::Int) | (Varchar(s), ColumnType::Int) => {
+            let parsed = s.trim().parse();
+            Int(parsed.map_err(|_| format!("'{}' is not a valid INT", s))?)
+        }
+        (Char(s), ColumnType::Float) | (Varchar(s), ColumnType::Float) => {
+            let parsed = parse_float(&s);
+            Float(parsed.map_err(|_| format!("'{}' is not a valid FLOAT", s))?)
+        }
+        (val, coltype) => cast(val, coltype)?,
+    };
+    Ok(ret)
+}
+
+/// Evaluate an expression on a row, NULL if any operand is NULL
+pub fn eval_expr(expr: &Expr, row: Row) -> DBResult<Option<ColumnVal>> {
+    let ret = match expr {
+        Expr::Binary(lhs, op, rhs) => {
+            let lhs = eval_expr(lhs, row)?;
+            let rhs = eval_expr(rhs, row)?;
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => Some(calc_binary(lhs, op, rhs)?),
+                _ => None,
+            }
+        }
+        Expr::ColumnRef(colref) => eval_colref(colref, row)?,
+        Expr::IntLit(i) => Some(ColumnVal::Int(*i)),
+        Expr::FloatLit(f) => Some(ColumnVal::Float(*f)),
+        Expr::StringLit(s) => Some(ColumnVal::Varchar(s.clone())),
+        Expr::Null => None,
+        Expr::Case { whens, else_ } => {
+            for (cond, expr) in whens {
+                if eval_cond(cond, row)? == Some(true) {
+                    return eval_expr(expr, row);
+                }
+            }
+            match else_ {
+                Some(expr) => eval_expr(expr, row)?,
+                None => None,
+            }
+        }
+        Expr::Cast(expr, coltype) => match eval_expr(expr, row)? {
+            Some(val) => Some(convert(val, (*coltype).into())?),
+            None => None,
+        },
+        Expr::Func(name, args) if name == "COALESCE" || name == "IFNULL" => {
+            coalesce(name, args, row)?
+        }
+        Expr::Func(name, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval_expr(arg, row))
+                .collect::<DBResult<Vec<_>>>()?;
+            call_func(name, args)?
+        }
+    };
+    Ok(ret)
+}
+
+/// The first argument that is not NULL, later arguments are not evaluated.
+/// `IFNULL(a, b)` is `COALESCE` of two arguments
+fn coalesce(name: &str, args: &[Expr], row: Row) -> DBResult<Option<ColumnVal>> {
+    let arity = match name {
+        "IFNULL" => 2..=2,
+        _ => 1..=usize::MAX,
+    };
+    if !arity.contains(&args.len()) {
+        return Err(format!("wrong number of arguments to {}", name).into());
+    }
+    // the result has the type of any argument, so literals must not be of different kinds
+    let is_numeric = |arg: &Expr| match arg {
+        Expr::IntLit(_) | Expr::FloatLit(_) => Some(true),
+        Expr::StringLit(_) => Some(false),
+        _ => None,
+    };
+    let mut kinds = args.iter().filter_map(is_numeric);
+    if let Some(kind) = kinds.next() {
+        if kinds.any(|other| other != kind) {
+            return Err(format!("arguments of {} are of incompatible types", name).into());
+        }
+    }
+    for arg in args {
+        if let Some(val) = eval_expr(arg, row)? {
+            return Ok(Some(val));
+        }
+    }
+    Ok(None)
+}
+
+/// Call a scalar function, NULL if any argument is NULL
+fn call_func(name: &str, args: Vec<Option<ColumnVal>>) -> DBResult<Option<ColumnVal>> {
+    use ColumnVal::*;
+    let arity = match name {
+        "UPPER" | "LOWER" | "LENGTH" | "ABS" | "YEAR" | "MONTH" | "DAY" => 1..=1,
+        "ROUND" => 1..=2,
+        "SUBSTR" => 2..=3,
+        _ => return Err(format!("unknown function {}", name).into()),
+    };
+    if !arity.contains(&args.len()) {
+        return Err(format!("wrong number of arguments to {}", name).into());
+    }
+    let args = match args.into_iter().collect::<Option<Vec<_>>>() {
+        Some(args) => args,
+        None => return Ok(None),
+    };
+    let type_error = || -> DBResult<_> {
+        let types = args.iter().map(|arg| format!("{:?}", arg.coltype()));
+        let types = types.collect::<Vec<_>>().join(", ");
+        Err(format!("function {} cannot be applied to {}", name, types).into())
+    };
+    let ret = match (name, args.as_slice()) {
+        ("UPPER", [Char(s)]) => Char(s.to_uppercase()),
+        ("UPPER", [Varchar(s)]) => Varchar(s.to_uppercase()),
+        ("LOWER", [Char(s)]) => Char(s.to_lowercase()),
+        ("LOWER", [Varchar(s)]) => Varchar(s.to_lowercase()),
+        ("LENGTH", [Char(s) | Varchar(s)]) => Int(s.chars().count() as _),
+        // characters from position `start` counting from 1, like SQL does
+        ("SUBSTR", [Char(s) | Varchar(s), Int(start), rest @ ..]) => {
+            let end = match rest {
+                [] => i64::MAX,
+                [Int(len)] if *len < 0 => {
+                    return Err("negative length in SUBSTR".into());
+                }
+                [Int(len)] => *start as i64 + *len as i64,
+                _ => return type_error(),
+            };
+            let sub = s
+                .chars()
+                .enumerate()
+                .filter(|&(i, _)| {
+                    let pos = i as i64 + 1;
+                    pos >= *start as i64 && pos < end
+                })
+                .map(|(_, c)| c)
+                .collect();
+            Varchar(sub)
+        }
+        ("ABS", [Int(i)]) => Int(i
+            .checked_abs()
+            .ok_or(format!("integer overflow in ABS({})", i))?),
+        ("ABS", [Float(f)]) => Float(f.abs()),
+        // to `digits` decimal places, or to tens, hundreds, ... if it is negative
+        ("ROUND", [val, rest @ ..]) => {
+            let digits = match rest {
+                [] => 0,
+                [Int(digits)] => *digits,
+                _ => return type_error(),
+            };
+            match val {
+                Int(i) if digits >= 0 => Int(*i),
+                Int(i) => {
+                    let scale = 10_i64
+                        .checked_pow(digits.unsigned_abs())
+                        .unwrap_or(i64::MAX);
+                    let half = if *i < 0 { -scale / 2 } else { scale / 2 };
+                    let rounded = (*i as i64 + half) / scale * scale;
+                    Int(rounded
+                        .try_into()
+                        .map_err(|_| format!("integer overflow in ROUND({}, {})", i, digits))?)
+                }
+                Float(f) => {
+                    let scale = 10_f64.powi(digits);
+                    Float(((*f as f64 * scale).round() / scale) as f32)
+                }
+                _ => return type_error(),
+            }
+        }
+        ("YEAR", [Date(d)]) => Int(d.year()),
+        ("MONTH", [Date(d)]) => Int(d.month() as _),
+        ("DAY", [Date(d)]) => Int(d.day() as _),
+        _ => return type_error(),
+    };
+    Ok(Some(ret))
+}
+
+/// Evaluate a condition on a row, `None` stands for unknown,
+/// which is the result of comparing with NULL
+pub fn eval_cond(cond: &CondExpr, row: Row) -> DBResult<Option<bool>> {
+    let ret = match cond {
+        CondExpr::True => Some(true),
+        CondExpr::False => Some(false),
+        CondExpr::Binary(lhs, op, rhs) => {
+            let lhs = eval_cond(lhs, row)?;
+            let rhs = eval_cond(rhs, row)?;
+            match op {
+                LogicOp::AND => match (lhs, rhs) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                },
+                LogicOp::OR => match (lhs, rhs) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                },
+            }
+        }
+        CondExpr::Not(cond) => eval_cond(cond, row)?.map(|b| !b),
+        CondExpr::Term(CalcExpr::Compare(lhs, op, rhs)) => {
+            match (eval_expr(lhs, row)?, eval_expr(rhs, row)?) {
+                (Some(lhs), Some(rhs)) => {
+                    let (lhs, rhs) = coerce(lhs, rhs);
+                    Some(comp_colval(&Some(lhs), *op, &Some(rhs))?)
+                }
+                (lhs, rhs) if *op == CompareOp::NULLSAFEEQ => Some(lhs == rhs),
+                _ => None,
+            }
+        }
+        CondExpr::Term(CalcExpr::IsNull(expr)) => Some(eval_expr(expr, row)?.is_none()),
+        CondExpr::Term(CalcExpr::In(expr, list)) => match eval_expr(expr, row)? {
+            None => None,
+            Some(val) => {
+                let mut ret = Some(false);
+                for item in list {
+                    match eval_expr(item, row)? {
+                        None => ret = None,
+                        Some(item) => {
+                            let (val, item) = coerce(val.clone(), item);
+                            if val == item {
+                                return Ok(Some(true));
+                            }
+                        }
+                    }
+                }
+                ret
+            }
+        },
+        CondExpr::Term(CalcExpr::InTuple(tuple, list)) => {
+            let vals = tuple
+                .iter()
+                .map(|expr| eval_expr(expr, row))
+                .collect::<DBResult<Vec<_>>>()?;
+            let mut ret = Some(false);
+            for item in list {
+                // unknown if no items differ but some are NULL
+                let mut eq = Some(true);
+                for (val, item) in vals.iter().zip(item) {
+                    match (val, eval_expr(item, row)?) {
+                        (Some(val), Some(item)) => {
+                            let (val, item) = coerce(val.clone(), item);
+                            if val != item {
+                                eq = Some(false);
+                                break;
+                            }
+                        }
+                        _ => eq = None,
+                    }
+                }
+                match eq {
+                    Some(true) => return Ok(Some(true)),
+                    None => ret = None,
+                    Some(false) => {}
+                }
+            }
+            ret
+        }
+        CondExpr::Term(
+            CalcExpr::InSelect(..) | CalcExpr::CompareSelect(..) | CalcExpr::Exists(_),
+        ) => return Err("subqueries are only supported in WHERE".into()),
+    };
+    Ok(ret)
+}
+
+/// Convert string literals to the type of the value they are compared with
+pub fn coerce(lhs: ColumnVal, rhs: ColumnVal) -> (ColumnVal, ColumnVal) {
+    use ColumnVal::*;
+    match (lhs, rhs) {
+        (Char(lhs), Varchar(rhs)) => (Varchar(lhs), Varchar(rhs)),
+        (Varchar(lhs), Char(rhs)) => (Varchar(lhs), Varchar(rhs)),
+        (Date(lhs), Char(rhs)) | (Date(lhs), Varchar(rhs)) => match parse_date(&rhs) {
+            Some(rhs) => (Date(lhs), Date(rhs)),
+            None => (Date(lhs), Varchar(rhs)),
+        },
+        (Char(lhs), Date(rhs)) | (Varchar(lhs), Date(rhs)) => match parse_date(&lhs) {
+            Some(lhs) => (Date(lhs), Date(rhs)),
+            None => (Varchar(lhs), Date(rhs)),
+        },
+        pair => pair,
+    }
+}
+
+fn calc_binary(lhs: ColumnVal, op: &BinaryOp, rhs: ColumnVal) -> DBResult<ColumnVal> {
+    use ColumnVal::*;
+    let ret = match (lhs, rhs) {
+        (Int(lhs), Int(rhs)) => {
+            let ret = match op {
+                BinaryOp::ADD => lhs.checked_add(rhs),
+                BinaryOp::SUB => lhs.checked_sub(rhs),
+                BinaryOp::MUL => lhs.checked_mul(rhs),
+                BinaryOp::DIV => {
+                    if rhs == 0 {
+                        return Err("division by zero".into());
+                    }
+                    if SETTINGS.borrow().int_division == IntDivision::Float {
+                        return Ok(Float(lhs as f32 / rhs as f32));
+                    }
+                    lhs.checked_div(rhs)
+                }
+            };
+            Int(ret.ok_or(format!("integer overflow in {} {} {}", lhs, op, rhs))?)
+        }
+        (Int(lhs), Float(rhs)) => Float(calc_float(lhs as f32, op, rhs)),
+        (Float(lhs), Int(rhs)) => Float(calc_float(lhs, op, rhs as f32)),
+        (Float(lhs), Float(rhs)) => Float(calc_float(lhs, op, rhs)),
+        (lhs, rhs) => {
+            return Err(format!(
+                "operator {} cannot be applied to {:?} and {:?}",
+                op,
+                lhs.coltype(),
+                rhs.coltype()
+            )
+            .into())
+        }
+    };
+    Ok(ret)
+}
+
+fn calc_float(lhs: f32, op: &BinaryOp, rhs: f32) -> f32 {
+    match op {
+        BinaryOp::ADD => lhs + rhs,
+        BinaryOp::SUB => lhs - rhs,
+        BinaryOp::MUL => lhs * rhs,
+        BinaryOp::DIV => lhs / rhs,
+    }
+}
@@ -1,4 +1,10 @@
 mod aggregate;
 pub mod database;
 pub mod exec;
+mod expr;
+mod query;
 mod relation;
+pub mod system;
+
+#[cfg(test)]
+pub mod tests;
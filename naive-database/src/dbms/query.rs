@@ -0,0 +1,1073 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use naive_sql_parser::{
+    CalcExpr, ColumnRef, CondExpr, Expr, FromItem, IndexHint, NullsOrder, Select, Selectors,
+    SingleSelector, SortOrder, Union,
+};
+
+use crate::{
+    config::{MAX_JOIN_TABLE, SETTINGS},
+    defines::RowID,
+    error::DBResult,
+    record::{ColumnType, ColumnVal, Table},
+    utils::{external_sort::ExternalSort, table::print_rows},
+};
+
+use super::{
+    aggregate::aggregate,
+    database::ensure_table,
+    expr::{coerce, eval_colref, eval_cond, eval_expr, Row},
+    relation::{apply_index_hint, matching_rows, resolve_subqueries, resolve_tables, TableRef},
+    system::{is_system_table, system_table},
+};
+
+/// Rows produced by a query, not backed by a table
+#[derive(Debug)]
+pub struct ResultSet {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<Option<ColumnVal>>>,
+}
+
+impl ResultSet {
+    pub fn print(&self, out: &mut dyn Write) -> io::Result<()> {
+        print_rows(out, &self.header, &self.rows)
+    }
+}
+
+/// Where the rows of a query come from
+enum Source {
+    Stored(Vec<TableRef>, Vec<[RowID; MAX_JOIN_TABLE]>),
+    Derived(String, ResultSet),
+}
+
+impl Source {
+    fn len(&self) -> usize {
+        match self {
+            Source::Stored(_, rows) => rows.len(),
+            Source::Derived(_, result) => result.rows.len(),
+        }
+    }
+
+    fn row(&self, i: usize) -> Row<'_> {
+        match self {
+            Source::Stored(tables, rows) => Row::Stored(tables, &rows[i][..tables.len()]),
+            Source::Derived(name, result) => Row::Derived(name, &result.header, &result.rows[i]),
+        }
+    }
+
+    /// Names of all columns, for `SELECT *`
+    fn header(&self) -> DBResult<Vec<String>> {
+        match self {
+            Source::Stored(tables, _) => {
+                let mut columns = vec![];
+                for table in tables {
+                    columns.push(ensure_table(table.id, |table| {
+                        table
+                            .meta
+                            .columns
+                            .iter()
+                            .map(|col| col.name.clone())
+                            .collect::<Vec<_>>()
+                    }));
+                }
+                // columns of the same name in joined tables are qualified by the table (or its alias)
+                let mut header = vec![];
+                for (i, (table, names)) in tables.iter().zip(&columns).enumerate() {
+                    for name in names {
+                        let shared = columns
+                            .iter()
+                            .enumerate()
+                            .any(|(j, other)| j != i && other.contains(name));
+                        header.push(if shared {
+                            format!("{}.{}", table.name, name)
+                        } else {
+                            name.clone()
+                        });
+                    }
+                }
+                Ok(header)
+            }
+            Source::Derived(_, result) => Ok(result.header.clone()),
+        }
+    }
+
+    /// Values of all columns, for `SELECT *`
+    fn values(&self, i: usize) -> DBResult<Vec<Option<ColumnVal>>> {
+        match self {
+            Source::Stored(tables, rows) => {
+                let mut vals = vec![];
+                for (table, &rid) in tables.iter().zip(rows[i].iter()) {
+                    vals.extend(ensure_table(table.id, |table| table.select_row(rid))?);
+                }
+                Ok(vals)
+            }
+            Source::Derived(_, result) => Ok(result.rows[i].clone()),
+        }
+    }
+}
+
+fn column_name(colref: &ColumnRef) -> &str {
+    match colref {
+        ColumnRef::Ident(column) | ColumnRef::Attr { column, .. } => column,
+    }
+}
+
+fn same_column(lhs: &ColumnRef, rhs: &ColumnRef) -> bool {
+    match (lhs, rhs) {
+        (
+            ColumnRef::Attr {
+                table_name: ltable,
+                column: lcol,
+            },
+            ColumnRef::Attr {
+                table_name: rtable,
+                column: rcol,
+            },
+        ) => ltable == rtable && lcol == rcol,
+        _ => column_name(lhs) == column_name(rhs),
+    }
+}
+
+fn selector_name(selector: &SingleSelector) -> String {
+    use SingleSelector::*;
+    match selector {
+        Single(_, Some(alias))
+        | Aggregate(_, _, Some(alias))
+        | CountAll(Some(alias))
+        | Expr(_, Some(alias)) => alias.clone(),
+        Single(colref, None) => column_name(colref).to_owned(),
+        Aggregate(aggr, colref, None) => format!("{:?}({})", aggr, colref),
+        CountAll(None) => "COUNT(*)".to_owned(),
+        Expr(expr, None) => expr.to_string(),
+    }
+}
+
+fn source(
+    from: &[FromItem],
+    condition: Option<&CondExpr>,
+    index_hint: Option<&IndexHint>,
+) -> DBResult<Source> {
+    let cond = &resolve_subqueries(condition.unwrap_or(&CondExpr::True))?;
+    let derived = match from {
+        [FromItem::Derived(select, name)] => Some((query(select)?, name)),
+        [FromItem::Table(table, alias)] if is_system_table(table) => {
+            Some((system_table(table)?, alias.as_ref().unwrap_or(table)))
+        }
+        _ => None,
+    };
+    if let Some((ResultSet { header, rows }, name)) = derived {
+        if let Some(hint) = index_hint {
+            return Err(format!("table {} in index hint is not a stored table", hint.table).into());
+        }
+        let mut matched = vec![];
+        for vals in rows {
+            if eval_cond(cond, Row::Derived(name, &header, &vals))? == Some(true) {
+                matched.push(vals);
+            }
+        }
+        let result = ResultSet {
+            header,
+            rows: matched,
+        };
+        return Ok(Source::Derived(name.clone(), result));
+    }
+
+    let mut items = vec![];
+    for item in from {
+        match item {
+            FromItem::Table(name, _) if is_system_table(name) => {
+                return Err(
+                    format!("system table {} cannot be joined with other tables", name).into(),
+                )
+            }
+            FromItem::Table(name, alias) => items.push((name, alias)),
+            FromItem::Derived(..) => {
+                return Err("a derived table cannot be joined with other tables".into())
+            }
+        }
+    }
+    let mut tables = resolve_tables(items.into_iter())?;
+    if let Some(hint) = index_hint {
+        apply_index_hint(hint, cond, &mut tables)?;
+    }
+    if tables.is_empty() {
+        // a single row without any column, for `SELECT 1`
+        if condition.is_some() {
+            return Err("WHERE clause requires FROM".into());
+        }
+        return Ok(Source::Stored(tables, vec![[0; MAX_JOIN_TABLE]]));
+    }
+    // rows of a table with an indexed primary key come in key order, so that output
+    // without ORDER BY, and LIMIT with it, is stable
+    let single = match &tables[..] {
+        [table] => Some(table.id),
+        _ => None,
+    };
+    if let (Some(id), CondExpr::True) = (single, cond) {
+        // every row is in, as the index on the primary key lists them
+        let all = ensure_table(id, |table| {
+            table.rows_by_primary(|rows| rows.map(|rid| [rid, 0]).collect::<Vec<_>>())
+        });
+        if let Some(rows) = all {
+            return Ok(Source::Stored(tables, rows));
+        }
+    }
+    let mut rows = matching_rows(cond, &tables)?
+        .into_iter()
+        .collect::<Vec<_>>();
+    rows.sort_unstable();
+    if let Some(id) = single {
+        // only the matched rows are sorted, a point lookup does not walk the whole index
+        rows = ensure_table(id, |table| sort_by_primary(table, rows))?;
+    }
+    Ok(Source::Stored(tables, rows))
+}
+
+/// Sort rows of `table` by their primary key, as its index on the key would list them,
+/// rows are left as they are if the key is not indexed
+fn sort_by_primary(
+    table: &Table,
+    rows: Vec<[RowID; MAX_JOIN_TABLE]>,
+) -> DBResult<Vec<[RowID; MAX_JOIN_TABLE]>> {
+    if table.primary_index().is_none() {
+        return Ok(rows);
+    }
+    let mut keyed = rows
+        .into_iter()
+        .map(|rids| {
+            let key = table.select_cols(rids[0], table.meta.primary.iter().copied())?;
+            Ok((key, rids))
+        })
+        .collect::<DBResult<Vec<_>>>()?;
+    // keys of the primary index are unique, rows are already in RowID order for equal ones anyway
+    keyed.sort_by(|(lhs, _), (rhs, _)| {
+        lhs.iter()
+            .zip(rhs)
+            .map(|(lhs, rhs)| cmp_sort_key(lhs, rhs))
+            .fold(Ordering::Equal, Ordering::then)
+    });
+    Ok(keyed.into_iter().map(|(_, rids)| rids).collect())
+}
+
+/// Run a query into memory, rows are evaluated one by one,
+/// which supports derived tables, grouping, sorting and computed columns
+pub fn query(select: &Select) -> DBResult<ResultSet> {
+    let (limit, offset) = limit_offset(select)?;
+    if let Some(result) = count_all(select)? {
+        return Ok(result);
+    }
+    let source = source(
+        &select.from,
+        select.condition.as_ref(),
+        select.index_hint.as_ref(),
+    )?;
+    let group_by = select.group_by.as_ref();
+
+    let (header, groups, rows) = match &select.selectors {
+        Selectors::All => {
+            if group_by.is_some() {
+                return Err("SELECT * cannot be used with GROUP BY".into());
+            }
+            let rows = (0..source.len())
+                .map(|i| source.values(i))
+                .collect::<DBResult<_>>()?;
+            let groups = (0..source.len()).map(|i| vec![i]).collect();
+            (source.header()?, groups, rows)
+        }
+        Selectors::Part(selectors) => {
+            let mut header = selectors.iter().map(selector_name).collect::<Vec<_>>();
+            // columns of the same name from different tables are qualified by the table,
+            // as they are for `SELECT *`
+            let names = header.clone();
+            for (name, selector) in header.iter_mut().zip(selectors) {
+                if let SingleSelector::Single(colref @ ColumnRef::Attr { .. }, None) = selector {
+                    if names.iter().filter(|other| *other == name).count() > 1 {
+                        *name = colref.to_string();
+                    }
+                }
+            }
+            let aggregated = selectors.iter().any(|selector| {
+                matches!(
+                    selector,
+                    SingleSelector::Aggregate(..) | SingleSelector::CountAll(_)
+                )
+            });
+            if aggregated || group_by.is_some() {
+                check_grouped(selectors, &header, &select.order_by, group_by)?;
+            }
+            let groups = group(&source, group_by, aggregated)?;
+            let rows = project(&source, selectors, &groups)?;
+            check_case_types(selectors, &header, &rows)?;
+            (header, groups, rows)
+        }
+    };
+
+    let (keys, mut rows) = if select.order_by.is_empty() {
+        if select.with_ties {
+            return Err("WITH TIES requires ORDER BY".into());
+        }
+        (vec![], rows)
+    } else {
+        sort(&source, &header, &groups, rows, &select.order_by)?
+    };
+    let offset = offset.min(rows.len());
+    rows.drain(..offset);
+    if let Some(mut limit) = limit {
+        if select.with_ties && limit > 0 {
+            let keys = &keys[offset..];
+            while limit < rows.len() && keys[limit] == keys[limit - 1] {
+                limit += 1;
+            }
+        }
+        rows.truncate(limit);
+    }
+    Ok(ResultSet { header, rows })
+}
+
+/// Values of a group of GROUP BY or of a row of UNION, which are the same key when their values
+/// are equal, e.g. `CHAR` and `VARCHAR` of the same text, or 0.0 and -0.0, and NULL is only
+/// the same as NULL
+struct ValueKey(Vec<Option<ColumnVal>>);
+
+impl Ord for ValueKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let cmp = |(lhs, rhs): (&Option<ColumnVal>, &Option<ColumnVal>)| match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => {
+                let (lhs, rhs) = coerce(lhs.clone(), rhs.clone());
+                // values of types which cannot be compared are never equal
+                lhs.partial_cmp(&rhs)
+                    .unwrap_or_else(|| lhs.coltype().cmp(&rhs.coltype()))
+            }
+            _ => cmp_sort_key(lhs, rhs),
+        };
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(cmp)
+            .fold(Ordering::Equal, Ordering::then)
+    }
+}
+
+impl PartialOrd for ValueKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ValueKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for ValueKey {}
+
+/// The result of `SELECT COUNT(*) FROM table` without any other clause, which is the number
+/// of rows kept by the table rather than counted from its rows
+fn count_all(select: &Select) -> DBResult<Option<ResultSet>> {
+    let (selector, table, alias) = match (&select.selectors, &select.from[..]) {
+        (Selectors::Part(selectors), [FromItem::Table(table, alias)]) => match &selectors[..] {
+            [selector @ SingleSelector::CountAll(_)] => (selector, table, alias),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    if is_system_table(table)
+        || select.condition.is_some()
+        || select.group_by.is_some()
+        || select.index_hint.is_some()
+        || !select.order_by.is_empty()
+        || select.limit.is_some()
+        || select.offset.is_some()
+        || select.with_ties
+    {
+        return Ok(None);
+    }
+    let tables = resolve_tables(std::iter::once((table, alias)))?;
+    let count = ensure_table(tables[0].id, |table| table.row_count());
+    Ok(Some(ResultSet {
+        header: vec![selector_name(selector)],
+        rows: vec![vec![Some(ColumnVal::Int(count as _))]],
+    }))
+}
+
+/// Run both queries of a UNION, the columns are named after the left one. Duplicate rows are
+/// removed unless `ALL` is given, rows are keyed by their values as groups of GROUP BY are
+pub fn union(args: &Union) -> DBResult<ResultSet> {
+    let mut result = query(&args.left)?;
+    let right = query(&args.right)?;
+    if result.header.len() != right.header.len() {
+        return Err(format!(
+            "both sides of UNION should select the same number of columns, got {} and {}",
+            result.header.len(),
+            right.header.len()
+        )
+        .into());
+    }
+    for (i, name) in result.header.iter().enumerate() {
+        if let (Some(ltype), Some(rtype)) =
+            (column_type(&result.rows, i), column_type(&right.rows, i))
+        {
+            if !compatible(ltype, rtype) {
+                return Err(format!(
+                    "column {} of UNION is {:?} on the left but {:?} on the right",
+                    name, ltype, rtype
+                )
+                .into());
+            }
+        }
+    }
+    result.rows.extend(right.rows);
+    if !args.all {
+        let mut seen = BTreeSet::new();
+        result.rows.retain(|row| seen.insert(ValueKey(row.clone())));
+    }
+    Ok(result)
+}
+
+/// The type of the `i`th column of `rows`, which is that of its first value other than NULL
+fn column_type(rows: &[Vec<Option<ColumnVal>>], i: usize) -> Option<ColumnType> {
+    rows.iter()
+        .find_map(|row| row[i].as_ref())
+        .map(ColumnVal::coltype)
+}
+
+/// Results of a CASE column should be of compatible types, or the column has no defined type
+fn check_case_types(
+    selectors: &[SingleSelector],
+    header: &[String],
+    rows: &[Vec<Option<ColumnVal>>],
+) -> DBResult<()> {
+    for (i, selector) in selectors.iter().enumerate() {
+        if !matches!(selector, SingleSelector::Expr(Expr::Case { .. }, _)) {
+            continue;
+        }
+        if let Some(first) = column_type(rows, i) {
+            let types = rows.iter().filter_map(|row| row[i].as_ref());
+            if let Some(other) = types
+                .map(ColumnVal::coltype)
+                .find(|&coltype| !compatible(first, coltype))
+            {
+                return Err(format!(
+                    "results of CASE in column {} are both {:?} and {:?}",
+                    header[i], first, other
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether values of the types can be in the same column, numbers with numbers,
+/// strings with strings and dates with dates
+fn compatible(lhs: ColumnType, rhs: ColumnType) -> bool {
+    use ColumnType::*;
+    matches!(
+        (lhs, rhs),
+        (Int | Float, Int | Float) | (Char | Varchar, Char | Varchar) | (Date, Date)
+    )
+}
+
+/// Validate LIMIT and OFFSET of a query, which cannot be negative
+fn limit_offset(select: &Select) -> DBResult<(Option<usize>, usize)> {
+    let count = |name, count: i64| -> DBResult<usize> {
+        if count < 0 {
+            return Err(format!("{} must not be negative, got {}", name, count).into());
+        }
+        Ok(count as usize)
+    };
+    let limit = select
+        .limit
+        .map(|limit| count("LIMIT", limit))
+        .transpose()?;
+    let offset = count("OFFSET", select.offset.unwrap_or(0))?;
+    Ok((limit, offset))
+}
+
+fn same_expr(lhs: &Expr, rhs: &Expr) -> bool {
+    match (lhs, rhs) {
+        (Expr::ColumnRef(lhs), Expr::ColumnRef(rhs)) => same_column(lhs, rhs),
+        _ => lhs == rhs,
+    }
+}
+
+/// Whether an expression is the same for all rows in a group,
+/// i.e. it only refers to the grouped expression, if any
+fn is_grouped(expr: &Expr, group_by: Option<&Expr>) -> bool {
+    if matches!(group_by, Some(group_expr) if same_expr(expr, group_expr)) {
+        return true;
+    }
+    match expr {
+        Expr::Binary(lhs, _, rhs) => is_grouped(lhs, group_by) && is_grouped(rhs, group_by),
+        Expr::ColumnRef(_) => false,
+        Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) | Expr::Null => true,
+        Expr::Case { whens, else_ } => {
+            whens
+                .iter()
+                .all(|(cond, expr)| is_cond_grouped(cond, group_by) && is_grouped(expr, group_by))
+                && else_.iter().all(|expr| is_grouped(expr, group_by))
+        }
+        Expr::Func(_, args) => args.iter().all(|arg| is_grouped(arg, group_by)),
+        Expr::Cast(expr, _) => is_grouped(expr, group_by),
+    }
+}
+
+fn is_cond_grouped(cond: &CondExpr, group_by: Option<&Expr>) -> bool {
+    match cond {
+        CondExpr::True | CondExpr::False => true,
+        CondExpr::Binary(lhs, _, rhs) => {
+            is_cond_grouped(lhs, group_by) && is_cond_grouped(rhs, group_by)
+        }
+        CondExpr::Not(cond) => is_cond_grouped(cond, group_by),
+        CondExpr::Term(CalcExpr::Compare(lhs, _, rhs)) => {
+            is_grouped(lhs, group_by) && is_grouped(rhs, group_by)
+        }
+        CondExpr::Term(CalcExpr::IsNull(expr)) => is_grouped(expr, group_by),
+        // a subquery is the same for every row
+        CondExpr::Term(CalcExpr::InSelect(expr, _) | CalcExpr::CompareSelect(expr, ..)) => {
+            is_grouped(expr, group_by)
+        }
+        CondExpr::Term(CalcExpr::Exists(_)) => true,
+        CondExpr::Term(CalcExpr::In(expr, list)) => {
+            is_grouped(expr, group_by) && list.iter().all(|item| is_grouped(item, group_by))
+        }
+        CondExpr::Term(CalcExpr::InTuple(tuple, list)) => tuple
+            .iter()
+            .chain(list.iter().flatten())
+            .all(|expr| is_grouped(expr, group_by)),
+    }
+}
+
+/// Position of the output column an ORDER BY expression refers to by name, if any
+fn output_column(expr: &Expr, header: &[String]) -> Option<usize> {
+    match expr {
+        Expr::ColumnRef(ColumnRef::Ident(name)) => header.iter().position(|col| col == name),
+        _ => None,
+    }
+}
+
+/// Other than aggregates, selected and sorted expressions should be the same for all rows in a group
+fn check_grouped(
+    selectors: &[SingleSelector],
+    header: &[String],
+    order_by: &[(Expr, SortOrder, Option<NullsOrder>)],
+    group_by: Option<&Expr>,
+) -> DBResult<()> {
+    for selector in selectors {
+        let grouped = match selector {
+            SingleSelector::Single(colref, _) => {
+                is_grouped(&Expr::ColumnRef(colref.clone()), group_by)
+            }
+            SingleSelector::Expr(expr, _) => is_grouped(expr, group_by),
+            SingleSelector::Aggregate(..) | SingleSelector::CountAll(_) => true,
+        };
+        if !grouped {
+            return Err(format!(
+                "{} must appear in GROUP BY or be used in an aggregate",
+                selector_name(selector)
+            )
+            .into());
+        }
+    }
+    for (expr, ..) in order_by {
+        if output_column(expr, header).is_none() && !is_grouped(expr, group_by) {
+            return Err(format!(
+                "{} must appear in GROUP BY or be used in an aggregate",
+                expr
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Indices of source rows in each group
+fn group(source: &Source, group_by: Option<&Expr>, aggregated: bool) -> DBResult<Vec<Vec<usize>>> {
+    let groups = match group_by {
+        Some(group_expr) => {
+            // groups are kept in order of first appearance
+            let mut groups: Vec<Vec<usize>> = vec![];
+            let mut group_of = BTreeMap::new();
+            for i in 0..source.len() {
+                let key = ValueKey(vec![eval_expr(group_expr, source.row(i))?]);
+                let group = *group_of.entry(key).or_insert_with(|| {
+                    groups.push(vec![]);
+                    groups.len() - 1
+                });
+                groups[group].push(i);
+            }
+            groups
+        }
+        None if aggregated => vec![(0..source.len()).collect()],
+        None => (0..source.len()).map(|i| vec![i]).collect(),
+    };
+    Ok(groups)
+}
+
+/// The row non-aggregated expressions of a group are evaluated on,
+/// an empty group only happens when aggregating without GROUP BY, where only constants are allowed
+fn first_row<'a>(source: &'a Source, group: &[usize]) -> Row<'a> {
+    match group.first() {
+        Some(&i) => source.row(i),
+        None => Row::Stored(&[], &[]),
+    }
+}
+
+fn project(
+    source: &Source,
+    selectors: &[SingleSelector],
+    groups: &[Vec<usize>],
+) -> DBResult<Vec<Vec<Option<ColumnVal>>>> {
+    use SingleSelector::*;
+    let mut rows = Vec::with_capacity(groups.len());
+    for group in groups {
+        let first = first_row(source, group);
+        let mut row = Vec::with_capacity(selectors.len());
+        for selector in selectors {
+            let val = match selector {
+                Single(colref, _) => eval_colref(colref, first)?,
+                Expr(expr, _) => eval_expr(expr, first)?,
+                Aggregate(aggr, colref, _) => {
+                    let vals = group
+                        .iter()
+                        .map(|&i| eval_colref(colref, source.row(i)))
+                        .collect::<DBResult<Vec<_>>>()?;
+                    aggregate(aggr, vals.into_iter())?
+                }
+                CountAll(_) => Some(ColumnVal::Int(group.len() as _)),
+            };
+            row.push(val);
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// NULL is the greatest, as in indices
+fn cmp_sort_key(lhs: &Option<ColumnVal>, rhs: &Option<ColumnVal>) -> Ordering {
+    match (lhs, rhs) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(lhs), Some(rhs)) => {
+            let (lhs, rhs) = coerce(lhs.clone(), rhs.clone());
+            lhs.partial_cmp(&rhs).unwrap_or(Ordering::Equal)
+        }
+    }
+}
+
+type Rows = Vec<Vec<Option<ColumnVal>>>;
+/// The sort key of an output row along with it
+type Keyed = (Vec<Option<ColumnVal>>, Vec<Option<ColumnVal>>);
+
+/// Sort output rows, ORDER BY may refer to output columns by name,
+/// other expressions are evaluated like non-aggregated selectors.
+/// Returns the sort keys of the sorted rows along with them
+///
+/// Rows beyond the `sort_buffer_rows` setting are sorted in runs spilled to `temp_dir`
+fn sort(
+    source: &Source,
+    header: &[String],
+    groups: &[Vec<usize>],
+    rows: Vec<Vec<Option<ColumnVal>>>,
+    order_by: &[(Expr, SortOrder, Option<NullsOrder>)],
+) -> DBResult<(Rows, Rows)> {
+    // stable, rows with equal keys are kept in their original order,
+    // NULLS FIRST and NULLS LAST place NULL regardless of the sort order
+    let cmp = |(lhs, _): &Keyed, (rhs, _): &Keyed| {
+        lhs.iter()
+            .zip(rhs)
+            .zip(order_by)
+            .map(|((lhs, rhs), (_, order, nulls))| match (lhs, rhs, nulls) {
+                (None, Some(_), Some(NullsOrder::FIRST)) => Ordering::Less,
+                (None, Some(_), Some(NullsOrder::LAST)) => Ordering::Greater,
+                (Some(_), None, Some(nulls)) => match nulls {
+                    NullsOrder::FIRST => Ordering::Greater,
+                    NullsOrder::LAST => Ordering::Less,
+                },
+                _ => match order {
+                    SortOrder::ASC => cmp_sort_key(lhs, rhs),
+                    SortOrder::DESC => cmp_sort_key(lhs, rhs).reverse(),
+                },
+            })
+            .fold(Ordering::Equal, Ordering::then)
+    };
+    let (capacity, dir) = {
+        let settings = SETTINGS.borrow();
+        (settings.sort_buffer_rows, settings.temp_dir.clone())
+    };
+    let mut sorter = ExternalSort::new(capacity, dir, cmp);
+    for (row, group) in rows.into_iter().zip(groups) {
+        let mut key = Vec::with_capacity(order_by.len());
+        for (expr, ..) in order_by {
+            let val = match output_column(expr, header) {
+                Some(pos) => row[pos].clone(),
+                None => eval_expr(expr, first_row(source, group))?,
+            };
+            key.push(val);
+        }
+        sorter.push((key, row))?;
+    }
+    Ok(sorter.finish()?.into_iter().unzip())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::atomic::Ordering};
+
+    use naive_sql_parser::{Selectors, SingleSelector, SqlStmt, SqlStmtsParser};
+
+    use super::ResultSet;
+    use crate::{
+        config::BASE_DIR,
+        dbms::tests::{query_sql, run, with_database},
+        error::DBResult,
+        record::ColumnVal,
+        utils::external_sort::SPILLED_RUNS,
+    };
+
+    #[test]
+    fn groups_keyed_by_value() -> DBResult<()> {
+        with_database("value_keys", || {
+            run("CREATE TABLE t (f FLOAT, c CHAR(4), v VARCHAR(4));")?;
+            // -0.0 is equal to 0.0, though it is printed differently
+            run("INSERT INTO t VALUES (0.0, 'a', 'a'), ((0.0 - 1.0) * 0.0, 'b', 'a');")?;
+            let result = query_sql("SELECT f, COUNT(*) FROM t GROUP BY f;")?;
+            assert_eq!(result.rows.len(), 1);
+            assert_eq!(result.rows[0][1], Some(ColumnVal::Int(2)));
+            let result = query_sql("SELECT f FROM t WHERE c = 'a' UNION SELECT f FROM t;")?;
+            assert_eq!(result.rows.len(), 1);
+
+            // so is the same text in CHAR and VARCHAR
+            let result = query_sql(
+                "SELECT CASE WHEN c = 'a' THEN c ELSE v END, COUNT(*) FROM t \
+                GROUP BY CASE WHEN c = 'a' THEN c ELSE v END;",
+            )?;
+            assert_eq!(result.rows.len(), 1);
+            let result = query_sql("SELECT c FROM t WHERE c = 'a' UNION SELECT v FROM t;")?;
+            assert_eq!(result.rows.len(), 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn select_with_aliases() -> DBResult<()> {
+        let stmts = SqlStmtsParser::new()
+            .parse("SELECT a AS x, COUNT(*) AS n, MAX(b) AS top, b FROM t;")
+            .unwrap();
+        let aliases = match &stmts[0] {
+            SqlStmt::Select(select) => match &select.selectors {
+                Selectors::Part(selectors) => selectors
+                    .iter()
+                    .map(|selector| match selector {
+                        SingleSelector::Single(_, alias)
+                        | SingleSelector::Aggregate(_, _, alias)
+                        | SingleSelector::CountAll(alias)
+                        | SingleSelector::Expr(_, alias) => alias.as_deref(),
+                    })
+                    .collect::<Vec<_>>(),
+                Selectors::All => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(aliases, [Some("x"), Some("n"), Some("top"), None]);
+
+        with_database("select_alias", || {
+            run("CREATE TABLE t (a INT, b INT); CREATE TABLE u (a INT, c INT);")?;
+            run("INSERT INTO t VALUES (1, 2), (3, 4); INSERT INTO u VALUES (1, 5);")?;
+            let header = |sql| -> DBResult<_> { Ok(query_sql(sql)?.header) };
+            assert_eq!(header("SELECT a AS x, b FROM t;")?, ["x", "b"]);
+            assert_eq!(
+                header("SELECT COUNT(*) AS n, MAX(b) AS top FROM t;")?,
+                ["n", "top"]
+            );
+            assert_eq!(header("SELECT COUNT(*) AS n FROM t;")?, ["n"]);
+            assert_eq!(
+                header("SELECT t.a AS x, u.c AS y FROM t, u WHERE t.a = u.a;")?,
+                ["x", "y"]
+            );
+            // only columns without an alias are qualified when their names are shared
+            assert_eq!(
+                header("SELECT t.a AS a, u.a FROM t, u WHERE t.a = u.a;")?,
+                ["a", "u.a"]
+            );
+            assert_eq!(
+                header("SELECT COUNT(*) AS n, MAX(u.c) AS top FROM t, u WHERE t.a = u.a;")?,
+                ["n", "top"]
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn select_from_grouped_subquery() -> DBResult<()> {
+        with_database("derived_table", || {
+            run("CREATE TABLE emp (name VARCHAR(10), dept VARCHAR(10), salary INT);")?;
+            run(
+                "INSERT INTO emp VALUES ('a', 'dev', 10), ('b', 'dev', 20), ('c', 'dev', 30), \
+                ('d', 'ops', 5), ('e', 'ops', NULL), ('f', 'hr', 7);",
+            )?;
+
+            let result = query_sql(
+                "SELECT dept, n, total FROM \
+                (SELECT dept, COUNT(*) AS n, SUM(salary) AS total FROM emp GROUP BY dept) AS sub \
+                WHERE n > 1;",
+            )?;
+            assert_eq!(result.header, ["dept", "n", "total"]);
+            assert_eq!(
+                result.rows,
+                [
+                    vec![
+                        Some(ColumnVal::Varchar("dev".to_owned())),
+                        Some(ColumnVal::Int(3)),
+                        Some(ColumnVal::Int(60)),
+                    ],
+                    vec![
+                        Some(ColumnVal::Varchar("ops".to_owned())),
+                        Some(ColumnVal::Int(2)),
+                        Some(ColumnVal::Int(5)),
+                    ],
+                ]
+            );
+
+            let result =
+                query_sql("SELECT sub.top FROM (SELECT MAX(salary) AS top FROM emp) sub;")?;
+            assert_eq!(result.rows, [vec![Some(ColumnVal::Int(30))]]);
+            assert!(query_sql("SELECT name, COUNT(*) FROM emp GROUP BY dept;").is_err());
+            run(
+                "SELECT * FROM (SELECT dept FROM emp WHERE salary > 6) AS sub WHERE dept = 'dev';",
+            )?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn union_of_selects() -> DBResult<()> {
+        with_database("union", || {
+            run("CREATE TABLE a (id INT, name VARCHAR(10), d DATE);")?;
+            run("CREATE TABLE b (n INT, title CHAR(10));")?;
+            run("INSERT INTO a VALUES (1, 'x', '2020-01-01'), (2, 'y', NULL), (2, 'y', NULL);")?;
+            run("INSERT INTO b VALUES (2, 'y'), (3, NULL);")?;
+
+            let int = |i| Some(ColumnVal::Int(i));
+            let text = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            let result = query_sql("SELECT id, name FROM a UNION SELECT n, title FROM b;")?;
+            assert_eq!(result.header, ["id", "name"]);
+            assert_eq!(
+                result.rows,
+                [
+                    vec![int(1), text("x")],
+                    vec![int(2), text("y")],
+                    vec![int(3), None],
+                ]
+            );
+            let result = query_sql("SELECT id, name FROM a union all SELECT n, title FROM b;")?;
+            assert_eq!(result.rows.len(), 5);
+            // each side has its own ORDER BY and LIMIT
+            let result = query_sql(
+                "SELECT id FROM a ORDER BY id DESC LIMIT 1 UNION ALL SELECT n FROM b ORDER BY n;",
+            )?;
+            assert_eq!(result.rows, [vec![int(2)], vec![int(2)], vec![int(3)]]);
+
+            assert!(query_sql("SELECT id FROM a UNION SELECT n, title FROM b;").is_err());
+            assert!(query_sql("SELECT d FROM a UNION SELECT title FROM b;").is_err());
+            // a column of only NULLs goes with any type
+            let result = query_sql("SELECT d FROM a WHERE id = 2 UNION SELECT title FROM b;")?;
+            let char_y = Some(ColumnVal::Char("y".to_owned()));
+            assert_eq!(result.rows, [vec![None], vec![char_y]]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn order_and_group_by_case() -> DBResult<()> {
+        with_database("case_expr", || {
+            run("CREATE TABLE task (name VARCHAR(10), status VARCHAR(10), score INT);")?;
+            run(
+                "INSERT INTO task VALUES ('a', 'normal', 80), ('b', 'urgent', 30), ('c', 'normal', NULL), \
+                ('d', 'urgent', 95), ('e', 'low', 59);",
+            )?;
+
+            let names = |result: ResultSet| -> Vec<Option<ColumnVal>> {
+                result
+                    .rows
+                    .into_iter()
+                    .map(|mut row| row.remove(0))
+                    .collect()
+            };
+            let name = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            let result = query_sql(
+                "SELECT name FROM task \
+                ORDER BY CASE status WHEN 'urgent' THEN 0 WHEN 'normal' THEN 1 ELSE 2 END, name DESC;",
+            )?;
+            assert_eq!(
+                names(result),
+                [name("d"), name("b"), name("c"), name("a"), name("e")]
+            );
+            // NULL sorts last, and first in descending order
+            let result = query_sql("SELECT name FROM task ORDER BY score DESC LIMIT 2;")?;
+            assert_eq!(names(result), [name("c"), name("d")]);
+            let result =
+                query_sql("SELECT name FROM task ORDER BY score DESC NULLS LAST LIMIT 2;")?;
+            assert_eq!(names(result), [name("d"), name("a")]);
+            let result = query_sql("SELECT name FROM task ORDER BY score NULLS FIRST LIMIT 2;")?;
+            assert_eq!(names(result), [name("c"), name("b")]);
+            assert!(query_sql("SELECT name FROM task ORDER BY score NULLS MIDDLE;").is_err());
+
+            let result = query_sql(
+                "SELECT CASE WHEN score >= 60 THEN 'pass' WHEN score < 60 THEN 'fail' END AS grade, \
+                COUNT(*) AS n FROM task \
+                GROUP BY CASE WHEN score >= 60 THEN 'pass' WHEN score < 60 THEN 'fail' END \
+                ORDER BY n DESC, grade;",
+            )?;
+            assert_eq!(result.header, ["grade", "n"]);
+            assert_eq!(
+                result.rows,
+                [
+                    vec![name("fail"), Some(ColumnVal::Int(2))],
+                    vec![name("pass"), Some(ColumnVal::Int(2))],
+                    vec![None, Some(ColumnVal::Int(1))],
+                ]
+            );
+            run("SELECT name, score FROM task ORDER BY score;")?;
+            assert!(
+                query_sql("SELECT status, COUNT(*) FROM task GROUP BY status ORDER BY score;")
+                    .is_err()
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn case_result_types() -> DBResult<()> {
+        with_database("case_types", || {
+            run("CREATE TABLE exam (name VARCHAR(10), score INT, grade FLOAT);")?;
+            run("INSERT INTO exam VALUES ('a', 80, 3.5), ('b', 30, NULL), ('c', NULL, 1.0);")?;
+
+            let result = query_sql(
+                "SELECT CASE WHEN score >= 60 THEN 'pass' ELSE 'fail' END FROM exam ORDER BY name;",
+            )?;
+            let text = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            assert_eq!(
+                result.rows,
+                [vec![text("pass")], vec![text("fail")], vec![text("fail")]]
+            );
+            // no branch holds and there is no ELSE
+            let result = query_sql("SELECT CASE WHEN score > 90 THEN name END FROM exam;")?;
+            assert!(result.rows.iter().all(|row| row[0].is_none()));
+            // numbers of both types can be in the same column
+            run("SELECT CASE WHEN score > 50 THEN score ELSE grade END FROM exam;")?;
+            run("SELECT CASE WHEN score > 50 THEN 1 WHEN score > 10 THEN 0.5 END FROM exam;")?;
+
+            assert!(
+                query_sql("SELECT CASE WHEN score > 50 THEN 1 ELSE 'low' END FROM exam;").is_err()
+            );
+            assert!(
+                query_sql("SELECT CASE score WHEN 80 THEN 'a' WHEN 30 THEN 2 END FROM exam;")
+                    .is_err()
+            );
+            assert!(
+                query_sql("SELECT CASE WHEN score > 50 THEN name ELSE score END FROM exam;")
+                    .is_err()
+            );
+            // only the values given are checked
+            run(
+                "SELECT CASE WHEN score > 50 THEN name ELSE score END FROM exam WHERE score > 50;",
+            )?;
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn limit_and_offset_validation() -> DBResult<()> {
+        with_database("limit_offset", || {
+            run("CREATE TABLE t (a INT);")?;
+            run("INSERT INTO t VALUES (1), (2), (3);")?;
+            assert!(run("SELECT * FROM t LIMIT -1;").is_err());
+            assert!(run("SELECT * FROM t LIMIT 1 OFFSET -1;").is_err());
+            assert_eq!(query_sql("SELECT * FROM t LIMIT 2;")?.rows.len(), 2);
+            assert_eq!(
+                query_sql("SELECT a FROM t ORDER BY a LIMIT 5 OFFSET 2;")?
+                    .rows
+                    .len(),
+                1
+            );
+            // offsets beyond the range of INT are valid
+            let result = query_sql("SELECT * FROM t LIMIT 10 OFFSET 3000000000;")?;
+            assert!(result.rows.is_empty());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn limit_with_ties() -> DBResult<()> {
+        with_database("limit_ties", || {
+            run("CREATE TABLE t (name VARCHAR(10), score INT);")?;
+            run(
+                "INSERT INTO t VALUES ('a', 90), ('b', 80), ('c', 80), ('d', 80), ('e', 70), \
+                ('f', NULL), ('g', NULL);",
+            )?;
+            let names = |sql: &str| -> DBResult<Vec<_>> {
+                let rows = query_sql(sql)?.rows;
+                Ok(rows.into_iter().map(|row| row[0].to_owned()).collect())
+            };
+            let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            let ties =
+                names("SELECT name FROM t ORDER BY score DESC NULLS LAST LIMIT 2 WITH TIES;")?;
+            assert_eq!(ties, [str("a"), str("b"), str("c"), str("d")]);
+            // the row at the limit has no tie
+            let ties =
+                names("SELECT name FROM t ORDER BY score DESC NULLS LAST LIMIT 4 WITH TIES;")?;
+            assert_eq!(ties.len(), 4);
+            // within the rows after the offset
+            let ties = names("SELECT name FROM t ORDER BY score LIMIT 1 WITH TIES OFFSET 1;")?;
+            assert_eq!(ties, [str("b"), str("c"), str("d")]);
+            // NULLs tie with each other
+            let ties = names("SELECT name FROM t ORDER BY score NULLS FIRST LIMIT 1 WITH TIES;")?;
+            assert_eq!(ties, [str("f"), str("g")]);
+            let ties = names("SELECT name FROM t ORDER BY score, name LIMIT 2 WITH TIES;")?;
+            assert_eq!(ties, [str("e"), str("b")]);
+            assert!(names("SELECT name FROM t ORDER BY score LIMIT 0 WITH TIES;")?.is_empty());
+            assert!(run("SELECT name FROM t LIMIT 2 WITH TIES;").is_err());
+            assert!(run("SELECT name FROM t ORDER BY score LIMIT 2 WITH PEERS;").is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn order_by_spills_to_disk() -> DBResult<()> {
+        with_database("external_sort", || {
+            run("CREATE TABLE t (k INT, v INT);")?;
+            let values = (0..200)
+                .map(|i| format!("({}, {})", (i * 37) % 23, i))
+                .collect::<Vec<_>>();
+            run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+            let temp_dir = BASE_DIR.join("external_sort_tmp");
+            fs::create_dir_all(&temp_dir)?;
+            run(&format!("SET temp_dir = '{}';", temp_dir.display()))?;
+            run("SET sort_buffer_rows = 16;")?;
+
+            let spilled = SPILLED_RUNS.load(Ordering::SeqCst);
+            let rows = query_sql("SELECT k, v FROM t ORDER BY k;")?.rows;
+            assert!(SPILLED_RUNS.load(Ordering::SeqCst) - spilled >= 12);
+            // stable, rows of the same key stay in insertion order
+            let mut expected = (0..200).map(|i| ((i * 37) % 23, i)).collect::<Vec<_>>();
+            expected.sort_by_key(|&(k, _)| k);
+            let expected = expected
+                .into_iter()
+                .map(|(k, v)| vec![Some(ColumnVal::Int(k)), Some(ColumnVal::Int(v))])
+                .collect::<Vec<_>>();
+            assert_eq!(rows, expected);
+            assert_eq!(fs::read_dir(&temp_dir)?.count(), 0);
+
+            assert!(run("SET sort_buffer_rows = 0;").is_err());
+            assert!(run("SET temp_dir = 'no/such/dir';").is_err());
+            Ok(())
+        })
+    }
+}
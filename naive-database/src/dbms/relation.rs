@@ -1,14 +1,21 @@
 use std::{collections::HashSet, vec};
 
-use like::Like;
-use naive_sql_parser::{CalcExpr, ColumnRef, CompareOp, CondExpr, Expr, LogicOp};
+use naive_sql_parser::{
+    CalcExpr, ColumnRef, CompareOp, CondExpr, Expr, IndexHint, LogicOp, Select,
+};
 
 use crate::{
     config::MAX_JOIN_TABLE,
-    dbms::database::{ensure_table, get_table, get_table_id},
+    dbms::{
+        database::{ensure_table, get_table, get_table_id},
+        expr::{eval_cond, Row},
+        query::query,
+    },
     defines::{ColID, RowID, TableID},
     error::DBResult,
-    record::{vec_to_buf, ColumnVal},
+    init::check_terminating,
+    record::{vec_to_buf, ColumnVal, ScanPath},
+    utils::like_match,
 };
 
 #[derive(Debug)]
@@ -18,13 +25,6 @@ pub enum Logic<T> {
 }
 
 impl<T> Logic<T> {
-    fn not(self) -> Self {
-        match self {
-            Self::Pos(x) => Self::Neg(x),
-            Self::Neg(x) => Self::Pos(x),
-        }
-    }
-
     fn get(self) -> T {
         match self {
             Self::Pos(x) => x,
@@ -33,7 +33,13 @@ impl<T> Logic<T> {
     }
 }
 
-fn comp_colval(lhs: &Option<ColumnVal>, op: CompareOp, rhs: &Option<ColumnVal>) -> DBResult<bool> {
+/// Whether `lhs op rhs` is true, a comparison with NULL is unknown and so never true,
+/// except by `<=>`
+pub fn comp_colval(
+    lhs: &Option<ColumnVal>,
+    op: CompareOp,
+    rhs: &Option<ColumnVal>,
+) -> DBResult<bool> {
     macro_rules! check_like {
         ( $( $name:ident )* ) => {
         $(
@@ -47,175 +53,631 @@ fn comp_colval(lhs: &Option<ColumnVal>, op: CompareOp, rhs: &Option<ColumnVal>)
     }
 
     use CompareOp::*;
+    if op != NULLSAFEEQ && (lhs.is_none() || rhs.is_none()) {
+        return Ok(false);
+    }
     let ret = match op {
-        EQ => lhs == rhs,
+        EQ | NULLSAFEEQ => lhs == rhs,
         NE => lhs != rhs,
         GT => lhs > rhs,
         LT => lhs < rhs,
         GE => lhs >= rhs,
         LE => lhs <= rhs,
-        LIKE => {
+        LIKE | NOTLIKE | ILIKE | NOTILIKE => {
             use ColumnVal::*;
             check_like! { lhs rhs };
-            return Like::<true>::like(lhs.as_str(), rhs).map_err(Into::into);
-        }
-        NOTLIKE => {
-            use ColumnVal::*;
-            check_like! { lhs rhs };
-            return Like::<true>::not_like(lhs.as_str(), rhs).map_err(Into::into);
+            return like_match(lhs.as_str(), op, rhs);
         }
     };
     Ok(ret)
 }
 
-fn calc_term(expr: &CalcExpr, tables: &[String]) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
-    let compare = |lhs: &Expr, op, rhs: &Expr| -> DBResult<_> {
-        let (ltable, lcol) = match lhs {
-            Expr::ColumnRef(colref) => match colref {
-                ColumnRef::Ident(ident) => (table_of_column(ident, tables)?, ident),
-                ColumnRef::Attr {
-                    table_name: table,
-                    column,
-                } => (table.as_str(), column),
-            },
-            _ => {
-                return Err(
-                    "expect column on the left-hand side when comparing in where clause".into(),
-                )
+/// The table and name of the column on the left-hand side of a predicate
+fn left_column<'a>(lhs: &'a Expr, tables: &'a [TableRef]) -> DBResult<(&'a TableRef, &'a str)> {
+    match lhs {
+        Expr::ColumnRef(colref) => match colref {
+            ColumnRef::Ident(ident) => Ok((table_of_column(ident, tables)?, ident)),
+            ColumnRef::Attr {
+                table_name: table,
+                column,
+            } => Ok((table_by_name(table, tables)?, column)),
+        },
+        _ => Err("expect column on the left-hand side when comparing in where clause".into()),
+    }
+}
+
+/// Pair each of `rows` of `table` with every row of the other table if two are joined
+fn joined_rows(
+    rows: impl Iterator<Item = RowID>,
+    table: &TableRef,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    if tables.len() > 1 {
+        if tables[0].name == table.name {
+            get_cartesian(rows, tables[1].id, false)
+        } else {
+            get_cartesian(rows, tables[0].id, true)
+        }
+    } else {
+        Ok(rows.map(|rid| [rid, 0]).collect())
+    }
+}
+
+fn compare(
+    lhs: &Expr,
+    op: CompareOp,
+    rhs: &Expr,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    if op == CompareOp::NULLSAFEEQ && *rhs == Expr::Null {
+        return null_rows(lhs, true, tables);
+    }
+    let (ltable, lcol) = left_column(lhs, tables)?;
+    let lid = ltable.id;
+    let rows = ensure_table(lid, |table| -> DBResult<_> {
+        let ret = match rhs {
+            // unknown for every row
+            Expr::Null => HashSet::new(),
+            Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) => {
+                let col = [table.meta.get_column_id(lcol).unwrap()];
+                let expr = &[rhs];
+                let col_val = table.exprs2colval(expr, &col)?;
+                let rows = table.filter_rows(&col, op, &col_val, ltable.forced_path(&col))?;
+                joined_rows(rows.into_iter(), ltable, tables)?
             }
-        };
-        let lid = get_table_id(ltable).ok_or(format!("table {} does not exist", ltable))?;
-        let rows = ensure_table(lid, |table| -> DBResult<_> {
-            let ret = match rhs {
-                Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) | Expr::Null => {
-                    let col = [table.meta.get_column_id(lcol).unwrap()];
-                    let expr = &[rhs];
-                    let col_val = table.exprs2colval(expr, &col);
-                    let rows = table.filter_rows(&col, op, &col_val)?;
-                    if tables.len() > 1 {
-                        if tables[0] == ltable {
-                            get_cartesian(rows.iter().cloned(), &tables[1], false)?
-                        } else {
-                            get_cartesian(rows.iter().cloned(), &tables[0], true)?
-                        }
-                    } else {
-                        let mut ret = HashSet::new();
-                        for rid in rows {
-                            ret.insert([rid, 0]);
-                        }
-                        ret
-                    }
-                }
-                Expr::ColumnRef(rcolref) => {
-                    let (rtable, rcol) = match rcolref {
-                        ColumnRef::Ident(ident) => (table_of_column(ident, tables)?, ident),
-                        ColumnRef::Attr {
-                            table_name: table,
-                            column,
-                        } => (table.as_str(), column),
-                    };
-                    let rid =
-                        get_table_id(rtable).ok_or(format!("table {} does not exist", ltable))?;
-                    let lcol = get_table(lid, |table| -> ColID {
-                        table.meta.get_column_id(lcol).unwrap()
-                    });
-                    let rcol = get_table(rid, |table| -> ColID {
-                        table.meta.get_column_id(rcol).unwrap()
-                    });
-                    if ltable == rtable {
-                        let ret = get_table(lid, |table| -> DBResult<_> {
-                            let mut ret = vec![];
-                            for rid in table.rows() {
-                                let cols = [lcol, rcol];
-                                let cols = cols.iter().cloned();
-                                let vals = table.select_cols(rid, cols)?;
-                                let result = unsafe {
-                                    comp_colval(vals.get_unchecked(0), op, vals.get_unchecked(1))?
-                                };
-                                if result {
-                                    ret.push(rid);
-                                }
+            Expr::ColumnRef(rcolref) => {
+                let (rtable, rcol) = match rcolref {
+                    ColumnRef::Ident(ident) => (table_of_column(ident, tables)?, ident),
+                    ColumnRef::Attr {
+                        table_name: table,
+                        column,
+                    } => (table_by_name(table, tables)?, column),
+                };
+                let rid = rtable.id;
+                let lcol = get_table(lid, |table| -> ColID {
+                    table.meta.get_column_id(lcol).unwrap()
+                });
+                let rcol = get_table(rid, |table| -> ColID {
+                    table.meta.get_column_id(rcol).unwrap()
+                });
+                if ltable.name == rtable.name {
+                    let ret = get_table(lid, |table| -> DBResult<_> {
+                        let mut ret = vec![];
+                        for rid in table.rows() {
+                            check_terminating()?;
+                            let cols = [lcol, rcol];
+                            let cols = cols.iter().cloned();
+                            let vals = table.select_cols(rid, cols)?;
+                            let result = unsafe {
+                                comp_colval(vals.get_unchecked(0), op, vals.get_unchecked(1))?
+                            };
+                            if result {
+                                ret.push(rid);
                             }
-
-                            Ok(ret)
-                        })?;
-                        if tables.len() == 1 {
-                            ret.iter().map(|&rid| [rid, 0]).collect()
-                        } else if tables[0] == ltable {
-                            get_cartesian(ret.iter().cloned(), &tables[1], false)?
-                        } else {
-                            get_cartesian(ret.iter().cloned(), &tables[0], true)?
                         }
-                    } else if tables[0] == ltable {
-                        get_match_rows(lid, lcol, rid, rcol, op)?
-                    } else {
-                        get_match_rows(rid, rcol, lid, lcol, op.rev())?
-                    }
+
+                        Ok(ret)
+                    })?;
+                    joined_rows(ret.into_iter(), ltable, tables)?
+                } else if tables[0].name == ltable.name {
+                    get_match_rows(ltable, lcol, rtable, rcol, op)?
+                } else {
+                    get_match_rows(rtable, rcol, ltable, lcol, op.rev())?
                 }
-                Expr::Binary(_, _, _) => todo!(),
-            };
-            Ok(ret)
-        })?;
-        Ok(rows)
+            }
+            // evaluated on each row by `evaluated_rows`
+            Expr::Binary(_, _, _) | Expr::Case { .. } | Expr::Func(..) | Expr::Cast(..) => {
+                unreachable!()
+            }
+        };
+        Ok(ret)
+    })?;
+    Ok(rows)
+}
+
+/// Rows where the column `lhs` is NULL, or where it is not if `is_null` is false
+fn null_rows(
+    lhs: &Expr,
+    is_null: bool,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    let (ltable, lcol) = left_column(lhs, tables)?;
+    let rows = ensure_table(ltable.id, |table| {
+        let col = table.meta.get_column_id(lcol).unwrap();
+        table.null_rows(col, is_null)
+    })?;
+    joined_rows(rows.into_iter(), ltable, tables)
+}
+
+/// Rows where `lhs` equals one of the non-NULL items of `list`
+fn in_rows(
+    lhs: &Expr,
+    list: &[Expr],
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    let mut rows = HashSet::new();
+    for item in list.iter().filter(|item| !matches!(item, Expr::Null)) {
+        rows.extend(compare(lhs, CompareOp::EQ, item, tables)?);
+    }
+    Ok(rows)
+}
+
+/// Rows where the columns of `tuple` equal one of the tuples of `list`, each found by
+/// `filter_rows` so an index on exactly those columns is used when it pays off. Unless
+/// `tuple` is columns of one table and `list` holds literals, `expr` is evaluated on every row
+fn in_tuple_rows(
+    expr: &CalcExpr,
+    tuple: &[Expr],
+    list: &[Vec<Expr>],
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    let is_literal = |item: &Expr| {
+        matches!(
+            item,
+            Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) | Expr::Null
+        )
+    };
+    let columns = tuple
+        .iter()
+        .map(|expr| left_column(expr, tables))
+        .collect::<DBResult<Vec<_>>>();
+    let columns = match columns {
+        Ok(columns)
+            if columns
+                .iter()
+                .all(|(table, _)| table.name == columns[0].0.name)
+                && list.iter().flatten().all(is_literal) =>
+        {
+            columns
+        }
+        _ => return evaluated_rows(expr, true, tables),
     };
+    let ltable = columns[0].0;
+    let rows = ensure_table(ltable.id, |table| -> DBResult<_> {
+        let cols = columns
+            .iter()
+            .map(|(_, col)| table.meta.get_column_id(col).unwrap())
+            .collect::<Vec<_>>();
+        let mut rows = HashSet::new();
+        // a tuple with NULL is never equal
+        for item in list.iter().filter(|item| !item.contains(&Expr::Null)) {
+            for (expr, &col) in item.iter().zip(&cols) {
+                table.check_column_type(expr, col)?;
+            }
+            let item = item.iter().collect::<Vec<_>>();
+            let colval = table.exprs2colval(&item, &cols)?;
+            rows.extend(table.filter_rows(
+                &cols,
+                CompareOp::EQ,
+                &colval,
+                ltable.forced_path(&cols),
+            )?);
+        }
+        Ok(rows)
+    })?;
+    joined_rows(rows.into_iter(), ltable, tables)
+}
 
-    let rows = match expr {
-        CalcExpr::In(_, _) => todo!(),
-        CalcExpr::Compare(lhs, op, rhs) => compare(lhs, *op, rhs)?,
-        CalcExpr::IsNull(_) => todo!(),
+/// Whether a predicate involves values computed from columns, which no table can filter by
+fn is_computed(expr: &CalcExpr) -> bool {
+    let computed = |expr: &Expr| {
+        matches!(
+            expr,
+            Expr::Binary(..) | Expr::Case { .. } | Expr::Func(..) | Expr::Cast(..)
+        )
     };
-    Ok(rows)
+    match expr {
+        CalcExpr::Compare(lhs, _, rhs) => computed(lhs) || computed(rhs),
+        CalcExpr::IsNull(lhs) => computed(lhs),
+        CalcExpr::In(lhs, list) => computed(lhs) || list.iter().any(computed),
+        // decided by `in_tuple_rows`
+        CalcExpr::InTuple(..) => false,
+        // replaced by `resolve_subqueries`
+        CalcExpr::InSelect(..) | CalcExpr::CompareSelect(..) | CalcExpr::Exists(_) => {
+            unreachable!()
+        }
+    }
+}
+
+/// Rows where a predicate evaluates to `expected`, evaluating it on every (possibly joined) row
+fn evaluated_rows(
+    expr: &CalcExpr,
+    expected: bool,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    let rows: Vec<_> = ensure_table(tables[0].id, |table| table.rows().collect());
+    let cond = CondExpr::Term(expr.clone());
+    let mut ret = HashSet::new();
+    for rids in joined_rows(rows.into_iter(), &tables[0], tables)? {
+        check_terminating()?;
+        let row = Row::Stored(tables, &rids[..tables.len()]);
+        if eval_cond(&cond, row)? == Some(expected) {
+            ret.insert(rids);
+        }
+    }
+    Ok(ret)
+}
+
+/// Rows where a predicate is true
+fn calc_term(expr: &CalcExpr, tables: &[TableRef]) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    if is_computed(expr) {
+        return evaluated_rows(expr, true, tables);
+    }
+    match expr {
+        CalcExpr::In(lhs, list) => in_rows(lhs, list, tables),
+        CalcExpr::InTuple(tuple, list) => in_tuple_rows(expr, tuple, list, tables),
+        CalcExpr::Compare(lhs, op, rhs) => compare(lhs, *op, rhs, tables),
+        CalcExpr::IsNull(lhs) => null_rows(lhs, true, tables),
+        CalcExpr::InSelect(..) | CalcExpr::CompareSelect(..) | CalcExpr::Exists(_) => {
+            unreachable!()
+        }
+    }
+}
+
+/// Rows where a predicate is false, which are not the complement of where it is true,
+/// since a predicate is neither on a NULL
+fn calc_negated_term(
+    expr: &CalcExpr,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    if is_computed(expr) {
+        return evaluated_rows(expr, false, tables);
+    }
+    match expr {
+        // `x NOT IN (1, NULL)` is never true
+        CalcExpr::In(_, list) if list.iter().any(|item| matches!(item, Expr::Null)) => {
+            Ok(HashSet::new())
+        }
+        CalcExpr::In(lhs, list) => {
+            let rows = null_rows(lhs, false, tables)?;
+            let in_rows = in_rows(lhs, list, tables)?;
+            Ok(rows.difference(&in_rows).copied().collect())
+        }
+        // false where each tuple has an item differing, which needs every item of the row
+        CalcExpr::InTuple(..) => evaluated_rows(expr, false, tables),
+        // never unknown, so false on every row where it is not true
+        CalcExpr::Compare(_, CompareOp::NULLSAFEEQ, _) => evaluated_rows(expr, false, tables),
+        CalcExpr::Compare(lhs, op, rhs) => compare(lhs, op.not(), rhs, tables),
+        CalcExpr::IsNull(lhs) => null_rows(lhs, false, tables),
+        CalcExpr::InSelect(..) | CalcExpr::CompareSelect(..) | CalcExpr::Exists(_) => {
+            unreachable!()
+        }
+    }
+}
+
+fn binary(
+    lhs: Logic<HashSet<[RowID; MAX_JOIN_TABLE]>>,
+    op: &LogicOp,
+    rhs: Logic<HashSet<[RowID; MAX_JOIN_TABLE]>>,
+) -> Logic<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    use Logic::*;
+    match op {
+        LogicOp::OR => match (lhs, rhs) {
+            (Pos(lhs), Pos(rhs)) => Pos(lhs.union(&rhs).copied().collect()),
+            (Neg(lhs), Neg(rhs)) => Neg(lhs.intersection(&rhs).copied().collect()),
+            (Pos(lhs), Neg(rhs)) | (Neg(rhs), Pos(lhs)) => {
+                Neg(rhs.difference(&lhs).copied().collect())
+            }
+        },
+        LogicOp::AND => match (lhs, rhs) {
+            (Pos(lhs), Pos(rhs)) => Pos(lhs.intersection(&rhs).copied().collect()),
+            (Neg(lhs), Neg(rhs)) => Neg(lhs.union(&rhs).copied().collect()),
+            (Pos(lhs), Neg(rhs)) | (Neg(rhs), Pos(lhs)) => {
+                Pos(lhs.difference(&rhs).copied().collect())
+            }
+        },
+    }
 }
 
+/// Rows where `cond` is true, as `Pos(rows)`, or as `Neg(rows)` for all rows except `rows`
+///
+/// A condition is unknown rather than true or false on rows where it compares NULL,
+/// `Pos` of a predicate only holds rows where it is true and NOT takes rows where it is
+/// false from `negation`, so rows where it is unknown never match under AND, OR and NOT
 pub fn relation(
     cond: &CondExpr,
-    ctx: &[String],
+    ctx: &[TableRef],
 ) -> DBResult<Logic<HashSet<[RowID; MAX_JOIN_TABLE]>>> {
-    let binary = |lhs: &CondExpr,
-                  op: &LogicOp,
-                  rhs: &CondExpr|
-     -> DBResult<Logic<HashSet<[RowID; MAX_JOIN_TABLE]>>> {
-        let lhs = relation(lhs, ctx)?;
-        let rhs = relation(rhs, ctx)?;
-        let ret = match op {
-            naive_sql_parser::LogicOp::OR => match (lhs, rhs) {
-                (Pos(lhs), Pos(rhs)) => Pos(lhs.union(&rhs).copied().collect()),
-                (Neg(lhs), Neg(rhs)) => Neg(lhs.intersection(&rhs).copied().collect()),
-                (Pos(lhs), Neg(rhs)) | (Neg(rhs), Pos(lhs)) => {
-                    Neg(rhs.difference(&lhs).copied().collect())
-                }
-            },
-            naive_sql_parser::LogicOp::AND => match (lhs, rhs) {
-                (Pos(lhs), Pos(rhs)) => Pos(lhs.intersection(&rhs).copied().collect()),
-                (Neg(lhs), Neg(rhs)) => Neg(lhs.union(&rhs).copied().collect()),
-                (Pos(lhs), Neg(rhs)) | (Neg(rhs), Pos(lhs)) => {
-                    Pos(lhs.difference(&rhs).copied().collect())
-                }
-            },
-        };
-        Ok(ret)
-    };
-
     use Logic::*;
     let ret = match cond {
         CondExpr::True => Neg(HashSet::new()),
         CondExpr::False => Pos(HashSet::new()),
-        CondExpr::Binary(lhs, op, rhs) => binary(lhs, op, rhs)?,
-        CondExpr::Not(expr) => relation(expr, ctx)?.not(),
+        CondExpr::Binary(lhs, op, rhs) => binary(relation(lhs, ctx)?, op, relation(rhs, ctx)?),
+        CondExpr::Not(expr) => negation(expr, ctx)?,
         CondExpr::Term(expr) => Pos(calc_term(expr, ctx)?),
     };
     Ok(ret)
 }
 
-pub fn table_of_column<'t>(col_name: &str, tables: &'t [String]) -> DBResult<&'t str> {
-    let mut ret = None;
+/// Rows where `cond` is false. NOT is pushed down to the predicates rather than taking
+/// the complement of where `cond` is true, which would include rows where it is unknown
+fn negation(
+    cond: &CondExpr,
+    ctx: &[TableRef],
+) -> DBResult<Logic<HashSet<[RowID; MAX_JOIN_TABLE]>>> {
+    use Logic::*;
+    let ret = match cond {
+        CondExpr::True => Pos(HashSet::new()),
+        CondExpr::False => Neg(HashSet::new()),
+        // De Morgan's laws
+        CondExpr::Binary(lhs, op, rhs) => {
+            let op = match op {
+                LogicOp::AND => LogicOp::OR,
+                LogicOp::OR => LogicOp::AND,
+            };
+            binary(negation(lhs, ctx)?, &op, negation(rhs, ctx)?)
+        }
+        CondExpr::Not(expr) => relation(expr, ctx)?,
+        CondExpr::Term(expr) => Pos(calc_negated_term(expr, ctx)?),
+    };
+    Ok(ret)
+}
+
+/// `cond` with the subqueries in it replaced by the literals they give, `x IN (SELECT ...)`
+/// by `x IN (...)`, `x > (SELECT ...)` by `x > ...` and `EXISTS (SELECT ...)` by TRUE or FALSE,
+/// which holds for all rows or none. Only uncorrelated subqueries are supported, each is run
+/// once before any row is compared
+pub fn resolve_subqueries(cond: &CondExpr) -> DBResult<CondExpr> {
+    let ret = match cond {
+        CondExpr::Binary(lhs, op, rhs) => CondExpr::Binary(
+            Box::new(resolve_subqueries(lhs)?),
+            op.clone(),
+            Box::new(resolve_subqueries(rhs)?),
+        ),
+        CondExpr::Not(cond) => CondExpr::Not(Box::new(resolve_subqueries(cond)?)),
+        CondExpr::Term(CalcExpr::InSelect(lhs, select)) => {
+            let list = subquery_values(select)?;
+            // false even if `lhs` is NULL
+            if list.is_empty() {
+                CondExpr::False
+            } else {
+                CondExpr::Term(CalcExpr::In(lhs.clone(), list))
+            }
+        }
+        CondExpr::Term(CalcExpr::CompareSelect(lhs, op, select)) => {
+            let mut values = subquery_values(select)?;
+            if values.len() > 1 {
+                return Err(format!(
+                    "subquery compared with {} should give at most one row, got {}",
+                    lhs,
+                    values.len()
+                )
+                .into());
+            }
+            let rhs = values.pop().unwrap_or(Expr::Null);
+            CondExpr::Term(CalcExpr::Compare(lhs.clone(), *op, Box::new(rhs)))
+        }
+        CondExpr::Term(CalcExpr::Exists(select)) => {
+            if query(select)?.rows.is_empty() {
+                CondExpr::False
+            } else {
+                CondExpr::True
+            }
+        }
+        cond => cond.clone(),
+    };
+    Ok(ret)
+}
+
+/// Values of the only column selected by a subquery, as literals
+fn subquery_values(select: &Select) -> DBResult<Vec<Expr>> {
+    let result = query(select)?;
+    if result.header.len() != 1 {
+        return Err(format!(
+            "subquery should select one column, got {}",
+            result.header.len()
+        )
+        .into());
+    }
+    let literal = |val| match val {
+        None => Expr::Null,
+        Some(ColumnVal::Int(i)) => Expr::IntLit(i),
+        Some(ColumnVal::Float(f)) => Expr::FloatLit(f),
+        Some(ColumnVal::Char(s)) | Some(ColumnVal::Varchar(s)) => Expr::StringLit(s),
+        Some(ColumnVal::Date(date)) => Expr::StringLit(date.to_string()),
+    };
+    Ok(result
+        .rows
+        .into_iter()
+        .map(|mut row| literal(row.pop().unwrap()))
+        .collect())
+}
+
+/// All (possibly joined) rows of `tables` satisfying `cond`
+pub fn matching_rows(
+    cond: &CondExpr,
+    tables: &[TableRef],
+) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
+    let cond = &resolve_subqueries(cond)?;
+    let mut table_ids = vec![];
+    for table in tables {
+        ensure_table(table.id, |_| {});
+        table_ids.push(table.id);
+    }
+    let rows = match relation(cond, tables)? {
+        Logic::Pos(x) => x,
+        Logic::Neg(x) => {
+            if table_ids.len() == 1 {
+                let full: HashSet<_> = ensure_table(table_ids[0], |table| -> DBResult<_> {
+                    let mut ret = HashSet::new();
+                    for rid in table.rows() {
+                        check_terminating()?;
+                        ret.insert([rid, 0]);
+                    }
+                    Ok(ret)
+                })?;
+                full.difference(&x).copied().collect()
+            } else if table_ids.len() == 2 {
+                let lrows: Vec<_> = ensure_table(table_ids[0], |ltable| ltable.rows().collect());
+                let rrows: Vec<_> = ensure_table(table_ids[1], |rtable| rtable.rows().collect());
+                let mut full = HashSet::new();
+                for lrow in lrows {
+                    check_terminating()?;
+                    for &rrow in &rrows {
+                        full.insert([lrow, rrow]);
+                    }
+                }
+                full.difference(&x).copied().collect()
+            } else {
+                return Err(format!("at most {} tables can be joined", MAX_JOIN_TABLE).into());
+            }
+        }
+    };
+    Ok(rows)
+}
+
+/// A table in FROM, `name` is what the query calls it, i.e. its alias if given
+#[derive(Debug, Clone)]
+pub struct TableRef {
+    pub name: String,
+    pub id: TableID,
+    /// Columns of the index an index hint forces comparisons on them to use
+    pub index_hint: Option<Vec<ColID>>,
+}
+
+impl TableRef {
+    /// The index path if comparisons on `cols` are forced to use the index, i.e. `cols` are
+    /// its leading columns, otherwise the table decides
+    fn forced_path(&self, cols: &[ColID]) -> Option<ScanPath> {
+        match &self.index_hint {
+            Some(hint) if hint.starts_with(cols) => Some(ScanPath::Index),
+            _ => None,
+        }
+    }
+}
+
+/// Resolve tables in FROM to their ids, each should be referred to by a distinct name
+pub fn resolve_tables<'a>(
+    items: impl Iterator<Item = (&'a String, &'a Option<String>)>,
+) -> DBResult<Vec<TableRef>> {
+    let mut tables: Vec<TableRef> = vec![];
+    for (table, alias) in items {
+        let id = get_table_id(table).ok_or(format!("table {} does not exist", table))?;
+        let name = alias.as_ref().unwrap_or(table);
+        if tables.iter().any(|prev| &prev.name == name) {
+            return Err(format!(
+                "table name {} appears more than once in FROM, give them distinct aliases",
+                name
+            )
+            .into());
+        }
+        tables.push(TableRef {
+            name: name.clone(),
+            id,
+            index_hint: None,
+        });
+    }
+    Ok(tables)
+}
+
+/// Force comparisons in `cond` to use the index named by `hint`, which should exist
+/// and be used by at least one of them
+pub fn apply_index_hint(
+    hint: &IndexHint,
+    cond: &CondExpr,
+    tables: &mut [TableRef],
+) -> DBResult<()> {
+    let pos = tables
+        .iter()
+        .position(|table| table.name == hint.table)
+        .ok_or(format!("table {} in index hint is not in FROM", hint.table))?;
+    let cols = ensure_table(tables[pos].id, |table| -> DBResult<_> {
+        let cols = table
+            .meta
+            .get_columns_id(&hint.columns)
+            .ok_or(format!("no such column in table {}", hint.table))?;
+        if !table
+            .indices
+            .contains_key(&(vec_to_buf(&cols), cols.len() as u8))
+        {
+            return Err(format!(
+                "no index on ({}) of table {}",
+                hint.columns.join(", "),
+                hint.table
+            )
+            .into());
+        }
+        Ok(cols)
+    })?;
+    tables[pos].index_hint = Some(cols);
+    if !uses_index(cond, &tables[pos], tables)? {
+        return Err(format!(
+            "index on ({}) of table {} cannot be used by the WHERE clause",
+            hint.columns.join(", "),
+            hint.table
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Whether a comparison in `cond` looks up rows of `table` by its hinted index,
+/// i.e. comparing the column with a literal, joining on it, or `IN`, including tuples of columns
+fn uses_index(cond: &CondExpr, table: &TableRef, tables: &[TableRef]) -> DBResult<bool> {
+    let is_hinted = |expr: &Expr| -> DBResult<bool> {
+        let (expr_table, col) = left_column(expr, tables)?;
+        if expr_table.name != table.name {
+            return Ok(false);
+        }
+        let col = get_table(table.id, |t| t.meta.get_column_id(col));
+        Ok(matches!(col, Some(col) if table.forced_path(&[col]).is_some()))
+    };
+    let ret = match cond {
+        CondExpr::True | CondExpr::False => false,
+        CondExpr::Binary(lhs, _, rhs) => {
+            uses_index(lhs, table, tables)? || uses_index(rhs, table, tables)?
+        }
+        CondExpr::Not(cond) => uses_index(cond, table, tables)?,
+        CondExpr::Term(CalcExpr::Compare(
+            _,
+            CompareOp::LIKE | CompareOp::NOTLIKE | CompareOp::ILIKE | CompareOp::NOTILIKE,
+            _,
+        )) => false,
+        CondExpr::Term(CalcExpr::Compare(lhs, _, rhs)) => match rhs.as_ref() {
+            Expr::IntLit(_) | Expr::FloatLit(_) | Expr::StringLit(_) => is_hinted(lhs)?,
+            // rows of one table are looked up for each row of the other one
+            Expr::ColumnRef(_) => {
+                left_column(lhs, tables)?.0.name != left_column(rhs, tables)?.0.name
+                    && (is_hinted(lhs)? || is_hinted(rhs)?)
+            }
+            _ => false,
+        },
+        CondExpr::Term(
+            CalcExpr::In(lhs, _) | CalcExpr::InSelect(lhs, _) | CalcExpr::CompareSelect(lhs, ..),
+        ) => is_hinted(lhs)?,
+        CondExpr::Term(CalcExpr::InTuple(tuple, _)) => {
+            let mut cols = vec![];
+            for expr in tuple {
+                match left_column(expr, tables) {
+                    Ok((expr_table, col)) if expr_table.name == table.name => {
+                        cols.extend(get_table(table.id, |t| t.meta.get_column_id(col)));
+                    }
+                    _ => return Ok(false),
+                }
+            }
+            table.forced_path(&cols).is_some()
+        }
+        CondExpr::Term(CalcExpr::IsNull(_) | CalcExpr::Exists(_)) => false,
+    };
+    Ok(ret)
+}
+
+/// Find the table referred to by `name`, which is its alias if it has one
+pub fn table_by_name<'t>(name: &str, tables: &'t [TableRef]) -> DBResult<&'t TableRef> {
+    tables
+        .iter()
+        .find(|table| table.name == name)
+        .ok_or_else(|| format!("select column from unrelated table {}", name).into())
+}
+
+pub fn table_of_column<'t>(col_name: &str, tables: &'t [TableRef]) -> DBResult<&'t TableRef> {
+    let mut ret: Option<&TableRef> = None;
     for tbl in tables {
-        let id = get_table_id(tbl).unwrap();
-        if get_table(id, |table| table.meta.get_column_id(col_name)).is_some() {
+        if get_table(tbl.id, |table| table.meta.get_column_id(col_name)).is_some() {
             if let Some(prev_tbl) = ret {
                 return Err(format!(
                     "column {} appears simultaneously in table {} and {} ambiguously",
-                    col_name, prev_tbl, tbl
+                    col_name, prev_tbl.name, tbl.name
                 )
                 .into());
             } else {
@@ -232,46 +694,51 @@ pub fn table_of_column<'t>(col_name: &str, tables: &'t [String]) -> DBResult<&'t
 
 pub fn get_cartesian(
     rows: impl Iterator<Item = RowID>,
-    table_name: &str,
+    table_id: TableID,
     on_left: bool,
 ) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
-    let table_id =
-        get_table_id(table_name).ok_or(format!("table {} does not exist", table_name))?;
-
-    let ret = ensure_table(table_id, |table| {
+    ensure_table(table_id, |table| {
         let table_rows: Vec<_> = table.rows().collect();
         if on_left {
             let mut ret = HashSet::new();
             for rrid in rows {
+                check_terminating()?;
                 for &lrid in &table_rows {
                     ret.insert([lrid, rrid]);
                 }
             }
-            ret
+            Ok(ret)
         } else {
             let mut ret = HashSet::new();
             for lrid in rows {
+                check_terminating()?;
                 for &rrid in &table_rows {
                     ret.insert([lrid, rrid]);
                 }
             }
-            ret
+            Ok(ret)
         }
-    });
-    Ok(ret)
+    })
 }
 
 fn get_match_rows(
-    ltable_id: TableID,
+    ltable: &TableRef,
     lcol: ColID,
-    rtable_id: TableID,
+    rtable: &TableRef,
     rcol: ColID,
     op: CompareOp,
 ) -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
-    let lhas_index = get_table(ltable_id, |table| -> bool {
-        let col_buf = vec_to_buf(&[lcol]);
-        table.indices.get(&(col_buf, 1)).is_some()
-    });
+    let (ltable_id, rtable_id) = (ltable.id, rtable.id);
+    let (lpath, rpath) = (ltable.forced_path(&[lcol]), rtable.forced_path(&[rcol]));
+    // rows are looked up in the table whose index is forced
+    let lhas_index = match (lpath, rpath) {
+        (Some(_), _) => true,
+        (None, Some(_)) => false,
+        (None, None) => get_table(ltable_id, |table| -> bool {
+            let col_buf = vec_to_buf(&[lcol]);
+            table.indices.get(&(col_buf, 1)).is_some()
+        }),
+    };
     let mut ret = HashSet::new();
     if lhas_index {
         ret = get_table(
@@ -282,9 +749,9 @@ fn get_match_rows(
                     |ltable| -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
                         let mut ret = HashSet::new();
                         for rrid in rtable.rows() {
+                            check_terminating()?;
                             let data = rtable.select(rrid, rcol)?;
-                            let lrows = ltable.filter_rows(&[lcol], op, &[data])?;
-                            for lrid in lrows {
+                            for lrid in ltable.filter_rows(&[lcol], op, &[data], lpath)? {
                                 ret.insert([lrid, rrid]);
                             }
                         }
@@ -303,9 +770,9 @@ fn get_match_rows(
                     |rtable| -> DBResult<HashSet<[RowID; MAX_JOIN_TABLE]>> {
                         let mut ret = HashSet::new();
                         for lrid in ltable.rows() {
+                            check_terminating()?;
                             let data = ltable.select(lrid, lcol)?;
-                            let rrows = rtable.filter_rows(&[rcol], op, &[data])?;
-                            for rrid in rrows {
+                            for rrid in rtable.filter_rows(&[rcol], op, &[data], rpath)? {
                                 ret.insert([lrid, rrid]);
                             }
                         }
@@ -318,3 +785,375 @@ fn get_match_rows(
     }
     Ok(ret)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use naive_sql_parser::CompareOp;
+
+    use crate::{
+        dbms::{
+            database,
+            tests::{query_sql, row_count, run, with_database},
+        },
+        error::DBResult,
+        record::{ColumnVal, ScanPath, INDEX_SCANS},
+    };
+
+    #[test]
+    fn select_with_table_aliases() -> DBResult<()> {
+        with_database("table_alias", || {
+            run("CREATE TABLE dept (id INT, title VARCHAR(10));")?;
+            run("CREATE TABLE emp (name VARCHAR(10), dept INT, salary INT);")?;
+            run("INSERT INTO dept VALUES (1, 'dev'), (2, 'ops');")?;
+            run("INSERT INTO emp VALUES ('a', 1, 10), ('b', 2, 20), ('c', 1, 30);")?;
+
+            let result = query_sql(
+                "SELECT e.name, d.title FROM emp e, dept AS d WHERE e.dept = d.id AND e.salary > 15;",
+            )?;
+            assert_eq!(
+                result.rows,
+                [
+                    vec![
+                        Some(ColumnVal::Varchar("b".to_owned())),
+                        Some(ColumnVal::Varchar("ops".to_owned())),
+                    ],
+                    vec![
+                        Some(ColumnVal::Varchar("c".to_owned())),
+                        Some(ColumnVal::Varchar("dev".to_owned())),
+                    ],
+                ]
+            );
+            run("SELECT e.name, d.title FROM emp e, dept d WHERE e.dept = d.id;")?;
+            run("SELECT SUM(e.salary) FROM emp AS e WHERE e.dept = 1;")?;
+
+            // the alias replaces the table name
+            assert!(run("SELECT emp.name FROM emp e;").is_err());
+            assert!(query_sql("SELECT * FROM emp, emp;").is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn self_join_with_aliases() -> DBResult<()> {
+        with_database("self_join", || {
+            run("CREATE TABLE emp (id INT, name VARCHAR(10), mgr INT);")?;
+            run("INSERT INTO emp VALUES (1, 'boss', NULL), (2, 'amy', 1), (3, 'bob', 1), (4, 'cat', 3);")?;
+
+            let result = query_sql(
+                "SELECT e.name, m.name AS manager FROM emp e, emp m WHERE e.mgr = m.id ORDER BY e.id;",
+            )?;
+            let name = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            assert_eq!(
+                result.rows,
+                [
+                    vec![name("amy"), name("boss")],
+                    vec![name("bob"), name("boss")],
+                    vec![name("cat"), name("bob")],
+                ]
+            );
+            let result = query_sql("SELECT * FROM emp e, emp m WHERE e.mgr = m.id AND m.id = 3;")?;
+            assert_eq!(
+                result.header,
+                ["e.id", "e.name", "e.mgr", "m.id", "m.name", "m.mgr"]
+            );
+            assert_eq!(result.rows.len(), 1);
+
+            let result = query_sql("SELECT e.name, m.name FROM emp e, emp m WHERE e.mgr = m.id;")?;
+            assert_eq!(result.header, ["e.name", "m.name"]);
+            run("SELECT COUNT(m.id) FROM emp e, emp m WHERE e.mgr = m.id AND e.id > 2;")?;
+            run("SELECT * FROM emp e, emp m WHERE m.name = 'boss';")?;
+            // unqualified columns are ambiguous
+            assert!(run("SELECT name FROM emp e, emp m WHERE e.mgr = m.id;").is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn where_subqueries() -> DBResult<()> {
+        with_database("subquery", || {
+            run("CREATE TABLE t (id INT, x INT, d DATE);")?;
+            run("CREATE TABLE u (tid INT, y INT, name VARCHAR(4));")?;
+            run("INSERT INTO t VALUES (1, 10, '2020-01-01'), (2, 20, '2021-01-01'), (3, NULL, NULL);")?;
+            run("INSERT INTO u VALUES (1, 15, 'a'), (3, 5, 'b'), (NULL, 15, 'c');")?;
+
+            let ids = |cond: &str| -> DBResult<Vec<_>> {
+                let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+                let result = query_sql(&sql)?;
+                Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+            };
+            let int = |i| Some(ColumnVal::Int(i));
+            assert_eq!(ids("id IN (SELECT tid FROM u)")?, [int(1), int(3)]);
+            // the NULL selected makes NOT IN unknown for the other rows
+            assert_eq!(ids("id NOT IN (SELECT tid FROM u)")?, []);
+            assert_eq!(ids("id NOT IN (SELECT tid FROM u WHERE y > 10)")?, []);
+            assert_eq!(
+                ids("id NOT IN (SELECT tid FROM u WHERE tid > 0)")?,
+                [int(2)]
+            );
+            // nothing is in an empty subquery, not even NULL
+            assert_eq!(ids("x IN (SELECT y FROM u WHERE y > 100)")?, []);
+            assert_eq!(
+                ids("x NOT IN (SELECT y FROM u WHERE y > 100)")?,
+                [int(1), int(2), int(3)]
+            );
+            assert_eq!(ids("x > (SELECT MAX(y) FROM u)")?, [int(2)]);
+            assert_eq!(ids("x < (SELECT y FROM u WHERE name = 'a')")?, [int(1)]);
+            assert_eq!(ids("d IN (SELECT d FROM t WHERE id = 2)")?, [int(2)]);
+            // a scalar subquery without rows is NULL
+            assert_eq!(ids("x = (SELECT y FROM u WHERE y > 100)")?, []);
+            assert_eq!(
+                query_sql(
+                    "SELECT id FROM (SELECT id, x FROM t) AS s WHERE x < (SELECT MAX(y) FROM u);"
+                )?
+                .rows,
+                [vec![int(1)]]
+            );
+
+            assert!(ids("x = (SELECT y FROM u)").is_err());
+            assert!(ids("id IN (SELECT tid, y FROM u)").is_err());
+            assert!(
+                query_sql("SELECT CASE WHEN x IN (SELECT y FROM u) THEN 1 END FROM t;").is_err()
+            );
+
+            run("DELETE FROM t WHERE id IN (SELECT tid FROM u WHERE y < 10);")?;
+            run("UPDATE t SET x = 0 WHERE x < (SELECT MAX(y) FROM u);")?;
+            let result = query_sql("SELECT id, x FROM t ORDER BY id;")?;
+            assert_eq!(result.rows, [vec![int(1), int(0)], vec![int(2), int(20)]]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn exists_subqueries() -> DBResult<()> {
+        with_database("exists", || {
+            run("CREATE TABLE t (id INT, x INT);")?;
+            run("CREATE TABLE u (y INT);")?;
+            run("INSERT INTO t VALUES (1, 10), (2, NULL);")?;
+            run("INSERT INTO u VALUES (5), (NULL);")?;
+
+            let count = |cond: &str| -> DBResult<usize> {
+                let sql = format!("SELECT id FROM t WHERE {};", cond);
+                Ok(query_sql(&sql)?.rows.len())
+            };
+            assert_eq!(count("EXISTS (SELECT * FROM u)")?, 2);
+            assert_eq!(count("EXISTS (SELECT * FROM u WHERE y > 5)")?, 0);
+            assert_eq!(count("NOT EXISTS (SELECT * FROM u WHERE y > 5)")?, 2);
+            // a row of NULL is still a row
+            assert_eq!(count("EXISTS (SELECT y FROM u WHERE y IS NULL)")?, 2);
+            assert_eq!(count("x > 5 AND EXISTS (SELECT * FROM u WHERE y = 5)")?, 1);
+            assert_eq!(count("x > 5 OR NOT EXISTS (SELECT * FROM u)")?, 1);
+
+            run("DELETE FROM t WHERE NOT EXISTS (SELECT * FROM u WHERE y > 100);")?;
+            assert_eq!(row_count("t"), 0);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn in_with_tuples() -> DBResult<()> {
+        with_database("in_tuple", || {
+            run("CREATE TABLE t (a INT, b VARCHAR(4), c INT); CREATE INDEX ON t (a, b);")?;
+            let values = (0..100)
+                .map(|i| format!("({}, '{}', {})", i % 10, i / 10, i))
+                .collect::<Vec<_>>();
+            run(&format!(
+                "INSERT INTO t VALUES {}, (NULL, '0', 100);",
+                values.join(", ")
+            ))?;
+            let ints = |sql| -> DBResult<Vec<_>> {
+                let rows = query_sql(sql)?.rows;
+                let mut ints = rows
+                    .into_iter()
+                    .map(|row| match row[0] {
+                        Some(ColumnVal::Int(i)) => Some(i),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                ints.sort_unstable();
+                Ok(ints)
+            };
+            let id = database::get_table_id("t").unwrap();
+            database::ensure_table(id, |table| {
+                let key = [
+                    Some(ColumnVal::Int(1)),
+                    Some(ColumnVal::Varchar("2".into())),
+                ];
+                assert_eq!(
+                    table.scan_path(&[0, 1], CompareOp::EQ, &key),
+                    ScanPath::Index
+                );
+            });
+
+            let before = INDEX_SCANS.load(Ordering::Relaxed);
+            let found = ints("SELECT c FROM t WHERE (a, b) IN ((1, '2'), (3, '4'), (3, '10'));")?;
+            assert_eq!(found, [Some(21), Some(43)]);
+            assert!(INDEX_SCANS.load(Ordering::Relaxed) - before >= 3);
+            let found = ints("SELECT /*+ INDEX(t a, b) */ c FROM t WHERE (a, b) IN ((9, '9'));")?;
+            assert_eq!(found, [Some(99)]);
+            // same as comparing the items one by one
+            let found = ints("SELECT c FROM t WHERE (a, c + 1) IN ((5, 6), (5, 7), (6, 17));")?;
+            assert_eq!(found, [Some(5), Some(16)]);
+            let found = ints("SELECT c FROM t WHERE (a, b) IN ((NULL, '0'), (0, '0'));")?;
+            assert_eq!(found, [Some(0)]);
+            // a row differing from a tuple in any item is not in it, even if some other item is
+            // NULL, otherwise NULL makes it unknown whether the row is in the tuple
+            let found = ints("SELECT c FROM t WHERE c >= 97 AND (a, b) NOT IN ((7, '9'));")?;
+            assert_eq!(found, [Some(98), Some(99), Some(100)]);
+            let found = ints("SELECT c FROM t WHERE c >= 97 AND (a, b) NOT IN ((NULL, '9'));")?;
+            assert_eq!(found, [Some(100)]);
+            let found = ints("SELECT c FROM t WHERE c > 90 AND (a, b) NOT IN ((NULL, '0'));")?;
+            assert_eq!(found.len(), 9);
+
+            assert!(run("SELECT * FROM t WHERE (a, b) IN ((1, '2'), (3));").is_err());
+            assert!(run("SELECT * FROM t WHERE (a, b) IN ((1, '2', 3));").is_err());
+            assert!(run("SELECT * FROM t WHERE (a, b) IN ((1, 2));").is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn index_used_only_for_selective_predicates() -> DBResult<()> {
+        with_database("index_selectivity", || {
+            run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a);")?;
+            let values = (0..100)
+                .map(|i| format!("({}, {})", if i < 90 { 0 } else { i }, i))
+                .collect::<Vec<_>>();
+            run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+
+            let id = database::get_table_id("t").unwrap();
+            let check = |op, key, path, len| {
+                database::ensure_table(id, |table| -> DBResult<()> {
+                    let key = [Some(ColumnVal::Int(key))];
+                    assert_eq!(table.scan_path(&[0], op, &key), path);
+                    assert_eq!(table.filter_rows(&[0], op, &key, None)?.len(), len);
+                    Ok(())
+                })
+            };
+            // 90% of rows match, scanning is cheaper than following the index
+            check(CompareOp::EQ, 0, ScanPath::Full, 90)?;
+            check(CompareOp::EQ, 95, ScanPath::Index, 1)?;
+            check(CompareOp::GE, 90, ScanPath::Index, 10)?;
+            check(CompareOp::NE, 0, ScanPath::Index, 10)?;
+
+            run("SET index_selectivity = 0.95;")?;
+            check(CompareOp::EQ, 0, ScanPath::Index, 90)?;
+            assert!(run("SET index_selectivity = 2;").is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn indexed_and_scanned_rows_agree_on_nulls() -> DBResult<()> {
+        with_database("index_nulls", || {
+            run("CREATE TABLE t (id INT, a INT); CREATE INDEX ON t (a);")?;
+            run("INSERT INTO t VALUES (1, 5), (2, NULL), (3, 3), (4, 5), (5, NULL), (6, 7);")?;
+
+            let id = database::get_table_id("t").unwrap();
+            let rows = |op, key: Option<i32>, path| {
+                database::ensure_table(id, |table| -> DBResult<_> {
+                    let key = [key.map(ColumnVal::Int)];
+                    table.filter_rows(&[1], op, &key, Some(path))
+                })
+            };
+            let ops = [
+                CompareOp::EQ,
+                CompareOp::NE,
+                CompareOp::LT,
+                CompareOp::LE,
+                CompareOp::GT,
+                CompareOp::GE,
+                CompareOp::NULLSAFEEQ,
+            ];
+            for &op in &ops {
+                for &key in &[Some(5), None] {
+                    let scanned = rows(op, key, ScanPath::Full)?;
+                    assert_eq!(rows(op, key, ScanPath::Index)?, scanned, "{} {:?}", op, key);
+                }
+            }
+            assert_eq!(rows(CompareOp::NE, Some(5), ScanPath::Index)?.len(), 2);
+            assert_eq!(rows(CompareOp::NULLSAFEEQ, None, ScanPath::Index)?.len(), 2);
+
+            let ids = |sql: &str| -> DBResult<Vec<_>> {
+                let rows = query_sql(sql)?.rows;
+                Ok(rows.into_iter().map(|row| row[0].clone()).collect())
+            };
+            // `a + 0` is evaluated on every row instead of looked up
+            for op in &["=", "<>"] {
+                let hinted = "SELECT /*+ INDEX(t a) */ id FROM t WHERE a";
+                assert_eq!(
+                    ids(&format!("{} {} 5 ORDER BY id;", hinted, op))?,
+                    ids(&format!(
+                        "SELECT id FROM t WHERE a + 0 {} 5 ORDER BY id;",
+                        op
+                    ))?,
+                );
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn index_hint_forces_index() -> DBResult<()> {
+        with_database("index_hint", || {
+            run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a);")?;
+            run("CREATE TABLE s (id INT);")?;
+            run("INSERT INTO t VALUES (1, 1), (2, 2), (3, 3); INSERT INTO s VALUES (3);")?;
+            // the index is never worth reading unless forced
+            run("SET index_selectivity = 0;")?;
+            let index_scans = |sql: &str| -> DBResult<_> {
+                let before = INDEX_SCANS.load(Ordering::Relaxed);
+                let rows = query_sql(sql)?.rows;
+                Ok((rows, INDEX_SCANS.load(Ordering::Relaxed) - before))
+            };
+            let int = |i| vec![Some(ColumnVal::Int(i))];
+            assert_eq!(
+                index_scans("SELECT b FROM t WHERE a >= 2;")?,
+                (vec![int(2), int(3)], 0)
+            );
+            assert_eq!(
+                index_scans("SELECT /*+ INDEX(t a) */ b FROM t WHERE a >= 2;")?,
+                (vec![int(2), int(3)], 1)
+            );
+            // one lookup for each row of s
+            assert_eq!(
+                index_scans("SELECT /*+ INDEX(t a) */ s.id FROM s, t WHERE s.id = t.a;")?,
+                (vec![int(3)], 1)
+            );
+            run("SELECT /*+ INDEX(t a) */ b FROM t WHERE a >= 2;")?;
+
+            // no such index, no comparison using it, or no such table
+            assert!(run("SELECT /*+ INDEX(t b) */ * FROM t WHERE b = 1;").is_err());
+            assert!(run("SELECT /*+ INDEX(t a) */ * FROM t WHERE b = 1;").is_err());
+            assert!(run("SELECT /*+ INDEX(t a) */ * FROM t WHERE a LIKE '1';").is_err());
+            assert!(run("SELECT /*+ INDEX(u a) */ * FROM t WHERE a = 1;").is_err());
+            assert!(query_sql("SELECT /*+ INDEX(t a) */ * FROM t WHERE b = 1;").is_err());
+
+            // a composite index is forced on comparisons of its leading columns
+            run("CREATE TABLE u (a INT, b INT); CREATE INDEX ON u (a, b);")?;
+            run("INSERT INTO u VALUES (1, 1), (2, 3), (2, NULL), (2, 2), (3, 1);")?;
+            let hinted = |cond| {
+                format!(
+                    "SELECT /*+ INDEX(u a, b) */ b FROM u WHERE {} ORDER BY b;",
+                    cond
+                )
+            };
+            assert_eq!(
+                index_scans(&hinted("a = 2"))?,
+                (vec![int(2), int(3), vec![None]], 1)
+            );
+            assert_eq!(
+                index_scans(&hinted("a > 1"))?,
+                (vec![int(1), int(2), int(3), vec![None]], 1)
+            );
+            assert_eq!(index_scans(&hinted("a <= 1"))?, (vec![int(1)], 1));
+            assert_eq!(
+                index_scans(&hinted("(a, b) IN ((2, 3), (3, 1))"))?,
+                (vec![int(1), int(3)], 2)
+            );
+            assert!(run(&hinted("b = 1")).is_err());
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,234 @@
+//! Read-only tables describing the current database, e.g. `SELECT * FROM __columns`,
+//! and dumps of internal structures for debugging
+
+use std::collections::HashMap;
+
+use crate::{
+    config::{
+        BASE_DIR, LOAD_BATCH_SIZE, MAX_CHAR_LEN, MAX_COMP_INDEX, MAX_JOIN_TABLE, PAGE_SIZE,
+        SETTINGS,
+    },
+    defines::{ColID, TableID},
+    error::DBResult,
+    filesystem::page_manager::cache_size,
+    record::{
+        vec_to_buf,
+        ColumnVal::{self, Int, Varchar},
+        RefAction, Table,
+    },
+    utils::table::get_coltype,
+};
+
+use super::{database, query::ResultSet};
+
+const SYSTEM_TABLES: [&str; 3] = ["__columns", "__indexes", "__foreign_keys"];
+
+pub fn is_system_table(name: &str) -> bool {
+    SYSTEM_TABLES.contains(&name)
+}
+
+/// Build the rows of a system table from the metadata of all tables
+pub fn system_table(name: &str) -> DBResult<ResultSet> {
+    match name {
+        "__columns" => columns(),
+        "__indexes" => indexes(),
+        "__foreign_keys" => foreign_keys(),
+        _ => Err(format!("system table {} does not exist", name).into()),
+    }
+}
+
+fn flag(is_set: bool) -> Option<ColumnVal> {
+    Some(Varchar(if is_set { "Yes" } else { "No" }.to_owned()))
+}
+
+fn action(action: RefAction) -> Option<ColumnVal> {
+    let action = match action {
+        RefAction::Restrict => "RESTRICT",
+        RefAction::Cascade => "CASCADE",
+        RefAction::SetNull => "SET NULL",
+    };
+    Some(Varchar(action.to_owned()))
+}
+
+fn header(names: &[&str]) -> Vec<String> {
+    names.iter().map(|&name| name.to_owned()).collect()
+}
+
+fn column_names(table: &Table, cols: &[ColID]) -> Option<ColumnVal> {
+    let names = cols
+        .iter()
+        .map(|&col| table.meta.columns[col as usize].name.as_str())
+        .collect::<Vec<_>>();
+    Some(Varchar(names.join(", ")))
+}
+
+/// Every column of every table, with its type and constraints, like `DESC` for all tables
+fn columns() -> DBResult<ResultSet> {
+    let header = header(&[
+        "table_name",
+        "column_name",
+        "data_type",
+        "not_null",
+        "primary_key",
+        "unique_key",
+        "foreign_key",
+    ]);
+    let mut rows = vec![];
+    for (table_name, id) in database::table_ids()? {
+        database::ensure_table(id, |table| {
+            for col in &table.meta.columns {
+                rows.push(vec![
+                    Some(Varchar(table_name.clone())),
+                    Some(Varchar(col.name.clone())),
+                    Some(Varchar(get_coltype(col.coltype, col.colsize))),
+                    flag(col.constraints.is_not_null()),
+                    flag(col.constraints.is_primary_key()),
+                    flag(col.constraints.is_unique()),
+                    flag(col.constraints.is_foreign_key()),
+                ]);
+            }
+        });
+    }
+    Ok(ResultSet { header, rows })
+}
+
+/// Every index of every table, with the number of entries in it
+fn indexes() -> DBResult<ResultSet> {
+    let header = header(&["table_name", "column_names", "is_unique", "entries"]);
+    let mut rows = vec![];
+    for (table_name, id) in database::table_ids()? {
+        database::ensure_table(id, |table| {
+            let mut indices = table.indices.iter().collect::<Vec<_>>();
+            indices.sort_by_key(|&(&key, _)| key);
+            for ((col, len), index) in indices {
+                let cols = &col[..*len as usize];
+                rows.push(vec![
+                    Some(Varchar(table_name.clone())),
+                    column_names(table, cols),
+                    flag(index.borrow().unique),
+                    Some(Int(index.borrow().list.len() as _)),
+                ]);
+            }
+        });
+    }
+    Ok(ResultSet { header, rows })
+}
+
+/// Every foreign key, from the referencing (child) table to the referenced (parent) table
+fn foreign_keys() -> DBResult<ResultSet> {
+    let header = header(&[
+        "table_name",
+        "column_names",
+        "ref_table_name",
+        "ref_column_names",
+        "on_delete",
+        "on_update",
+    ]);
+    // referenced tables cannot be loaded while another table is borrowed,
+    // so the keys are collected before looking up names of referenced columns
+    let mut names = HashMap::new();
+    let mut keys: Vec<(TableID, Vec<ColID>, TableID, Vec<ColID>)> = vec![];
+    for (table_name, id) in database::table_ids()? {
+        database::ensure_table(id, |table| {
+            let mut table_keys = table.meta.foreign_key.iter().collect::<Vec<_>>();
+            table_keys.sort();
+            for (cols, (ftable, fcols)) in table_keys {
+                keys.push((id, cols.clone(), *ftable, fcols.clone()));
+            }
+        });
+        names.insert(id, table_name);
+    }
+
+    let mut rows = vec![];
+    for (id, key_cols, fid, fcols) in keys {
+        let cols = database::ensure_table(id, |table| column_names(table, &key_cols));
+        // the actions are kept by the referenced table
+        let (fcol_names, policy) = database::ensure_table(fid, |ftable| {
+            let policy = ftable.meta.as_foreign_key[&fcols][&(id, key_cols)];
+            (column_names(ftable, &fcols), policy)
+        });
+        rows.push(vec![
+            Some(Varchar(names[&id].clone())),
+            cols,
+            Some(Varchar(names[&fid].clone())),
+            fcol_names,
+            action(policy.on_delete),
+            action(policy.on_update),
+        ]);
+    }
+    Ok(ResultSet { header, rows })
+}
+
+/// Settings of the session, which `SET` changes, followed by the limits the database
+/// is built with, e.g. `SHOW CONFIG`
+pub fn config() -> ResultSet {
+    let header = header(&["name", "value", "kind"]);
+    let row = |name: &str, value: String, kind: &str| {
+        vec![
+            Some(Varchar(name.to_owned())),
+            Some(Varchar(value)),
+            Some(Varchar(kind.to_owned())),
+        ]
+    };
+    let mut rows = vec![];
+    for (name, value) in SETTINGS.borrow().values() {
+        rows.push(row(name, value, "setting"));
+    }
+    let cache_pages = match cache_size() {
+        // the kernel decides how many mapped pages stay in memory
+        usize::MAX => "unlimited".to_owned(),
+        pages => pages.to_string(),
+    };
+    let constants = [
+        ("data_dir", BASE_DIR.display().to_string()),
+        ("cache_pages", cache_pages),
+        ("page_size", PAGE_SIZE.to_string()),
+        ("load_batch_size", LOAD_BATCH_SIZE.to_string()),
+        ("max_comp_index", MAX_COMP_INDEX.to_string()),
+        ("max_join_table", MAX_JOIN_TABLE.to_string()),
+        ("max_char_len", MAX_CHAR_LEN.to_string()),
+    ];
+    for (name, value) in constants.iter().cloned() {
+        rows.push(row(name, value, "constant"));
+    }
+    ResultSet { header, rows }
+}
+
+/// Entries of the index on `cols` of a table in B-tree order, with the row they point to,
+/// its values of the indexed columns and which of them are NULL, e.g. `.indexdump t a, b`
+pub fn index_dump(table_name: &str, cols: &[String]) -> DBResult<ResultSet> {
+    let id =
+        database::get_table_id(table_name).ok_or(format!("table {} does not exist", table_name))?;
+    database::ensure_table(id, |table| {
+        let col_ids = table
+            .meta
+            .get_columns_id(cols)
+            .ok_or(format!("no such column in table {}", table_name))?;
+        let index = table
+            .indices
+            .get(&(vec_to_buf(&col_ids), col_ids.len() as u8))
+            .ok_or(format!(
+                "no index on ({}) of table {}",
+                cols.join(", "),
+                table_name
+            ))?
+            .borrow();
+
+        let mut header = vec!["rid".to_owned()];
+        header.extend(cols.iter().cloned());
+        header.push("is_null".to_owned());
+        let mut rows = vec![];
+        for key in &index.list {
+            let entry = key.to_ref();
+            let mut row = vec![Some(Int(entry.rid as _))];
+            for &col in &col_ids {
+                row.push(table.select(entry.rid, col)?);
+            }
+            // one bit per indexed column, the first column is the rightmost bit
+            let is_null = format!("{:0width$b}", entry.is_null, width = col_ids.len());
+            row.push(Some(Varchar(is_null)));
+            rows.push(row);
+        }
+        Ok(ResultSet { header, rows })
+    })
+}
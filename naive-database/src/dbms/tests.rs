@@ -0,0 +1,2456 @@
+use std::{
+    fs,
+    io::{stdout, Write},
+    sync::{atomic::Ordering, Mutex, PoisonError},
+};
+
+use lazy_static::lazy_static;
+use naive_sql_parser::{
+    CompareOp, Expr, Selectors, SingleSelector, SpannedSqlStmtsParser, SqlStmt, SqlStmtsParser,
+};
+
+use crate::{
+    api::Database,
+    cli::{exec_script, load_csv},
+    config::{
+        NullLayout, Settings, BASE_DIR, LOAD_BATCH_SIZE, MAX_COMP_INDEX, PAGE_SIZE, SETTINGS,
+    },
+    error::DBResult,
+    filesystem::{page_manager::cache_stats, storage},
+    init::cancel_statement,
+    record::{ColumnType, ColumnVal, ScanPath, Table, TableMeta},
+    repl::{complete_name, dot_command, exec_stmt, parse_input, TIMER},
+    utils::{parse_date, persistence::Persistence},
+};
+
+use super::{
+    database,
+    exec::{constraint_violations, insert_batch, Exec, ExecResult},
+    expr::{eval_expr, Row},
+    query::{self, query, ResultSet},
+    relation::TableRef,
+    system,
+};
+
+lazy_static! {
+    // the database and the page manager are global, tests touching them must not run in parallel
+    pub static ref TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+pub(crate) fn run(sqls: &str) -> DBResult<()> {
+    let stmts = SqlStmtsParser::new()
+        .parse(sqls)
+        .map_err(|e| format!("{:?}", e))?;
+    stmts.exec().map(|_| ())
+}
+
+pub(crate) fn with_database<T>(name: &str, test: impl FnOnce() -> DBResult<T>) -> DBResult<T> {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    *SETTINGS.borrow_mut() = Settings::default();
+    // CSV files loaded by tests are on disk, databases are wherever `storage` keeps them
+    fs::create_dir_all(BASE_DIR.as_path())?;
+    if !storage::is_dir(&BASE_DIR) {
+        storage::create_dir(&BASE_DIR)?;
+    }
+    database::create_database(name)?;
+    assert!(database::change_database(name)?);
+    let ret = test();
+    database::write_back()?;
+    ret
+}
+
+pub(crate) fn query_sql(sql: &str) -> DBResult<ResultSet> {
+    let mut stmts = SqlStmtsParser::new()
+        .parse(sql)
+        .map_err(|e| format!("{:?}", e))?;
+    match stmts.pop() {
+        Some(SqlStmt::Select(select)) => query(&select),
+        Some(SqlStmt::Union(union)) => query::union(&union),
+        _ => Err("not a query".into()),
+    }
+}
+
+/// Parse the expression of `SELECT <expr>`
+fn parse_expr(expr: &str) -> Expr {
+    let mut stmts = SqlStmtsParser::new()
+        .parse(&format!("SELECT {};", expr))
+        .unwrap();
+    match stmts.pop() {
+        Some(SqlStmt::Select(select)) => match select.selectors {
+            Selectors::Part(mut selectors) => match selectors.pop() {
+                Some(SingleSelector::Expr(expr, _)) => expr,
+                Some(SingleSelector::Single(colref, _)) => Expr::ColumnRef(colref),
+                _ => unreachable!(),
+            },
+            Selectors::All => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+pub(crate) fn row_count(table: &str) -> usize {
+    let id = database::get_table_id(table).unwrap();
+    database::ensure_table(id, |table| table.rows().count())
+}
+
+#[test]
+fn csv_rejects_null_in_not_null_column() -> DBResult<()> {
+    with_database("csv_not_null", || {
+        run("CREATE TABLE t (a INT NOT NULL, b INT);")?;
+        let csv = BASE_DIR.join("csv_not_null.csv");
+        fs::write(&csv, "1,2\nNULL,3\n4,NULL\n")?;
+        load_csv(&csv, "csv_not_null", "t", LOAD_BATCH_SIZE)?;
+        assert_eq!(row_count("t"), 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn set_null_string() -> DBResult<()> {
+    with_database("set_null_string", || {
+        assert_eq!(SETTINGS.borrow().null_string, "NULL");
+        run("SET null_string = '(null)';")?;
+        assert_eq!(SETTINGS.borrow().null_string, "(null)");
+        assert!(run("SET no_such_setting = 1;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn int_division_setting() -> DBResult<()> {
+    with_database("int_division", || {
+        let div = || eval_expr(&parse_expr("5 / 2"), Row::Derived("", &[], &[]));
+        assert_eq!(div()?, Some(ColumnVal::Int(2)));
+        run("SET int_division = float;")?;
+        assert_eq!(div()?, Some(ColumnVal::Float(2.5)));
+        run("SET int_division = 'truncate';")?;
+        assert_eq!(div()?, Some(ColumnVal::Int(2)));
+        assert!(run("SET int_division = round;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn csv_load_maintains_index() -> DBResult<()> {
+    with_database("csv_index", || {
+        run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a);")?;
+        let csv = BASE_DIR.join("csv_index.csv");
+        fs::write(&csv, "1,10\n2,20\n3,30\n")?;
+        load_csv(&csv, "csv_index", "t", LOAD_BATCH_SIZE)?;
+        // look rows up through the index however many of them match
+        run("SET index_selectivity = 1;")?;
+        let id = database::get_table_id("t").unwrap();
+        database::ensure_table(id, |table| -> DBResult<()> {
+            assert_eq!(table.indices.len(), 1);
+            let key = [Some(ColumnVal::Int(2))];
+            assert_eq!(table.scan_path(&[0], CompareOp::EQ, &key), ScanPath::Index);
+            let rows = table.filter_rows(&[0], CompareOp::EQ, &[Some(ColumnVal::Int(2))], None)?;
+            assert_eq!(rows.len(), 1);
+            let rid = *rows.iter().next().unwrap();
+            assert_eq!(table.select(rid, 1)?, Some(ColumnVal::Int(20)));
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn csv_load_rejects_duplicates_in_batch() -> DBResult<()> {
+    with_database("csv_unique", || {
+        run("CREATE TABLE t (a INT NOT NULL, b VARCHAR(10), PRIMARY KEY (a));")?;
+        run("CREATE INDEX ON t (b);")?;
+        let csv = BASE_DIR.join("csv_unique.csv");
+        // the second 1 is in the same batch as the first, the second 2 in the next one
+        fs::write(&csv, "3,c\n1,a\n1,b\n2,x1\n2,x2\n4,x1\n")?;
+        load_csv(&csv, "csv_unique", "t", 4)?;
+        assert_eq!(row_count("t"), 4);
+
+        let result = query_sql("SELECT a FROM t WHERE b = 'x1' ORDER BY a;")?;
+        let a = |i| vec![Some(ColumnVal::Int(i))];
+        assert_eq!(result.rows, [a(2), a(4)]);
+        let result = query_sql("SELECT a FROM t ORDER BY b;")?;
+        assert_eq!(result.rows, [a(1), a(3), a(2), a(4)]);
+        Ok(())
+    })
+}
+
+#[test]
+fn deferred_constraints_reject_whole_batch() -> DBResult<()> {
+    with_database("csv_deferred", || {
+        run("CREATE TABLE parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE child (pid INT, FOREIGN KEY (pid) REFERENCES parent (id));")?;
+        run("INSERT INTO parent VALUES (1), (2);")?;
+        run("SET CONSTRAINTS DEFERRED;")?;
+        let csv = BASE_DIR.join("csv_deferred.csv");
+        // 3 in the first batch has no parent, the second batch is fine
+        fs::write(&csv, "1\n2\n3\n1\n2\n2\n")?;
+        load_csv(&csv, "csv_deferred", "child", 3)?;
+        assert_eq!(row_count("child"), 3);
+
+        assert!(run("INSERT INTO child VALUES (1), (5);").is_err());
+        assert!(run("INSERT INTO parent VALUES (3), (3);").is_err());
+        assert!(run("INSERT INTO parent VALUES (4), (2);").is_err());
+        assert_eq!((row_count("child"), row_count("parent")), (3, 2));
+        run("INSERT INTO parent VALUES (3), (4);")?;
+        run("INSERT INTO child VALUES (4), (3), (4);")?;
+        assert_eq!((row_count("child"), row_count("parent")), (6, 4));
+
+        // keys are then looked up in the index
+        run("CREATE INDEX ON parent (id);")?;
+        assert!(run("INSERT INTO parent VALUES (5), (1);").is_err());
+        run("INSERT INTO parent VALUES (5), (6);")?;
+        assert_eq!(row_count("parent"), 6);
+        Ok(())
+    })
+}
+
+#[test]
+fn check_constraint_on_every_write_path() -> DBResult<()> {
+    with_database("csv_check", || {
+        run("CREATE TABLE t (a INT, s CHAR(4), CHECK (a IN (1, 2, 3)), CHECK (s IN ('x', 'y')));")?;
+        run("INSERT INTO t VALUES (1, 'x'), (NULL, NULL);")?;
+        assert!(run("INSERT INTO t VALUES (4, 'x');").is_err());
+        assert!(run("INSERT INTO t VALUES (2, 'z');").is_err());
+        assert!(run("UPDATE t SET a = 5 WHERE a = 1;").is_err());
+        assert!(run("UPDATE t SET a = a + 9 WHERE a = 1;").is_err());
+        run("UPDATE t SET a = a + 2 WHERE a = 1;")?;
+
+        let csv = BASE_DIR.join("csv_check.csv");
+        fs::write(&csv, "2,y\n7,x\n1,y\n")?;
+        load_csv(&csv, "csv_check", "t", LOAD_BATCH_SIZE)?;
+        assert_eq!(row_count("t"), 4);
+        let result = query_sql("SELECT a FROM t WHERE a = 7;")?;
+        assert!(result.rows.is_empty());
+        Ok(())
+    })
+}
+
+#[test]
+fn csv_load_reserves_exact_pages() -> DBResult<()> {
+    with_database("csv_reserve", || {
+        run("CREATE TABLE t (a INT, b INT);")?;
+        let id = database::get_table_id("t").unwrap();
+        let max_slot = database::ensure_table(id, |table| table.meta.max_slot()) as usize;
+        let n_rows = max_slot * 3 + 1;
+        let csv = BASE_DIR.join("csv_reserve.csv");
+        let content: String = (0..n_rows).map(|i| format!("{},{}\n", i, i)).collect();
+        fs::write(&csv, content)?;
+        load_csv(&csv, "csv_reserve", "t", 100)?;
+        assert_eq!(row_count("t"), n_rows);
+        let pages = storage::page_count(&BASE_DIR.join("csv_reserve").join("t.data"))?;
+        assert_eq!(pages, 4);
+        Ok(())
+    })
+}
+
+#[test]
+fn select_computed_expression() -> DBResult<()> {
+    with_database("select_expr", || {
+        run("CREATE TABLE t (price INT, qty FLOAT);")?;
+        run("INSERT INTO t VALUES (10, 1.5), (20, NULL);")?;
+        run("SELECT price * 2 AS twice, price + qty FROM t; SELECT 1 + 2;")?;
+
+        let id = database::get_table_id("t").unwrap();
+        let tables = [TableRef {
+            name: "t".to_owned(),
+            id,
+            index_hint: None,
+        }];
+        let mut rows = database::ensure_table(id, |table| table.rows().collect::<Vec<_>>());
+        rows.sort_unstable();
+        let eval = |expr: &str, row: &[_]| eval_expr(&parse_expr(expr), Row::Stored(&tables, row));
+        assert_eq!(eval("price * 2", &rows[..1])?, Some(ColumnVal::Int(20)));
+        assert_eq!(
+            eval("price + qty", &rows[..1])?,
+            Some(ColumnVal::Float(11.5))
+        );
+        assert_eq!(eval("price + qty", &rows[1..])?, None);
+        assert_eq!(eval("(1 + 2) * 3", &[])?, Some(ColumnVal::Int(9)));
+        assert!(eval("1 / 0", &[]).is_err());
+        assert!(eval("price + 'a'", &rows[..1]).is_err());
+        assert!(run("SELECT price * 2, COUNT(*) FROM t;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn string_functions() -> DBResult<()> {
+    with_database("string_functions", || {
+        run("CREATE TABLE t (id INT, name VARCHAR(10), code CHAR(4));")?;
+        run("INSERT INTO t VALUES (1, 'Alice', 'ab'), (2, 'Bernadette', 'CD'), (3, NULL, 'ef');")?;
+
+        let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+        let int = |i| Some(ColumnVal::Int(i));
+        let result =
+            query_sql("SELECT UPPER(name), lower(code), LENGTH(name) FROM t ORDER BY id;")?;
+        assert_eq!(
+            result.header,
+            ["UPPER(name)", "LOWER(code)", "LENGTH(name)"]
+        );
+        assert_eq!(
+            result.rows,
+            [
+                vec![str("ALICE"), Some(ColumnVal::Char("ab".to_owned())), int(5)],
+                vec![
+                    str("BERNADETTE"),
+                    Some(ColumnVal::Char("cd".to_owned())),
+                    int(10)
+                ],
+                vec![None, Some(ColumnVal::Char("ef".to_owned())), None],
+            ]
+        );
+
+        let ids = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        assert_eq!(ids("LENGTH(name) > 5")?, [int(2)]);
+        // unknown on NULL, so neither the predicate nor its negation holds
+        assert_eq!(ids("NOT LENGTH(name) > 5")?, [int(1)]);
+        assert_eq!(ids("UPPER(code) = 'AB' OR id = 3")?, [int(1), int(3)]);
+        assert_eq!(ids("code = LOWER(code)")?, [int(1), int(3)]);
+        assert_eq!(ids("id + 1 IN (2, 4)")?, [int(1), int(3)]);
+
+        let eval = |expr: &str| eval_expr(&parse_expr(expr), Row::Derived("", &[], &[]));
+        assert_eq!(eval("SUBSTR('abcdef', 2)")?, str("bcdef"));
+        assert_eq!(eval("SUBSTR('abcdef', 2, 3)")?, str("bcd"));
+        assert_eq!(eval("SUBSTR('abcdef', 0, 2)")?, str("a"));
+        assert_eq!(eval("SUBSTR('abc', 5)")?, str(""));
+        assert_eq!(eval("SUBSTR(NULL, 1)")?, None);
+        assert!(eval("SUBSTR('abc', 1, -1)").is_err());
+        assert!(eval("SUBSTR('abc', 'b')").is_err());
+        assert!(eval("UPPER(1)").is_err());
+        assert!(eval("LENGTH(1.5)").is_err());
+        assert!(eval("LENGTH('a', 'b')").is_err());
+        assert!(eval("NOSUCH('a')").is_err());
+
+        run("UPDATE t SET name = UPPER(name) WHERE LENGTH(name) < 6;")?;
+        let result = query_sql("SELECT name FROM t WHERE id = 1;")?;
+        assert_eq!(result.rows, [[str("ALICE")]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn numeric_and_date_functions() -> DBResult<()> {
+    with_database("numeric_functions", || {
+        run("CREATE TABLE emp (id INT, delta INT, salary FLOAT, hired DATE);")?;
+        run(
+            "INSERT INTO emp VALUES (1, -5, 1234.567, '2019-03-15'), (2, 7, -0.5, '2021-12-01'), \
+            (3, NULL, NULL, NULL);",
+        )?;
+
+        let int = |i| Some(ColumnVal::Int(i));
+        let float = |f| Some(ColumnVal::Float(f));
+        let result = query_sql(
+            "SELECT ABS(delta), ROUND(salary, 1), YEAR(hired), MONTH(hired), DAY(hired) \
+            FROM emp ORDER BY id;",
+        )?;
+        assert_eq!(
+            result.rows,
+            [
+                vec![int(5), float(1234.6), int(2019), int(3), int(15)],
+                vec![int(7), float(-0.5), int(2021), int(12), int(1)],
+                vec![None, None, None, None, None],
+            ]
+        );
+        let result = query_sql("SELECT id FROM emp WHERE YEAR(hired) > 2020;")?;
+        assert_eq!(result.rows, [[int(2)]]);
+
+        let eval = |expr: &str| eval_expr(&parse_expr(expr), Row::Derived("", &[], &[]));
+        assert_eq!(eval("ABS(-1.5)")?, float(1.5));
+        assert_eq!(eval("ROUND(2.5)")?, float(3.0));
+        assert_eq!(eval("ROUND(-2.5)")?, float(-3.0));
+        assert_eq!(eval("ROUND(17)")?, int(17));
+        assert_eq!(eval("ROUND(1250, -2)")?, int(1300));
+        assert_eq!(eval("ROUND(-1249, -2)")?, int(-1200));
+        assert_eq!(eval("ROUND(5, -20)")?, int(0));
+        assert_eq!(eval("ROUND(NULL, 2)")?, None);
+        assert!(eval("ABS(-2147483647 - 1)").is_err());
+        assert!(eval("ABS('a')").is_err());
+        assert!(eval("ROUND(1.5, 0.5)").is_err());
+        assert!(eval("ROUND(1, 2, 3)").is_err());
+        assert!(eval("YEAR(2020)").is_err());
+        assert!(eval("MONTH()").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn coalesce_and_ifnull() -> DBResult<()> {
+    with_database("coalesce", || {
+        run("CREATE TABLE t (id INT, name VARCHAR(10), nick VARCHAR(10), score INT);")?;
+        run(
+            "INSERT INTO t VALUES (1, 'ann', 'a', 9), (2, 'bob', NULL, NULL), (3, NULL, NULL, 3);",
+        )?;
+
+        let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+        let int = |i| Some(ColumnVal::Int(i));
+        let result =
+            query_sql("SELECT COALESCE(nick, name, 'anon'), IFNULL(score, 0) FROM t ORDER BY id;")?;
+        assert_eq!(
+            result.rows,
+            [
+                vec![str("a"), int(9)],
+                vec![str("bob"), int(0)],
+                vec![str("anon"), int(3)],
+            ]
+        );
+        let result = query_sql("SELECT id FROM t WHERE IFNULL(score, 5) >= 5 ORDER BY id;")?;
+        assert_eq!(result.rows, [[int(1)], [int(2)]]);
+
+        let eval = |expr: &str| eval_expr(&parse_expr(expr), Row::Derived("", &[], &[]));
+        assert_eq!(eval("COALESCE(NULL, NULL)")?, None);
+        // later arguments are not evaluated
+        assert_eq!(eval("COALESCE(1, 1 / 0)")?, int(1));
+        assert!(eval("COALESCE(NULL, 1 / 0)").is_err());
+        assert!(eval("COALESCE(NULL, 1, 'a')").is_err());
+        assert!(eval("IFNULL(1)").is_err());
+        assert!(eval("IFNULL(1, 2, 3)").is_err());
+        assert!(eval("COALESCE()").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn cast_between_types() -> DBResult<()> {
+    let eval = |expr: &str| eval_expr(&parse_expr(expr), Row::Derived("", &[], &[]));
+    let int = |i| Some(ColumnVal::Int(i));
+    let float = |f| Some(ColumnVal::Float(f));
+    let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+    let date = |s| Some(ColumnVal::Date(parse_date(s).unwrap()));
+    assert_eq!(eval("CAST(3 AS FLOAT)")?, float(3.0));
+    assert_eq!(eval("CAST(2.5 AS INT)")?, int(3));
+    assert_eq!(eval("CAST(-2.5 AS INT)")?, int(-3));
+    assert_eq!(eval("CAST(42 AS VARCHAR)")?, str("42"));
+    assert_eq!(
+        eval("CAST(1.5 AS CHAR)")?,
+        Some(ColumnVal::Char("1.5".to_owned()))
+    );
+    assert_eq!(eval("CAST(' 12 ' AS INT)")?, int(12));
+    assert_eq!(eval("CAST('0.25' AS FLOAT)")?, float(0.25));
+    assert_eq!(eval("CAST('2022-03-04' AS DATE)")?, date("2022-03-04"));
+    assert_eq!(
+        eval("CAST(CAST('2022/03/04' AS DATE) AS VARCHAR)")?,
+        str("2022-03-04")
+    );
+    assert_eq!(eval("CAST(NULL AS INT)")?, None);
+    assert!(eval("CAST('abc' AS INT)").is_err());
+    assert!(eval("CAST('1.5' AS INT)").is_err());
+    assert!(eval("CAST('x' AS FLOAT)").is_err());
+    assert!(eval("CAST('2022-13-01' AS DATE)").is_err());
+    assert!(eval("CAST(1 AS DATE)").is_err());
+    assert!(eval("CAST(3000000000.0 AS INT)").is_err());
+
+    with_database("cast", || {
+        run("CREATE TABLE t (id INT, code VARCHAR(10));")?;
+        run("INSERT INTO t VALUES (1, '1'), (2, '12'), (3, NULL);")?;
+        // compared as numbers rather than as strings
+        let result = query_sql("SELECT id, CAST(code AS INT) FROM t WHERE CAST(code AS INT) > 8;")?;
+        assert_eq!(result.header, ["id", "CAST(code AS INT)"]);
+        assert_eq!(result.rows, [[int(2), int(12)]]);
+        let result = query_sql("SELECT id FROM t WHERE CAST(id AS VARCHAR) = code;")?;
+        assert_eq!(result.rows, [[int(1)]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn system_columns_table() -> DBResult<()> {
+    with_database("system_columns", || {
+        run("CREATE TABLE item (id INT NOT NULL, label VARCHAR(20), PRIMARY KEY (id));")?;
+        run("CREATE TABLE other (price FLOAT);")?;
+
+        let result = query_sql(
+            "SELECT column_name, data_type, not_null, primary_key FROM __columns \
+            WHERE table_name = 'item';",
+        )?;
+        let text = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+        assert_eq!(
+            result.rows,
+            [
+                vec![text("id"), text("Int"), text("Yes"), text("Yes")],
+                vec![text("label"), text("VarChar(20)"), text("No"), text("No")],
+            ]
+        );
+        let result =
+            query_sql("SELECT c.table_name FROM __columns c WHERE c.data_type = 'Float';")?;
+        assert_eq!(result.rows, [vec![text("other")]]);
+        run("SELECT * FROM __columns;")?;
+        assert!(run("CREATE TABLE __columns (a INT);").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn system_indexes_and_foreign_keys() -> DBResult<()> {
+    with_database("system_keys", || {
+        run("CREATE TABLE parent (id INT NOT NULL, name VARCHAR(10), PRIMARY KEY (id));")?;
+        run("CREATE TABLE child (pid INT, score INT, \
+            FOREIGN KEY (pid) REFERENCES parent (id));")?;
+        run("INSERT INTO parent VALUES (1, 'a'), (2, 'b');")?;
+        run("INSERT INTO child VALUES (1, 10), (1, 20), (2, 30);")?;
+        run("CREATE INDEX ON child (score, pid);")?;
+
+        let text = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+        let result = query_sql(
+            "SELECT is_unique, entries FROM __indexes WHERE column_names = 'score, pid';",
+        )?;
+        assert_eq!(result.rows, [vec![text("No"), Some(ColumnVal::Int(3))]]);
+
+        let result = query_sql("SELECT * FROM __foreign_keys;")?;
+        assert_eq!(
+            result.header,
+            [
+                "table_name",
+                "column_names",
+                "ref_table_name",
+                "ref_column_names",
+                "on_delete",
+                "on_update"
+            ]
+        );
+        assert_eq!(
+            result.rows,
+            [vec![
+                text("child"),
+                text("pid"),
+                text("parent"),
+                text("id"),
+                text("RESTRICT"),
+                text("RESTRICT"),
+            ]]
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn not_excludes_nulls() -> DBResult<()> {
+    with_database("not_nulls", || {
+        run("CREATE TABLE t (name VARCHAR(10), score INT);")?;
+        run("INSERT INTO t VALUES ('ann', 1), ('bob', 2), ('cat', NULL), ('amy', 3), (NULL, 4);")?;
+
+        let scores = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT score FROM t WHERE {} ORDER BY score;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+        assert_eq!(scores("score NOT IN (1, 2)")?, [int(3), int(4)]);
+        assert_eq!(scores("NOT (score IN (1, 2))")?, [int(3), int(4)]);
+        // unknown for every row, no row is known not to be NULL
+        assert_eq!(scores("score NOT IN (1, NULL)")?, []);
+        assert_eq!(scores("score IN (1, NULL)")?, [int(1)]);
+        assert_eq!(scores("score IS NOT NULL")?.len(), 4);
+        assert_eq!(
+            scores("NOT (score IS NULL) AND score IN (2, 3)")?,
+            [int(2), int(3)]
+        );
+        assert_eq!(
+            scores("name NOT LIKE 'a%' AND score IS NOT NULL")?,
+            [int(2)]
+        );
+        assert_eq!(scores("NOT (name LIKE 'a%' OR score IN (1, 3))")?, [int(2)]);
+        assert_eq!(scores("NOT NOT (name LIKE 'a%')")?, [int(1), int(3)]);
+        Ok(())
+    })
+}
+
+#[test]
+fn like_with_escape() -> DBResult<()> {
+    with_database("like_escape", || {
+        run("CREATE TABLE t (id INT, code VARCHAR(10));")?;
+        run("INSERT INTO t VALUES (1, '10%'), (2, '100'), (3, 'a_b'), (4, 'a!b'), (5, 'a\\b');")?;
+
+        let ids = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+        assert_eq!(ids("code LIKE '10%'")?, [int(1), int(2)]);
+        assert_eq!(ids("code LIKE '10!%' ESCAPE '!'")?, [int(1)]);
+        assert_eq!(
+            ids("code NOT LIKE '%!%' ESCAPE '!'")?,
+            [int(2), int(3), int(4), int(5)]
+        );
+        assert_eq!(ids("code LIKE 'a!_b' ESCAPE '!'")?, [int(3)]);
+        assert_eq!(ids("code LIKE 'a!!b' ESCAPE '!'")?, [int(4)]);
+        // a backslash is an ordinary character when another escape is given
+        assert_eq!(ids("code LIKE 'a\\b' ESCAPE '!'")?, [int(5)]);
+        // evaluated on rows rather than filtered by the table
+        assert_eq!(
+            ids("id > 0 AND (code LIKE '%!%' ESCAPE '!' OR id = 3)")?,
+            [int(1), int(3)]
+        );
+        assert!(ids("code LIKE 'a!' ESCAPE '!'").is_err());
+        assert!(ids("code LIKE 'a' ESCAPE '!!'").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn ilike_ignores_case() -> DBResult<()> {
+    with_database("ilike", || {
+        run("CREATE TABLE t (id INT, name VARCHAR(10), pattern VARCHAR(10));")?;
+        run("INSERT INTO t VALUES (1, 'Apple', 'A%'), (2, 'apple', 'A%'), (3, 'APPLE_', '%e!_'), (4, 'pear', NULL);")?;
+
+        let ids = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+        assert_eq!(ids("name LIKE 'app%'")?, [int(2)]);
+        assert_eq!(ids("name ILIKE 'app%'")?, [int(1), int(2), int(3)]);
+        assert_eq!(ids("name NOT LIKE 'APP%'")?, [int(1), int(2), int(4)]);
+        assert_eq!(ids("name NOT ILIKE 'APP%'")?, [int(4)]);
+        assert_eq!(ids("name ILIKE '%E!_' ESCAPE '!'")?, [int(3)]);
+        // evaluated on rows rather than filtered by the table
+        assert_eq!(
+            ids("id = 4 OR name ILIKE 'apple'")?,
+            [int(1), int(2), int(4)]
+        );
+        assert_eq!(ids("name LIKE pattern")?, [int(1)]);
+        assert_eq!(ids("name ILIKE pattern")?, [int(1), int(2)]);
+        assert_eq!(ids("NOT name ILIKE pattern")?, [int(3)]);
+        Ok(())
+    })
+}
+
+#[test]
+fn show_tables_like() -> DBResult<()> {
+    with_database("show_like", || {
+        run("CREATE TABLE orders (id INT); CREATE TABLE order_items (id INT); CREATE TABLE users (id INT);")?;
+        run("SHOW TABLES LIKE 'order%'; SHOW DATABASES LIKE 'show%';")?;
+
+        let tables = |pattern| -> DBResult<Vec<_>> {
+            let mut names = database::table_names(pattern)?;
+            names.sort();
+            Ok(names)
+        };
+        assert_eq!(tables(None)?, ["order_items", "orders", "users"]);
+        assert_eq!(tables(Some("order%"))?, ["order_items", "orders"]);
+        assert_eq!(tables(Some("order\\_%"))?, ["order_items"]);
+        assert!(tables(Some("x%"))?.is_empty());
+
+        let dbs = database::database_names(Some("show\\_%"))?;
+        assert_eq!(dbs, ["show_like"]);
+        Ok(())
+    })
+}
+
+#[test]
+fn null_comparisons_are_unknown() -> DBResult<()> {
+    with_database("three_valued", || {
+        run("CREATE TABLE t (id INT, a INT, b INT);")?;
+        run("INSERT INTO t VALUES (1, 1, 1), (2, 1, NULL), (3, NULL, 2), (4, NULL, NULL);")?;
+
+        let ids = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+        assert_eq!(ids("a <> 2")?, [int(1), int(2)]);
+        assert_eq!(ids("a < 2")?, [int(1), int(2)]);
+        assert_eq!(ids("a = NULL")?, []);
+        assert_eq!(ids("a <> NULL")?, []);
+        assert_eq!(ids("a = b")?, [int(1)]);
+        assert_eq!(ids("NOT (a = b)")?, []);
+        // unknown OR true is true, unknown AND false is false
+        assert_eq!(ids("a = 1 OR b = 2")?, [int(1), int(2), int(3)]);
+        assert_eq!(ids("NOT (a = 1 AND b = 2)")?, [int(1)]);
+        assert_eq!(ids("NOT (a = 1 OR b = 1)")?, []);
+        assert_eq!(ids("NOT (a <> 1) OR b IS NULL")?, [int(1), int(2), int(4)]);
+        Ok(())
+    })
+}
+
+#[test]
+fn null_compares_with_no_operator() -> DBResult<()> {
+    with_database("null_operators", || {
+        run("CREATE TABLE t (id INT, a INT);")?;
+        run("INSERT INTO t VALUES (1, 1), (2, 2), (3, NULL);")?;
+        run("CREATE TABLE u (id INT, a INT);")?;
+        run("INSERT INTO u VALUES (10, 2), (20, NULL);")?;
+
+        let count = |sql: String| -> DBResult<usize> { Ok(query_sql(&sql)?.rows.len()) };
+        // rows where a comparison is true or false, not those where it is unknown
+        let known = |from: &str, cond: &str| -> DBResult<usize> {
+            let matched = count(format!("SELECT * FROM {} WHERE {};", from, cond))?;
+            Ok(matched + count(format!("SELECT * FROM {} WHERE NOT ({});", from, cond))?)
+        };
+        let check = || -> DBResult<()> {
+            for op in &["=", "<>", "<", ">", "<=", ">="] {
+                assert_eq!(known("t", &format!("a {} NULL", op))?, 0, "{}", op);
+                assert_eq!(known("t", &format!("a {} 2", op))?, 2, "{}", op);
+                assert_eq!(known("t", &format!("a {} id", op))?, 2, "{}", op);
+                assert_eq!(known("t, u", &format!("t.a {} u.a", op))?, 2, "{}", op);
+            }
+            Ok(())
+        };
+        check()?;
+        run("CREATE INDEX ON t (a);")?;
+        run("CREATE INDEX ON u (a);")?;
+        check()
+    })
+}
+
+#[test]
+fn null_safe_equality() -> DBResult<()> {
+    with_database("null_safe_eq", || {
+        run("CREATE TABLE t (id INT, a INT, b INT);")?;
+        run("INSERT INTO t VALUES (1, 1, 1), (2, 1, NULL), (3, NULL, 2), (4, NULL, NULL);")?;
+        run("CREATE TABLE u (id INT, a INT);")?;
+        run("INSERT INTO u VALUES (10, 1), (20, NULL);")?;
+
+        let ids = |cond: &str| -> DBResult<Vec<_>> {
+            let sql = format!("SELECT id FROM t WHERE {} ORDER BY id;", cond);
+            let result = query_sql(&sql)?;
+            Ok(result.rows.into_iter().map(|row| row[0].clone()).collect())
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+        let check = || -> DBResult<()> {
+            assert_eq!(ids("a <=> NULL")?, [int(3), int(4)]);
+            assert_eq!(ids("a <=> 1")?, [int(1), int(2)]);
+            assert_eq!(ids("a <=> b")?, [int(1), int(4)]);
+            assert_eq!(ids("a IS NOT DISTINCT FROM b")?, [int(1), int(4)]);
+            assert_eq!(ids("a IS DISTINCT FROM b")?, [int(2), int(3)]);
+            assert_eq!(ids("NOT (a <=> 1)")?, [int(3), int(4)]);
+            assert_eq!(ids("a + 0 <=> b")?, [int(1), int(4)]);
+
+            let result = query_sql("SELECT t.id, u.id FROM t, u WHERE t.a <=> u.a ORDER BY t.id;")?;
+            assert_eq!(
+                result.rows,
+                [
+                    vec![int(1), int(10)],
+                    vec![int(2), int(10)],
+                    vec![int(3), int(20)],
+                    vec![int(4), int(20)],
+                ]
+            );
+            Ok(())
+        };
+        check()?;
+        // the same rows are found by an index
+        run("CREATE INDEX ON t (a);")?;
+        run("CREATE INDEX ON u (a);")?;
+        check()
+    })
+}
+
+#[test]
+fn update_with_row_expression() -> DBResult<()> {
+    with_database("update_expr", || {
+        run("CREATE TABLE stock (name VARCHAR(10), qty INT, price FLOAT);")?;
+        run("INSERT INTO stock VALUES ('a', 1, 1.5), ('b', 5, 2.0), ('c', NULL, 3.0);")?;
+        run("UPDATE stock SET qty = qty + 1 WHERE price > 1.0;")?;
+        run("UPDATE stock SET price = qty * 2 WHERE name = 'b';")?;
+
+        let result = query_sql("SELECT qty, price FROM stock ORDER BY name;")?;
+        assert_eq!(
+            result.rows,
+            [
+                vec![Some(ColumnVal::Int(2)), Some(ColumnVal::Float(1.5))],
+                vec![Some(ColumnVal::Int(6)), Some(ColumnVal::Float(12.0))],
+                vec![None, Some(ColumnVal::Float(3.0))],
+            ]
+        );
+        assert!(run("UPDATE stock SET qty = price WHERE name = 'a';").is_err());
+        assert!(run("UPDATE stock SET name = name + 1 WHERE name = 'a';").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn update_checks_not_null_and_unique() -> DBResult<()> {
+    with_database("update_constraints", || {
+        run("CREATE TABLE users (id INT NOT NULL, email VARCHAR(20) UNIQUE, PRIMARY KEY (id));")?;
+        run("INSERT INTO users VALUES (1, 'a@x'), (2, 'b@x');")?;
+
+        assert!(run("UPDATE users SET id = NULL WHERE id = 1;").is_err());
+        // NULL computed per row is rejected as well
+        assert!(run("UPDATE users SET id = CASE WHEN id > 1 THEN id END WHERE id < 3;").is_err());
+        assert!(run("UPDATE users SET email = 'b@x' WHERE id = 1;").is_err());
+        // keeping its own value is not a duplicate
+        run("UPDATE users SET email = 'a@x' WHERE id = 1;")?;
+        run("UPDATE users SET id = id WHERE id = 2;")?;
+
+        let result = query_sql("SELECT id, email FROM users ORDER BY id;")?;
+        let row = |id, email: &str| {
+            vec![
+                Some(ColumnVal::Int(id)),
+                Some(ColumnVal::Varchar(email.to_owned())),
+            ]
+        };
+        assert_eq!(result.rows, [row(1, "a@x"), row(2, "b@x")]);
+        Ok(())
+    })
+}
+
+#[test]
+fn insert_rejects_duplicates_within_statement() -> DBResult<()> {
+    with_database("insert_batch_unique", || {
+        run("CREATE TABLE t (id INT NOT NULL, name VARCHAR(10), PRIMARY KEY (id));")?;
+        run("INSERT INTO t VALUES (1, 'a');")?;
+
+        // nothing is inserted, neither before nor after the duplicate
+        assert!(run("INSERT INTO t VALUES (2, 'b'), (3, 'c'), (2, 'd'), (4, 'e');").is_err());
+        assert!(run("INSERT INTO t VALUES (5, 'f'), (1, 'g');").is_err());
+        assert_eq!(row_count("t"), 1);
+        run("INSERT INTO t VALUES (2, 'b'), (3, 'c');")?;
+        assert_eq!(row_count("t"), 3);
+
+        // NULL equals nothing, so rows with NULL in a unique column do not collide
+        run("CREATE TABLE u (a INT, UNIQUE (a));")?;
+        run("INSERT INTO u VALUES (NULL), (1), (NULL);")?;
+        assert!(run("INSERT INTO u VALUES (2), (2);").is_err());
+        assert_eq!(row_count("u"), 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn update_cascades_to_referencing_column() -> DBResult<()> {
+    with_database("cascade_update", || {
+        run("CREATE TABLE parent (name VARCHAR(10), id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE child (note VARCHAR(10), pid INT, \
+            FOREIGN KEY (pid) REFERENCES parent (id) ON UPDATE CASCADE);")?;
+        run("INSERT INTO parent VALUES ('a', 1), ('b', 2), ('c', 3);")?;
+        run("INSERT INTO child VALUES ('x', 1), ('y', 2), ('z', 2), ('w', 3);")?;
+        run("UPDATE parent SET id = id + 10 WHERE id < 3;")?;
+
+        let result = query_sql("SELECT note, pid FROM child ORDER BY note;")?;
+        let row = |note: &str, pid| {
+            vec![
+                Some(ColumnVal::Varchar(note.to_owned())),
+                Some(ColumnVal::Int(pid)),
+            ]
+        };
+        assert_eq!(
+            result.rows,
+            [row("w", 3), row("x", 11), row("y", 12), row("z", 12)]
+        );
+        // the index on the referencing column follows the update
+        let result = query_sql("SELECT note FROM child WHERE pid = 12;")?;
+        assert_eq!(result.rows.len(), 2);
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_key_restrict_and_cascade() -> DBResult<()> {
+    with_database("foreign_actions", || {
+        run("CREATE TABLE parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE kept (pid INT, FOREIGN KEY (pid) REFERENCES parent (id));")?;
+        run("CREATE TABLE owned (pid INT);")?;
+        run(
+            "ALTER TABLE owned ADD CONSTRAINT FOREIGN KEY (pid) REFERENCES parent (id) \
+            ON DELETE CASCADE ON UPDATE RESTRICT;",
+        )?;
+        run("INSERT INTO parent VALUES (1), (2), (3);")?;
+        run("INSERT INTO kept VALUES (1);")?;
+        run("INSERT INTO owned VALUES (2), (2), (3);")?;
+
+        // RESTRICT by default, rejected without deleting or updating anything
+        assert!(run("DELETE FROM parent WHERE id < 3;").is_err());
+        assert!(run("UPDATE parent SET id = 4 WHERE id = 1;").is_err());
+        assert!(run("UPDATE parent SET id = 5 WHERE id = 3;").is_err());
+        assert_eq!(row_count("parent"), 3);
+
+        run("DELETE FROM parent WHERE id = 2;")?;
+        assert_eq!(row_count("parent"), 2);
+        assert_eq!(row_count("owned"), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_key_delete_set_null() -> DBResult<()> {
+    with_database("foreign_set_null", || {
+        run("CREATE TABLE parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        assert!(run("CREATE TABLE strict (pid INT NOT NULL, \
+            FOREIGN KEY (pid) REFERENCES parent (id) ON DELETE SET NULL);")
+        .is_err());
+        run("CREATE TABLE child (note VARCHAR(10), pid INT, \
+            FOREIGN KEY (pid) REFERENCES parent (id) ON DELETE SET NULL);")?;
+        run("CREATE INDEX ON child (pid);")?;
+        run("INSERT INTO parent VALUES (1), (2);")?;
+        run("INSERT INTO child VALUES ('x', 1), ('y', 2), ('z', 2);")?;
+        // every duplicate key is found through the index
+        assert_eq!(
+            query_sql("SELECT note FROM child WHERE pid = 2;")?
+                .rows
+                .len(),
+            2
+        );
+        assert_eq!(
+            query_sql("SELECT note FROM child WHERE pid >= 2;")?
+                .rows
+                .len(),
+            2
+        );
+        run("DELETE FROM parent WHERE id = 2;")?;
+
+        assert_eq!(row_count("child"), 3);
+        let result = query_sql("SELECT note, pid FROM child ORDER BY note;")?;
+        let row = |note: &str, pid| vec![Some(ColumnVal::Varchar(note.to_owned())), pid];
+        let pid = Some(ColumnVal::Int(1));
+        assert_eq!(result.rows, [row("x", pid), row("y", None), row("z", None)]);
+        let text = |s: &str| vec![Some(ColumnVal::Varchar(s.to_owned()))];
+        // the index no longer finds the nulled rows under the deleted key
+        assert!(query_sql("SELECT note FROM child WHERE pid = 2;")?
+            .rows
+            .is_empty());
+        assert_eq!(
+            query_sql("SELECT note FROM child WHERE pid = 1;")?.rows,
+            [text("x")]
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_key_actions_reach_grandchildren() -> DBResult<()> {
+    with_database("foreign_grandchildren", || {
+        run("CREATE TABLE a (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE b (id INT NOT NULL, aid INT, PRIMARY KEY (id), \
+            FOREIGN KEY (aid) REFERENCES a (id) ON DELETE CASCADE);")?;
+        run("CREATE TABLE c (bid INT, FOREIGN KEY (bid) REFERENCES b (id) ON DELETE CASCADE);")?;
+        run("CREATE TABLE d (bid INT, FOREIGN KEY (bid) REFERENCES b (id) ON DELETE SET NULL);")?;
+        // both foreign keys find the same rows, which are deleted once
+        run("CREATE TABLE e (x INT, y INT, \
+            FOREIGN KEY (x) REFERENCES a (id) ON DELETE CASCADE, \
+            FOREIGN KEY (y) REFERENCES a (id) ON DELETE CASCADE);")?;
+        run("INSERT INTO a VALUES (1), (2);")?;
+        run("INSERT INTO b VALUES (10, 1), (20, 2);")?;
+        run("INSERT INTO c VALUES (10), (10), (20);")?;
+        run("INSERT INTO d VALUES (10), (20);")?;
+        run("INSERT INTO e VALUES (1, 1), (2, 2);")?;
+
+        run("DELETE FROM a WHERE id = 1;")?;
+        let counts: Vec<_> = ["a", "b", "c", "d", "e"].map(row_count).into();
+        assert_eq!(counts, [1, 1, 1, 2, 1]);
+        let result = query_sql("SELECT bid FROM d ORDER BY bid;")?;
+        assert_eq!(result.rows, [vec![Some(ColumnVal::Int(20))], vec![None]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn set_null_updates_grandchildren() -> DBResult<()> {
+    with_database("set_null_grandchildren", || {
+        run("CREATE TABLE p (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE q (pid INT, FOREIGN KEY (pid) REFERENCES p (id) ON DELETE SET NULL);")?;
+        run("CREATE TABLE r (qp INT, FOREIGN KEY (qp) REFERENCES q (pid) ON UPDATE CASCADE);")?;
+        run("CREATE TABLE s (qp INT, FOREIGN KEY (qp) REFERENCES q (pid));")?;
+        run("INSERT INTO p VALUES (1), (2);")?;
+        run("INSERT INTO q VALUES (1), (2);")?;
+        run("INSERT INTO r VALUES (1), (2);")?;
+        run("INSERT INTO s VALUES (2);")?;
+
+        // the referenced value of r goes to NULL, and r follows it
+        run("DELETE FROM p WHERE id = 1;")?;
+        let int = |i| Some(ColumnVal::Int(i));
+        let result = query_sql("SELECT qp FROM r ORDER BY qp;")?;
+        assert_eq!(result.rows, [vec![int(2)], vec![None]]);
+        // which s restricts
+        assert!(run("DELETE FROM p WHERE id = 2;").is_err());
+        assert_eq!(row_count("p"), 1);
+        let result = query_sql("SELECT pid FROM q ORDER BY pid;")?;
+        assert_eq!(result.rows, [vec![int(2)], vec![None]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_key_null_references_nothing() -> DBResult<()> {
+    with_database("foreign_null", || {
+        run("CREATE TABLE parent (id INT, k INT UNIQUE);")?;
+        run("CREATE TABLE owned (id INT, pk INT, \
+            FOREIGN KEY (pk) REFERENCES parent (k) ON DELETE CASCADE ON UPDATE CASCADE);")?;
+        run("CREATE TABLE kept (pk INT, FOREIGN KEY (pk) REFERENCES parent (k));")?;
+        run("INSERT INTO parent VALUES (1, 1), (2, NULL);")?;
+        run("INSERT INTO owned VALUES (10, 1), (20, NULL);")?;
+        run("INSERT INTO kept VALUES (NULL);")?;
+
+        run("DELETE FROM parent WHERE id = 2;")?;
+        run("INSERT INTO parent VALUES (3, NULL);")?;
+        run("UPDATE parent SET k = 5 WHERE id = 3;")?;
+        let result = query_sql("SELECT id, pk FROM owned ORDER BY id;")?;
+        let int = |i| Some(ColumnVal::Int(i));
+        assert_eq!(result.rows, [vec![int(10), int(1)], vec![int(20), None]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn int_compares_with_float() -> DBResult<()> {
+    with_database("int_float", || {
+        run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a);")?;
+        run("CREATE TABLE f (x FLOAT);")?;
+        run("INSERT INTO t VALUES (1, 1), (2, 2), (3, 3), (NULL, 4);")?;
+        run("INSERT INTO f VALUES (1.5), (3.0);")?;
+        let ints = |sql: &str| -> DBResult<Vec<_>> {
+            let rows = query_sql(sql)?.rows;
+            let mut ints = rows
+                .into_iter()
+                .map(|row| match row[0] {
+                    Some(ColumnVal::Int(i)) => i,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>();
+            ints.sort_unstable();
+            Ok(ints)
+        };
+        for hint in ["", "/*+ INDEX(t a) */"] {
+            let sql = |cond| format!("SELECT {} b FROM t WHERE {};", hint, cond);
+            assert_eq!(ints(&sql("a > 1.5"))?, [2, 3]);
+            assert_eq!(ints(&sql("a <= 2.5"))?, [1, 2]);
+            assert_eq!(ints(&sql("a = 2.0"))?, [2]);
+            assert!(ints(&sql("a = 2.5"))?.is_empty());
+            assert_eq!(ints(&sql("a <> 2.5"))?, [1, 2, 3]);
+            assert_eq!(ints(&sql("a IN (1.0, 2.5, 3)"))?, [1, 3]);
+        }
+        assert_eq!(ints("SELECT b FROM t WHERE a + 0 >= 1.5;")?, [2, 3]);
+        // the index of t is looked up with FLOAT values of f
+        assert_eq!(ints("SELECT t.b FROM t, f WHERE t.a = f.x;")?, [3]);
+        assert_eq!(ints("SELECT t.b FROM t, f WHERE f.x < t.a;")?, [2, 3]);
+        Ok(())
+    })
+}
+
+#[test]
+fn rename_database() -> DBResult<()> {
+    with_database("rename_db", || {
+        run("CREATE TABLE t (a INT); CREATE INDEX ON t (a); INSERT INTO t VALUES (1), (2);")?;
+        database::create_database("rename_other")?;
+        // the database in use, a missing one, or onto an existing one
+        assert!(run("ALTER DATABASE rename_db RENAME TO rename_new;").is_err());
+        assert!(run("ALTER DATABASE rename_other RENAME TO rename_db;").is_err());
+        assert!(run("ALTER DATABASE rename_none RENAME TO rename_new;").is_err());
+
+        assert!(database::change_database("rename_other")?);
+        assert!(run("ALTER DATABASE rename_db RENAME TO rename_other;").is_err());
+        run("ALTER DATABASE rename_db RENAME TO rename_new;")?;
+        assert!(!database::change_database("rename_db")?);
+        assert!(database::change_database("rename_new")?);
+        let rows = query_sql("SELECT a FROM t WHERE a = 2;")?.rows;
+        assert_eq!(rows, [[Some(ColumnVal::Int(2))]]);
+        run("INSERT INTO t VALUES (3);")?;
+        assert_eq!(row_count("t"), 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn conflicting_constraints_rejected() -> DBResult<()> {
+    with_database("conflicting_constraints", || {
+        let rejects = |sql: &str, msg: &str| {
+            let err = run(sql).unwrap_err().to_string();
+            assert!(err.contains(msg), "{}: {}", sql, err);
+            assert!(database::get_table_id("t").is_none());
+        };
+        rejects(
+            "CREATE TABLE t (a INT NULL PRIMARY KEY);",
+            "cannot be declared NULL",
+        );
+        rejects(
+            "CREATE TABLE t (a INT NULL, b INT, PRIMARY KEY (b, a));",
+            "column a is in the primary key",
+        );
+        rejects(
+            "CREATE TABLE t (a INT PRIMARY KEY, b INT PRIMARY KEY);",
+            "single primary key",
+        );
+        rejects(
+            "CREATE TABLE t (a INT PRIMARY KEY, PRIMARY KEY (a));",
+            "single primary key",
+        );
+        rejects(
+            "CREATE TABLE t (a INT, a FLOAT);",
+            "column a is defined more than once",
+        );
+        rejects(
+            "CREATE TABLE t (a INT, PRIMARY KEY (a, a));",
+            "appears twice in PRIMARY KEY",
+        );
+        rejects(
+            "CREATE TABLE t (a INT, UNIQUE (a, a));",
+            "appears twice in UNIQUE",
+        );
+        rejects(
+            "CREATE TABLE t (a INT NOT NULL, CHECK (a IN (1, 2)), CHECK (a IN (3)));",
+            "allow no value",
+        );
+        // a constraint may come before the primary key it conflicts with
+        run("CREATE TABLE p (id INT PRIMARY KEY);")?;
+        rejects(
+            "CREATE TABLE t (a INT, FOREIGN KEY (a) REFERENCES p (id) ON DELETE SET NULL, \
+            PRIMARY KEY (a));",
+            "requires column a to be nullable",
+        );
+
+        // NULL is the default, and a nullable column may allow no other value
+        run("CREATE TABLE t (a INT NULL UNIQUE, b INT, CHECK (b IN (1)), CHECK (b IN (2)));")?;
+        run("INSERT INTO t VALUES (1, NULL);")?;
+        assert!(run("INSERT INTO t VALUES (2, 1);").is_err());
+        run("CREATE TABLE u (a INT PRIMARY KEY);")?;
+        run("INSERT INTO u VALUES (1);")?;
+        assert!(run("INSERT INTO u VALUES (1);").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn foreign_key_columns_must_match() -> DBResult<()> {
+    with_database("foreign_columns", || {
+        run("CREATE TABLE parent (id INT NOT NULL, code VARCHAR(4), PRIMARY KEY (id));")?;
+        // mismatched arity
+        assert!(
+            run("CREATE TABLE a (x INT, y INT, FOREIGN KEY (x, y) REFERENCES parent (id));")
+                .is_err()
+        );
+        // mismatched types
+        assert!(run("CREATE TABLE b (x FLOAT, FOREIGN KEY (x) REFERENCES parent (id));").is_err());
+        run("CREATE TABLE c (x VARCHAR(4), y INT);")?;
+        assert!(
+            run("ALTER TABLE c ADD CONSTRAINT FOREIGN KEY (x) REFERENCES parent (id);").is_err()
+        );
+        assert!(
+            run("ALTER TABLE c ADD CONSTRAINT FOREIGN KEY (x, y) REFERENCES parent (id);").is_err()
+        );
+        run("ALTER TABLE c ADD CONSTRAINT FOREIGN KEY (y) REFERENCES parent (id);")?;
+        Ok(())
+    })
+}
+
+#[test]
+fn metadata_of_another_build_is_refused() -> DBResult<()> {
+    with_database("meta_header", || {
+        run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a, b);")?;
+        database::write_back()?;
+        let file = BASE_DIR
+            .join("meta_header")
+            .join(TableMeta::format_meta_filename("t"));
+        assert!(TableMeta::load(&file).is_ok());
+
+        // as if written by a build with a larger MAX_COMP_INDEX, the header is
+        // 4 bytes of magic followed by the format version and the constant
+        let original = storage::read(&file)?;
+        let mut bytes = original.clone();
+        let max_comp_index = MAX_COMP_INDEX as u32 + 1;
+        bytes[8..12].copy_from_slice(&max_comp_index.to_le_bytes());
+        storage::write(&file, &bytes)?;
+        let err = TableMeta::load(&file).unwrap_err().to_string();
+        assert!(err.contains(&format!("MAX_COMP_INDEX = {}", max_comp_index)));
+
+        // or by a build with a newer format
+        let mut bytes = original;
+        bytes[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        storage::write(&file, &bytes)?;
+        let err = TableMeta::load(&file).unwrap_err().to_string();
+        assert!(err.contains(&format!("format version {}", u32::MAX)));
+
+        bytes[..4].copy_from_slice(b"\0\0\0\0");
+        storage::write(&file, &bytes)?;
+        assert!(TableMeta::load(&file).is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn slot_layout_after_reload() -> DBResult<()> {
+    with_database("slot_layout", || {
+        let ints = (0..8).map(|i| format!("c{} INT", i)).collect::<Vec<_>>();
+        run(&format!(
+            "CREATE TABLE t ({}, v VARCHAR(10), d DATE, f FLOAT);",
+            ints.join(", ")
+        ))?;
+        // 11 columns take 2 bytes of null bits, a VARCHAR(10) is NUL-terminated
+        let check = || {
+            let id = database::get_table_id("t").unwrap();
+            database::ensure_table(id, |table| {
+                let meta = &table.meta;
+                let starts = (0..meta.colnum())
+                    .map(|col| meta.entry_range_within_slot(col).start)
+                    .collect::<Vec<_>>();
+                assert_eq!(starts, [2, 6, 10, 14, 18, 22, 26, 30, 34, 45, 49]);
+                assert_eq!(meta.entry_range_within_slot(9), 45..49);
+                assert_eq!(meta.nullbit_size(), 2);
+                assert_eq!(meta.slot_size(), 53);
+            })
+        };
+        check();
+        database::write_back()?;
+        assert!(database::change_database("slot_layout")?);
+        check();
+        Ok(())
+    })
+}
+
+#[test]
+fn row_count_from_page_headers() -> DBResult<()> {
+    with_database("row_count", || {
+        run("CREATE TABLE t (a INT);")?;
+        let id = database::get_table_id("t").unwrap();
+        let max_slot = database::ensure_table(id, |table| table.meta.max_slot()) as i32;
+        let values = (0..max_slot * 2 + 5)
+            .map(|i| format!("({})", i))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        run("DELETE FROM t WHERE a < 10;")?;
+        let count = database::ensure_table(id, |table| table.stored_row_count())?;
+        assert_eq!(count, max_slot as usize * 2 - 5);
+        assert_eq!(count, row_count("t"));
+        run("SELECT COUNT(*) FROM t;")?;
+        Ok(())
+    })
+}
+
+#[test]
+fn row_count_kept_by_every_write_path() -> DBResult<()> {
+    with_database("kept_row_count", || {
+        run("CREATE TABLE parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE child (pid INT, v VARCHAR(100)); \
+            ALTER TABLE child ADD CONSTRAINT FOREIGN KEY (pid) REFERENCES parent (id) \
+            ON DELETE CASCADE;")?;
+        let counts = |table| {
+            let id = database::get_table_id(table).unwrap();
+            database::ensure_table(id, |table| -> DBResult<_> {
+                Ok((table.row_count(), table.stored_row_count()?))
+            })
+        };
+
+        let parents = (0..50).map(|i| format!("({})", i)).collect::<Vec<_>>();
+        run(&format!(
+            "INSERT INTO parent VALUES {};",
+            parents.join(", ")
+        ))?;
+        let children = (0..500)
+            .map(|i| format!("({}, 'child {}')", i % 50, i))
+            .collect::<Vec<_>>();
+        run(&format!(
+            "INSERT INTO child VALUES {};",
+            children.join(", ")
+        ))?;
+        let csv = BASE_DIR.join("kept_row_count.csv");
+        fs::write(&csv, "1,a\n2,b\n3,c\n")?;
+        load_csv(&csv, "kept_row_count", "child", LOAD_BATCH_SIZE)?;
+        let child = database::get_table_id("child").unwrap();
+        insert_batch(child, &[vec![Some(ColumnVal::Int(4)), None]])?;
+        assert_eq!(counts("child")?, (504, 504));
+
+        // deleted directly and by cascading, some of them rejected
+        run("DELETE FROM child WHERE pid < 10;")?;
+        run("DELETE FROM parent WHERE id >= 40;")?;
+        assert!(run("INSERT INTO child VALUES (45, 'x');").is_err());
+        assert_eq!(counts("parent")?, (40, 40));
+        assert_eq!(counts("child")?, (300, 300));
+        run("INSERT INTO child VALUES (20, 'again');")?;
+
+        database::write_back()?;
+        assert!(database::change_database("kept_row_count")?);
+        assert_eq!(counts("child")?, (301, 301));
+        assert_eq!(
+            query_sql("SELECT COUNT(*) FROM child;")?.rows,
+            [[Some(ColumnVal::Int(301))]]
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn any_match_stops_at_first_match() -> DBResult<()> {
+    with_database("any_match", || {
+        run("CREATE TABLE t (a INT, b INT);")?;
+        let values = (0..2000)
+            .map(|i| format!("({}, {})", i, i % 10))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        let id = database::get_table_id("t").unwrap();
+        let int = |i| [Some(ColumnVal::Int(i))];
+        database::ensure_table(id, |table| -> DBResult<()> {
+            let mut read = 0;
+            let rows = table.rows().inspect(|_| read += 1);
+            let rid = table.first_match(rows, &[1], CompareOp::EQ, &int(3))?;
+            assert!(rid.is_some());
+            assert_eq!(read, 4);
+            assert!(table.any_match(&[0], CompareOp::GE, &int(1999))?);
+            assert!(!table.any_match(&[0], CompareOp::GT, &int(1999))?);
+            Ok(())
+        })?;
+
+        run("CREATE INDEX ON t (a);")?;
+        database::ensure_table(id, |table| -> DBResult<()> {
+            assert!(table.any_match(&[0], CompareOp::EQ, &int(1000))?);
+            assert!(!table.any_match(&[0], CompareOp::LT, &int(0))?);
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn full_scan_in_rowid_order() -> DBResult<()> {
+    with_database("full_scan", || {
+        run("CREATE TABLE t (a INT, b INT);")?;
+        run("INSERT INTO t VALUES (3, 1), (1, 2), (2, 3);")?;
+        run("CREATE INDEX ON t (a);")?;
+        let id = database::get_table_id("t").unwrap();
+        database::ensure_table(id, |table| -> DBResult<()> {
+            let rows = table.rows().collect::<Vec<_>>();
+            assert!(rows.windows(2).all(|pair| pair[0] < pair[1]));
+            let values = rows
+                .iter()
+                .map(|&rid| table.select(rid, 0))
+                .collect::<DBResult<Vec<_>>>()?;
+            let int = |i| Some(ColumnVal::Int(i));
+            assert_eq!(values, vec![int(3), int(1), int(2)]);
+            Ok(())
+        })
+    })
+}
+
+#[test]
+fn select_in_primary_key_order() -> DBResult<()> {
+    with_database("primary_scan", || {
+        run("CREATE TABLE t (id INT NOT NULL, v INT);")?;
+        run("INSERT INTO t VALUES (3, 0), (1, 1), (2, 2);")?;
+        run("CREATE INDEX ON t (v);")?;
+        run("ALTER TABLE t ADD PRIMARY KEY (id);")?;
+        run("INSERT INTO t VALUES (0, 3);")?;
+        let int = |i| vec![Some(ColumnVal::Int(i))];
+        let check = || -> DBResult<()> {
+            let result = query_sql("SELECT id FROM t;")?;
+            assert_eq!(result.rows, (0..4).map(int).collect::<Vec<_>>());
+            let result = query_sql("SELECT id FROM t WHERE v >= 1 LIMIT 2;")?;
+            assert_eq!(result.rows, [int(0), int(1)]);
+            // while the rows of the table are still scanned by page
+            let id = database::get_table_id("t").unwrap();
+            database::ensure_table(id, |table| {
+                let ids = table
+                    .rows()
+                    .map(|rid| table.select(rid, 0))
+                    .collect::<DBResult<Vec<_>>>()?;
+                assert_eq!(ids, [3, 1, 2, 0].map(|i| Some(ColumnVal::Int(i))));
+                Ok(())
+            })
+        };
+        check()?;
+        database::write_back()?;
+        assert!(database::change_database("primary_scan")?);
+        check()
+    })
+}
+
+#[test]
+fn warmed_table_reads_from_cache() -> DBResult<()> {
+    with_database("warm", || {
+        run("CREATE TABLE t (a INT, s VARCHAR(100));")?;
+        let values = (0..500)
+            .map(|i| format!("({}, 'row {}')", i, i))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        // reopening the database closes the data file, which drops its pages from the cache
+        database::write_back()?;
+        assert!(database::change_database("warm")?);
+
+        let pages = database::warm_table("t")?;
+        assert!(pages > 1);
+        let before = cache_stats();
+        assert_eq!(row_count("t"), 500);
+        let after = cache_stats();
+        assert_eq!(after.misses, before.misses);
+        assert!(after.hits - before.hits >= pages as usize);
+        Ok(())
+    })
+}
+
+#[test]
+fn checksum_ignores_row_order() -> DBResult<()> {
+    with_database("checksum", || {
+        run("CREATE TABLE t (a INT, f FLOAT, s VARCHAR(20), d DATE);")?;
+        run("CREATE TABLE u (a INT, f FLOAT, s VARCHAR(20), d DATE);")?;
+        let values = (0..300)
+            .map(|i| match i % 10 {
+                0 => format!("({}, NULL, NULL, NULL)", i),
+                _ => format!("({}, {}.5, 'row {}', '2022-01-{:02}')", i, i, i, i % 28 + 1),
+            })
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        // the same rows in reverse order, some of them written twice and deleted
+        let reversed = values.iter().rev().cloned().collect::<Vec<_>>();
+        run(&format!("INSERT INTO u VALUES {};", reversed.join(", ")))?;
+        run(&format!(
+            "INSERT INTO u VALUES {};",
+            values[..50].join(", ")
+        ))?;
+        run("DELETE FROM u WHERE a < 50; ")?;
+        run(&format!(
+            "INSERT INTO u VALUES {};",
+            values[..50].join(", ")
+        ))?;
+
+        let checksum = database::table_checksum("t")?;
+        assert_eq!(database::table_checksum("u")?, checksum);
+        // the same after the tables are written back and loaded again
+        database::write_back()?;
+        assert!(database::change_database("checksum")?);
+        assert_eq!(database::table_checksum("t")?, checksum);
+
+        run("UPDATE t SET s = 'changed' WHERE a = 7;")?;
+        assert_ne!(database::table_checksum("t")?, checksum);
+        run("UPDATE t SET s = 'row 7' WHERE a = 7;")?;
+        assert_eq!(database::table_checksum("t")?, checksum);
+        // a NULL replaced by a value
+        run("UPDATE t SET s = 'was null' WHERE a = 10;")?;
+        assert_ne!(database::table_checksum("t")?, checksum);
+        let result = query_sql("SELECT s FROM t WHERE a = 10;")?;
+        assert_eq!(
+            result.rows,
+            [[Some(ColumnVal::Varchar("was null".to_owned()))]]
+        );
+        run("UPDATE t SET s = NULL WHERE a = 10;")?;
+        assert_eq!(database::table_checksum("t")?, checksum);
+        run("DELETE FROM t WHERE a = 0;")?;
+        assert_ne!(database::table_checksum("t")?, checksum);
+        Ok(())
+    })
+}
+
+#[test]
+fn zone_maps_skip_pages_out_of_range() -> DBResult<()> {
+    with_database("zone_map", || {
+        run("CREATE TABLE t (a INT, s VARCHAR(100));")?;
+        let values = (0..1000)
+            .map(|i| format!("({}, 'row {}')", i, i))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", values.join(", ")))?;
+        run("UPDATE t SET a = 5000 WHERE a = 0;")?;
+        database::write_back()?;
+        assert!(database::change_database("zone_map")?);
+
+        let id = database::get_table_id("t").unwrap();
+        let pages = database::ensure_table(id, |table| table.meta.page_count()) as usize;
+        assert!(pages > 4);
+        // pages read from files, which never happens when they are all in memory
+        let misses = cache_stats().misses;
+        let read = || cache_stats().misses - misses;
+        let expected = if cfg!(feature = "memory") { 0 } else { 2 };
+        let result = query_sql("SELECT a FROM t WHERE a >= 998;")?;
+        assert_eq!(result.rows.len(), 3);
+        // the last page and the first one, which has 5000 after the update
+        assert_eq!(read(), expected);
+        assert_eq!(query_sql("SELECT a FROM t WHERE a = 0;")?.rows.len(), 0);
+        assert_eq!(query_sql("SELECT a FROM t WHERE a < 10;")?.rows.len(), 9);
+        assert_eq!(query_sql("SELECT a FROM t WHERE a < 0;")?.rows.len(), 0);
+        assert_eq!(read(), expected);
+        assert_eq!(row_count("t"), 1000);
+        Ok(())
+    })
+}
+
+#[test]
+fn insert_batch_matches_single_inserts() -> DBResult<()> {
+    with_database("insert_batch", || {
+        for name in ["single", "batch"] {
+            run(&format!(
+                "CREATE TABLE {} (id INT NOT NULL, v VARCHAR(10), PRIMARY KEY (id));",
+                name
+            ))?;
+            run(&format!("CREATE INDEX ON {} (v);", name))?;
+        }
+        let n = 1000;
+        let rows = (0..n)
+            .map(|i| {
+                let v = (i % 100 != 0).then(|| ColumnVal::Varchar(format!("v{}", i % 50)));
+                vec![Some(ColumnVal::Int(i)), v]
+            })
+            .collect::<Vec<_>>();
+        let batch = database::get_table_id("batch").unwrap();
+        let rids = insert_batch(batch, &rows)?;
+        assert_eq!(rids.len(), n as usize);
+        // one statement for each row, parsed together
+        let inserts = (0..n)
+            .map(|i| {
+                let v = match i % 100 {
+                    0 => "NULL".to_owned(),
+                    _ => format!("'v{}'", i % 50),
+                };
+                format!("INSERT INTO single VALUES ({}, {});", i, v)
+            })
+            .collect::<String>();
+        run(&inserts)?;
+
+        let select = |table: &str| {
+            query_sql(&format!(
+                "SELECT * FROM {} WHERE v = 'v42' OR id >= 990 ORDER BY id;",
+                table
+            ))
+        };
+        assert_eq!(select("batch")?.rows, select("single")?.rows);
+        assert_eq!(row_count("batch"), row_count("single"));
+        let entries = |table| {
+            let result = query_sql(&format!(
+                "SELECT entries FROM __indexes WHERE table_name = '{}';",
+                table
+            ))?;
+            DBResult::Ok(result.rows)
+        };
+        assert_eq!(entries("batch")?, entries("single")?);
+
+        // nothing is inserted if a row is rejected
+        let bad = vec![
+            vec![Some(ColumnVal::Int(n)), None],
+            vec![Some(ColumnVal::Int(0)), None],
+        ];
+        assert!(insert_batch(batch, &bad).is_err());
+        let bad = vec![vec![Some(ColumnVal::Varchar("x".to_owned())), None]];
+        assert!(insert_batch(batch, &bad).is_err());
+        assert_eq!(row_count("batch"), n as usize);
+        Ok(())
+    })
+}
+
+#[test]
+fn nan_and_infinity_in_float_columns() -> DBResult<()> {
+    with_database("float_nan", || {
+        run("CREATE TABLE t (f FLOAT);")?;
+        // NaN and infinities only come from loading, literals cannot spell them
+        let csv = BASE_DIR.join("float_nan.csv");
+        fs::write(&csv, "1.5\nNaN\ninf\nNULL\n-2.5\n-inf\nNaN\n0.5\n")?;
+        load_csv(&csv, "float_nan", "t", LOAD_BATCH_SIZE)?;
+        let floats = |sql: &str| -> DBResult<Vec<_>> {
+            let rows = query_sql(sql)?.rows;
+            Ok(rows
+                .into_iter()
+                .map(|row| match row[0] {
+                    Some(ColumnVal::Float(f)) => Some(f),
+                    None => None,
+                    _ => unreachable!(),
+                })
+                .collect())
+        };
+        let sorted = floats("SELECT f FROM t ORDER BY f;")?;
+        let expected = [-f32::INFINITY, -2.5, 0.5, 1.5, f32::INFINITY];
+        for (val, expected) in sorted.iter().zip(&expected) {
+            assert_eq!(*val, Some(*expected));
+        }
+        // NaN is greater than any other value, NULL is still last
+        assert!(sorted[5].unwrap().is_nan() && sorted[6].unwrap().is_nan());
+        assert_eq!(sorted[7], None);
+
+        assert!(floats("SELECT MAX(f) FROM t;")?[0].unwrap().is_nan());
+        assert_eq!(floats("SELECT MIN(f) FROM t;")?, [Some(-f32::INFINITY)]);
+        // the same rows without and with an index
+        for indexed in &[false, true] {
+            if *indexed {
+                run("CREATE INDEX ON t (f);")?;
+            }
+            let greater = floats("SELECT f FROM t WHERE f > 1.0 ORDER BY f;")?;
+            assert_eq!(greater.len(), 4);
+            assert_eq!(greater[..2], [Some(1.5), Some(f32::INFINITY)]);
+            let count = query_sql("SELECT COUNT(*) FROM t WHERE f < 0.0;")?.rows;
+            assert_eq!(count, [[Some(ColumnVal::Int(2))]]);
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn insert_computed_values() -> DBResult<()> {
+    with_database("insert_expr", || {
+        run("CREATE TABLE t (a INT NOT NULL, s VARCHAR(5), f FLOAT, d DATE);")?;
+        run(
+            "INSERT INTO t VALUES (1 + 2 * 3, UPPER('ab'), 7 / 2, CAST('2022-01-02' AS DATE)), \
+            (CAST('4' AS INT), CASE WHEN 1 < 2 THEN 'yes' END, 1.5 * 2, NULL), (5, 'c', 1, NULL);",
+        )?;
+        let rows = query_sql("SELECT * FROM t ORDER BY a;")?.rows;
+        let int = |i| Some(ColumnVal::Int(i));
+        let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+        let float = |f| Some(ColumnVal::Float(f));
+        let date = Some(ColumnVal::Date(parse_date("2022-01-02").unwrap()));
+        assert_eq!(
+            rows,
+            [
+                vec![int(4), str("yes"), float(3.0), None],
+                vec![int(5), str("c"), float(1.0), None],
+                vec![int(7), str("AB"), float(3.0), date],
+            ]
+        );
+        // columns cannot be referenced, and computed values are checked like literals
+        assert!(run("INSERT INTO t VALUES (a + 1, 'x', 1.0, NULL);").is_err());
+        assert!(run("INSERT INTO t VALUES (1 / 0, 'x', 1.0, NULL);").is_err());
+        assert!(run("INSERT INTO t VALUES (1 + 1, UPPER('toolong'), 1.0, NULL);").is_err());
+        assert!(run("INSERT INTO t VALUES (NULL + 1, 'x', 1.0, NULL);").is_err());
+        assert!(run("INSERT INTO t VALUES (1 + 1, 2 + 2, 1.0, NULL);").is_err());
+        assert!(run("INSERT INTO t VALUES (1 + 1, 'x', 1.0);").is_err());
+        assert_eq!(row_count("t"), 3);
+        Ok(())
+    })
+}
+
+#[test]
+fn float_out_of_range_rejected() -> DBResult<()> {
+    with_database("float_range", || {
+        run("CREATE TABLE t (f FLOAT);")?;
+        run("INSERT INTO t VALUES (3.0e38), (-3.0e38), (0.1);")?;
+        assert!(run("INSERT INTO t VALUES (4.0e38);").is_err());
+        assert!(run("INSERT INTO t VALUES (-1.0e39);").is_err());
+        assert!(run("SELECT * FROM t WHERE f < 1.0e50;").is_err());
+        assert!(run("INSERT INTO t VALUES (CAST('1e39' AS FLOAT));").is_err());
+        assert_eq!(row_count("t"), 3);
+
+        let csv = BASE_DIR.join("float_range.csv");
+        fs::write(&csv, "1.5\n1e39\n")?;
+        assert!(load_csv(&csv, "float_range", "t", LOAD_BATCH_SIZE).is_err());
+        fs::write(&csv, "1.5\n-inf\n")?;
+        load_csv(&csv, "float_range", "t", LOAD_BATCH_SIZE)?;
+        assert_eq!(row_count("t"), 5);
+        Ok(())
+    })
+}
+
+#[test]
+fn unexpected_literals_are_errors() -> DBResult<()> {
+    for (expr, coltype) in [
+        ("a", ColumnType::Int),
+        ("1 + 2", ColumnType::Int),
+        ("'abc'", ColumnType::Int),
+        ("1", ColumnType::Varchar),
+        ("'2022-13-40'", ColumnType::Date),
+    ] {
+        assert!(Table::expr2colval(&parse_expr(expr), coltype).is_err());
+    }
+    let val = Table::expr2colval(&parse_expr("1"), ColumnType::Float)?;
+    assert_eq!(val, Some(ColumnVal::Float(1.0)));
+
+    with_database("unexpected_literal", || {
+        run("CREATE TABLE t (a INT, d DATE); INSERT INTO t VALUES (1, '2022-01-02');")?;
+        assert!(run("SELECT * FROM t WHERE a = 'abc';").is_err());
+        assert!(run("SELECT * FROM t WHERE d < 3;").is_err());
+        assert!(run("SELECT * FROM t WHERE (a, d) IN ((1, 2));").is_err());
+        assert_eq!(query_sql("SELECT * FROM t WHERE a < 1.5;")?.rows.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn separate_null_layout() -> DBResult<()> {
+    with_database("null_layout", || {
+        let create = "(id INT NOT NULL, a INT, s VARCHAR(20), c CHAR(3), d DATE, PRIMARY KEY (id))";
+        run(&format!("CREATE TABLE inline {};", create))?;
+        run("SET null_layout = separate;")?;
+        run(&format!("CREATE TABLE separate {};", create))?;
+        assert!(run("SET null_layout = sideways;").is_err());
+
+        // more than a page of rows, with some deleted and updated
+        let rows = (0..1000)
+            .map(|i| match i % 4 {
+                0 => format!("({}, NULL, 'row {}', NULL, '2022-01-02')", i, i),
+                1 => format!("({}, {}, NULL, 'abc', NULL)", i, i),
+                2 => format!("({}, NULL, NULL, NULL, NULL)", i),
+                _ => format!("({}, {}, 'row {}', 'xyz', '2022-03-04')", i, i, i),
+            })
+            .collect::<Vec<_>>();
+        let insert = format!("INSERT INTO {{}} VALUES {};", rows.join(", "));
+        for table in &["inline", "separate"] {
+            for sql in &[
+                insert.as_str(),
+                "DELETE FROM {} WHERE id < 100 AND a IS NULL;",
+                "UPDATE {} SET a = 7 WHERE id > 900 AND a IS NULL;",
+                "UPDATE {} SET s = NULL WHERE id > 800;",
+            ] {
+                run(&sql.replace("{}", table))?;
+            }
+        }
+        let layout = |table| {
+            let id = database::get_table_id(table).unwrap();
+            database::ensure_table(id, |table| table.meta.null_layout)
+        };
+        assert_eq!(layout("inline"), NullLayout::Inline);
+        assert_eq!(layout("separate"), NullLayout::Separate);
+
+        for sql in &[
+            "SELECT * FROM {} ORDER BY id;",
+            "SELECT id FROM {} WHERE a IS NULL ORDER BY id;",
+            "SELECT id FROM {} WHERE s IS NULL AND d IS NOT NULL ORDER BY id;",
+            "SELECT COUNT(a), COUNT(s), COUNT(c), COUNT(*) FROM {};",
+        ] {
+            let inline = query_sql(&sql.replace("{}", "inline"))?.rows;
+            let separate = query_sql(&sql.replace("{}", "separate"))?.rows;
+            assert!(!inline.is_empty());
+            assert_eq!(inline, separate, "{}", sql);
+        }
+        let nulls = query_sql("SELECT COUNT(*) FROM separate WHERE a IS NULL;")?.rows;
+        assert_eq!(nulls, [[Some(ColumnVal::Int(401))]]);
+        Ok(())
+    })
+}
+
+#[test]
+fn show_table_status() -> DBResult<()> {
+    with_database("table_status", || {
+        run("CREATE TABLE big (a INT, s VARCHAR(200)); CREATE INDEX ON big (a);")?;
+        run("CREATE INDEX ON big (s); CREATE TABLE small (a INT);")?;
+        let rows = (0..500)
+            .map(|i| format!("({}, 'row {}')", i, i))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO big VALUES {};", rows.join(", ")))?;
+        run("INSERT INTO small VALUES (1), (2); DELETE FROM small WHERE a = 1;")?;
+        run("SHOW TABLE STATUS;")?;
+
+        // unloaded tables are read from their metadata files
+        for _ in 0..2 {
+            let mut statuses = database::table_status(None)?;
+            statuses.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+            let summary = statuses
+                .iter()
+                .map(|status| (status.name.as_str(), status.rows, status.indices))
+                .collect::<Vec<_>>();
+            assert_eq!(summary, [("big", 500, 2), ("small", 1, 0)]);
+            assert!(statuses[0].pages > 1);
+            assert_eq!(statuses[1].pages, 1);
+            for status in &statuses {
+                assert!(status.file_pages >= status.pages as u64);
+            }
+            assert!(database::change_database("table_status")?);
+        }
+        assert_eq!(database::table_status(Some("sm%"))?.len(), 1);
+        assert!(run("SHOW TABLE STATS;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn drop_tables_like() -> DBResult<()> {
+    with_database("drop_like", || {
+        run("CREATE TABLE parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE tmp_a (a INT); CREATE TABLE tmp_b (b INT); CREATE TABLE tmpc (c INT);")?;
+        run("CREATE TABLE tmp_child (pid INT, FOREIGN KEY (pid) REFERENCES parent (id));")?;
+        run("INSERT INTO parent VALUES (1), (2); INSERT INTO tmp_child VALUES (1);")?;
+        run("CREATE TABLE tmp_parent (id INT NOT NULL, PRIMARY KEY (id));")?;
+        run("CREATE TABLE child (pid INT, FOREIGN KEY (pid) REFERENCES tmp_parent (id));")?;
+
+        // tmp_parent is still referenced by child, so nothing is dropped
+        assert!(run("DROP TABLE LIKE 'tmp\\_%';").is_err());
+        assert_eq!(database::table_names(None)?.len(), 7);
+        assert!(run("DROP TABLE tmp_parent;").is_err());
+        run("DROP TABLE child;")?;
+
+        run("DROP TABLE LIKE 'tmp\\_%';")?;
+        let mut names = database::table_names(None)?;
+        names.sort();
+        assert_eq!(names, ["parent", "tmpc"]);
+        // the dropped foreign key no longer restricts its parent
+        run("DELETE FROM parent WHERE id = 1;")?;
+        run("DROP TABLE parent;")?;
+        run("DROP TABLE LIKE 'none%';")?;
+        assert!(run("DROP TABLE parent;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn check_proposed_constraints() -> DBResult<()> {
+    with_database("check_constraint", || {
+        run("CREATE TABLE t (id INT, a INT, c VARCHAR(5));")?;
+        run("INSERT INTO t VALUES (1, 10, 'x'), (2, 20, 'y'), (3, 10, 'z'), (4, NULL, 'x');")?;
+        run("INSERT INTO t VALUES (5, 30, NULL), (6, 10, 'y'), (7, 20, 'x');")?;
+        let violations = |sql: &str| -> DBResult<Vec<i32>> {
+            let mut stmts = SqlStmtsParser::new()
+                .parse(sql)
+                .map_err(|e| format!("{:?}", e))?;
+            let rows = match stmts.pop() {
+                Some(SqlStmt::CheckConstraint(args)) => constraint_violations(&args)?.rows,
+                _ => return Err("not a constraint check".into()),
+            };
+            Ok(rows
+                .into_iter()
+                .map(|row| match row[0] {
+                    Some(ColumnVal::Int(id)) => id,
+                    _ => unreachable!(),
+                })
+                .collect())
+        };
+
+        // rows sharing a key are grouped together
+        assert_eq!(
+            violations("CHECK CONSTRAINT ON t UNIQUE (a);")?,
+            [1, 3, 6, 2, 7]
+        );
+        assert_eq!(violations("CHECK CONSTRAINT ON t UNIQUE (a, c);")?, []);
+        assert_eq!(violations("CHECK CONSTRAINT ON t UNIQUE (id);")?, []);
+        assert_eq!(violations("CHECK CONSTRAINT ON t NOT NULL (a);")?, [4]);
+        assert_eq!(
+            violations("CHECK CONSTRAINT ON t CHECK (c IN ('x', 'y'));")?,
+            [3]
+        );
+        assert!(violations("CHECK CONSTRAINT ON t NOT NULL (b);").is_err());
+
+        // nothing is added
+        run("CHECK CONSTRAINT ON t UNIQUE (a);")?;
+        run("INSERT INTO t VALUES (8, 10, NULL);")?;
+        assert_eq!(row_count("t"), 8);
+        Ok(())
+    })
+}
+
+#[test]
+fn if_exists_and_if_not_exists() -> DBResult<()> {
+    with_database("if_exists", || {
+        run("CREATE TABLE t (a INT); INSERT INTO t VALUES (1);")?;
+        assert!(run("CREATE TABLE t (b INT);").is_err());
+        run("CREATE TABLE IF NOT EXISTS t (b INT);")?;
+        // the existing table is kept as it is
+        assert_eq!(row_count("t"), 1);
+        run("CREATE TABLE IF NOT EXISTS u (b INT); INSERT INTO u VALUES (2);")?;
+
+        run("DROP TABLE IF EXISTS u; DROP TABLE IF EXISTS u;")?;
+        assert!(run("DROP TABLE u;").is_err());
+        assert_eq!(database::table_names(None)?, ["t"]);
+
+        assert!(run("CREATE DATABASE if_exists;").is_err());
+        run("CREATE DATABASE IF NOT EXISTS if_exists;")?;
+        run("CREATE DATABASE IF NOT EXISTS if_exists_other;")?;
+        run("DROP DATABASE IF EXISTS if_exists_other; DROP DATABASE IF EXISTS if_exists_other;")?;
+        assert!(run("DROP DATABASE if_exists_other;").is_err());
+        assert!(!database::database_names(None)?.contains(&"if_exists_other".to_owned()));
+        Ok(())
+    })
+}
+
+#[test]
+fn default_null_columns() -> DBResult<()> {
+    with_database("default_null", || {
+        run("CREATE TABLE t (a INT DEFAULT NULL, b INT NULL DEFAULT NULL, c INT NOT NULL);")?;
+        let id = database::get_table_id("t").unwrap();
+        let not_null = database::ensure_table(id, |table| {
+            table
+                .meta
+                .columns
+                .iter()
+                .map(|col| col.constraints.is_not_null())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(not_null, [false, false, true]);
+        run("INSERT INTO t VALUES (NULL, NULL, 1);")?;
+        assert!(run("INSERT INTO t VALUES (1, 1, NULL);").is_err());
+
+        assert!(run("CREATE TABLE u (a INT NOT NULL DEFAULT NULL);").is_err());
+        assert!(run("CREATE TABLE u (a INT DEFAULT NULL, PRIMARY KEY (a));").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn drop_database_before_use() -> DBResult<()> {
+    with_database("drop_before_use", || {
+        run("CREATE DATABASE drop_before_use_other;")?;
+        // closes the database, as in a session where none has been used yet
+        database::write_back()?;
+        run("DROP DATABASE drop_before_use_other;")?;
+        assert!(run("DROP DATABASE drop_before_use_other;").is_err());
+        assert!(!BASE_DIR.join("drop_before_use_other").exists());
+
+        assert!(database::change_database("drop_before_use")?);
+        assert!(run("DROP DATABASE drop_before_use;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn temporary_tables_dropped_on_close() -> DBResult<()> {
+    with_database("temporary", || {
+        run("CREATE TABLE t (a INT); INSERT INTO t VALUES (1);")?;
+        run("CREATE TEMPORARY TABLE t (a INT, b INT); INSERT INTO t VALUES (2, 3), (4, 5);")?;
+        assert!(run("CREATE TEMPORARY TABLE t (a INT);").is_err());
+        run("CREATE TEMPORARY TABLE IF NOT EXISTS t (a INT);")?;
+        // the temporary table shadows the table of the database
+        assert_eq!(row_count("t"), 2);
+        run("CREATE TEMPORARY TABLE staging (a INT, PRIMARY KEY (a));")?;
+        run("INSERT INTO staging VALUES (2), (8);")?;
+        let result = query_sql("SELECT t.b FROM t, staging WHERE t.a = staging.a;")?;
+        assert_eq!(result.rows.len(), 1);
+        let mut names = database::table_names(None)?;
+        names.sort();
+        assert_eq!(names, ["staging", "t"]);
+        let data_file = TableMeta::format_data_filename("staging");
+        assert!(!BASE_DIR.join("temporary").join(data_file).exists());
+
+        assert!(run("CREATE TABLE r (a INT, FOREIGN KEY (a) REFERENCES staging (a));").is_err());
+        run("CREATE TEMPORARY TABLE r (a INT, FOREIGN KEY (a) REFERENCES staging (a));")?;
+        assert!(run("INSERT INTO r VALUES (9);").is_err());
+
+        // the table of the database is visible again once the temporary one is dropped
+        run("DROP TABLE t;")?;
+        assert_eq!(row_count("t"), 1);
+        run("CREATE TEMPORARY TABLE t (a INT); INSERT INTO t VALUES (6);")?;
+
+        // the session ends with the database closed
+        database::write_back()?;
+        let temp_dir = SETTINGS.borrow().temp_dir.clone();
+        let temp_dir = temp_dir.join(format!("naive-db-temporary-{}", std::process::id()));
+        assert!(!temp_dir.exists());
+        assert!(database::change_database("temporary")?);
+        let mut names = database::table_names(None)?;
+        names.sort();
+        assert_eq!(names, ["r", "t"]);
+        assert_eq!(row_count("t"), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn free_pages_at_end_released() -> DBResult<()> {
+    with_database("free_pages", || {
+        run("CREATE TABLE t (a INT, s VARCHAR(200));")?;
+        let rows = (0..500)
+            .map(|i| format!("({}, 'row {}')", i, i))
+            .collect::<Vec<_>>();
+        run(&format!("INSERT INTO t VALUES {};", rows.join(", ")))?;
+        let status = || -> DBResult<(u32, u64)> {
+            // written back, then read again from disk
+            database::write_back()?;
+            assert!(database::change_database("free_pages")?);
+            let status = database::table_status(None)?.pop().unwrap();
+            Ok((status.pages, status.file_pages))
+        };
+        let (pages, _) = status()?;
+
+        // rows freed at the start stay, but the pages emptied at the end are released
+        run("DELETE FROM t WHERE a < 20; DELETE FROM t WHERE a >= 250;")?;
+        let (half, file_pages) = status()?;
+        assert!(half <= pages / 2 + 1);
+        assert_eq!(file_pages, half as u64);
+
+        // the lowest free slots are filled first, so the file does not grow
+        run("INSERT INTO t VALUES (1000, 'new'), (1001, 'new');")?;
+        assert_eq!(status()?, (half, half as u64));
+        assert_eq!(row_count("t"), 232);
+        let result = query_sql("SELECT a FROM t WHERE s = 'new';")?;
+        assert_eq!(result.rows.len(), 2);
+
+        run("DELETE FROM t WHERE a >= 0;")?;
+        assert_eq!(status()?, (0, 1));
+        run("INSERT INTO t VALUES (1, 'again');")?;
+        assert_eq!(status()?, (1, 1));
+        assert_eq!(row_count("t"), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn corrupt_catalog_reported_on_use() -> DBResult<()> {
+    with_database("corrupt_catalog", || {
+        run("CREATE DATABASE corrupt_catalog_other;")?;
+        storage::write(
+            &BASE_DIR
+                .join("corrupt_catalog_other")
+                .join("database.tablemeta"),
+            b"\xff",
+        )?;
+        let err = run("USE corrupt_catalog_other;").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("failed to load database corrupt_catalog_other"));
+        let err = run("USE corrupt_catalog_none;").unwrap_err();
+        assert_eq!(err.to_string(), "database does not exist");
+        assert!(database::change_database("corrupt_catalog")?);
+        Ok(())
+    })
+}
+
+#[test]
+fn show_config() -> DBResult<()> {
+    with_database("config", || {
+        let value = |name: &str| {
+            let config = system::config();
+            let row = config
+                .rows
+                .into_iter()
+                .find(|row| row[0] == Some(ColumnVal::Varchar(name.to_owned())))
+                .unwrap();
+            match &row[1] {
+                Some(ColumnVal::Varchar(value)) => value.clone(),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(value("null_string"), "NULL");
+        assert_eq!(value("int_division"), "truncate");
+        run("SET null_string = '<null>'; SET int_division = float;")?;
+        assert_eq!(value("null_string"), "<null>");
+        assert_eq!(value("int_division"), "float");
+        assert_eq!(value("page_size"), PAGE_SIZE.to_string());
+        assert_eq!(value("max_comp_index"), MAX_COMP_INDEX.to_string());
+        run("SHOW CONFIG;")?;
+        Ok(())
+    })
+}
+
+#[test]
+fn least_recently_used_tables_written_back() -> DBResult<()> {
+    with_database("evict_tables", || {
+        run("SET max_loaded_tables = 2;")?;
+        for name in &["a", "b", "c", "d"] {
+            let sql = "CREATE TABLE {} (id INT NOT NULL, PRIMARY KEY (id)); \
+                INSERT INTO {} VALUES (1), (2);";
+            run(&sql.replace("{}", name))?;
+            assert!(database::loaded_tables() <= 2);
+        }
+        // evicted tables are loaded again with their rows and indices
+        for (i, name) in ["a", "b", "c", "d", "a"].iter().enumerate() {
+            let sql = format!("INSERT INTO {} VALUES ({});", name, 10 + i);
+            run(&sql)?;
+            assert!(run(&"INSERT INTO {} VALUES (1);".replace("{}", name)).is_err());
+            assert!(database::loaded_tables() <= 2);
+        }
+        for name in &["a", "b", "c", "d"] {
+            assert_eq!(row_count(name), if *name == "a" { 4 } else { 3 });
+        }
+        assert!(run("SET max_loaded_tables = 0;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn library_api() -> DBResult<()> {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    *SETTINGS.borrow_mut() = Settings::default();
+    let db = Database::open(BASE_DIR.as_path())?;
+    assert!(Database::open(BASE_DIR.join("other")).is_err());
+    // a second handle would share the database in use
+    assert!(Database::open(BASE_DIR.as_path()).is_err());
+
+    db.execute("CREATE DATABASE library_api; USE library_api;")?;
+    db.execute("CREATE TABLE t (a INT NOT NULL, b VARCHAR(10));")?;
+    let inserted = db.execute("INSERT INTO t VALUES (1, 'x'), (2, NULL), (3, 'z');")?;
+    assert_eq!(inserted.affected, 3);
+
+    let result = db.execute("SELECT a, b FROM t WHERE a >= 2 ORDER BY a;")?;
+    assert_eq!(result.header.len(), 2);
+    assert_eq!(
+        result.rows,
+        [
+            vec![Some(ColumnVal::Int(2)), None],
+            vec![
+                Some(ColumnVal::Int(3)),
+                Some(ColumnVal::Varchar("z".into()))
+            ],
+        ]
+    );
+    assert_eq!(db.execute("UPDATE t SET b = 'y' WHERE a < 3;")?.affected, 2);
+    assert_eq!(db.execute("DELETE FROM t WHERE a = 1;")?.affected, 1);
+
+    let tables = db.execute("SHOW TABLES;")?;
+    assert_eq!(tables.rows, [vec![Some(ColumnVal::Varchar("t".into()))]]);
+    let columns = db.execute("DESC t;")?;
+    assert_eq!(columns.rows.len(), 2);
+    assert_eq!(columns.rows[0][0], Some(ColumnVal::Varchar("a".into())));
+
+    assert!(db.execute("SELECT * FROM missing;").is_err());
+    assert!(db.execute("SELEC 1;").is_err());
+    db.close()?;
+
+    // closing writes back and leaves the database
+    let db = Database::open(BASE_DIR.as_path())?;
+    assert!(db.execute("SELECT * FROM t;").is_err());
+    db.execute("USE library_api;")?;
+    assert_eq!(db.execute("SELECT * FROM t;")?.rows.len(), 2);
+
+    // dropping the handle closes it too
+    drop(db);
+    let db = Database::open(BASE_DIR.as_path())?;
+    assert!(db.execute("SELECT * FROM t;").is_err());
+    db.close()?;
+    Ok(())
+}
+
+#[test]
+fn unique_violation_names_duplicate() -> DBResult<()> {
+    with_database("unique_violation", || {
+        // the values listed by the error
+        let duplicates = |sql| {
+            let err = run(sql).unwrap_err().to_string();
+            let (_, values) = err.split_once("duplicate value(s): ").unwrap();
+            values.to_owned()
+        };
+        run("CREATE TABLE t (a INT, b VARCHAR(10));")?;
+        run("INSERT INTO t VALUES (1, 'x'), (2, 'y'), (1, 'z'), (2, 'y'), (1, 'x');")?;
+        assert_eq!(duplicates("ALTER TABLE t ADD PRIMARY KEY (a);"), "1");
+
+        run("SET verbose_errors = on;")?;
+        assert_eq!(
+            duplicates("ALTER TABLE t ADD PRIMARY KEY (a, b);"),
+            "(2, 'y'), (1, 'x')"
+        );
+        assert_eq!(duplicates("ALTER TABLE t ADD PRIMARY KEY (a);"), "1, 2");
+
+        run("DELETE FROM t WHERE b <> 'y';")?;
+        assert!(run("ALTER TABLE t ADD PRIMARY KEY (b);").is_err());
+        run("DELETE FROM t WHERE a = 2;")?;
+        run("ALTER TABLE t ADD PRIMARY KEY (b);")?;
+
+        // strings sharing a prefix are compared in full while the index is built
+        run("CREATE TABLE parent (name VARCHAR(10));")?;
+        run("CREATE TABLE child (name VARCHAR(10));")?;
+        run("INSERT INTO parent VALUES ('prefix1'), ('prefix2'), ('prefix1');")?;
+        let sql = "ALTER TABLE child ADD CONSTRAINT FOREIGN KEY (name) REFERENCES parent (name);";
+        assert_eq!(duplicates(sql), "'prefix1'");
+        run("DELETE FROM parent WHERE name = 'prefix1';")?;
+        run(sql)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn exec_returns_results() -> DBResult<()> {
+    with_database("exec_results", || {
+        let exec = |sql: &str| -> DBResult<ExecResult> {
+            let mut stmts = SqlStmtsParser::new()
+                .parse(sql)
+                .map_err(|e| format!("{:?}", e))?;
+            stmts.pop().ok_or("no statement")?.exec()
+        };
+        let rows = |sql: &str| match exec(sql) {
+            Ok(ExecResult::Rows(rows, header, 0)) => (rows, header),
+            ret => panic!("{:?}", ret),
+        };
+        let int = |i| Some(ColumnVal::Int(i));
+
+        let empty = exec("CREATE TABLE t (a INT, b INT);")?;
+        assert!(matches!(empty, ExecResult::Empty));
+        assert!(matches!(
+            exec("INSERT INTO t VALUES (1, 2), (3, 4), (5, 6);")?,
+            ExecResult::Affected(3)
+        ));
+        assert!(matches!(
+            exec("UPDATE t SET b = b + 1 WHERE a > 1;")?,
+            ExecResult::Affected(2)
+        ));
+
+        let (mut result, header) = rows("SELECT a, b FROM t WHERE a > 1;");
+        result.sort_by_key(|row| format!("{:?}", row));
+        assert_eq!(result, [[int(3), int(5)], [int(5), int(7)]]);
+        assert_eq!(header, ["a", "b"]);
+        let (result, header) = rows("SELECT COUNT(*) AS n FROM t;");
+        assert_eq!((result, header), (vec![vec![int(3)]], vec!["n".to_owned()]));
+        let (result, header) = rows("SELECT MAX(a), SUM(b) FROM t;");
+        assert_eq!(result, [[int(5), int(14)]]);
+        assert_eq!(header, ["MAX(a)", "SUM(b)"]);
+        // a column cannot be selected with aggregates without GROUP BY, whether the query
+        // is paged or not
+        for sql in &[
+            "SELECT a, COUNT(b) FROM t WHERE a = 1;",
+            "SELECT a, COUNT(b) FROM t WHERE a = 1 LIMIT 5;",
+        ] {
+            let err = exec(sql).unwrap_err().to_string();
+            assert!(err.ends_with("must appear in GROUP BY or be used in an aggregate"));
+        }
+
+        run("CREATE TABLE u (a INT, c INT); INSERT INTO u VALUES (3, 30);")?;
+        let (result, header) = rows("SELECT t.b, u.c FROM t, u WHERE t.a = u.a;");
+        assert_eq!(result, [[int(5), int(30)]]);
+        assert_eq!(header, ["b", "c"]);
+        assert!(exec("SELECT COUNT(*), t.b FROM t, u WHERE t.a = u.a;").is_err());
+
+        let (result, _) = rows("SHOW TABLES;");
+        assert_eq!(result.len(), 2);
+        let (result, header) = rows("DESC u;");
+        assert_eq!(result.len(), 2);
+        assert_eq!(header[0], "Name");
+        match exec("DROP TABLE LIKE 'u%';")? {
+            ExecResult::Message(message) => assert_eq!(message, "1 table(s) dropped"),
+            ret => panic!("{:?}", ret),
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn script_error_located() -> DBResult<()> {
+    with_database("script_error", || {
+        let sqls = "CREATE TABLE t (a INT);\nINSERT INTO t VALUES (1);\n\n  \
+                    INSERT INTO missing VALUES (2);\nINSERT INTO t VALUES (3);";
+        let mut out = Vec::new();
+        let err = exec_script(sqls, &mut out).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "error in statement 3 near 'INSERT INTO missing VALUES (2)' at line 4: \
+             table name not found"
+        );
+        assert_eq!(row_count("t"), 1);
+        // statements before the failing one are done
+        assert_eq!(String::from_utf8(out)?, "1 row(s) affected\n");
+
+        let sqls = "SELECT a FROM t; SELECT a, b FROM t\n WHERE a = 1;";
+        let mut out = Vec::new();
+        let err = exec_script(sqls, &mut out).unwrap_err().to_string();
+        assert!(err.starts_with("error in statement 2 near 'SELECT a, b FROM t' at line 1"));
+        assert!(String::from_utf8(out)?.ends_with("1 items in total\n"));
+        // long statements are cut
+        let sqls = "SELECT * FROM t WHERE a = 1 AND a = 1 AND a = 1 AND b = 1;";
+        let err = exec_script(sqls, &mut Vec::new()).unwrap_err().to_string();
+        let near = "near 'SELECT * FROM t WHERE a = 1 AND a = 1 AN...'";
+        assert!(err.starts_with(&format!("error in statement 1 {}", near)));
+        Ok(())
+    })
+}
+
+#[test]
+fn repl_input_split_into_statements() {
+    let parser = SpannedSqlStmtsParser::new();
+    // number of statements, none if more input is needed
+    let parse = |sql: &str, end| {
+        let stmts = parse_input(&parser, sql, end).map_err(|e| format!("{:?}", e))?;
+        Ok::<_, String>(stmts.map(|stmts| stmts.len()))
+    };
+    assert_eq!(parse("SELECT a", false), Ok(None));
+    assert_eq!(parse("SELECT a\nFROM t;", false), Ok(Some(1)));
+    assert_eq!(parse("SELECT a FROM t; USE db;", false), Ok(Some(2)));
+    assert_eq!(parse("SELECT a FROM t; SELECT b", false), Ok(None));
+    // a semicolon in a string literal does not end the statement
+    assert_eq!(parse("INSERT INTO t VALUES ('a;", false), Ok(None));
+    assert_eq!(parse("INSERT INTO t VALUES ('a;\n;');", false), Ok(Some(1)));
+    assert_eq!(parse("SELECT a FROM t WHERE b = ';'; USE", false), Ok(None));
+
+    assert!(parse("SELECT a", true).is_err());
+    assert!(parse("INSERT INTO t VALUES ('a;", true).is_err());
+    assert!(parse("SELECT FROM t;", false).is_err());
+}
+
+#[test]
+fn repl_completes_names() -> DBResult<()> {
+    with_database("completion", || {
+        run("CREATE TABLE users (id INT, name VARCHAR(10));")?;
+        run("CREATE TABLE orders (id INT, user_id INT, note VARCHAR(10));")?;
+        let complete = |line: &str| complete_name(line, line.len());
+
+        assert_eq!(
+            complete("SELECT * FROM ")?,
+            (14, vec!["orders".into(), "users".into()])
+        );
+        assert_eq!(complete("select * from us")?, (14, vec!["users".into()]));
+        assert_eq!(complete("INSERT INTO o")?, (12, vec!["orders".into()]));
+        assert_eq!(complete("UPDATE u")?, (7, vec!["users".into()]));
+        // columns of the tables in the line
+        assert_eq!(
+            complete_name("SELECT n FROM users", 8)?,
+            (7, vec!["name".into()])
+        );
+        assert_eq!(
+            complete("SELECT * FROM users, orders WHERE n")?.1,
+            vec!["name", "note"]
+        );
+        assert_eq!(
+            complete("SELECT * FROM users WHERE orders.u")?,
+            (33, vec!["user_id".into()])
+        );
+        assert_eq!(complete("SELECT i")?.1, Vec::<String>::new());
+        Ok(())
+    })
+}
+
+#[test]
+fn repl_output_redirected() -> DBResult<()> {
+    with_database("output_redirect", || {
+        run("CREATE TABLE t (a INT);")?;
+        let parse = |sql: &str| -> DBResult<SqlStmt> {
+            let mut stmts = SqlStmtsParser::new()
+                .parse(sql)
+                .map_err(|e| format!("{:?}", e))?;
+            Ok(stmts.pop().ok_or("no statement")?)
+        };
+        let mut buf = Vec::new();
+        exec_stmt(&parse("INSERT INTO t VALUES (1), (2);")?, &mut buf)?;
+        exec_stmt(&parse("SELECT a FROM t;")?, &mut buf)?;
+        let printed = String::from_utf8(buf)?;
+        assert!(printed.starts_with("2 row(s) affected\n"));
+        assert!(printed.ends_with("2 items in total\n"));
+
+        let file = BASE_DIR.join("output_redirect.txt");
+        let mut out: Box<dyn Write> = Box::new(stdout());
+        dot_command(&format!(".output {}", file.display()), &mut out)?;
+        dot_command(".checksum t", &mut out)?;
+        dot_command(".output stdout", &mut out)?;
+        dot_command(".checksum t", &mut out)?;
+        let checksum = fs::read_to_string(&file)?;
+        assert_eq!(checksum.lines().count(), 1);
+        assert_eq!(checksum.trim_end().len(), 16);
+        Ok(())
+    })
+}
+
+#[test]
+fn repl_timer_toggled() -> DBResult<()> {
+    let mut out: Box<dyn Write> = Box::new(stdout());
+    dot_command(".timer off", &mut out)?;
+    assert!(!TIMER.load(Ordering::Relaxed));
+    dot_command(".timer ON", &mut out)?;
+    assert!(TIMER.load(Ordering::Relaxed));
+    assert!(dot_command(".timer", &mut out).is_err());
+    assert!(dot_command(".timer maybe", &mut out).is_err());
+    assert!(TIMER.load(Ordering::Relaxed));
+    Ok(())
+}
+
+#[test]
+fn cancelled_scan_fails() -> DBResult<()> {
+    with_database("cancel_scan", || {
+        run("CREATE TABLE t (a INT, b VARCHAR(5));")?;
+        run("INSERT INTO t VALUES (1, 'x'), (2, 'y');")?;
+        let id = database::load_table("t")?;
+        let scan = |col, op, val| {
+            database::ensure_table(id, |table| {
+                table.filter_rows(&[col], op, &[Some(val)], None)
+            })
+        };
+        cancel_statement();
+        let err = scan(0, CompareOp::EQ, ColumnVal::Int(1)).unwrap_err();
+        assert_eq!(err.to_string(), "query cancelled");
+        let err = scan(1, CompareOp::LIKE, ColumnVal::Varchar("%".into())).unwrap_err();
+        assert_eq!(err.to_string(), "query cancelled");
+        // the next statement is not cancelled
+        run("SELECT * FROM t WHERE a = 1;")?;
+        assert_eq!(scan(0, CompareOp::EQ, ColumnVal::Int(1))?.len(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn select_rows_limited() -> DBResult<()> {
+    with_database("max_rows", || {
+        let exec = |sql: &str| -> DBResult<_> {
+            let mut stmts = SqlStmtsParser::new()
+                .parse(sql)
+                .map_err(|e| format!("{:?}", e))?;
+            match stmts.pop().ok_or("no statement")?.exec()? {
+                ExecResult::Rows(rows, _, omitted) => Ok((rows, omitted)),
+                ret => Err(format!("{:?}", ret).into()),
+            }
+        };
+        run("CREATE TABLE t (a INT);")?;
+        run("CREATE TABLE u (b INT);")?;
+        run("INSERT INTO t VALUES (5), (4), (3), (2), (1);")?;
+        run("INSERT INTO u VALUES (1), (2);")?;
+        run("SET max_rows = 2;")?;
+
+        let (rows, omitted) = exec("SELECT * FROM t;")?;
+        assert_eq!((rows.len(), omitted), (2, 3));
+        let (rows, omitted) = exec("SELECT a FROM t ORDER BY a;")?;
+        let int = |i| vec![Some(ColumnVal::Int(i))];
+        assert_eq!((rows, omitted), (vec![int(1), int(2)], 3));
+        let (rows, omitted) = exec("SELECT * FROM t, u;")?;
+        assert_eq!((rows.len(), omitted), (2, 8));
+        // aggregates are computed over all rows
+        assert_eq!(exec("SELECT COUNT(*) FROM t, u;")?, (vec![int(10)], 0));
+        assert_eq!(exec("SELECT SUM(a) FROM t;")?, (vec![int(15)], 0));
+        assert_eq!(exec("SELECT * FROM t WHERE a < 3;")?.1, 0);
+        let (rows, omitted) = exec("SELECT a FROM t UNION ALL SELECT b FROM u;")?;
+        assert_eq!((rows.len(), omitted), (2, 5));
+
+        run("SET max_rows = 0;")?;
+        assert_eq!(exec("SELECT * FROM t, u;")?.0.len(), 10);
+        assert!(run("SET max_rows = -1;").is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn database_names_with_unusual_characters() -> DBResult<()> {
+    with_database("current_db", || {
+        assert_eq!(database::current_database().as_deref(), Some("current_db"));
+        for name in ["_", "9lives", "__db_2__", "UPPER_lower"] {
+            run(&format!("CREATE DATABASE {};", name))?;
+            run(&format!("USE {};", name))?;
+            assert_eq!(database::current_database().as_deref(), Some(name));
+            let err = run(&format!("DROP DATABASE {};", name)).unwrap_err();
+            assert!(err.to_string().starts_with("database already opened"));
+            let err = run(&format!("ALTER DATABASE current_db RENAME TO {};", name));
+            assert!(err
+                .unwrap_err()
+                .to_string()
+                .starts_with("database already opened"));
+            run("USE current_db;")?;
+            run(&format!("DROP DATABASE {};", name))?;
+        }
+        database::write_back()?;
+        assert_eq!(database::current_database(), None);
+        Ok(())
+    })
+}
+
+#[test]
+fn invalid_names_rejected() -> DBResult<()> {
+    with_database("invalid_names", || {
+        database::create_database("invalid_other")?;
+        let long = "x".repeat(65);
+        for name in [
+            "",
+            " ",
+            "../escape",
+            "a/b",
+            "a\\b",
+            ".",
+            "nul\0",
+            "dot.ted",
+            &long,
+        ] {
+            assert!(database::create_database(name).is_err(), "{:?}", name);
+            assert!(database::change_database(name).is_err(), "{:?}", name);
+            assert!(database::drop_database(name).is_err(), "{:?}", name);
+            assert!(database::rename_database("invalid_other", name).is_err());
+            assert!(
+                database::create_table(name, &[], false).is_err(),
+                "{:?}",
+                name
+            );
+        }
+        let err = database::create_database("../escape")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("invalid database name \"../escape\""));
+        assert!(!BASE_DIR.join("..").join("escape").exists());
+
+        // names the grammar takes are valid
+        run("CREATE TABLE `with space` (a INT); CREATE TABLE $t (a INT);")?;
+        run("INSERT INTO `with space` VALUES (1);")?;
+        assert_eq!(row_count("with space"), 1);
+        run("CREATE DATABASE $db; DROP DATABASE $db;")?;
+        Ok(())
+    })
+}
+
+#[test]
+fn quoted_names() -> DBResult<()> {
+    with_database("quoted_names", || {
+        run("CREATE TABLE `order` (`order` INT, \"first name\" VARCHAR(10), `select` INT);")?;
+        run("CREATE TABLE `with space` (a INT);")?;
+        run("INSERT INTO `order` VALUES (1, 'x', 2), (2, 'y', 3);")?;
+        run("UPDATE `order` SET \"first name\" = 'z' WHERE `order` = 2;")?;
+        run("CREATE INDEX ON `order` (\"first name\");")?;
+        let rows = query_sql(
+            "SELECT `select`, t.\"first name\" AS \"the name\" FROM `order` AS t \
+             WHERE \"first name\" = 'z';",
+        )?;
+        assert_eq!(rows.header, ["select", "the name"]);
+        assert_eq!(
+            rows.rows,
+            [[
+                Some(ColumnVal::Int(3)),
+                Some(ColumnVal::Varchar("z".into()))
+            ]]
+        );
+        // a file name cannot contain every character a quoted name can
+        assert!(run("CREATE TABLE `a/b` (a INT);").is_err());
+
+        // completions are quoted as needed
+        let (_, names) = complete_name("SELECT * FROM ", 14)?;
+        assert_eq!(names, ["`order`", "`with space`"]);
+        let (_, names) = complete_name("SELECT f FROM `order`", 8)?;
+        assert_eq!(names, ["`first name`"]);
+        Ok(())
+    })
+}
@@ -2,7 +2,7 @@
 
 use std::{
     fs::{self, File, OpenOptions},
-    io::{ErrorKind, Read, Result, Seek, SeekFrom, Write},
+    io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write},
     path::Path,
 };
 
@@ -72,11 +72,23 @@ pub fn fs_read_page_to(file: &mut File, pagenum: PageNum, buf: &mut [u8]) -> Res
     }
 }
 
+/// A write failing for lack of space is reported as such, rather than as a generic IO error
+pub fn fs_disk_full(e: Error) -> Error {
+    if e.kind() == ErrorKind::StorageFull {
+        Error::new(
+            ErrorKind::StorageFull,
+            "disk full, the write was not completed",
+        )
+    } else {
+        e
+    }
+}
+
 pub fn fs_write_page(file: &mut File, pagenum: PageNum, buf: &Page) -> Result<()> {
     let seekfrom = SeekFrom::Start((pagenum as u64) << PAGE_SIZE_IDX);
     file.seek(seekfrom)?;
-    file.write_all(buf)?;
-    file.sync_data()?;
+    file.write_all(buf).map_err(fs_disk_full)?;
+    file.sync_data().map_err(fs_disk_full)?;
     Ok(())
 }
 
@@ -84,11 +96,12 @@ pub fn fs_write_page_from(file: &mut File, pagenum: PageNum, buf: &[u8]) -> Resu
     let seekfrom = SeekFrom::Start((pagenum as u64) << PAGE_SIZE_IDX);
     file.seek(seekfrom)?;
     let len = buf.len().min(PAGE_SIZE);
-    file.write_all(buf)?;
+    file.write_all(buf).map_err(fs_disk_full)?;
     if len < PAGE_SIZE {
-        file.write_all(&[0; PAGE_SIZE][..PAGE_SIZE - len])?;
+        file.write_all(&[0; PAGE_SIZE][..PAGE_SIZE - len])
+            .map_err(fs_disk_full)?;
     }
-    file.sync_data()?;
+    file.sync_data().map_err(fs_disk_full)?;
     Ok(())
 }
 
@@ -96,10 +109,15 @@ pub fn fs_page_count(file: &File) -> Result<u64> {
     Ok(file.metadata()?.len() / PAGE_SIZE as u64)
 }
 
+/// Shrink the file to `n` pages, the pages after them are discarded
+pub fn fs_truncate_page(file: &File, n: PageNum) -> Result<()> {
+    file.set_len((n as u64) << PAGE_SIZE_IDX)
+}
+
 /// set file capacity to `n` pages when its capacity is lower than `n`
 /// or keep its length when it already has larger length
 /// `n-1` would be the greatest pagenum without setting a greater length
 pub fn fs_reserve_page(file: &File, n: PageNum) -> Result<()> {
     let len = file.metadata()?.len().max((n as u64) << PAGE_SIZE_IDX);
-    file.set_len(len)
+    file.set_len(len).map_err(fs_disk_full)
 }
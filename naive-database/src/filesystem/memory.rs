@@ -0,0 +1,238 @@
+//! Files kept in memory rather than on disk, for the `memory` feature. Both the
+//! page manager and the storage of other files are backed by them, so nothing
+//! touches disk and everything is gone when the process exits
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    config::PAGE_NUM_ON_CREATE,
+    defines::PageNum,
+    page::{Page, PageBuf},
+    utils::serial_cell::SerialCell,
+};
+
+pub use super::CacheStats;
+
+fn not_found() -> Error {
+    ErrorKind::NotFound.into()
+}
+
+fn already_exists() -> Error {
+    ErrorKind::AlreadyExists.into()
+}
+
+#[derive(Default)]
+struct MemoryFs {
+    dirs: HashSet<PathBuf>,
+    files: HashMap<PathBuf, Vec<u8>>,
+    /// Files of pages, kept as pages so that their headers are aligned
+    pages: HashMap<PathBuf, Vec<PageBuf>>,
+    /// Files of pages opened by the page manager
+    open: HashSet<PathBuf>,
+    stats: CacheStats,
+}
+
+impl MemoryFs {
+    fn exists(&self, path: &Path) -> bool {
+        self.dirs.contains(path) || self.files.contains_key(path) || self.pages.contains_key(path)
+    }
+
+    /// Pages of an open file, grown to have page `pagenum`
+    fn page(&mut self, filepath: &Path, pagenum: PageNum) -> Result<&mut PageBuf> {
+        if !self.open.contains(filepath) {
+            return Err(not_found());
+        }
+        // every page is in memory already
+        self.stats.hits += 1;
+        let pages = self.pages.get_mut(filepath).ok_or_else(not_found)?;
+        let pagenum = pagenum as usize;
+        if pages.len() <= pagenum {
+            pages.resize(pagenum + 1, PageBuf::new());
+        }
+        Ok(&mut pages[pagenum])
+    }
+}
+
+lazy_static! {
+    static ref MEMORY_FS: SerialCell<MemoryFs> = SerialCell::new(MemoryFs::default());
+}
+
+pub fn open_file(filepath: &Path) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    if !inner.open.insert(filepath.to_owned()) {
+        return Err(already_exists());
+    }
+    if !inner.pages.contains_key(filepath) {
+        let pages = vec![PageBuf::new(); PAGE_NUM_ON_CREATE as usize];
+        inner.pages.insert(filepath.to_owned(), pages);
+    }
+    Ok(())
+}
+
+pub fn close_file(filepath: &Path) -> Result<()> {
+    if MEMORY_FS.borrow_mut().open.remove(filepath) {
+        Ok(())
+    } else {
+        Err(not_found())
+    }
+}
+
+pub fn read_page<T>(
+    filepath: &Path,
+    pagenum: PageNum,
+    action: impl FnOnce(&Page) -> T,
+) -> Result<T> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    let page = inner.page(filepath, pagenum)?;
+    Ok(action(page))
+}
+
+pub fn modify_page<T>(
+    filepath: &Path,
+    pagenum: PageNum,
+    action: impl FnOnce(&mut Page) -> T,
+) -> Result<T> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    let page = inner.page(filepath, pagenum)?;
+    Ok(action(page))
+}
+
+pub fn prefetch_page(filepath: &Path, pagenum: PageNum) -> Result<()> {
+    read_page(filepath, pagenum, |_| ())
+}
+
+pub fn flush_all() -> Result<()> {
+    Ok(())
+}
+
+pub fn reserve_page(filepath: &Path, n: PageNum) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    let pages = inner.pages.get_mut(filepath).ok_or_else(not_found)?;
+    if pages.len() < n as usize {
+        pages.resize(n as usize, PageBuf::new());
+    }
+    Ok(())
+}
+
+pub fn truncate_file(filepath: &Path, n: PageNum) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    let pages = inner.pages.get_mut(filepath).ok_or_else(not_found)?;
+    pages.truncate(n as usize);
+    Ok(())
+}
+
+pub fn cache_stats() -> CacheStats {
+    MEMORY_FS.borrow().stats
+}
+
+/// All pages are in memory
+pub fn cache_size() -> usize {
+    usize::MAX
+}
+
+pub fn create_dir(dir: &Path) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    if inner.exists(dir) {
+        return Err(already_exists());
+    }
+    inner.dirs.insert(dir.to_owned());
+    Ok(())
+}
+
+pub fn remove_dir_all(dir: &Path) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    if !inner.dirs.contains(dir) {
+        return Err(not_found());
+    }
+    inner.dirs.retain(|path| !path.starts_with(dir));
+    inner.files.retain(|path, _| !path.starts_with(dir));
+    inner.pages.retain(|path, _| !path.starts_with(dir));
+    inner.open.retain(|path| !path.starts_with(dir));
+    Ok(())
+}
+
+/// Rename a file or a directory along with everything in it
+pub fn rename(from: &Path, to: &Path) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    if !inner.exists(from) {
+        return Err(not_found());
+    }
+    let moved = |path: &PathBuf| match path.strip_prefix(from) {
+        Ok(rest) if rest.as_os_str() == "" => to.to_owned(),
+        Ok(rest) => to.join(rest),
+        Err(_) => path.clone(),
+    };
+    inner.dirs = inner.dirs.iter().map(moved).collect();
+    inner.files = inner
+        .files
+        .drain()
+        .map(|(path, file)| (moved(&path), file))
+        .collect();
+    inner.pages = inner
+        .pages
+        .drain()
+        .map(|(path, file)| (moved(&path), file))
+        .collect();
+    inner.open = inner.open.iter().map(moved).collect();
+    Ok(())
+}
+
+pub fn is_dir(path: &Path) -> bool {
+    MEMORY_FS.borrow().dirs.contains(path)
+}
+
+pub fn exists(path: &Path) -> bool {
+    MEMORY_FS.borrow().exists(path)
+}
+
+/// Names of the directories in `dir`
+pub fn list_dirs(dir: &Path) -> Result<Vec<String>> {
+    let inner = MEMORY_FS.borrow();
+    if !inner.dirs.contains(dir) {
+        return Err(not_found());
+    }
+    let names = inner
+        .dirs
+        .iter()
+        .filter(|path| path.parent() == Some(dir))
+        .filter_map(|path| path.file_name()?.to_str().map(ToOwned::to_owned))
+        .collect();
+    Ok(names)
+}
+
+pub fn read(file: &Path) -> Result<Vec<u8>> {
+    MEMORY_FS
+        .borrow()
+        .files
+        .get(file)
+        .cloned()
+        .ok_or_else(not_found)
+}
+
+/// Replace the content of a file, which is created if missing
+pub fn write(file: &Path, data: &[u8]) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    inner.files.insert(file.to_owned(), data.to_owned());
+    Ok(())
+}
+
+/// Remove a file, which may not exist
+pub fn ensure_remove(file: &Path) -> Result<()> {
+    let mut inner = MEMORY_FS.borrow_mut();
+    inner.files.remove(file);
+    inner.pages.remove(file);
+    Ok(())
+}
+
+/// Number of pages a file of pages has room for
+pub fn page_count(file: &Path) -> Result<u64> {
+    let inner = MEMORY_FS.borrow();
+    let pages = inner.pages.get(file).ok_or_else(not_found)?;
+    Ok(pages.len() as u64)
+}
@@ -1,16 +1,22 @@
 use std::{
     collections::HashMap,
     fs::File,
+    hint::black_box,
     io::{Error, ErrorKind, Result},
     path::{Path, PathBuf},
 };
 
+use fixedbitset::FixedBitSet;
 use lazy_static::lazy_static;
 use memmap::{MmapMut, MmapOptions};
 
 use crate::{config::PAGE_SIZE, defines::PageNum, page::Page, utils::serial_cell::SerialCell};
 
-use super::file_manager::{fs_create_file, fs_open_file, fs_reserve_page};
+pub use super::CacheStats;
+
+use super::file_manager::{
+    fs_create_file, fs_disk_full, fs_open_file, fs_reserve_page, fs_truncate_page,
+};
 
 fn not_found() -> Error {
     ErrorKind::NotFound.into()
@@ -22,12 +28,35 @@ fn already_exists() -> Error {
 
 struct MmapManager {
     map_record: HashMap<PathBuf, (File, MmapMut)>,
+    /// Pages of each file accessed since it was mapped, the first access
+    /// of a page faults it in and is counted as a miss
+    touched: HashMap<PathBuf, FixedBitSet>,
+    stats: CacheStats,
 }
 
 impl MmapManager {
     fn new() -> Self {
         Self {
             map_record: HashMap::new(),
+            touched: HashMap::new(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    fn touch(&mut self, filepath: &Path, pagenum: PageNum) {
+        // the path is only copied the first time a page of the file is touched
+        let touched = match self.touched.get_mut(filepath) {
+            Some(touched) => touched,
+            None => self.touched.entry(filepath.to_owned()).or_default(),
+        };
+        let pagenum = pagenum as usize;
+        if pagenum >= touched.len() {
+            touched.grow(pagenum + 1);
+        }
+        if touched.put(pagenum) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
         }
     }
 }
@@ -49,11 +78,9 @@ pub fn open_file(filepath: &Path) -> Result<()> {
 }
 
 pub fn close_file(filepath: &Path) -> Result<()> {
-    MMAP_MANAGER
-        .borrow_mut()
-        .map_record
-        .remove(filepath)
-        .ok_or_else(not_found)?;
+    let mut inner = MMAP_MANAGER.borrow_mut();
+    inner.map_record.remove(filepath).ok_or_else(not_found)?;
+    inner.touched.remove(filepath);
     Ok(())
 }
 
@@ -63,11 +90,12 @@ pub fn read_page<T>(
     action: impl FnOnce(&Page) -> T,
 ) -> Result<T> {
     let mut inner = MMAP_MANAGER.borrow_mut();
+    inner.touch(filepath, pagenum);
     let (file, mmap) = inner.map_record.get_mut(filepath).ok_or_else(not_found)?;
     let start = pagenum as usize * PAGE_SIZE;
     let end = start + PAGE_SIZE;
     if end >= mmap.len() {
-        file.set_len(end as u64)?;
+        file.set_len(end as u64).map_err(fs_disk_full)?;
         *mmap = unsafe { MmapOptions::new().map_mut(file)? };
     }
     let range = &mmap[start..end];
@@ -80,20 +108,28 @@ pub fn modify_page<T>(
     action: impl FnOnce(&mut Page) -> T,
 ) -> Result<T> {
     let mut inner = MMAP_MANAGER.borrow_mut();
+    inner.touch(filepath, pagenum);
     let (file, mmap) = inner.map_record.get_mut(filepath).ok_or_else(not_found)?;
     let start = pagenum as usize * PAGE_SIZE;
     let end = start + PAGE_SIZE;
     if end >= mmap.len() {
-        file.set_len(end as u64)?;
+        file.set_len(end as u64).map_err(fs_disk_full)?;
         *mmap = unsafe { MmapOptions::new().map_mut(file)? };
     }
     let range = &mut mmap[start..end];
     Ok(action(unsafe { Page::from_mut_unchecked(range) }))
 }
 
+/// Fault a page in by reading all of it
+pub fn prefetch_page(filepath: &Path, pagenum: PageNum) -> Result<()> {
+    read_page(filepath, pagenum, |page| {
+        black_box(page.iter().fold(0, |acc, &byte| acc ^ byte));
+    })
+}
+
 pub fn flush_all() -> Result<()> {
     for (_, mmap) in MMAP_MANAGER.borrow().map_record.values() {
-        mmap.flush()?;
+        mmap.flush().map_err(fs_disk_full)?;
     }
     Ok(())
 }
@@ -105,3 +141,29 @@ pub fn reserve_page(filepath: &Path, n: PageNum) -> Result<()> {
     *mmap = unsafe { MmapOptions::new().map_mut(file)? };
     Ok(())
 }
+
+/// Shrink a file to `n` pages, which must not be 0 as an empty file cannot be mapped
+pub fn truncate_file(filepath: &Path, n: PageNum) -> Result<()> {
+    let mut inner = MMAP_MANAGER.borrow_mut();
+    let (file, mmap) = inner.map_record.get_mut(filepath).ok_or_else(not_found)?;
+    mmap.flush()?;
+    fs_truncate_page(file, n)?;
+    *mmap = unsafe { MmapOptions::new().map_mut(file)? };
+    // pages mapped again later are faulted in again
+    if let Some(touched) = inner.touched.get_mut(filepath) {
+        if (n as usize) < touched.len() {
+            touched.set_range(n as usize.., false);
+        }
+    }
+    Ok(())
+}
+
+pub fn cache_stats() -> CacheStats {
+    MMAP_MANAGER.borrow().stats
+}
+
+/// Number of pages the cache holds, all pages of open files are mapped
+/// and the kernel keeps as many of them in memory as it can
+pub fn cache_size() -> usize {
+    usize::MAX
+}
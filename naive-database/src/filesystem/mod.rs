@@ -1,12 +1,32 @@
 pub mod file_manager;
 
-#[cfg(not(feature = "mmap"))]
-#[path = "page_manager.rs"]
-pub mod page_manager;
+/// Page requests answered from the cache and those read from files since start
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+#[cfg(any(feature = "memory", test))]
+#[cfg_attr(not(feature = "memory"), allow(dead_code))]
+pub mod memory;
+
+#[cfg(feature = "memory")]
+pub use memory as page_manager;
+
+#[cfg(feature = "memory")]
+pub use memory as storage;
 
-#[cfg(feature = "mmap")]
+#[cfg(all(feature = "mmap", not(feature = "memory")))]
 #[path = "mmap.rs"]
 pub mod page_manager;
 
+#[cfg(not(any(feature = "mmap", feature = "memory")))]
+#[path = "page_manager.rs"]
+pub mod page_manager;
+
+#[cfg(not(feature = "memory"))]
+pub mod storage;
+
 #[cfg(test)]
 mod tests;
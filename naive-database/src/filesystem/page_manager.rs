@@ -19,8 +19,11 @@ use crate::{
     },
 };
 
+pub use super::CacheStats;
+
 use super::file_manager::{
-    fs_create_file, fs_open_file, fs_read_page_to, fs_reserve_page, fs_write_page_from,
+    fs_create_file, fs_open_file, fs_read_page_to, fs_reserve_page, fs_truncate_page,
+    fs_write_page_from,
 };
 
 fn not_found() -> Error {
@@ -40,6 +43,7 @@ struct PageManager {
     page_cache: Vec<PageBuf>,
     lru: LruRecord,
     dirty: FixedBitSet,
+    stats: CacheStats,
 }
 
 impl PageManager {
@@ -51,6 +55,7 @@ impl PageManager {
             index_record: BiHashMap::new(),
             lru: LruRecord::new(cache_size),
             dirty: FixedBitSet::with_capacity(cache_size),
+            stats: CacheStats::default(),
         }
     }
 
@@ -82,7 +87,10 @@ impl PageManager {
             None => (false, self.lru.find_furthest()),
         };
 
-        if !hit {
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
             let insert_result = self
                 .index_record
                 .insert((filepath.to_owned(), pagenum), cache_index);
@@ -133,6 +141,22 @@ impl PageManager {
         }
     }
 
+    /// Cached pages past the new end are dropped without being written back
+    fn truncate_file(&mut self, filepath: &Path, n: PageNum) -> Result<()> {
+        let cache_indexes: Vec<_> = self
+            .index_record
+            .iter()
+            .filter(|((name, pagenum), _)| name == filepath && *pagenum >= n)
+            .map(|(_, cache_index)| *cache_index)
+            .collect();
+        for cache_index in cache_indexes {
+            self.index_record.remove_by_right(&cache_index);
+            self.dirty.set(cache_index, false);
+        }
+        let file = self.file_record.get(filepath).ok_or_else(not_found)?;
+        fs_truncate_page(file, n)
+    }
+
     fn get_read(&mut self, filepath: &Path, pagenum: PageNum) -> Result<&Page> {
         self.get_page(filepath, pagenum, false).map(|page| &*page)
     }
@@ -187,10 +211,32 @@ pub fn modify_page<T>(
     Ok(action(page))
 }
 
+/// Bring a page into the cache without using it
+pub fn prefetch_page(filepath: &Path, pagenum: PageNum) -> Result<()> {
+    PAGE_MANAGER
+        .borrow_mut()
+        .get_read(filepath, pagenum)
+        .map(|_| ())
+}
+
 pub fn flush_all() -> Result<()> {
     PAGE_MANAGER.borrow_mut().flush_all()
 }
 
+pub fn cache_stats() -> CacheStats {
+    PAGE_MANAGER.borrow().stats
+}
+
+/// Number of pages the cache holds
+pub fn cache_size() -> usize {
+    PAGE_MANAGER.borrow().page_cache.len()
+}
+
+/// Shrink a file to `n` pages
+pub fn truncate_file(filepath: &Path, n: PageNum) -> Result<()> {
+    PAGE_MANAGER.borrow_mut().truncate_file(filepath, n)
+}
+
 pub fn reserve_page(filepath: &Path, n: PageNum) -> Result<()> {
     let inner = PAGE_MANAGER.borrow();
     let file = inner.file_record.get(filepath).ok_or_else(not_found)?;
@@ -0,0 +1,70 @@
+//! Files other than pages, i.e. directories of databases and metadata, kept on disk
+
+use std::{
+    fs::{self, File},
+    io::{Result, Write},
+    path::{Path, PathBuf},
+};
+
+use super::file_manager::{fs_disk_full, fs_ensure_remove, fs_page_count};
+
+pub fn create_dir(dir: &Path) -> Result<()> {
+    fs::create_dir(dir)
+}
+
+pub fn remove_dir_all(dir: &Path) -> Result<()> {
+    fs::remove_dir_all(dir)
+}
+
+pub fn rename(from: &Path, to: &Path) -> Result<()> {
+    fs::rename(from, to)
+}
+
+pub fn is_dir(path: &Path) -> bool {
+    path.is_dir()
+}
+
+pub fn exists(path: &Path) -> bool {
+    path.exists()
+}
+
+/// Names of the directories in `dir`
+pub fn list_dirs(dir: &Path) -> Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.metadata()?.is_dir() {
+            names.extend(entry.file_name().to_str().map(ToOwned::to_owned));
+        }
+    }
+    Ok(names)
+}
+
+pub fn read(file: &Path) -> Result<Vec<u8>> {
+    fs::read(file)
+}
+
+/// Replace the content of a file, which is created if missing. The content is written to
+/// a temporary file renamed over the file, so a failed write leaves the file as it was
+pub fn write(file: &Path, data: &[u8]) -> Result<()> {
+    let mut temp = file.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let written = File::create(&temp)
+        .and_then(|mut f| f.write_all(data).and_then(|_| f.sync_data()))
+        .and_then(|_| fs::rename(&temp, file));
+    if written.is_err() {
+        fs_ensure_remove(&temp).ok();
+    }
+    written.map_err(fs_disk_full)
+}
+
+/// Remove a file, which may not exist
+pub fn ensure_remove(file: &Path) -> Result<()> {
+    fs_ensure_remove(file)
+}
+
+/// Number of pages a file of pages has room for
+pub fn page_count(file: &Path) -> Result<u64> {
+    fs_page_count(&File::open(file)?)
+}
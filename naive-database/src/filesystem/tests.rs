@@ -1,10 +1,11 @@
-use std::{io::Result, iter::from_fn};
+use std::{io::Result, iter::from_fn, sync::PoisonError};
 
 use rand::prelude::*;
 use tempfile::tempdir;
 
 use crate::{
     config::{LRU_SIZE, PAGE_SIZE},
+    dbms::tests::TEST_LOCK,
     defines::PageNum,
     page::PageBuf,
 };
@@ -13,6 +14,7 @@ use super::{file_manager::*, page_manager::*};
 
 #[test]
 fn simple_cache_test() -> Result<()> {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
     let file_num = 20;
     let max_pagenum = (LRU_SIZE / file_num) as PageNum;
 
@@ -105,3 +107,76 @@ fn test_simple_io() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn memory_files_not_on_disk() -> Result<()> {
+    use std::path::Path;
+
+    use super::memory;
+
+    let base = Path::new("memory_test");
+    let dir = base.join("db");
+    let file = dir.join("table.data");
+    memory::create_dir(base)?;
+    memory::create_dir(&dir)?;
+    memory::write(&dir.join("table.meta"), b"meta")?;
+    memory::open_file(&file)?;
+    memory::modify_page(&file, 5, |page| page[..4].copy_from_slice(b"page"))?;
+    assert_eq!(memory::page_count(&file)?, 6);
+    memory::close_file(&file)?;
+    assert!(!dir.exists());
+
+    let renamed = base.join("renamed");
+    memory::rename(&dir, &renamed)?;
+    assert!(!memory::exists(&dir));
+    assert_eq!(memory::list_dirs(base)?, vec!["renamed"]);
+    assert_eq!(memory::read(&renamed.join("table.meta"))?, b"meta");
+    let file = renamed.join("table.data");
+    memory::open_file(&file)?;
+    memory::read_page(&file, 5, |page| assert_eq!(&page[..4], b"page"))?;
+    memory::truncate_file(&file, 2)?;
+    assert_eq!(memory::page_count(&file)?, 2);
+    memory::close_file(&file)?;
+
+    memory::remove_dir_all(base)?;
+    assert!(!memory::exists(&renamed.join("table.meta")));
+    assert!(memory::page_count(&file).is_err());
+    Ok(())
+}
+
+#[test]
+fn disk_full_reported() -> Result<()> {
+    use std::{fs::OpenOptions, io::ErrorKind, path::Path};
+
+    // writes to /dev/full fail with ENOSPC
+    let dev_full = Path::new("/dev/full");
+    if !dev_full.exists() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().write(true).open(dev_full)?;
+    let err = fs_write_page(&mut file, 0, &PageBuf::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::StorageFull);
+    assert!(err.to_string().contains("disk full"));
+    Ok(())
+}
+
+/// Only files on disk are written through a temporary file
+#[test]
+#[cfg(not(feature = "memory"))]
+fn failed_metadata_write_keeps_file() -> Result<()> {
+    use std::fs;
+
+    use super::storage;
+
+    let tempdir = tempdir()?;
+    let file = tempdir.path().join("table.meta");
+    storage::write(&file, b"old")?;
+    storage::write(&file, b"new")?;
+    assert_eq!(fs::read(&file)?, b"new");
+
+    // the temporary file cannot be created where a directory is
+    fs::create_dir(tempdir.path().join("table.meta.tmp"))?;
+    assert!(storage::write(&file, b"partial").is_err());
+    assert_eq!(fs::read(&file)?, b"new");
+    Ok(())
+}
@@ -10,7 +10,8 @@ use crate::{
     config::MAX_COMP_INDEX,
     dbms::database::{ensure_table, get_table},
     defines::{ColID, RowID, TableID},
-    record::ColumnVal,
+    error::DBResult,
+    record::{format_key, ColumnVal},
     utils::persistence::Persistence,
 };
 
@@ -40,7 +41,7 @@ impl EntryRef {
     // still need check here
     fn comp_at(&self, other: &Self, idx: u8) -> Ordering {
         debug_assert!(idx < self.len);
-        // null treated as the greatest elem
+        // null is less than any value, and nulls are ordered by rid
         match (self.nullat(idx), other.nullat(idx)) {
             (true, true) => return self.rid.cmp(&other.rid),
             (true, false) => return Ordering::Less,
@@ -81,6 +82,17 @@ impl EntryRef {
             l.partial_cmp(colval).unwrap()
         })
     }
+
+    /// Compare the leading `len` columns of the entry with `data`
+    fn comp_with_data(&self, data: &[Option<ColumnVal>], len: u8) -> Ordering {
+        for idx in 0..len {
+            let cmp_res = self.comp_with_data_at(&data[idx as usize], idx);
+            if !cmp_res.is_eq() {
+                return cmp_res;
+            }
+        }
+        Ordering::Equal
+    }
 }
 
 impl PartialEq for EntryRef {
@@ -115,6 +127,12 @@ impl PartialOrd for EntryRef {
 pub enum IndexKey {
     Ref(EntryRef),
     Data([Option<ColumnVal>; MAX_COMP_INDEX]),
+    /// Data sorted before (`Less`) or after (`Greater`) all entries equal to it,
+    /// a range bound equal to some entries would only find one of them
+    Bound([Option<ColumnVal>; MAX_COMP_INDEX], Ordering),
+    /// Data of the leading columns of the index, how many of them, and which side
+    /// of the entries starting with them it sorts, like `Bound`
+    Prefix([Option<ColumnVal>; MAX_COMP_INDEX], u8, Ordering),
 }
 
 impl IndexKey {
@@ -124,22 +142,38 @@ impl IndexKey {
             _ => panic!("not ref"),
         }
     }
+
+    /// Key of the leading columns of an index, equal to every entry starting with `colval`
+    pub fn prefix(colval: &[Option<ColumnVal>]) -> Self {
+        match colval.into() {
+            Self::Data(data) => Self::Prefix(data, colval.len() as _, Ordering::Equal),
+            _ => unreachable!(),
+        }
+    }
+
+    fn bound(self, side: Ordering) -> Self {
+        match self {
+            Self::Data(data) => Self::Bound(data, side),
+            Self::Prefix(data, len, _) => Self::Prefix(data, len, side),
+            key => key,
+        }
+    }
+
+    fn side(&self) -> Ordering {
+        match self {
+            Self::Bound(_, side) | Self::Prefix(_, _, side) => *side,
+            _ => Ordering::Equal,
+        }
+    }
 }
 
 impl From<&[Option<ColumnVal>]> for IndexKey {
     fn from(colval: &[Option<ColumnVal>]) -> Self {
-        use std::mem::MaybeUninit;
-        let len;
-        let mut buf: [Option<ColumnVal>; MAX_COMP_INDEX] = unsafe {
-            let mut buf: [MaybeUninit<Option<ColumnVal>>; MAX_COMP_INDEX] =
-                MaybeUninit::uninit().assume_init();
-            len = colval.len();
-            for (i, val) in colval.iter().enumerate() {
-                buf[i].write(val.clone());
-            }
-            transmute(buf)
-        };
-        (&mut buf[len..]).fill(None);
+        // unused trailing columns are NULL
+        let mut buf: [Option<ColumnVal>; MAX_COMP_INDEX] = Default::default();
+        for (slot, val) in buf.iter_mut().zip(colval) {
+            *slot = val.clone();
+        }
         Self::Data(buf)
     }
 }
@@ -192,29 +226,25 @@ impl Ord for IndexKey {
         use IndexKey::*;
 
         match (self, other) {
-            (Ref(eref), Data(data)) => {
-                let mut cmp_res = Ordering::Equal;
-                for idx in 0..eref.len {
-                    cmp_res = eref.comp_with_data_at(&data[idx as usize], idx);
-                    if !cmp_res.is_eq() {
-                        return cmp_res;
-                    }
-                }
-                cmp_res
+            (Ref(eref), Data(data)) | (Ref(eref), Bound(data, _)) => {
+                eref.comp_with_data(data, eref.len).then(other.side().reverse())
             }
-            (Data(data), Ref(eref)) => {
-                let mut cmp_res = Ordering::Equal;
-                for idx in 0..eref.len {
-                    cmp_res = eref.comp_with_data_at(&data[idx as usize], idx);
-                    if !cmp_res.is_eq() {
-                        return cmp_res.reverse();
-                    }
-                }
-                cmp_res
+            (Data(data), Ref(eref)) | (Bound(data, _), Ref(eref)) => {
+                eref.comp_with_data(data, eref.len).reverse().then(self.side())
+            }
+            (Ref(eref), Prefix(data, len, _)) => {
+                eref.comp_with_data(data, *len).then(other.side().reverse())
+            }
+            (Prefix(data, len, _), Ref(eref)) => {
+                eref.comp_with_data(data, *len).reverse().then(self.side())
             }
 
             (Ref(l), Ref(r)) => l.cmp(r),
-            (Data(l), Data(r)) => l.partial_cmp(r).unwrap(),
+            // trailing columns of a prefix are NULL, like unused ones of data
+            (Data(l) | Bound(l, _) | Prefix(l, _, _), Data(r) | Bound(r, _) | Prefix(r, _, _)) => l
+                .partial_cmp(r)
+                .unwrap()
+                .then(self.side().cmp(&other.side())),
         }
     }
 }
@@ -225,6 +255,8 @@ pub struct ColIndex {
     pub len: u8,
     pub col: [ColID; MAX_COMP_INDEX],
     pub list: BTreeSet<IndexKey>,
+    /// No two entries have the same key, unless it has NULL
+    pub unique: bool,
 }
 
 impl ColIndex {
@@ -233,16 +265,71 @@ impl ColIndex {
         len: u8,
         col: [ColID; MAX_COMP_INDEX],
         list: BTreeSet<IndexKey>,
+        unique: bool,
     ) -> Self {
         Self {
             tbl: id,
             col,
             list,
             len,
+            unique,
         }
     }
 
-    pub fn insert_record(&mut self, row_id: RowID, data: &[Option<ColumnVal>]) {
+    /// Values of the indexed columns of a row
+    fn key_data(&self, data: &[Option<ColumnVal>]) -> Vec<Option<ColumnVal>> {
+        self.col[..self.len as usize]
+            .iter()
+            .map(|&col| data[col as usize].clone())
+            .collect()
+    }
+
+    /// Whether some entry has the values of `key` in its indexed columns
+    pub fn contains_key(&self, key: &[Option<ColumnVal>]) -> bool {
+        self.list.contains(&key.into())
+    }
+
+    /// Fail if the index is unique and already has the key of the row. A key with NULL
+    /// is never a duplicate, as NULL equals nothing
+    pub fn check_unique(&self, data: &[Option<ColumnVal>]) -> DBResult<()> {
+        if !self.unique {
+            return Ok(());
+        }
+        let key = self.key_data(data);
+        if key.iter().all(Option::is_some) && self.contains_key(&key) {
+            return Err(format!("duplicate key {} in unique index", format_key(&key)).into());
+        }
+        Ok(())
+    }
+
+    /// Positions of rows a unique index would reject, as their keys are in the index
+    /// already or are the same as the key of a row before them. Nothing is rejected by
+    /// an index which is not unique
+    pub fn duplicate_records(
+        &self,
+        records: &[impl AsRef<[Option<ColumnVal>]>],
+    ) -> BTreeSet<usize> {
+        let mut duplicates = BTreeSet::new();
+        if !self.unique {
+            return duplicates;
+        }
+        let mut keys = records
+            .iter()
+            .map(|data| self.key_data(data.as_ref()))
+            .enumerate()
+            .filter(|(_, key)| key.iter().all(Option::is_some))
+            .collect::<Vec<_>>();
+        // a stable sort keeps the first row with a key before the others
+        keys.sort_by(|(_, lhs), (_, rhs)| lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal));
+        for (i, (pos, key)) in keys.iter().enumerate() {
+            if (i > 0 && keys[i - 1].1 == *key) || self.contains_key(key) {
+                duplicates.insert(*pos);
+            }
+        }
+        duplicates
+    }
+
+    fn key_of(&self, row_id: RowID, data: &[Option<ColumnVal>]) -> IndexKey {
         let mut fastcmp_buf: [FastCmp; MAX_COMP_INDEX] =
             unsafe { transmute([0u8; MAX_COMP_INDEX * size_of::<FastCmp>()]) };
         let mut is_null = 0u8;
@@ -253,7 +340,7 @@ impl ColIndex {
                 is_null |= 1 << i;
             }
         }
-        let index_key = EntryRef {
+        EntryRef {
             col: self.col,
             fast_cmp: fastcmp_buf,
             is_null,
@@ -261,30 +348,66 @@ impl ColIndex {
             rid: row_id,
             tbl: self.tbl,
         }
-        .into();
-        self.list.insert(index_key);
+        .into()
     }
 
-    pub fn remove_record(&mut self, row_id: RowID, data: &[Option<ColumnVal>]) {
-        let mut fastcmp_buf: [FastCmp; MAX_COMP_INDEX] =
-            unsafe { transmute([0u8; MAX_COMP_INDEX * size_of::<FastCmp>()]) };
-        let mut is_null = 0u8;
-        for i in 0..self.len {
-            if let Some(colval) = data.get(self.col[i as usize] as usize).unwrap() {
-                fastcmp_buf[i as usize] = colval.into();
-            } else {
-                is_null |= 1 << i;
+    /// Insert the entry of a row, which a unique index rejects if it has the key already
+    pub fn insert_record(&mut self, row_id: RowID, data: &[Option<ColumnVal>]) -> DBResult<()> {
+        self.check_unique(data)?;
+        let index_key = self.key_of(row_id, data);
+        self.list.insert(index_key);
+        Ok(())
+    }
+
+    /// Insert entries of many rows. They are sorted by their values first, which unlike
+    /// comparing entries does not look rows up in the table, and an empty index is built
+    /// from the sorted entries at once. A unique index rejects them all if one of them
+    /// has the key of an entry or of another row
+    pub fn extend_records<'a>(
+        &mut self,
+        records: impl Iterator<Item = (RowID, &'a [Option<ColumnVal>])>,
+    ) -> DBResult<()> {
+        let cols = &self.col[..self.len as usize];
+        let mut records = records.collect::<Vec<_>>();
+        if self.unique {
+            let datas = records.iter().map(|&(_, data)| data).collect::<Vec<_>>();
+            if let Some(&pos) = self.duplicate_records(&datas).first() {
+                let key = self.key_data(datas[pos]);
+                return Err(format!("duplicate key {} in unique index", format_key(&key)).into());
             }
         }
-        let index_key: IndexKey = EntryRef {
-            col: self.col,
-            fast_cmp: fastcmp_buf,
-            is_null,
-            len: self.len as _,
-            rid: row_id,
-            tbl: self.tbl,
+        records.sort_unstable_by(|(lrid, ldata), (rrid, rdata)| {
+            // the same order as `EntryRef`
+            for &col in cols {
+                let ord = match (&ldata[col as usize], &rdata[col as usize]) {
+                    (None, None) => return lrid.cmp(rrid),
+                    (None, Some(_)) => Ordering::Less,
+                    (Some(_), None) => Ordering::Greater,
+                    (Some(lval), Some(rval)) => FastCmp::from(lval)
+                        .cmp(&FastCmp::from(rval))
+                        .then_with(|| lval.partial_cmp(rval).unwrap()),
+                };
+                if !ord.is_eq() {
+                    return ord;
+                }
+            }
+            lrid.cmp(rrid)
+        });
+
+        let keys = records
+            .into_iter()
+            .map(|(rid, data)| self.key_of(rid, data))
+            .collect::<Vec<_>>();
+        if self.list.is_empty() {
+            self.list = keys.into_iter().collect();
+        } else {
+            self.list.extend(keys);
         }
-        .into();
+        Ok(())
+    }
+
+    pub fn remove_record(&mut self, row_id: RowID, data: &[Option<ColumnVal>]) {
+        let index_key = self.key_of(row_id, data);
         self.list.remove(&index_key);
     }
 
@@ -328,13 +451,16 @@ impl ColIndex {
 
     #[inline]
     pub fn lower_bound_eq<T: Into<IndexKey>>(&self, key: T) -> Option<&EntryRef> {
-        self.list.range(key.into()..).next().map(|key| key.to_ref())
+        self.list
+            .range(key.into().bound(Ordering::Less)..)
+            .next()
+            .map(|key| key.to_ref())
     }
 
     #[inline]
     pub fn upper_bound_eq<T: Into<IndexKey>>(&self, key: T) -> Option<&EntryRef> {
         self.list
-            .range(..=key.into())
+            .range(..=key.into().bound(Ordering::Greater))
             .next_back()
             .map(|key| key.to_ref())
     }
@@ -346,7 +472,9 @@ impl ColIndex {
         upper_key: T,
     ) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range(lower_key.into()..=upper_key.into())
+            .range(
+                lower_key.into().bound(Ordering::Less)..=upper_key.into().bound(Ordering::Greater),
+            )
             .map(|key| key.to_ref().rid)
     }
 
@@ -357,11 +485,14 @@ impl ColIndex {
         upper_key: T,
     ) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range(..lower_key.into())
+            .range(..lower_key.into().bound(Ordering::Less))
             .map(|key| key.to_ref().rid)
             .chain(
                 self.list
-                    .range((Excluded(upper_key.into()), Unbounded))
+                    .range((
+                        Excluded(upper_key.into().bound(Ordering::Greater)),
+                        Unbounded,
+                    ))
                     .map(|key| key.to_ref().rid),
             )
     }
@@ -369,7 +500,7 @@ impl ColIndex {
     #[inline]
     pub fn lower_range_rows<T: Into<IndexKey>>(&self, key: T) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range((Unbounded, Excluded(key.into())))
+            .range((Unbounded, Excluded(key.into().bound(Ordering::Less))))
             .map(|key| key.to_ref().rid)
     }
 
@@ -379,14 +510,14 @@ impl ColIndex {
         key: T,
     ) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range((Unbounded, Included(key.into())))
+            .range((Unbounded, Included(key.into().bound(Ordering::Greater))))
             .map(|key| key.to_ref().rid)
     }
 
     #[inline]
     pub fn upper_range_rows<T: Into<IndexKey>>(&self, key: T) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range((Excluded(key.into()), Unbounded))
+            .range((Excluded(key.into().bound(Ordering::Greater)), Unbounded))
             .map(|key| key.to_ref().rid)
     }
 
@@ -396,7 +527,7 @@ impl ColIndex {
         key: T,
     ) -> impl Iterator<Item = RowID> + '_ {
         self.list
-            .range((Included(key.into()), Unbounded))
+            .range((Included(key.into().bound(Ordering::Less)), Unbounded))
             .map(|key| key.to_ref().rid)
     }
 
@@ -425,3 +556,197 @@ impl Persistence for ColIndex {
         Self::format_filename(self.tbl, &self.col[..self.len as usize])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        dbms::{
+            database, system,
+            tests::{query_sql, row_count, run, with_database},
+        },
+        error::DBResult,
+        record::ColumnVal,
+    };
+
+    #[test]
+    fn index_dump_in_key_order() -> DBResult<()> {
+        with_database("index_dump", || {
+            run("CREATE TABLE t (a INT, b INT); CREATE INDEX ON t (a, b);")?;
+            run("INSERT INTO t VALUES (3, 1), (1, 2), (2, 2), (2, NULL), (-5, 0), (2, 1);")?;
+            let dump = system::index_dump("t", &["a".to_owned(), "b".to_owned()])?;
+            assert_eq!(dump.header, ["rid", "a", "b", "is_null"]);
+            let int = |i| Some(ColumnVal::Int(i));
+            let keys = dump
+                .rows
+                .iter()
+                .map(|row| (row[1].clone(), row[2].clone()))
+                .collect::<Vec<_>>();
+            // NULL sorts before other values in an index
+            assert_eq!(
+                keys,
+                [
+                    (int(-5), int(0)),
+                    (int(1), int(2)),
+                    (int(2), None),
+                    (int(2), int(1)),
+                    (int(2), int(2)),
+                    (int(3), int(1)),
+                ]
+            );
+            let is_null = Some(ColumnVal::Varchar("10".to_owned()));
+            assert_eq!(dump.rows[2][3], is_null);
+            assert!(system::index_dump("t", &["b".to_owned()]).is_err());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn string_index_order() -> DBResult<()> {
+        with_database("string_index", || {
+            run("CREATE TABLE t (s VARCHAR(10)); CREATE INDEX ON t (s);")?;
+            // sharing the first 4 bytes, shorter than 4 bytes, and non-ASCII bytes
+            let strings = [
+                "abcdxyz",
+                "zz",
+                "abcdefg",
+                "b",
+                "\u{e9}t\u{e9}",
+                "ab",
+                "abcd",
+                "abc",
+            ];
+            for s in strings {
+                run(&format!("INSERT INTO t VALUES ('{}');", s))?;
+            }
+            let mut sorted = strings.to_vec();
+            sorted.sort_unstable();
+            let str = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+
+            let dump = system::index_dump("t", &["s".to_owned()])?;
+            let keys = dump
+                .rows
+                .iter()
+                .map(|row| row[1].clone())
+                .collect::<Vec<_>>();
+            assert_eq!(keys, sorted.iter().map(|s| str(s)).collect::<Vec<_>>());
+            // entries compared with a value rather than with each other
+            let strs = |sql| -> DBResult<Vec<_>> {
+                let mut rows = query_sql(sql)?.rows;
+                rows.sort_unstable_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+                Ok(rows
+                    .into_iter()
+                    .map(|row| row[0].clone())
+                    .collect::<Vec<_>>())
+            };
+            assert_eq!(
+                strs("SELECT /*+ INDEX(t s) */ s FROM t WHERE s > 'abcdf';")?,
+                [str("abcdxyz"), str("b"), str("zz"), str("\u{e9}t\u{e9}")]
+            );
+            assert_eq!(
+                strs("SELECT /*+ INDEX(t s) */ s FROM t WHERE s = 'abcdefg';")?,
+                [str("abcdefg")]
+            );
+            assert_eq!(
+                strs("SELECT /*+ INDEX(t s) */ s FROM t WHERE s < 'abcd';")?,
+                [str("ab"), str("abc")]
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn high_bit_strings_in_index() -> DBResult<()> {
+        with_database("high_bit_index", || {
+            run("CREATE TABLE t (s CHAR(8), n INT); CREATE INDEX ON t (s);")?;
+            // multi-byte UTF-8 sequences start with bytes >= 0x80
+            run(
+                "INSERT INTO t VALUES ('\u{fc}ber', 1), ('zebra', 2), ('\u{e4}rger', 3), \
+                ('apfel', 4), ('\u{e9}clair', 5), ('\u{f1}u', 6), ('\u{2603}', 7);",
+            )?;
+            let ns = |cond| -> DBResult<Vec<_>> {
+                let mut ns = vec![];
+                for hint in ["", "/*+ INDEX(t s) */"] {
+                    let sql = format!("SELECT {} n FROM t WHERE {};", hint, cond);
+                    let mut rows = query_sql(&sql)?.rows;
+                    rows.sort_unstable_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap());
+                    ns.push(
+                        rows.into_iter()
+                            .map(|row| row[0].clone())
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                // the same rows with and without the index
+                assert_eq!(ns[0], ns[1], "{}", cond);
+                Ok(ns.pop().unwrap())
+            };
+            let int = |i| Some(ColumnVal::Int(i));
+            assert_eq!(ns("s > 'zebra'")?, [int(1), int(3), int(5), int(6), int(7)]);
+            assert_eq!(ns("s < '\u{e9}'")?, [int(2), int(3), int(4)]);
+            assert_eq!(
+                ns("s >= '\u{e9}clair' AND s <= '\u{fc}ber'")?,
+                [int(1), int(5), int(6)]
+            );
+            assert_eq!(ns("s = '\u{2603}'")?, [int(7)]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn unique_index_rejects_duplicates() -> DBResult<()> {
+        with_database("unique_index", || {
+            run("CREATE TABLE t (a INT NOT NULL, b INT); CREATE INDEX ON t (a);")?;
+            run("INSERT INTO t VALUES (1, 1), (2, NULL);")?;
+            let is_unique = || -> DBResult<_> {
+                let sql = "SELECT is_unique FROM __indexes WHERE table_name = 't';";
+                Ok(query_sql(sql)?.rows.remove(0).remove(0))
+            };
+            let text = |s: &str| Some(ColumnVal::Varchar(s.to_owned()));
+            assert_eq!(is_unique()?, text("No"));
+            // the plain index is replaced by a unique one
+            run("ALTER TABLE t ADD PRIMARY KEY (a);")?;
+            assert_eq!(is_unique()?, text("Yes"));
+            assert!(run("INSERT INTO t VALUES (1, 3);").is_err());
+            assert!(run("INSERT INTO t VALUES (3, 3), (3, 4);").is_err());
+            // rejected rows and updates never reach the pages
+            assert!(run("UPDATE t SET a = 1 WHERE a = 2;").is_err());
+            assert_eq!(row_count("t"), 2);
+            let result = query_sql("SELECT a FROM t ORDER BY a;")?;
+            assert_eq!(
+                result.rows,
+                [vec![Some(ColumnVal::Int(1))], vec![Some(ColumnVal::Int(2))]]
+            );
+            run("UPDATE t SET a = 3 WHERE a = 2;")?;
+            assert!(run("INSERT INTO t VALUES (3, 5);").is_err());
+            run("UPDATE t SET a = 2 WHERE a = 3;")?;
+
+            let id = database::get_table_id("t").unwrap();
+            let int = |i| Some(ColumnVal::Int(i));
+            database::ensure_table(id, |table| {
+                // the index rejects a key it has, or keys repeated among rows, by itself
+                assert!(table.insert_index_at(100, &[int(2), int(5)]).is_err());
+                let rows = [vec![int(7), None], vec![int(7), int(1)]];
+                assert!(table.extend_indices(&[100, 101], &rows).is_err());
+                let index = table.indices.values().next().unwrap().borrow();
+                assert_eq!(index.list.len(), 2);
+            });
+
+            // keys with NULL are never duplicates in the index, as rows set to NULL show
+            run(
+                "CREATE TABLE child (pid INT, FOREIGN KEY (pid) REFERENCES t (a) ON DELETE SET NULL);",
+            )?;
+            run("CREATE TABLE grandchild (cid INT);")?;
+            run("ALTER TABLE grandchild ADD CONSTRAINT FOREIGN KEY (cid) REFERENCES child (pid);")?;
+            run("INSERT INTO child VALUES (1), (2);")?;
+            run("DELETE FROM t WHERE a > 0;")?;
+            let result = query_sql("SELECT pid FROM child WHERE pid IS NULL;")?;
+            assert_eq!(result.rows.len(), 2);
+
+            // duplicates already in a plain index are found when it is made unique
+            run("CREATE TABLE v (a INT NOT NULL); CREATE INDEX ON v (a);")?;
+            run("INSERT INTO v VALUES (1), (1);")?;
+            assert!(run("ALTER TABLE v ADD PRIMARY KEY (a);").is_err());
+            run("INSERT INTO v VALUES (1);")?;
+            Ok(())
+        })
+    }
+}
@@ -4,7 +4,7 @@ use chrono::NaiveDate;
 use serde::Serialize;
 use std::{cmp::Ordering, mem};
 
-use crate::record::{ColumnType, ColumnVal};
+use crate::record::{cmp_float, ColumnType, ColumnVal};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct FastCmp {
@@ -24,13 +24,14 @@ impl FastCmp {
     pub fn from_colval(colval: &ColumnVal) -> Self {
         use ColumnVal::*;
         let data = match colval {
+            // the first 4 bytes padded with zeros, compared as unsigned they order strings by
+            // the prefix, and strings sharing it are compared in full by the index
             Char(s) | Varchar(s) => {
-                let mut data = 0;
-                for c in s.as_bytes().iter().take(4) {
-                    data <<= 8;
-                    data += *c as i32;
+                let mut prefix = [0; 4];
+                for (byte, c) in prefix.iter_mut().zip(s.as_bytes()) {
+                    *byte = *c;
                 }
-                data
+                u32::from_be_bytes(prefix) as i32
             }
             Int(i) => *i,
             Float(f) => f.to_bits() as _,
@@ -70,7 +71,8 @@ impl Ord for FastCmp {
     fn cmp(&self, other: &Self) -> Ordering {
         use ColumnType::*;
         match self.coltype {
-            Int | Char | Varchar => self.data.cmp(&other.data),
+            Int => self.data.cmp(&other.data),
+            Char | Varchar => (self.data as u32).cmp(&(other.data as u32)),
             Date => unsafe {
                 let lhs: NaiveDate = mem::transmute(self.data);
                 let rhs: NaiveDate = mem::transmute(other.data);
@@ -79,8 +81,7 @@ impl Ord for FastCmp {
             Float => {
                 let lhs = f32::from_bits(self.data as _);
                 let rhs = f32::from_bits(other.data as _);
-                lhs.partial_cmp(&rhs)
-                    .expect("I'm not expecting an NaN here")
+                cmp_float(lhs as f64, rhs as f64)
             }
         }
     }
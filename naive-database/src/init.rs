@@ -1,13 +1,132 @@
-use std::fs;
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+};
 
-use crate::{config::BASE_DIR, dbms::database, error::DBResult, filesystem::page_manager};
+use lazy_static::lazy_static;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+use crate::{
+    config::BASE_DIR,
+    dbms::database,
+    error::DBResult,
+    filesystem::{page_manager, storage},
+};
+
+/// Set once a termination signal is received, statements in execution stop early
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+/// Set when an interrupt cancels the statement in execution, cleared when the next one begins
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether an interrupt cancels the statement in execution rather than terminating
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Set once the database is written back for the process to exit, a termination signal
+/// received afterwards does not write it back again
+static WRITTEN_BACK: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// Held by whatever reads or changes the database, such as a statement in execution,
+    /// so that it is only written back in between
+    static ref EXECUTING: Mutex<()> = Mutex::new(());
+}
 
 pub fn init() {
-    if fs::create_dir(BASE_DIR.as_path()).is_ok() {}
+    if storage::create_dir(BASE_DIR.as_path()).is_ok() {}
 }
 
-pub fn write_back() -> DBResult<()> {
+fn flush() -> DBResult<()> {
     database::write_back()?;
     page_manager::flush_all()?;
     Ok(())
 }
+
+/// Write everything back, once nothing reads or changes the database
+pub fn write_back() -> DBResult<()> {
+    let _executing = hold_database();
+    flush()
+}
+
+/// Write everything back before the process exits, a termination signal received
+/// afterwards only exits
+pub fn write_back_on_exit() -> DBResult<()> {
+    let _executing = hold_database();
+    WRITTEN_BACK.store(true, Ordering::SeqCst);
+    flush()
+}
+
+/// Write back and exit on SIGTERM or SIGINT, after the statement in execution is aborted
+/// and whatever else holds the database is done. SIGINT only cancels the statement once
+/// `cancel_on_interrupt` is called
+///
+/// To check it by hand, run a long `load` or `exec`, `kill -TERM` the process,
+/// then all rows reported as inserted should be found after restarting
+pub fn handle_signals() -> DBResult<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if signal == SIGINT && INTERACTIVE.load(Ordering::SeqCst) {
+                cancel_statement();
+                continue;
+            }
+            TERMINATING.store(true, Ordering::SeqCst);
+            let _executing = hold_database();
+            if WRITTEN_BACK.load(Ordering::SeqCst) {
+                process::exit(128 + signal);
+            }
+            let code = match flush() {
+                Ok(()) => 128 + signal,
+                Err(e) => {
+                    eprintln!("Failed to exit correctly because of {:?}", e);
+                    1
+                }
+            };
+            process::exit(code);
+        }
+    });
+    Ok(())
+}
+
+/// Let SIGINT, i.e. Ctrl-C, cancel the statement in execution instead of terminating
+pub fn cancel_on_interrupt() {
+    INTERACTIVE.store(true, Ordering::SeqCst);
+}
+
+/// Make the statement in execution fail at its next check
+pub fn cancel_statement() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+/// Fails once a termination signal is received or the statement in execution is cancelled,
+/// checked by long running loops such as scans
+pub fn check_terminating() -> DBResult<()> {
+    if TERMINATING.load(Ordering::SeqCst) {
+        return Err("aborted by termination signal".into());
+    }
+    if CANCELLED.load(Ordering::Relaxed) {
+        return Err("query cancelled".into());
+    }
+    Ok(())
+}
+
+/// Keep the database from being written back until the guard is dropped, for anything
+/// other than a statement that reads or changes it, e.g. REPL commands and completion
+pub fn hold_database() -> MutexGuard<'static, ()> {
+    EXECUTING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Keep the database from being written back on termination until the guard is dropped
+pub fn begin_statement() -> DBResult<MutexGuard<'static, ()>> {
+    let guard = hold_database();
+    CANCELLED.store(false, Ordering::SeqCst);
+    check_terminating()?;
+    Ok(guard)
+}
@@ -0,0 +1,35 @@
+//! A naive relational database, usable from the command line or as a library
+//!
+//! ```no_run
+//! use naive_database::Database;
+//!
+//! let db = Database::open("data")?;
+//! db.execute("CREATE DATABASE shop; USE shop;")?;
+//! db.execute("CREATE TABLE item (id INT, name VARCHAR(20));")?;
+//! let result = db.execute("SELECT * FROM item;")?;
+//! for row in &result.rows {
+//!     println!("{:?}", row);
+//! }
+//! db.close()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[macro_use]
+extern crate serde;
+
+mod api;
+pub mod cli;
+mod config;
+mod dbms;
+mod defines;
+pub mod error;
+mod filesystem;
+mod index;
+pub mod init;
+mod page;
+mod record;
+mod repl;
+mod utils;
+
+pub use api::{Database, QueryResult};
+pub use record::ColumnVal;
@@ -1,26 +1,13 @@
-use cli::run_cli;
-use error::DBResult;
-use init::{init, write_back};
-
-#[macro_use]
-extern crate serde;
-
-mod cli;
-mod config;
-mod dbms;
-mod defines;
-mod error;
-mod filesystem;
-mod index;
-mod init;
-mod page;
-mod record;
-mod repl;
-mod utils;
+use naive_database::{
+    cli::run_cli,
+    error::DBResult,
+    init::{handle_signals, init, write_back_on_exit},
+};
 
 fn main() -> DBResult<()> {
     init();
+    handle_signals()?;
     run_cli()?;
-    write_back().map_err(|e| format!("Failed to exit correctly because of {:?}", e))?;
+    write_back_on_exit().map_err(|e| format!("Failed to exit correctly because of {:?}", e))?;
     Ok(())
 }
@@ -291,6 +291,20 @@ impl FixedPageHeader {
         iter_bits(&self.slot).take(max_slot).all(identity)
     }
 
+    /// Panic in debug builds if a link of page `pagenum` points past the last one of
+    /// the `max_pagenum` pages in its file, which only a corrupted header does
+    #[inline]
+    pub fn debug_check(&self, pagenum: PageNum, max_pagenum: PageNum) {
+        debug_assert!(
+            self.prev_page < max_pagenum && self.next_page < max_pagenum,
+            "corrupted header of page {}: prev {}, next {}, only {} page(s) in file",
+            pagenum,
+            self.prev_page,
+            self.next_page,
+            max_pagenum
+        );
+    }
+
     #[inline]
     pub fn from_page(page: &Page) -> &Self {
         page.header()
@@ -322,3 +336,28 @@ impl Debug for FixedPageHeader {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PageBuf;
+
+    #[test]
+    fn header_within_file_passes_check() {
+        let mut buf = PageBuf::new();
+        let header = buf.header_mut();
+        header.prev_page = 1;
+        header.next_page = 3;
+        header.debug_check(2, 4);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "corrupted header of page 2")]
+    fn corrupted_header_fails_check() {
+        let mut buf = PageBuf::new();
+        let header = buf.header_mut();
+        header.prev_page = 1;
+        header.next_page = 4;
+        header.debug_check(2, 4);
+    }
+}
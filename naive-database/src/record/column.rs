@@ -1,4 +1,5 @@
 use std::{
+    cmp::Ordering,
     convert::TryFrom,
     error::Error,
     fmt::{self, Display, Formatter},
@@ -7,7 +8,7 @@ use std::{
 use bitflags::bitflags;
 use chrono::NaiveDate;
 use naive_sql_parser::{Column as ASTColumn, ColumnType as ASTColumnType};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{config::DEFAULT_SIZE, utils::Identity};
 
@@ -58,7 +59,7 @@ macro_rules! impl_colval {
     ( $( $hkt:ident $name:ident ),* ) => {
 $(
 // Null is expressed through `Option`
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum $name {
     Int($hkt<i32>),
     Float($hkt<f32>),
@@ -102,12 +103,6 @@ impl From<$hkt<NaiveDate>> for $name {
     }
 }
 
-impl PartialOrd for $name {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        use $name::*;
-        cmp_enum!((self, other); Int Float Char Varchar Date)
-    }
-}
 )*
 }}
 
@@ -116,6 +111,33 @@ impl_colval! {
     Vec ColumnValVec
 }
 
+impl PartialEq for ColumnVal {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+/// Total order of floats, NaN is equal to itself and greater than any other value,
+/// infinities compare as usual
+pub fn cmp_float(lhs: f64, rhs: f64) -> Ordering {
+    lhs.partial_cmp(&rhs)
+        .unwrap_or_else(|| lhs.is_nan().cmp(&rhs.is_nan()))
+}
+
+/// Values of the same type compare as such, and an `INT` compares with a `FLOAT` by value.
+/// Floats are ordered by `cmp_float`, so only values of incomparable types give `None`
+impl PartialOrd for ColumnVal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        use ColumnVal::*;
+        match (self, other) {
+            (Int(lhs), Float(rhs)) => Some(cmp_float(*lhs as f64, *rhs as f64)),
+            (Float(lhs), Int(rhs)) => Some(cmp_float(*lhs as f64, *rhs as f64)),
+            (Float(lhs), Float(rhs)) => Some(cmp_float(*lhs as f64, *rhs as f64)),
+            _ => cmp_enum!((self, other); Int Float Char Varchar Date),
+        }
+    }
+}
+
 impl Display for ColumnVal {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
         match self {
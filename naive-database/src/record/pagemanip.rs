@@ -9,6 +9,7 @@ use crate::{
     error::DBResult,
     filesystem::page_manager::{modify_page, read_page},
     page::{FixedPageHeader as Header, Page},
+    utils::parse_float,
 };
 
 use super::{ColumnType, ColumnVal};
@@ -195,7 +196,7 @@ pub fn parse_write_entry(val: &str, coltype: ColumnType, entry: &mut [u8]) -> DB
             bincode::serialize_into(entry, &i)?;
         }
         Float => {
-            let f: f32 = val.parse()?;
+            let f = parse_float(val)?;
             bincode::serialize_into(entry, &f)?;
         }
         Date => {
@@ -3,34 +3,35 @@ use std::{
     collections::{BTreeSet, HashMap, HashSet},
     convert::identity,
     ffi::CStr,
-    fs::{self},
     intrinsics::transmute,
     mem::size_of,
     ops::Range,
     path::{Path, PathBuf}, slice::SliceIndex,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use chrono::NaiveDate;
-use like::Like;
-use naive_sql_parser::{CompareOp, Expr};
+use bincode::Options;
+use chrono::{Datelike, NaiveDate};
+use naive_sql_parser::{CompareOp, Expr, RefAction as ASTRefAction};
 use serde::Serialize;
 
 use crate::{
-    config::{MAX_COMP_INDEX, PAGE_HEADER_LEN, PAGE_SIZE},
+    config::{NullLayout, MAX_COMP_INDEX, PAGE_HEADER_LEN, PAGE_SIZE, SETTINGS},
     defines::{ColID, PageNum, RowID, TableID},
     error::DBResult,
     filesystem::{
-        file_manager::fs_ensure_remove,
         page_manager::{self, modify_page, read_page, reserve_page},
+        storage,
     },
     index::{
-        colindex::{ColIndex, EntryRef, data2fastcmp},
+        colindex::{ColIndex, EntryRef, IndexKey, data2fastcmp},
         fast_cmp::FastCmp,
     },
-    page::FixedPageHeader,
+    init::check_terminating,
+    page::{FixedPageHeader, Page},
     utils::{
-        bit_at, clear_bit_at, iter_bits, parse_date, persistence::Persistence, set_bit_at,
-        table::print_data_row,
+        bit_at, clear_bit_at, iter_bits, like_match, parse_date, persistence::Persistence,
+        set_bit_at,
     },
 };
 
@@ -43,6 +44,46 @@ use super::{
 type ColV = ColumnVal;
 type NullColV = Option<ColumnVal>;
 
+/// What happens to referencing rows when the referenced row is deleted or updated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RefAction {
+    Restrict,
+    Cascade,
+    SetNull,
+}
+
+impl From<ASTRefAction> for RefAction {
+    fn from(action: ASTRefAction) -> Self {
+        match action {
+            ASTRefAction::Restrict => Self::Restrict,
+            ASTRefAction::Cascade => Self::Cascade,
+            ASTRefAction::SetNull => Self::SetNull,
+        }
+    }
+}
+
+/// Actions of a foreign key, kept by the referenced table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignPolicy {
+    pub on_delete: RefAction,
+    pub on_update: RefAction,
+}
+
+/// How the rows matching a predicate are found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanPath {
+    /// Look up the range of an index
+    Index,
+    /// Check every row of the table
+    Full,
+}
+
+/// Number of comparisons whose rows were looked up in an index, telling which path was taken
+pub static INDEX_SCANS: AtomicUsize = AtomicUsize::new(0);
+
+/// Foreign keys referencing some columns, by referencing table and columns
+pub type ForeignRefs = HashMap<(TableID, Vec<ColID>), ForeignPolicy>;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TableMeta {
     id: TableID,
@@ -57,10 +98,28 @@ pub struct TableMeta {
     pub rest_slot: u32,
 
     pub foreign_key: HashMap<Vec<ColID>, (TableID, Vec<ColID>)>,
-    pub as_foreign_key: HashMap<Vec<ColID>, HashSet<(TableID, Vec<ColID>)>>,
+    pub as_foreign_key: HashMap<Vec<ColID>, ForeignRefs>,
     pub primary: Vec<ColID>,
     pub unique: HashSet<Vec<ColID>>,
     pub index_record: HashSet<([ColID; MAX_COMP_INDEX], u8)>,
+    /// Values allowed in a column by `CHECK (column IN (...))`, NULL is always allowed
+    pub check: HashMap<ColID, Vec<ColumnVal>>,
+    /// Smallest and largest value of INT, FLOAT and DATE columns on each page, `None` for
+    /// a page without any value of the column. Only widened, deleted values are kept in range
+    zones: HashMap<ColID, Vec<Option<(ColumnVal, ColumnVal)>>>,
+    /// Number of rows, kept by `insert` and `delete` so that counting needs no pages
+    row_count: usize,
+    /// Where the null bits of rows are stored on pages, decided when the table is created
+    pub null_layout: NullLayout,
+
+    /// Read from metadata written before the row count, zone maps and foreign key actions
+    /// were kept, which are rebuilt or defaulted, and its index files are not read
+    #[serde(skip)]
+    legacy: bool,
+    /// Offset of each column within a slot, which starts with the null bits,
+    /// followed by the slot size. Derived from `columns` when they change or are loaded
+    #[serde(skip)]
+    offsets: Vec<u16>,
 }
 
 pub fn vec_to_buf(col_vec: &[ColID]) -> [ColID; MAX_COMP_INDEX] {
@@ -71,16 +130,207 @@ pub fn vec_to_buf(col_vec: &[ColID]) -> [ColID; MAX_COMP_INDEX] {
     col_buf
 }
 
+/// Whether values compare by `op` at all, comparing NULL with anything is unknown
+/// except by `<=>`, and unknown never matches
+fn is_known(op: CompareOp, vals: &[Option<ColumnVal>]) -> bool {
+    op == CompareOp::NULLSAFEEQ || vals.iter().all(Option::is_some)
+}
+
+/// Values of an index key as written in SQL, in parentheses if there are several
+pub(crate) fn format_key(key: &[Option<ColumnVal>]) -> String {
+    let values: Vec<_> = key
+        .iter()
+        .map(|val| match val {
+            Some(val) => val.to_string(),
+            None => "NULL".to_owned(),
+        })
+        .collect();
+    match values.as_slice() {
+        [value] => value.clone(),
+        _ => format!("({})", values.join(", ")),
+    }
+}
+
+/// FNV-1a hash of the values of a row, which unlike `DefaultHasher`
+/// is the same in every build
+fn row_hash(row: &[Option<ColumnVal>]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let mut write = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for val in row {
+        match val {
+            None => write(&[0]),
+            Some(ColumnVal::Int(i)) => {
+                write(&[1]);
+                write(&i.to_le_bytes());
+            }
+            Some(ColumnVal::Float(f)) => {
+                write(&[2]);
+                write(&f.to_bits().to_le_bytes());
+            }
+            Some(ColumnVal::Char(s) | ColumnVal::Varchar(s)) => {
+                write(&[3]);
+                write(&(s.len() as u32).to_le_bytes());
+                write(s.as_bytes());
+            }
+            Some(ColumnVal::Date(d)) => {
+                write(&[4]);
+                write(&d.num_days_from_ce().to_le_bytes());
+            }
+        }
+    }
+    hash
+}
+
+/// Stored before the metadata of a table, which is laid out by the build writing it,
+/// e.g. the index columns are arrays of `MAX_COMP_INDEX` ids
+#[derive(Debug, Serialize, Deserialize)]
+struct MetaHeader {
+    magic: [u8; 4],
+    /// Layout of the metadata and of the index files of the table, version 0 being
+    /// the metadata written without a header
+    version: u32,
+    max_comp_index: u32,
+}
+
+impl MetaHeader {
+    const MAGIC: [u8; 4] = *b"NVTB";
+    const VERSION: u32 = 1;
+
+    fn new() -> Self {
+        Self {
+            magic: Self::MAGIC,
+            version: Self::VERSION,
+            max_comp_index: MAX_COMP_INDEX as _,
+        }
+    }
+
+    /// Refuse metadata this build would misread
+    fn check(&self, file: &Path) -> DBResult<()> {
+        if self.version > Self::VERSION {
+            return Err(format!(
+                "{} has format version {}, but this build reads up to version {}",
+                file.display(),
+                self.version,
+                Self::VERSION
+            )
+            .into());
+        }
+        if self.max_comp_index != MAX_COMP_INDEX as u32 {
+            return Err(format!(
+                "{} was written with MAX_COMP_INDEX = {}, but this build has {}, \
+                 rebuild with the same value to open it",
+                file.display(),
+                self.max_comp_index,
+                MAX_COMP_INDEX
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Metadata of a table of version 0, written before it had a `MetaHeader`, when deleting or
+/// updating a referenced row always cascaded
+#[derive(Deserialize)]
+#[cfg_attr(test, derive(Serialize))]
+struct LegacyTableMeta {
+    id: TableID,
+    name: String,
+    columns: Vec<Column>,
+    named_constraint: HashMap<String, (ColID, Constraints)>,
+    available_pages: Option<PageNum>,
+    full_pages: Option<PageNum>,
+    max_pagenum: PageNum,
+    rest_slot: u32,
+    foreign_key: HashMap<Vec<ColID>, (TableID, Vec<ColID>)>,
+    as_foreign_key: HashMap<Vec<ColID>, HashSet<(TableID, Vec<ColID>)>>,
+    primary: Vec<ColID>,
+    unique: HashSet<Vec<ColID>>,
+    index_record: HashSet<([ColID; MAX_COMP_INDEX], u8)>,
+}
+
+impl From<LegacyTableMeta> for TableMeta {
+    fn from(legacy: LegacyTableMeta) -> Self {
+        let cascade = ForeignPolicy {
+            on_delete: RefAction::Cascade,
+            on_update: RefAction::Cascade,
+        };
+        let as_foreign_key = legacy
+            .as_foreign_key
+            .into_iter()
+            .map(|(cols, refs)| (cols, refs.into_iter().map(|r| (r, cascade)).collect()))
+            .collect();
+        let mut meta = Self {
+            columns: legacy.columns,
+            named_constraint: legacy.named_constraint,
+            available_pages: legacy.available_pages,
+            full_pages: legacy.full_pages,
+            max_pagenum: legacy.max_pagenum,
+            rest_slot: legacy.rest_slot,
+            foreign_key: legacy.foreign_key,
+            as_foreign_key,
+            primary: legacy.primary,
+            unique: legacy.unique,
+            index_record: legacy.index_record,
+            legacy: true,
+            ..Self::new(legacy.id, legacy.name)
+        };
+        meta.update_offsets();
+        meta
+    }
+}
+
 impl Persistence for TableMeta {
     fn filename(&self) -> String {
         Self::format_meta_filename(&self.name)
     }
 
+    fn store(&self, dir: &Path) -> DBResult<()> {
+        let file = dir.join(self.filename());
+        let mut bytes = bincode::serialize(&MetaHeader::new())?;
+        bincode::serialize_into(&mut bytes, self)?;
+        storage::write(&file, &bytes)?;
+        Ok(())
+    }
+
+    fn load(file: &Path) -> DBResult<Self> {
+        let bytes = storage::read(file)?;
+        let mut reader = bytes.as_slice();
+        let version = if bytes.starts_with(&MetaHeader::MAGIC) {
+            let header: MetaHeader = bincode::deserialize_from(&mut reader)?;
+            header.check(file)?;
+            header.version
+        } else {
+            0
+        };
+        match version {
+            0 => {
+                // the layout of `bincode::deserialize`, limited so that garbage lengths
+                // fail rather than allocate
+                let legacy: LegacyTableMeta = bincode::DefaultOptions::new()
+                    .with_fixint_encoding()
+                    .with_limit(bytes.len() as _)
+                    .deserialize(&bytes)
+                    .map_err(|_| format!("{} is not the metadata of a table", file.display()))?;
+                Ok(legacy.into())
+            }
+            _ => {
+                let mut meta: Self = bincode::deserialize_from(reader)?;
+                meta.update_offsets();
+                Ok(meta)
+            }
+        }
+    }
+
     fn delete_self(self, dir: &Path) -> DBResult<()> {
-        fs_ensure_remove(&dir.join(self.filename()))?;
+        storage::ensure_remove(&dir.join(self.filename()))?;
         for (col, len) in self.index_record {
             let idx_name = ColIndex::format_filename(self.id, &col[..len as usize]);
-            fs_ensure_remove(&dir.join(idx_name))?;
+            storage::ensure_remove(&dir.join(idx_name))?;
         }
         Ok(())
     }
@@ -102,6 +352,12 @@ impl TableMeta {
             index_record: HashSet::new(),
             primary: Vec::new(),
             unique: HashSet::new(),
+            check: HashMap::new(),
+            zones: HashMap::new(),
+            row_count: 0,
+            null_layout: NullLayout::Inline,
+            legacy: false,
+            offsets: vec![0],
         }
     }
 
@@ -113,17 +369,139 @@ impl TableMeta {
         &self.name
     }
 
+    /// Whether values of a column are kept in zone maps
+    fn has_zone(&self, col: ColID) -> bool {
+        matches!(
+            self.columns[col as usize].coltype,
+            ColumnType::Int | ColumnType::Float | ColumnType::Date
+        )
+    }
+
+    /// Take a value written to page `pagenum` into the zone map of its column
+    fn widen_zone(&mut self, pagenum: PageNum, col: ColID, val: &ColumnVal) {
+        if !self.has_zone(col) {
+            return;
+        }
+        let zones = self.zones.entry(col).or_default();
+        let pagenum = pagenum as usize;
+        if zones.len() <= pagenum {
+            zones.resize(pagenum + 1, None);
+        }
+        match &mut zones[pagenum] {
+            Some((min, max)) => {
+                if val < min {
+                    *min = val.clone();
+                } else if val > max {
+                    *max = val.clone();
+                }
+            }
+            zone => *zone = Some((val.clone(), val.clone())),
+        }
+    }
+
+    /// Pages that may have rows with `col` comparing with `val` by their zone maps,
+    /// `None` if the column has no zone map or `op` is not a range comparison
+    pub fn zone_pages(&self, col: ColID, op: CompareOp, val: &ColumnVal) -> Option<Vec<PageNum>> {
+        match op {
+            CompareOp::EQ
+            | CompareOp::NULLSAFEEQ
+            | CompareOp::GT
+            | CompareOp::GE
+            | CompareOp::LT
+            | CompareOp::LE => {}
+            _ => return None,
+        }
+        if !self.has_zone(col) {
+            return None;
+        }
+        let empty = vec![];
+        let zones = self.zones.get(&col).unwrap_or(&empty);
+        let may_match = |min: &ColumnVal, max: &ColumnVal| {
+            // values of another type are not comparable, the page is kept
+            let (lower, upper) = match (val.partial_cmp(min), val.partial_cmp(max)) {
+                (Some(lower), Some(upper)) => (lower, upper),
+                _ => return true,
+            };
+            match op {
+                CompareOp::EQ | CompareOp::NULLSAFEEQ => lower.is_ge() && upper.is_le(),
+                CompareOp::GT => upper.is_lt(),
+                CompareOp::GE => upper.is_le(),
+                CompareOp::LT => lower.is_gt(),
+                CompareOp::LE => lower.is_ge(),
+                _ => unreachable!(),
+            }
+        };
+        // pages past the zone map, like pages without a zone, have no value of the column
+        let pages = zones
+            .iter()
+            .take(self.max_pagenum as _)
+            .enumerate()
+            .filter_map(|(pagenum, zone)| match zone {
+                Some((min, max)) if may_match(min, max) => Some(pagenum as PageNum),
+                _ => None,
+            })
+            .collect();
+        Some(pages)
+    }
+
+    /// Number of data pages of the table
+    pub fn page_count(&self) -> PageNum {
+        self.max_pagenum
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    /// Whether the metadata was written before the row count was kept, see `Table::load_no_index`
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
     pub fn colnum(&self) -> ColID {
         self.columns.len() as _
     }
 
+    /// Refuse a value of column `col` not listed by its check constraint
+    pub fn check_value(&self, col: ColID, val: &ColumnVal) -> DBResult<()> {
+        match self.check.get(&col) {
+            Some(allowed) if !allowed.contains(val) => Err(format!(
+                "value {} of column {} violates its check constraint",
+                val, self.columns[col as usize].name
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn push_column(&mut self, column: Column) {
+        self.columns.push(column);
+        self.update_offsets();
+    }
+
+    fn update_offsets(&mut self) {
+        let len = self.columns.len();
+        let mut offset = (len / 8) as u16 + (len % 8 != 0) as u16;
+        let mut offsets = vec![offset];
+        for col in &self.columns {
+            offset += self._colsize(col);
+            offsets.push(offset);
+        }
+        self.offsets = offsets;
+    }
+
     // other table ref to self
-    pub fn add_foreign_key(&mut self, cols: &Vec<ColID>, fkeys: (TableID, Vec<ColID>)) {
+    pub fn add_foreign_key(
+        &mut self,
+        cols: &Vec<ColID>,
+        fkeys: (TableID, Vec<ColID>),
+        policy: ForeignPolicy,
+    ) {
         if let Some(v) = self.as_foreign_key.get_mut(cols) {
-            v.insert(fkeys);
+            v.insert(fkeys, policy);
         } else {
-            let mut v = HashSet::new();
-            v.insert(fkeys);
+            let mut v = HashMap::new();
+            v.insert(fkeys, policy);
             self.as_foreign_key.insert(cols.to_vec(), v);
         }
     }
@@ -149,35 +527,93 @@ impl TableMeta {
         Some(col_ids)
     }
 
+    /// Check that referencing columns `cols` of this table match
+    /// the referenced columns `fcols` of `fmeta` in number and type
+    pub fn check_foreign_columns(
+        &self,
+        cols: &[ColID],
+        fmeta: &TableMeta,
+        fcols: &[ColID],
+    ) -> DBResult<()> {
+        if cols.len() != fcols.len() {
+            return Err(format!(
+                "foreign key has {} column(s) but references {} column(s)",
+                cols.len(),
+                fcols.len()
+            )
+            .into());
+        }
+        for (&col, &fcol) in cols.iter().zip(fcols) {
+            let (col, fcol) = (&self.columns[col as usize], &fmeta.columns[fcol as usize]);
+            if col.coltype != fcol.coltype {
+                return Err(format!(
+                    "column {} of type {:?} cannot reference column {}.{} of type {:?}",
+                    col.name, col.coltype, fmeta.name, fcol.name, fcol.coltype
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the actions of a foreign key on referencing columns `cols` of this table
+    pub fn check_foreign_policy(&self, cols: &[ColID], policy: ForeignPolicy) -> DBResult<()> {
+        if policy.on_delete == RefAction::SetNull {
+            for &col in cols {
+                let column = &self.columns[col as usize];
+                if column.constraints.is_not_null() || self.primary.contains(&col) {
+                    return Err(format!(
+                        "ON DELETE SET NULL requires column {} to be nullable",
+                        column.name
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn nullbit_size(&self) -> u16 {
-        let len = self.columns.len();
-        (len / 8) as u16 + (len % 8 != 0) as u16
+        self.offsets[0]
     }
 
     #[inline]
     pub fn slot_size(&self) -> u16 {
-        self.nullbit_size()
-            + self
-                .columns
-                .iter()
-                .map(|col| self._colsize(col))
-                .sum::<u16>()
+        self.offsets[self.columns.len()]
     }
 
     #[inline]
-    pub fn slot_pos(&self, rid: RowID) -> (PageNum, Range<usize>) {
+    pub fn entry_pos(&self, rid: RowID, col: ColID) -> (PageNum, Range<usize>) {
         let (pagenum, slot) = rid2entry(rid);
-        (pagenum, self.slot_range(slot))
+        (pagenum, self.entry_range(slot, col))
     }
 
+    /// Range of the null bits of the row in `slot` within the data of its page
     #[inline]
-    pub fn entry_pos(&self, rid: RowID, col: ColID) -> (PageNum, Range<usize>) {
-        let (pagenum, slot) = self.slot_pos(rid);
-        let offset = self.entry_offset(col) as usize;
-        let len = self.actual_column_size(col) as usize;
-        let start = slot.start;
-        (pagenum, start + offset..start + offset + len)
+    fn null_range(&self, slot: usize) -> Range<usize> {
+        let size = self.nullbit_size() as usize;
+        let start = match self.null_layout {
+            NullLayout::Inline => slot * self.slot_size() as usize,
+            NullLayout::Separate => slot * size,
+        };
+        start..start + size
+    }
+
+    /// Range of column `col` of the row in `slot` within the data of its page
+    #[inline]
+    fn entry_range(&self, slot: usize, col: ColID) -> Range<usize> {
+        let within_slot = self.entry_range_within_slot(col);
+        let start = match self.null_layout {
+            NullLayout::Inline => slot * self.slot_size() as usize,
+            NullLayout::Separate => {
+                // the null bits of all slots, then the slots without their null bits
+                let nullbits = self.nullbit_size() as usize;
+                let data_size = self.slot_size() as usize - nullbits;
+                self.max_slot() as usize * nullbits + slot * data_size - nullbits
+            }
+        };
+        start + within_slot.start..start + within_slot.end
     }
 
     #[inline]
@@ -187,28 +623,14 @@ impl TableMeta {
         offset..offset + len
     }
 
-    #[inline]
-    fn slot_range(&self, slot: usize) -> Range<usize> {
-        let size = self.slot_size() as usize;
-        let start = slot * size;
-        start..start + size
-    }
-
     #[inline]
     fn entry_offset(&self, col: ColID) -> u16 {
-        self.nullbit_size()
-            + self
-                .columns
-                .iter()
-                .take(col as _)
-                .map(|col| self._colsize(col))
-                .sum::<u16>()
+        self.offsets[col as usize]
     }
 
     #[inline]
     pub fn actual_column_size(&self, col: ColID) -> u16 {
-        let col = &self.columns[col as usize];
-        self._colsize(col)
+        self.offsets[col as usize + 1] - self.offsets[col as usize]
     }
 
     #[inline]
@@ -230,6 +652,15 @@ impl TableMeta {
         (data_size / slot_size).min(FixedPageHeader::max_slot() as _)
     }
 
+    /// Forget the last page, which has no row
+    fn release_last_page(&mut self) {
+        self.max_pagenum -= 1;
+        self.rest_slot -= self.max_slot() as u32;
+        for zones in self.zones.values_mut() {
+            zones.truncate(self.max_pagenum as _);
+        }
+    }
+
     fn alloc_page(&mut self) -> PageNum {
         let pagenum = self.max_pagenum;
         self.max_pagenum += 1;
@@ -269,9 +700,16 @@ impl Table {
         let mut indices = HashMap::new();
         let dir = self.data_path.parent().unwrap();
         for &(col, len) in &meta.index_record {
-            let index = ColIndex::load(
-                &dir.join(ColIndex::format_filename(meta.id(), &col[..len as usize])),
-            )?;
+            let cols = &col[..len as usize];
+            let index = if meta.legacy {
+                // index files of legacy tables are laid out differently, build them again
+                let unique = meta.primary == cols
+                    || meta.unique.contains(cols)
+                    || meta.as_foreign_key.contains_key(cols);
+                self.create_index(cols, unique)?.1
+            } else {
+                ColIndex::load(&dir.join(ColIndex::format_filename(meta.id(), cols)))?
+            };
             indices.insert((col, len), RefCell::new(index));
         }
         Ok(indices)
@@ -281,21 +719,47 @@ impl Table {
         let meta = TableMeta::load(&dir.join(TableMeta::format_meta_filename(table_name)))?;
         let data_path = dir.join(TableMeta::format_data_filename(table_name));
         page_manager::open_file(&data_path)?;
-        Ok(Self {
+        let mut table = Self {
             meta,
             indices: HashMap::new(),
             data_path,
-        })
+        };
+        if table.meta.legacy {
+            table.rebuild_statistics()?;
+        }
+        Ok(table)
+    }
+
+    /// Count the rows and build the zone maps of a legacy table from its pages
+    fn rebuild_statistics(&mut self) -> DBResult<()> {
+        let zone_cols: Vec<_> = (0..self.meta.colnum())
+            .filter(|&col| self.meta.has_zone(col))
+            .collect();
+        let rids: Vec<_> = self.rows_by_brute().collect();
+        for &rid in &rids {
+            let (pagenum, _) = rid2entry(rid);
+            let vals = self.select_cols(rid, zone_cols.iter().copied())?;
+            for (&col, val) in zone_cols.iter().zip(&vals) {
+                if let Some(val) = val {
+                    self.meta.widen_zone(pagenum, col, val);
+                }
+            }
+        }
+        self.meta.row_count = rids.len();
+        Ok(())
     }
 
-    pub fn write_back(self) -> DBResult<()> {
+    /// Pages are flushed before the metadata is stored, so the metadata never refers to
+    /// pages that failed to be written, and a failed store keeps the previous metadata
+    pub fn write_back(mut self) -> DBResult<()> {
+        self.coalesce_free_pages()?;
+        page_manager::flush_all()?;
         let dir = self.data_path.parent().unwrap();
-        self.meta.store(dir)?;
         for (_, index) in self.indices.into_iter() {
             let index = index.into_inner();
             index.store(dir)?;
         }
-        page_manager::flush_all()?;
+        self.meta.store(dir)?;
         page_manager::close_file(&self.data_path)?;
         Ok(())
     }
@@ -308,7 +772,7 @@ impl Table {
             index.delete_self(dir)?;
         }
         page_manager::close_file(&self.data_path)?;
-        fs::remove_file(self.data_path)?;
+        storage::ensure_remove(&self.data_path)?;
         Ok(())
     }
 
@@ -320,7 +784,11 @@ impl Table {
         let col = self.meta.columns.get(col_id as usize).unwrap();
         let col_type = col.coltype;
         match expr {
-            Expr::Binary(_, _, _) | Expr::ColumnRef(_) => {
+            Expr::Binary(_, _, _)
+            | Expr::ColumnRef(_)
+            | Expr::Case { .. }
+            | Expr::Func(..)
+            | Expr::Cast(..) => {
                 return Err("binary and columnref not supported here".into());
             }
             Expr::IntLit(_) => {
@@ -380,11 +848,51 @@ impl Table {
         Err("primary keys cannot be null".into())
     }
 
+    /// Same checks as `check_type_insert`, but on already parsed data
+    /// , e.g. rows loaded from csv
+    pub fn check_row_data(&self, row_data: &[Option<ColumnVal>]) -> DBResult<()> {
+        if row_data.len() != self.meta.columns.len() {
+            return Err("value size not equal to column size".into());
+        }
+        let mut null_cols = Vec::new();
+        for (i, (col, val)) in self.meta.columns.iter().zip(row_data).enumerate() {
+            match val {
+                None => {
+                    if col.constraints.is_not_null() || col.constraints.is_primary_key() {
+                        return Err(format!("column {} cannot be null", i).into());
+                    }
+                    null_cols.push(i as ColID);
+                }
+                Some(val) if val.coltype() != col.coltype => {
+                    return Err(format!(
+                        "column {} of type {:?} cannot hold {}",
+                        i, col.coltype, val
+                    )
+                    .into());
+                }
+                Some(ColumnVal::Char(s)) | Some(ColumnVal::Varchar(s)) => {
+                    if s.len() > col.colsize.into() {
+                        return Err(format!("column {} longer than expected", i).into());
+                    }
+                }
+                Some(_) => {}
+            }
+            if let Some(val) = val {
+                self.meta.check_value(i as _, val)?;
+            }
+        }
+        if !self.meta.primary.is_empty()
+            && self.meta.primary.iter().all(|col| null_cols.contains(col))
+        {
+            return Err("primary keys cannot be null".into());
+        }
+        Ok(())
+    }
+
     pub fn check_data_exist(&self, row_data: &[Option<ColumnVal>], cols: &[ColID]) -> bool {
         let col_buf = vec_to_buf(cols);
         if let Some(index) = self.indices.get(&(col_buf, cols.len() as u8)) {
-            let index = index.borrow();
-            index.list.contains(&row_data.clone().into())
+            index.borrow().contains_key(row_data)
         } else {
             let rows = self.rows();
             for row in rows {
@@ -404,124 +912,236 @@ impl Table {
         }
     }
 
+    /// Rows where `cols` compare with `colval`, found by `path` if given, e.g. by an index hint,
+    /// or else by `scan_path`. `LIKE`, `ILIKE` and their negations always check every row.
+    /// Rows with NULL in `cols` never match, nor does any row if `colval` has NULL,
+    /// except by `<=>`
     pub fn filter_rows(
         &self,
         cols: &[ColID],
         op: CompareOp,
         colval: &[Option<ColumnVal>],
+        path: Option<ScanPath>,
     ) -> DBResult<HashSet<RowID>> {
+        if !is_known(op, colval) {
+            return Ok(HashSet::new());
+        }
+        let get_rows_by = |is_match: fn(&[NullColV], &[NullColV]) -> bool| {
+            self.get_rows_by(cols, op, colval, path, |data| {
+                is_known(op, data) && is_match(data, colval)
+            })
+        };
         let ret = match op {
-            CompareOp::EQ => self.get_rows_by(
-                colval,
-                cols,
-                |index| index.range_rows(colval.as_ref(), colval.as_ref()).collect(),
-                |record_data| record_data == colval,
-            ),
-            CompareOp::NE => self.get_rows_by(
-                colval,
-                cols,
-                |index| {
-                    index
-                        .out_range_rows(colval.as_ref(), colval.as_ref())
-                        .collect()
-                },
-                |record_data| record_data != colval,
-            ),
-            CompareOp::GT => self.get_rows_by(
-                colval,
-                cols,
-                |index| index.upper_range_rows(colval.as_ref()).collect(),
-                |record_data| record_data > colval,
-            ),
-            CompareOp::LT => self.get_rows_by(
-                colval,
-                cols,
-                |index| index.lower_range_rows(colval.as_ref()).collect(),
-                |record_data| record_data < colval,
-            ),
-            CompareOp::GE => self.get_rows_by(
-                colval,
-                cols,
-                |index| index.upper_eq_range_rows(colval.as_ref()).collect(),
-                |record_data| record_data >= colval,
-            ),
-            CompareOp::LE => self.get_rows_by(
-                colval,
-                cols,
-                |index| index.lower_eq_range_rows(colval.as_ref()).collect(),
-                |record_data| record_data <= colval,
-            ),
-            CompareOp::LIKE => {
+            CompareOp::EQ | CompareOp::NULLSAFEEQ => get_rows_by(|data, colval| data == colval)?,
+            CompareOp::NE => get_rows_by(|data, colval| data != colval)?,
+            CompareOp::GT => get_rows_by(|data, colval| data > colval)?,
+            CompareOp::LT => get_rows_by(|data, colval| data < colval)?,
+            CompareOp::GE => get_rows_by(|data, colval| data >= colval)?,
+            CompareOp::LE => get_rows_by(|data, colval| data <= colval)?,
+            CompareOp::LIKE | CompareOp::NOTLIKE | CompareOp::ILIKE | CompareOp::NOTILIKE => {
                 debug_assert_eq!(colval.len(), 1);
                 let colval = &colval[0];
                 let pattern = match colval {
                     Some(ColumnVal::Char(s)) | Some(ColumnVal::Varchar(s)) => s,
-                    _ => return Err("pattern used in `LIKE` or `NOT LIKE` must be a string".into()),
+                    _ => return Err(format!("pattern used in `{}` must be a string", op).into()),
                 };
                 let col = cols[0];
-                self.rows()
+                let rows = self
+                    .rows()
+                    // the scan stops once the statement is cancelled, which fails below
+                    .take_while(|_| check_terminating().is_ok())
                     .filter_map(|rid| {
                         self.select(rid, col)
                             .ok()?
                             .map(|data| match data {
                                 ColumnVal::Char(s) | ColumnVal::Varchar(s) => {
-                                    Like::<true>::like(s.as_str(), pattern).ok()?.then(|| rid)
+                                    like_match(s.as_str(), op, pattern).ok()?.then(|| rid)
                                 }
                                 _ => unreachable!(),
                             })
                             .and_then(identity)
                     })
-                    .collect()
-            }
-            CompareOp::NOTLIKE => {
-                debug_assert_eq!(colval.len(), 1);
-                let colval = &colval[0];
-                let pattern = match colval {
-                    Some(ColumnVal::Char(s)) | Some(ColumnVal::Varchar(s)) => s,
-                    _ => return Err("pattern used in `LIKE` or `NOT LIKE` must be a string".into()),
-                };
-                let col = cols[0];
-                self.rows()
-                    .filter_map(|rid| {
-                        self.select(rid, col)
-                            .ok()?
-                            .map(|data| match data {
-                                ColumnVal::Char(s) | ColumnVal::Varchar(s) => {
-                                    Like::<true>::not_like(s.as_str(), pattern)
-                                        .ok()?
-                                        .then(|| rid)
-                                }
-                                _ => unreachable!(),
-                            })
-                            .and_then(identity)
-                    })
-                    .collect()
+                    .collect();
+                check_terminating()?;
+                rows
             }
         };
         Ok(ret)
     }
 
+    /// Rows of an index within the range of a comparison
+    fn index_range<'a>(
+        index: &'a ColIndex,
+        op: CompareOp,
+        colval: &'a [NullColV],
+    ) -> Box<dyn Iterator<Item = RowID> + 'a> {
+        // values of leading columns of a composite index take in every entry starting with them
+        let key = if colval.len() < index.len as usize {
+            IndexKey::prefix(colval)
+        } else {
+            colval.into()
+        };
+        match op {
+            CompareOp::EQ | CompareOp::NULLSAFEEQ => Box::new(index.range_rows(key.clone(), key)),
+            CompareOp::NE => Box::new(index.out_range_rows(key.clone(), key)),
+            CompareOp::GT => Box::new(index.upper_range_rows(key)),
+            CompareOp::LT => Box::new(index.lower_range_rows(key)),
+            CompareOp::GE => Box::new(index.upper_eq_range_rows(key)),
+            CompareOp::LE => Box::new(index.lower_eq_range_rows(key)),
+            CompareOp::LIKE | CompareOp::NOTLIKE | CompareOp::ILIKE | CompareOp::NOTILIKE => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Decide how rows of a comparison on `cols` are found. An index on exactly `cols` is used
+    /// only if the fraction of its entries in range is at most the `index_selectivity` setting,
+    /// counting entries in range reads no pages but fetching them in index order does
+    pub fn scan_path(&self, cols: &[ColID], op: CompareOp, colval: &[NullColV]) -> ScanPath {
+        if !self.is_indexable(cols, colval) {
+            return ScanPath::Full;
+        }
+        let col_buf = vec_to_buf(cols);
+        let index = match self.indices.get(&(col_buf, cols.len() as u8)) {
+            Some(index) => index.borrow(),
+            None => return ScanPath::Full,
+        };
+        let limit = (index.list.len() as f32 * SETTINGS.borrow().index_selectivity) as usize;
+        // counting stops past the limit, a range too large is never walked to its end
+        let matched = Self::index_range(&index, op, colval).take(limit + 1).count();
+        if matched <= limit {
+            ScanPath::Index
+        } else {
+            ScanPath::Full
+        }
+    }
+
+    /// The index on exactly `cols`, or else one whose leading columns are `cols`,
+    /// which an index hint may force comparisons on them to use
+    fn index_on(&self, cols: &[ColID]) -> Option<&RefCell<ColIndex>> {
+        let exact = self.indices.get(&(vec_to_buf(cols), cols.len() as u8));
+        exact.or_else(|| {
+            self.indices
+                .iter()
+                .filter(|((col, len), _)| col[..*len as usize].starts_with(cols))
+                .min_by_key(|(key, _)| *key)
+                .map(|(_, index)| index)
+        })
+    }
+
+    /// Whether an index on `cols` can look up `colval`, its keys are only ordered among values
+    /// of the column types, so e.g. an `INT` column compared with a `FLOAT` is scanned instead
+    fn is_indexable(&self, cols: &[ColID], colval: &[NullColV]) -> bool {
+        use ColumnType::*;
+        cols.iter().zip(colval).all(|(&col, val)| {
+            let coltype = self.meta.columns[col as usize].coltype;
+            match val {
+                Some(val) => matches!(
+                    (val.coltype(), coltype),
+                    (Int, Int) | (Float, Float) | (Char | Varchar, Char | Varchar) | (Date, Date)
+                ),
+                None => true,
+            }
+        })
+    }
+
     fn get_rows_by(
         &self,
-        _cols_data: &[Option<ColumnVal>],
         cols: &[ColID],
-        with_index: impl FnOnce(&ColIndex) -> HashSet<RowID>,
+        op: CompareOp,
+        colval: &[NullColV],
+        path: Option<ScanPath>,
         is_match: impl Fn(&[NullColV]) -> bool,
-    ) -> HashSet<RowID> {
-        let mut filter_rows = HashSet::new();
-        let col_buf = vec_to_buf(cols);
-        if let Some(index) = self.indices.get(&(col_buf, cols.len() as u8)) {
-            let index = index.borrow();
-            filter_rows = with_index(&index);
-        } else {
-            for row in self.rows() {
-                let record_data = self.select_cols(row, cols.iter().copied()).unwrap();
-                if is_match(&record_data) {
+    ) -> DBResult<HashSet<RowID>> {
+        let path = match path {
+            Some(path) if self.is_indexable(cols, colval) => path,
+            _ => self.scan_path(cols, op, colval),
+        };
+        if path == ScanPath::Index {
+            INDEX_SCANS.fetch_add(1, Ordering::Relaxed);
+            let index = self.index_on(cols).ok_or("no index on the columns")?.borrow();
+            let rows = Self::index_range(&index, op, colval);
+            // entries with NULL keys are only in range of ones below or apart from a value
+            if let CompareOp::EQ | CompareOp::NULLSAFEEQ = op {
+                return Ok(rows.collect());
+            }
+            let mut filter_rows = HashSet::new();
+            for row in rows {
+                if is_known(op, &self.select_cols(row, cols.iter().copied())?) {
                     filter_rows.insert(row);
                 }
             }
+            return Ok(filter_rows);
         }
-        filter_rows
+        let rows: Box<dyn Iterator<Item = RowID>> = match (cols, colval) {
+            // pages whose range of the column cannot match are skipped
+            ([col], [Some(val)]) => match self.meta.zone_pages(*col, op, val) {
+                Some(pages) => Box::new(self.rows_in_pages(pages)),
+                None => Box::new(self.rows()),
+            },
+            _ => Box::new(self.rows()),
+        };
+        let mut filter_rows = HashSet::new();
+        for row in rows {
+            check_terminating()?;
+            let record_data = self.select_cols(row, cols.iter().copied()).unwrap();
+            if is_match(&record_data) {
+                filter_rows.insert(row);
+            }
+        }
+        Ok(filter_rows)
+    }
+
+    /// Whether any row has `cols` comparing with `colval`, stopping at the first one found,
+    /// which is the start of the range if there is an index on `cols`
+    pub fn any_match(&self, cols: &[ColID], op: CompareOp, colval: &[NullColV]) -> DBResult<bool> {
+        if let CompareOp::LIKE | CompareOp::NOTLIKE | CompareOp::ILIKE | CompareOp::NOTILIKE = op {
+            return Ok(!self.filter_rows(cols, op, colval, None)?.is_empty());
+        }
+        if let Some(index) = self.indices.get(&(vec_to_buf(cols), cols.len() as u8)) {
+            if self.is_indexable(cols, colval) {
+                let index = index.borrow();
+                let rows = Self::index_range(&index, op, colval);
+                return Ok(self.first_match(rows, cols, op, colval)?.is_some());
+            }
+        }
+        Ok(self.first_match(self.rows(), cols, op, colval)?.is_some())
+    }
+
+    /// The first of `rows` with `cols` comparing with `colval`, rows after it are not read,
+    /// NULL matches nothing but NULL by `<=>`
+    pub fn first_match(
+        &self,
+        rows: impl Iterator<Item = RowID>,
+        cols: &[ColID],
+        op: CompareOp,
+        colval: &[NullColV],
+    ) -> DBResult<Option<RowID>> {
+        if !is_known(op, colval) {
+            return Ok(None);
+        }
+        for rid in rows {
+            let data = self.select_cols(rid, cols.iter().copied())?;
+            let data = data.as_slice();
+            if !is_known(op, data) {
+                continue;
+            }
+            let is_match = match op {
+                CompareOp::EQ | CompareOp::NULLSAFEEQ => data == colval,
+                CompareOp::NE => data != colval,
+                CompareOp::GT => data > colval,
+                CompareOp::LT => data < colval,
+                CompareOp::GE => data >= colval,
+                CompareOp::LE => data <= colval,
+                CompareOp::LIKE | CompareOp::NOTLIKE | CompareOp::ILIKE | CompareOp::NOTILIKE => {
+                    return Err("`LIKE` is not supported when finding a matching row".into())
+                }
+            };
+            if is_match {
+                return Ok(Some(rid));
+            }
+        }
+        Ok(None)
     }
 
     // give the data on cols
@@ -531,6 +1151,10 @@ impl Table {
         cols: &[ColID],
     ) -> HashSet<RowID> {
         let mut filter_rows = HashSet::new();
+        // a NULL key refers to no row
+        if cols_data.iter().any(Option::is_none) {
+            return filter_rows;
+        }
         let col_buf = vec_to_buf(cols);
         if let Some(index) = self.indices.get(&(col_buf, cols.len() as u8)) {
             let index = index.borrow();
@@ -562,11 +1186,59 @@ impl Table {
         }
     }
 
-    pub fn insert_index_at(&self, row_id: RowID, row_data: &[Option<ColumnVal>]) {
+    /// Positions of rows about to be inserted which some unique index would reject
+    pub fn unique_index_duplicates(&self, rows: &[Vec<Option<ColumnVal>>]) -> BTreeSet<usize> {
+        self.indices
+            .values()
+            .flat_map(|index| index.borrow().duplicate_records(rows))
+            .collect()
+    }
+
+    /// Change the entries of a row in every index from `old` to `new` values, before the row
+    /// is changed in its page. Indices are left as they were if a unique index has the new
+    /// key already
+    pub fn update_index_at(
+        &self,
+        row_id: RowID,
+        old: &[Option<ColumnVal>],
+        new: &[Option<ColumnVal>],
+    ) -> DBResult<()> {
+        self.remove_index_at(row_id, old);
+        if let Err(e) = self.insert_index_at(row_id, new) {
+            self.insert_index_at(row_id, old)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Add a row to every index, none of them if a unique index has its key already
+    pub fn insert_index_at(&self, row_id: RowID, row_data: &[Option<ColumnVal>]) -> DBResult<()> {
+        for index in self.indices.values() {
+            index.borrow().check_unique(row_data)?;
+        }
         for (_, index) in self.indices.iter() {
             let mut index = index.borrow_mut();
-            index.insert_record(row_id, row_data);
+            index.insert_record(row_id, row_data)?;
         }
+        Ok(())
+    }
+
+    /// Insert rows into pages without adding them to indices, which is left to
+    /// `extend_indices` since comparing index entries needs the table not borrowed mutably
+    pub fn bulk_insert(&mut self, rows: &[Vec<Option<ColumnVal>>]) -> DBResult<Vec<RowID>> {
+        self.reserve_for(rows.len())?;
+        rows.iter().map(|row| self.insert(row)).collect()
+    }
+
+    /// Add rows inserted by `bulk_insert` to every index, all at once for each index.
+    /// Rows should have been checked against unique constraints, which unique indices
+    /// enforce again
+    pub fn extend_indices(&self, rids: &[RowID], rows: &[Vec<Option<ColumnVal>>]) -> DBResult<()> {
+        for index in self.indices.values() {
+            let records = rids.iter().copied().zip(rows.iter().map(Vec::as_slice));
+            index.borrow_mut().extend_records(records)?;
+        }
+        Ok(())
     }
 
     pub fn create_index(
@@ -589,13 +1261,21 @@ impl Table {
 
         // get the index key and build a ColIndex
         // if need to be unique, check half way
+        let verbose = SETTINGS.borrow().verbose_errors;
+        let mut duplicates: Vec<Vec<Option<ColumnVal>>> = vec![];
         let mut list = BTreeSet::new();
         for rid in self.rows() {
             let row_data = self.select_cols(rid, cols.iter().cloned())?;
 
             if unique_required && list.contains(&row_data.clone()[..].into()) {
-                return Err(format!("cols in table {} doesn't satisfy unique requirment", self.meta.name()).into());
-            } 
+                if !duplicates.contains(&row_data) {
+                    duplicates.push(row_data);
+                }
+                if verbose {
+                    continue;
+                }
+                break;
+            }
             let (fast_cmp, is_null) = data2fastcmp(&row_data);
             list.insert(
                 EntryRef {
@@ -610,10 +1290,33 @@ impl Table {
             );
         }
 
-        let col_index = ColIndex::new(self.meta.id(), len as _, colbuf, list);
+        if !duplicates.is_empty() {
+            let values: Vec<_> = duplicates.iter().map(|key| format_key(key)).collect();
+            return Err(format!(
+                "cols in table {} doesn't satisfy unique requirment, duplicate value(s): {}",
+                self.meta.name(),
+                values.join(", ")
+            )
+            .into());
+        }
+
+        let col_index = ColIndex::new(self.meta.id(), len as _, colbuf, list, unique_required);
         Ok(((colbuf, len as _), col_index))
     }
 
+    /// Whether there is an index on exactly `cols`
+    pub fn has_index(&self, cols: &[ColID]) -> bool {
+        self.indices
+            .contains_key(&(vec_to_buf(cols), cols.len() as u8))
+    }
+
+    /// Whether an index on exactly `cols` keeps their keys unique
+    pub fn has_unique_index(&self, cols: &[ColID]) -> bool {
+        self.indices
+            .get(&(vec_to_buf(cols), cols.len() as u8))
+            .is_some_and(|index| index.borrow().unique)
+    }
+
     pub fn insert_index(
         &mut self,
         ((colbuf, len), col_index): (([ColID; MAX_COMP_INDEX], u8), ColIndex),
@@ -635,6 +1338,10 @@ impl Table {
             clear_bit_at(&mut header.slot, slot);
             full
         })
+        .inspect(|_| {
+            self.meta.rest_slot += 1;
+            self.meta.row_count -= 1;
+        })
         .map(|full| -> DBResult<_> {
             if full {
                 let pos = {
@@ -657,6 +1364,8 @@ impl Table {
                     None => {}
                 }
                 self.meta.available_pages = Some(pagenum);
+                #[cfg(debug_assertions)]
+                self.check_page_lists()?;
             }
             Ok(())
         })
@@ -723,6 +1432,60 @@ impl Table {
         Ok(start)
     }
 
+    /// Shrink the data file by the pages without rows at its end, and relink the other
+    /// pages with free slots in ascending order, so that inserts fill the lowest pages
+    /// first and leave free pages at the end. Returns the number of pages released
+    pub fn coalesce_free_pages(&mut self) -> DBResult<PageNum> {
+        let mut pages = vec![];
+        if let Some(start) = self.meta.available_pages {
+            let mut iter = PageIter::new(start, &self.data_path);
+            pages.push(start);
+            while iter.next()?.is_some() {
+                pages.push(iter.pos());
+            }
+        }
+        let linked = pages.clone();
+        pages.sort_unstable();
+
+        let mut released = 0;
+        while let Some(&last) = pages.last() {
+            let empty = self.read_data_page(last, |page| page.header().total() == 0)?;
+            if last + released + 1 != self.meta.max_pagenum || !empty {
+                break;
+            }
+            pages.pop();
+            released += 1;
+        }
+        if !linked.starts_with(&pages) {
+            for (i, &pagenum) in pages.iter().enumerate() {
+                let prev = if i == 0 { pagenum } else { pages[i - 1] };
+                let next = pages.get(i + 1).copied().unwrap_or(pagenum);
+                modify_page(&self.data_path, pagenum, |page| {
+                    let header = page.header_mut();
+                    header.prev_page = prev;
+                    header.next_page = next;
+                })?;
+            }
+        } else if pages.len() != linked.len() {
+            // already in order, only the released tail is cut off
+            if let Some(&last) = pages.last() {
+                modify_page(&self.data_path, last, |page| page.header_mut().next_page = last)?;
+            }
+        }
+        self.meta.available_pages = pages.first().copied();
+        // the released pages are no longer linked
+        for _ in 0..released {
+            self.meta.release_last_page();
+        }
+        if released != 0 {
+            // an empty file cannot be mapped
+            page_manager::truncate_file(&self.data_path, self.meta.max_pagenum.max(1))?;
+        }
+        #[cfg(debug_assertions)]
+        self.check_page_lists()?;
+        Ok(released)
+    }
+
     #[inline]
     pub fn id(&self) -> TableID {
         self.meta.id
@@ -741,14 +1504,16 @@ impl Table {
             let full = header.is_full(max_slot);
 
             let rid = entry2rid(pagenum, slot);
-            let slot = &mut data[self.meta.slot_range(slot as _)];
+            let slot = slot as usize;
+            // the slot may keep null bits of a deleted row
             for (col, val) in val.iter().enumerate() {
+                let nullbits = &mut data[self.meta.null_range(slot)];
                 match val {
-                    None => set_bit_at(&mut slot[..self.meta.nullbit_size() as _], col),
-                    Some(expr) => colval_write_entry(
-                        expr,
-                        &mut slot[self.meta.entry_range_within_slot(col as _)],
-                    )?,
+                    None => set_bit_at(nullbits, col),
+                    Some(expr) => {
+                        clear_bit_at(nullbits, col);
+                        colval_write_entry(expr, &mut data[self.meta.entry_range(slot, col as _)])?
+                    }
                 }
             }
             Ok((full, rid))
@@ -756,6 +1521,12 @@ impl Table {
         .map_err(Into::into)
         .and_then(identity)
         .map(|(full, rid)| {
+            self.meta.row_count += 1;
+            for (col, val) in val.iter().enumerate() {
+                if let Some(val) = val {
+                    self.meta.widen_zone(pagenum, col as _, val);
+                }
+            }
             if full {
                 let pos = {
                     let mut iter = PageIter::new(pagenum, &self.data_path);
@@ -778,62 +1549,126 @@ impl Table {
                     None => {}
                 }
                 self.meta.full_pages = Some(pagenum);
+                #[cfg(debug_assertions)]
+                self.check_page_lists()?;
             }
             Ok(rid)
         })
         .and_then(identity)
     }
 
+    /// Grow the data file so that `n_slots` more rows fit without extending it again
+    ///
+    /// Pages are only reserved on disk, `max_pagenum` and `rest_slot` are still
+    /// updated by `alloc_page` when a page is actually used
     pub fn reserve_for(&mut self, n_slots: usize) -> DBResult<()> {
         let rest_slot = self.meta.rest_slot as _;
         if n_slots > rest_slot {
             let slot_needed = n_slots - rest_slot;
             let max_slot = self.meta.max_slot() as usize;
-            let remainder = slot_needed % max_slot;
-            let page_needed = slot_needed / max_slot + (remainder != 0) as usize;
+            let page_needed = slot_needed.div_ceil(max_slot);
             let pagenum = self.meta.max_pagenum + page_needed as PageNum;
-            self.meta.rest_slot = if remainder == 0 {
-                0
-            } else {
-                (max_slot - remainder) as _
-            };
             reserve_page(&self.data_path, pagenum)?;
         }
         Ok(())
     }
 
-    fn interpret_entry(&self, rid: RowID, col: ColID) -> DBResult<Option<ColumnVal>> {
+    /// Values of `cols` of a row, interpreted from a single read of its slot
+    fn interpret_entries(
+        &self,
+        rid: RowID,
+        cols: impl Iterator<Item = ColID>,
+    ) -> DBResult<Vec<Option<ColumnVal>>> {
+        self.read_slot(rid, |data, slot| {
+            cols.map(|col| self.interpret_entry(data, slot, col))
+                .collect()
+        })
+        .and_then(identity)
+    }
+
+    /// Value of column `col` of the row in `slot`, given the data of its page
+    fn interpret_entry(&self, data: &[u8], slot: usize, col: ColID) -> DBResult<Option<ColumnVal>> {
         use ColumnVal::*;
-        let entry_range = self.meta.entry_range_within_slot(col);
-        let nullbits = self.meta.nullbit_size() as usize;
+        let coltype = self.meta.columns[col as usize].coltype;
+        let nullbits = &data[self.meta.null_range(slot)];
+        if bit_at(nullbits, col as _) {
+            return Ok(None);
+        }
 
-        self.read_slot(rid, |slot| -> DBResult<Option<ColumnVal>> {
-            let coltype = self.meta.columns[col as usize].coltype;
-            let nullbits = &slot[..nullbits];
-            if bit_at(nullbits, col as _) {
-                return Ok(None);
+        let entry = &data[self.meta.entry_range(slot, col)];
+        let colval = match coltype {
+            ColumnType::Int => Int(bincode::deserialize(entry)?),
+            ColumnType::Float => Float(bincode::deserialize(entry)?),
+            ColumnType::Date => {
+                let i: i32 = bincode::deserialize(entry)?;
+                Date(unsafe { transmute(i) })
+            }
+            ColumnType::Char => {
+                let s = unsafe { CStr::from_ptr(entry as *const _ as *const _) };
+                Char(s.to_string_lossy().into())
             }
+            ColumnType::Varchar => {
+                let s = unsafe { CStr::from_ptr(entry as *const _ as *const _) };
+                Varchar(s.to_string_lossy().into())
+            }
+        };
+        Ok(Some(colval))
+    }
 
-            let entry = &slot[entry_range];
-            let colval = match coltype {
-                ColumnType::Int => Int(bincode::deserialize(entry)?),
-                ColumnType::Float => Float(bincode::deserialize(entry)?),
-                ColumnType::Date => {
-                    let i: i32 = bincode::deserialize(entry)?;
-                    Date(unsafe { transmute(i) })
-                }
-                ColumnType::Char => {
-                    let s = unsafe { CStr::from_ptr(entry as *const _ as *const _) };
-                    Char(s.to_string_lossy().into())
-                }
-                ColumnType::Varchar => {
-                    let s = unsafe { CStr::from_ptr(entry as *const _ as *const _) };
-                    Varchar(s.to_string_lossy().into())
-                }
-            };
-            Ok(Some(colval))
+    /// Read a page of the data file, its header is checked in debug builds
+    fn read_data_page<T>(&self, pagenum: PageNum, action: impl FnOnce(&Page) -> T) -> DBResult<T> {
+        let max_pagenum = self.meta.max_pagenum;
+        read_page(&self.data_path, pagenum, |page| {
+            // pages past the end are only reserved, their headers are not initialized yet
+            if pagenum < max_pagenum {
+                page.header().debug_check(pagenum, max_pagenum);
+            }
+            action(page)
         })
-        .and_then(identity)
+        .map_err(Into::into)
+    }
+
+    /// Read every data page into the page cache, so that the first query
+    /// on the table does not wait for the disk
+    pub fn warm(&self) -> DBResult<()> {
+        for pagenum in 0..self.meta.max_pagenum {
+            page_manager::prefetch_page(&self.data_path, pagenum)?;
+        }
+        Ok(())
+    }
+
+    /// Sum of the hashes of all rows, which does not depend on where or in which order
+    /// rows are stored, so tables with the same rows have the same checksum
+    pub fn checksum(&self) -> DBResult<u64> {
+        let mut sum = 0_u64;
+        for rid in self.rows() {
+            sum = sum.wrapping_add(row_hash(&self.select_row(rid)?));
+        }
+        Ok(sum)
+    }
+
+    /// Panic if a page is linked twice from the starts of the page lists,
+    /// e.g. if it is both available and full
+    #[cfg(debug_assertions)]
+    fn check_page_lists(&self) -> DBResult<()> {
+        let mut linked = HashSet::new();
+        let starts = [self.meta.available_pages, self.meta.full_pages];
+        for &start in starts.iter().flatten() {
+            let mut iter = PageIter::new(start, &self.data_path);
+            loop {
+                let pos = iter.pos();
+                assert!(
+                    linked.insert(pos),
+                    "page {} is linked twice in page lists of table {}",
+                    pos,
+                    self.meta.name
+                );
+                if iter.next()?.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(())
     }
 
     fn read_entry<T>(
@@ -843,36 +1678,74 @@ impl Table {
         action: impl FnOnce(&[u8]) -> T,
     ) -> DBResult<T> {
         let (pagenum, entry_range) = self.meta.entry_pos(rid, col);
-        read_page(&self.data_path, pagenum, |page| {
+        self.read_data_page(pagenum, |page| {
             let data = page.data();
             let slot = &data[entry_range];
             action(slot)
         })
-        .map_err(Into::into)
     }
 
-    fn read_slot<T>(&self, rid: RowID, action: impl FnOnce(&[u8]) -> T) -> DBResult<T> {
-        let (pagenum, slot_range) = self.meta.slot_pos(rid);
-        read_page(&self.data_path, pagenum, |page| {
-            let data = page.data();
-            let slot = &data[slot_range];
-            action(slot)
-        })
-        .map_err(Into::into)
+    /// Read the page of a row, `action` is given the data of the page and the slot of the row
+    fn read_slot<T>(&self, rid: RowID, action: impl FnOnce(&[u8], usize) -> T) -> DBResult<T> {
+        let (pagenum, slot) = rid2entry(rid);
+        self.read_data_page(pagenum, |page| action(page.data(), slot))
     }
 
-    pub fn rows(&self) -> Box<dyn Iterator<Item = RowID> + '_> {
-        if let Some(idx_iter) = self.rows_by_index() {
-            Box::new(idx_iter)
-        } else {
-            Box::new(self.rows_by_brute())
+    /// Every row of the table, in ascending RowID order, i.e. by page and then by slot
+    ///
+    /// Indices are never consulted, so the order does not depend on which indices exist,
+    /// see `rows_by_primary` for rows in key order
+    pub fn rows(&self) -> impl Iterator<Item = RowID> + '_ {
+        self.rows_by_brute()
+    }
+
+    /// Number of rows, without reading any page
+    pub fn row_count(&self) -> usize {
+        self.meta.row_count
+    }
+
+    /// Number of rows, counted from the slot bitmaps in page headers. Every page is read,
+    /// so this only exists for tests to check `row_count` against
+    #[cfg(test)]
+    pub fn stored_row_count(&self) -> DBResult<usize> {
+        let mut count = 0;
+        for pagenum in 0..self.meta.max_pagenum {
+            count += self.read_data_page(pagenum, |page| page.header().total())? as usize;
         }
+        Ok(count)
     }
 
     pub fn rows_by_brute(&self) -> impl Iterator<Item = RowID> + '_ {
+        self.rows_in_pages(0..self.meta.max_pagenum)
+    }
+
+    /// Rows where column `col` is NULL, or is not if `is_null` is false. Pages are read once
+    /// rather than once for each row, and only the null bits of the rows are looked at
+    pub fn null_rows(&self, col: ColID, is_null: bool) -> DBResult<Vec<RowID>> {
+        let max_slot = self.meta.max_slot() as usize;
+        let mut rows = vec![];
+        for pagenum in 0..self.meta.max_pagenum {
+            self.read_data_page(pagenum, |page| {
+                let data = page.data();
+                let slots = iter_bits(&page.header().slot).take(max_slot).enumerate();
+                for (slot, _) in slots.filter(|(_, exist)| *exist) {
+                    if bit_at(&data[self.meta.null_range(slot)], col as _) == is_null {
+                        rows.push(pagenum2rid(pagenum) + slot as RowID);
+                    }
+                }
+            })?;
+        }
+        Ok(rows)
+    }
+
+    /// Rows on `pages`, other pages are not read
+    pub fn rows_in_pages(
+        &self,
+        pages: impl IntoIterator<Item = PageNum> + 'static,
+    ) -> impl Iterator<Item = RowID> + '_ {
         let max_slot = self.meta.max_slot();
-        (0..self.meta.max_pagenum).flat_map(move |pagenum| {
-            read_page(&self.data_path, pagenum, |page| {
+        pages.into_iter().flat_map(move |pagenum| {
+            self.read_data_page(pagenum, |page| {
                 let header = page.header();
                 iter_bits(&header.slot)
                     .take(max_slot as _)
@@ -885,13 +1758,25 @@ impl Table {
         })
     }
 
-    pub fn rows_by_index(&self) -> Option<impl DoubleEndedIterator<Item = RowID> + '_> {
-        if let Some((_, first_idx)) = self.indices.iter().next() {
-            let idx = first_idx.borrow();
-            Some(idx.iter_rid().collect::<Vec<_>>().into_iter())
-        } else {
-            None
+    /// The index on exactly the primary key
+    pub fn primary_index(&self) -> Option<&RefCell<ColIndex>> {
+        let primary = &self.meta.primary;
+        if primary.is_empty() {
+            return None;
         }
+        self.indices
+            .get(&(vec_to_buf(primary), primary.len() as u8))
+    }
+
+    /// Run `action` on rows in the order of the index on the primary key,
+    /// `None` if there is no such index
+    pub fn rows_by_primary<T>(
+        &self,
+        action: impl FnOnce(&mut dyn DoubleEndedIterator<Item = RowID>) -> T,
+    ) -> Option<T> {
+        let idx = self.primary_index()?.borrow();
+        let result = action(&mut idx.iter_rid());
+        Some(result)
     }
 
     fn check_rid_exist(&self, rid: RowID) -> DBResult<()> {
@@ -899,9 +1784,7 @@ impl Table {
         if pagenum >= self.meta.max_pagenum {
             return Err("row does not exist".into());
         }
-        let has_slot = read_page(&self.data_path, pagenum, |page| {
-            bit_at(&page.header().slot, slot)
-        })?;
+        let has_slot = self.read_data_page(pagenum, |page| bit_at(&page.header().slot, slot))?;
         if !has_slot {
             dbg!(rid, pagenum, slot);
             return Err("row does not exist".into());
@@ -909,28 +1792,10 @@ impl Table {
         Ok(())
     }
 
-    pub fn print_val(&self, rows: &[RowID], cols: &[ColID]) {
-        if cols.is_empty() {
-            return;
-        }
-        if rows.is_empty() {
-            println!("No data found");
-            return;
-        }
-        let header = self.meta.columns.iter().map(|col| col.name.as_str());
-        let mut body = Vec::with_capacity(rows.len() * cols.len());
-        for &rid in rows {
-            let data = self.select_cols(rid, cols.iter().copied()).unwrap();
-            body.extend(data);
-        }
-        print_data_row(header, body.chunks_exact(cols.len()));
-        println!("{} items in total", rows.len());
-    }
-
     pub fn select(&self, rid: RowID, col: ColID) -> DBResult<Option<ColumnVal>> {
         self.check_rid_exist(rid)?;
-        let colval = self.interpret_entry(rid, col)?;
-        Ok(colval)
+        self.read_slot(rid, |data, slot| self.interpret_entry(data, slot, col))
+            .and_then(identity)
     }
 
     pub fn select_cols(
@@ -939,19 +1804,17 @@ impl Table {
         cols: impl Iterator<Item = ColID>,
     ) -> DBResult<Vec<Option<ColumnVal>>> {
         self.check_rid_exist(rid)?;
-        cols.map(|col| self.interpret_entry(rid, col)).collect()
+        self.interpret_entries(rid, cols)
     }
 
     pub fn select_row(&self, rid: RowID) -> DBResult<Vec<Option<ColumnVal>>> {
         self.check_rid_exist(rid)?;
-        (0..self.meta.colnum())
-            .map(|col| self.interpret_entry(rid, col))
-            .collect()
+        self.interpret_entries(rid, 0..self.meta.colnum())
     }
 
     pub fn update(&mut self, rid: RowID, col: ColID, val: &Option<ColumnVal>) -> DBResult<()> {
         let (pagenum, slot_num) = rid2entry(rid);
-        let (_, slot) = self.meta.slot_pos(rid);
+        let nullbits = self.meta.null_range(slot_num);
         let (_, entry_range) = self.meta.entry_pos(rid, col);
 
         modify_page(self.data_path.as_path(), pagenum, |page| -> DBResult<_> {
@@ -962,19 +1825,23 @@ impl Table {
 
             match val {
                 Some(val) => {
+                    clear_bit_at(&mut data[nullbits], col as _);
                     let entry = &mut data[entry_range];
                     colval_write_entry(val, entry)?;
                     Ok(())
                 }
                 None => {
-                    let slot = &mut data[slot];
-                    set_bit_at(slot, col as _);
+                    set_bit_at(&mut data[nullbits], col as _);
                     Ok(())
                 }
             }
         })
         .map_err(Into::into)
-        .and_then(identity)
+        .and_then(identity)?;
+        if let Some(val) = val {
+            self.meta.widen_zone(pagenum, col, val);
+        }
+        Ok(())
     }
 
     pub fn update_row(&mut self, rid: RowID, val: &[Option<ColumnVal>]) -> DBResult<()> {
@@ -986,24 +1853,27 @@ impl Table {
                 return Err("row does not exist".into());
             }
 
+            let nullbits = self.meta.null_range(slot_num);
             for (col, val) in val.iter().enumerate() {
                 match val {
                     Some(val) => {
-                        let (_, entry_range) = self.meta.entry_pos(rid, col as _);
-                        let entry = &mut data[entry_range];
+                        clear_bit_at(&mut data[nullbits.clone()], col as _);
+                        let entry = &mut data[self.meta.entry_range(slot_num, col as _)];
                         colval_write_entry(val, entry)?;
                     }
-                    None => {
-                        let (_, slot) = self.meta.slot_pos(rid);
-                        let slot = &mut data[slot];
-                        set_bit_at(slot, col as _);
-                    }
+                    None => set_bit_at(&mut data[nullbits.clone()], col as _),
                 }
             }
             Ok(())
         })
         .map_err(Into::into)
-        .and_then(identity)
+        .and_then(identity)?;
+        for (col, val) in val.iter().enumerate() {
+            if let Some(val) = val {
+                self.meta.widen_zone(pagenum, col as _, val);
+            }
+        }
+        Ok(())
     }
 
     fn write_entry<T>(
@@ -1021,12 +1891,15 @@ impl Table {
         .map_err(Into::into)
     }
 
-    fn write_slot<T>(&mut self, rid: RowID, action: impl FnOnce(&mut [u8]) -> T) -> DBResult<T> {
-        let (pagenum, slot_range) = self.meta.slot_pos(rid);
+    /// Modify the page of a row, `action` is given the data of the page and the slot of the row
+    fn write_slot<T>(
+        &mut self,
+        rid: RowID,
+        action: impl FnOnce(&mut [u8], usize) -> T,
+    ) -> DBResult<T> {
+        let (pagenum, slot) = rid2entry(rid);
         modify_page(&self.data_path, pagenum, |page| {
-            let data = page.data_mut();
-            let slot = &mut data[slot_range];
-            action(slot)
+            action(page.data_mut(), slot)
         })
         .map_err(Into::into)
     }
@@ -1043,42 +1916,136 @@ impl Table {
         slice_data
     }
 
-    pub fn record2data(&self, record: &[Expr]) -> Vec<Option<ColumnVal>> {
+    pub fn record2data(&self, record: &[Expr]) -> DBResult<Vec<Option<ColumnVal>>> {
         let mut row_data = Vec::new();
         for (i, col) in self.meta.columns.iter().enumerate() {
-            row_data.push(Self::expr2colval(&record[i], col.coltype))
+            row_data.push(Self::expr2colval(&record[i], col.coltype)?)
         }
-        row_data
+        Ok(row_data)
     }
 
-    pub fn exprs2colval(&self, record: &[&Expr], cols: &[ColID]) -> Vec<Option<ColumnVal>> {
+    pub fn exprs2colval(
+        &self,
+        record: &[&Expr],
+        cols: &[ColID],
+    ) -> DBResult<Vec<Option<ColumnVal>>> {
         let mut row_data = Vec::new();
         for (i, col) in cols.iter().enumerate() {
             row_data.push(Self::expr2colval(
                 record[i],
                 self.meta.columns[*col as usize].coltype,
-            ))
+            )?)
         }
-        row_data
+        Ok(row_data)
     }
 
-    pub fn expr2colval(expr: &Expr, coltype: ColumnType) -> Option<ColumnVal> {
+    /// Convert a literal to a value of a column of type `coltype`, which should have been
+    /// checked by `check_column_type`. Other expressions and literals of other types are errors
+    pub fn expr2colval(expr: &Expr, coltype: ColumnType) -> DBResult<Option<ColumnVal>> {
         use ColumnVal::*;
-        match expr {
-            Expr::IntLit(i) => match coltype {
-                ColumnType::Float => Some(Float(*i as _)),
-                ColumnType::Int => Some(Int(*i)),
-                _ => unreachable!("missing arg check"),
-            },
-            Expr::FloatLit(f) => Some(Float(*f)),
-            Expr::StringLit(s) => match coltype {
-                ColumnType::Char => Some(Char(s.clone())),
-                ColumnType::Varchar => Some(Varchar(s.clone())),
-                ColumnType::Date => Some(Date(parse_date(s).unwrap())),
-                _ => unreachable!("missing arg check"),
-            },
-            Expr::Null => None,
-            _ => unreachable!("missing arg check"),
+        let val = match (expr, coltype) {
+            (Expr::IntLit(i), ColumnType::Float) => Float(*i as _),
+            (Expr::IntLit(i), ColumnType::Int) => Int(*i),
+            // not converted to INT, it is compared with INT by value
+            (Expr::FloatLit(f), _) => Float(*f),
+            (Expr::StringLit(s), ColumnType::Char) => Char(s.clone()),
+            (Expr::StringLit(s), ColumnType::Varchar) => Varchar(s.clone()),
+            (Expr::StringLit(s), ColumnType::Date) => {
+                Date(parse_date(s).ok_or(format!("'{}' is not a valid date", s))?)
+            }
+            (Expr::Null, _) => return Ok(None),
+            (Expr::IntLit(_), _) | (Expr::StringLit(_), _) => {
+                return Err(format!("{} cannot be a value of type {:?}", expr, coltype).into())
+            }
+            _ => return Err(format!("{} is not a literal", expr).into()),
+        };
+        Ok(Some(val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LegacyTableMeta, TableMeta};
+    use crate::{
+        config::BASE_DIR,
+        dbms::{
+            database,
+            tests::{query_sql, row_count, run, with_database},
+        },
+        error::DBResult,
+        filesystem::storage,
+        index::colindex::ColIndex,
+        record::ColumnVal,
+        utils::persistence::Persistence,
+    };
+
+    /// Rewrite the metadata of a table as a build before `MetaHeader` did,
+    /// and spoil its index files, which that build laid out differently
+    fn make_legacy(db: &str, name: &str) -> DBResult<()> {
+        let dir = BASE_DIR.join(db);
+        let file = dir.join(TableMeta::format_meta_filename(name));
+        let meta = TableMeta::load(&file)?;
+        for (col, len) in &meta.index_record {
+            let index_file = ColIndex::format_filename(meta.id, &col[..*len as usize]);
+            storage::write(&dir.join(index_file), b"spoiled")?;
         }
+        let legacy = LegacyTableMeta {
+            id: meta.id,
+            name: meta.name,
+            columns: meta.columns,
+            named_constraint: meta.named_constraint,
+            available_pages: meta.available_pages,
+            full_pages: meta.full_pages,
+            max_pagenum: meta.max_pagenum,
+            rest_slot: meta.rest_slot,
+            foreign_key: meta.foreign_key,
+            as_foreign_key: meta
+                .as_foreign_key
+                .into_iter()
+                .map(|(cols, refs)| (cols, refs.into_keys().collect()))
+                .collect(),
+            primary: meta.primary,
+            unique: meta.unique,
+            index_record: meta.index_record,
+        };
+        storage::write(&file, &bincode::serialize(&legacy)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_metadata_is_migrated() -> DBResult<()> {
+        with_database("legacy_meta", || {
+            run("CREATE TABLE p (id INT NOT NULL, x FLOAT, PRIMARY KEY (id));")?;
+            run("CREATE TABLE c (pid INT, FOREIGN KEY (pid) REFERENCES p (id));")?;
+            run("CREATE INDEX ON c (pid);")?;
+            run("INSERT INTO p VALUES (1, 1.5), (2, 2.5), (3, 3.5);")?;
+            run("INSERT INTO c VALUES (1), (1), (2);")?;
+            database::write_back()?;
+            make_legacy("legacy_meta", "p")?;
+            make_legacy("legacy_meta", "c")?;
+            assert!(database::change_database("legacy_meta")?);
+
+            // rows are counted again, even for a table not loaded yet
+            let statuses = database::table_status(Some("p"))?;
+            assert_eq!(statuses[0].rows, 3);
+            let result = query_sql("SELECT id FROM p WHERE x > 2.0 ORDER BY id;")?;
+            let ids = [2, 3].map(|id| vec![Some(ColumnVal::Int(id))]);
+            assert_eq!(result.rows, ids);
+            // the index on the primary key is built again as a unique one
+            assert!(run("INSERT INTO p VALUES (1, 0.5);").is_err());
+            // foreign keys of that time cascaded
+            run("DELETE FROM p WHERE id = 1;")?;
+            assert_eq!(row_count("c"), 1);
+            let result = query_sql("SELECT pid FROM c WHERE pid = 2;")?;
+            assert_eq!(result.rows.len(), 1);
+
+            // and the table is written back in the current layout
+            database::write_back()?;
+            let file = BASE_DIR.join("legacy_meta").join(TableMeta::format_meta_filename("p"));
+            assert!(!TableMeta::load(&file)?.is_legacy());
+            assert!(database::change_database("legacy_meta")?);
+            assert_eq!(row_count("p"), 2);
+            Ok(())
+        })
     }
 }
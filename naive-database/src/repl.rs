@@ -1,60 +1,110 @@
-use std::{borrow::Borrow, error::Error};
+use std::{
+    borrow::Borrow,
+    error::Error,
+    fmt::Debug,
+    fs::File,
+    io::{self, stdout, BufWriter, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
-use rustyline::{error::ReadlineError, Cmd, Editor, KeyCode, KeyEvent, Modifiers, Movement};
+use rustyline::{
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Cmd, ConditionalEventHandler, Context, Editor, Event, EventContext, EventHandler, Helper,
+    KeyCode, KeyEvent, Modifiers, Movement, RepeatCount,
+};
 
-use naive_sql_parser::{ParseError, SingleSqlParser};
+use naive_sql_parser::{IdentifierParser, ParseError, SpannedSqlStmtsParser, SpannedStmt, SqlStmt};
 
-use crate::{config::REPL_HISTORY, dbms::exec::Exec};
+use crate::{
+    config::{REPL_HISTORY, REPL_MAX_ROWS, SETTINGS},
+    dbms::{
+        database::{column_names, table_checksum, table_names, warm_table},
+        exec::{Exec, ExecResult},
+        system::index_dump,
+    },
+    error::DBResult,
+    filesystem::page_manager::cache_stats,
+    init::{begin_statement, cancel_on_interrupt, hold_database},
+    utils::{
+        naive_timeit,
+        table::{print_rows, print_text},
+    },
+};
+
+/// Whether the time each statement takes is printed, see `.timer`
+pub static TIMER: AtomicBool = AtomicBool::new(true);
 
 pub fn main_loop() {
-    let parser = SingleSqlParser::new();
-    let mut rl = Editor::<()>::new();
+    // Ctrl-C while a statement runs cancels it, while editing it discards the input
+    cancel_on_interrupt();
+    // results of accidental queries on large tables are cut, see `SET max_rows`
+    SETTINGS.borrow_mut().max_rows = REPL_MAX_ROWS;
+    let parser = SpannedSqlStmtsParser::new();
+    // where results are printed, see `.output`
+    let mut output: Box<dyn Write> = Box::new(stdout());
+    let mut rl = Editor::<SqlHelper>::new();
+    rl.set_helper(Some(SqlHelper));
     if let Err(e) = rl.load_history(REPL_HISTORY.as_path()) {
         eprintln!("Failed to load history because of error: {}", e);
     }
 
     rl.bind_sequence(
         KeyEvent(KeyCode::Tab, Modifiers::NONE),
-        Cmd::Indent(Movement::ForwardChar(4)),
+        EventHandler::Conditional(Box::new(TabHandler)),
     );
 
     'main: loop {
         let mut sql = String::new();
         let mut prompt = "naive > ";
-        let mut extra_line = false;
 
         'single: loop {
-            match rl.readline(prompt) {
-                Ok(line) => {
-                    if sql.is_empty() && line.is_empty() {
-                        break 'single;
-                    } else {
-                        sql.push_str(&line)
-                    }
-                }
+            let line = match rl.readline(prompt) {
+                Ok(line) => line,
                 Err(ReadlineError::Interrupted) => break 'single,
                 Err(ReadlineError::Eof) => break 'main,
                 Err(err) => {
                     eprintln!("wtf... {:?}", err);
                     break 'main;
                 }
+            };
+            if sql.is_empty() && line.trim().is_empty() {
+                break 'single;
+            } else if sql.is_empty() && line.starts_with('.') {
+                if let Err(err) = dot_command(&line, &mut output) {
+                    handle_err(&line, err.borrow());
+                }
+                rl.add_history_entry(line);
+                break 'single;
+            }
+            // an empty line ends the input, as if the last statement were terminated
+            let end = line.trim().is_empty();
+            if end && !sql.ends_with(';') {
+                sql.push(';');
+            } else if !end {
+                if !sql.is_empty() {
+                    sql.push('\n');
+                }
+                sql.push_str(line.trim_end());
             }
 
             use ParseError::*;
-            match parser.parse(&sql) {
-                Ok(ast) if !extra_line => {
-                    if let Err(err) = ast.exec() {
-                        handle_err(&sql, err.borrow());
+            match parse_input(&parser, &sql, end) {
+                Ok(Some(stmts)) => {
+                    // every statement pasted at once is run, even if one before fails
+                    for stmt in stmts {
+                        if let Err(err) = exec_stmt(&stmt.stmt, &mut output) {
+                            handle_err(sql[stmt.start..stmt.end].trim_end(), err.borrow());
+                        }
                     }
-
-                    rl.add_history_entry(sql);
+                    rl.add_history_entry(&sql);
                     break 'single;
                 }
-                Err(UnrecognizedEOF { .. }) | Ok(_) => {
-                    extra_line = !extra_line;
-                    sql.push_str("\n        ");
-                    prompt = "    ... ";
-                }
+                Ok(None) => prompt = "    ... ",
                 Err(User { error }) => {
                     eprintln!("User error: {}", error);
                     break 'single;
@@ -64,7 +114,8 @@ pub fn main_loop() {
                         InvalidToken { location } => location,
                         UnrecognizedToken { token, .. } => token.0,
                         ExtraToken { token } => token.0,
-                        _ => unreachable!(),
+                        UnrecognizedEOF { location, .. } => location,
+                        User { .. } => unreachable!(),
                     };
                     let (prev, rest) = sql.split_at(location);
                     eprintln!(
@@ -84,6 +135,271 @@ pub fn main_loop() {
     }
 }
 
+/// Completes table names after the keywords naming a table, and column names of the
+/// tables mentioned in the line anywhere else
+struct SqlHelper;
+
+impl Completer for SqlHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        // nothing to complete without a database in use
+        let (start, names) = {
+            let _executing = hold_database();
+            complete_name(line, pos).unwrap_or_default()
+        };
+        let candidates = names
+            .into_iter()
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: name,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for SqlHelper {
+    type Hint = String;
+}
+
+impl Highlighter for SqlHelper {}
+
+impl Validator for SqlHelper {}
+
+impl Helper for SqlHelper {}
+
+/// Tab indents at the start of a line and completes a name elsewhere
+struct TabHandler;
+
+impl ConditionalEventHandler for TabHandler {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        if ctx.line()[..ctx.pos()].trim().is_empty() {
+            Some(Cmd::Indent(Movement::ForwardChar(4)))
+        } else {
+            Some(Cmd::Complete)
+        }
+    }
+}
+
+const TABLE_KEYWORDS: [&str; 7] = [
+    "from", "into", "update", "join", "table", "desc", "describe",
+];
+
+/// Where the name under the cursor at `pos` starts, and the names it may be completed to
+pub fn complete_name(line: &str, pos: usize) -> DBResult<(usize, Vec<String>)> {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_';
+    let before = &line[..pos];
+    let start = before.trim_end_matches(is_name_char).len();
+    let prefix = &before[start..];
+
+    let tables = table_names(None)?;
+    let mut names = if let Some(qualifier) = before[..start].strip_suffix('.') {
+        // `table.col`
+        let table = &qualifier[qualifier.trim_end_matches(is_name_char).len()..];
+        if tables.iter().any(|name| name == table) {
+            column_names(table)?
+        } else {
+            vec![]
+        }
+    } else {
+        let keyword = before[..start]
+            .rsplit(|c: char| !is_name_char(c))
+            .find(|word| !word.is_empty())
+            .unwrap_or_default();
+        if TABLE_KEYWORDS
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(keyword))
+        {
+            tables
+        } else {
+            let mut names = vec![];
+            for table in tables.iter().filter(|table| {
+                line.split(|c: char| !is_name_char(c))
+                    .any(|word| word == *table)
+            }) {
+                names.extend(column_names(table)?);
+            }
+            names
+        }
+    };
+    names.retain(|name| name.starts_with(prefix));
+    names.sort();
+    names.dedup();
+    Ok((start, names.iter().map(|name| quote_name(name)).collect()))
+}
+
+/// `name` as it is written in SQL, quoted unless it is an identifier other than a keyword
+fn quote_name(name: &str) -> String {
+    match IdentifierParser::new().parse(name) {
+        Ok(parsed) if parsed == name => name.to_owned(),
+        _ => format!("`{}`", name),
+    }
+}
+
+/// Statements of the input read so far, none if more lines are needed, which is the case
+/// until the last statement is terminated by `;` and no string literal is left open,
+/// unless it is the `end` of the input
+pub fn parse_input<'input>(
+    parser: &SpannedSqlStmtsParser,
+    sql: &'input str,
+    end: bool,
+) -> Result<Option<Vec<SpannedStmt>>, ParseError<usize, impl Debug + 'input, &'static str>> {
+    match parser.parse(sql) {
+        Ok(stmts) => Ok(Some(stmts)),
+        Err(ParseError::UnrecognizedEOF { .. }) if !end => Ok(None),
+        Err(ParseError::InvalidToken { location }) if !end && sql[location..].starts_with('\'') => {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn print_duration(action: &str, d: Duration, after: &str) {
+    if d.as_secs() != 0 {
+        println!(
+            "Completed {} in {:.3} seconds{}",
+            action,
+            d.as_secs_f32(),
+            after
+        );
+    } else {
+        let microsec = d.as_micros();
+        if microsec >= 1000 {
+            println!(
+                "Completed {} in {:.3} milliseconds{}",
+                action,
+                microsec as f32 / 1000.,
+                after
+            );
+        } else {
+            println!(
+                "Completed {} in {:.3} microseconds{}",
+                action,
+                d.as_nanos() as f32 / 1000.,
+                after
+            );
+        }
+    }
+}
+
+/// What the statement does, as reported with the time it took
+fn action(stmt: &SqlStmt) -> &'static str {
+    match stmt {
+        SqlStmt::CreateDB(_) => "create_database",
+        SqlStmt::CreateTB(_) => "create_table",
+        SqlStmt::CreateIdx(_) => "create_index",
+        SqlStmt::DropDB(_) => "drop_database",
+        SqlStmt::DropTB(_) => "drop_table",
+        SqlStmt::DropIdx(_) => "drop_index",
+        SqlStmt::Select(_) => "select",
+        SqlStmt::Union(_) => "union",
+        SqlStmt::Insert(_) => "insert",
+        SqlStmt::Update(_) => "update",
+        SqlStmt::Delete(_) => "delete",
+        SqlStmt::UseDB(_) => "use_database",
+        SqlStmt::Show(_) => "show",
+        SqlStmt::Desc(_) => "describe",
+        SqlStmt::Alter(_) => "alter_table",
+        SqlStmt::Set(_) => "set_variable",
+        SqlStmt::CheckConstraint(_) => "check_constraint",
+    }
+}
+
+fn print_result(out: &mut dyn Write, stmt: &SqlStmt, result: ExecResult) -> io::Result<()> {
+    match result {
+        // `SHOW` and `DESC` give text, printed without quotes
+        ExecResult::Rows(rows, header, _)
+            if matches!(stmt, SqlStmt::Show(_) | SqlStmt::Desc(_)) =>
+        {
+            print_text(out, &header, &rows)
+        }
+        ExecResult::Rows(rows, header, omitted) => {
+            print_rows(out, &header, &rows)?;
+            if omitted > 0 {
+                writeln!(out, "... ({} more rows, use LIMIT to see all)", omitted)?;
+            }
+            Ok(())
+        }
+        ExecResult::Affected(affected) => writeln!(out, "{} row(s) affected", affected),
+        ExecResult::Message(message) => writeln!(out, "{}", message),
+        ExecResult::Empty => Ok(()),
+    }
+}
+
+/// Run a statement, printing its result to `out` and the time it took
+pub fn exec_stmt(stmt: &SqlStmt, out: &mut dyn Write) -> DBResult<()> {
+    let (ret, d) = naive_timeit(|| stmt.exec());
+    let ret = ret.and_then(|result| {
+        print_result(out, stmt, result)?;
+        Ok(out.flush()?)
+    });
+    if TIMER.load(Ordering::Relaxed) {
+        print_duration(action(stmt), d, " (including possible io)");
+    }
+    ret
+}
+
+/// Run a REPL command starting with a dot, which is not SQL, printing to `out`
+pub fn dot_command(line: &str, out: &mut Box<dyn Write>) -> DBResult<()> {
+    // some commands read tables, which a termination signal should not write back meanwhile
+    let _executing = begin_statement()?;
+    let mut args = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|arg| !arg.is_empty());
+    match args.next().unwrap_or_default() {
+        ".indexdump" => {
+            let usage = "usage: .indexdump <table> <columns>";
+            let table = args.next().ok_or(usage)?;
+            let cols = args.map(str::to_owned).collect::<Vec<_>>();
+            if cols.is_empty() {
+                return Err(usage.into());
+            }
+            index_dump(table, &cols)?.print(out)?;
+        }
+        ".warm" => {
+            let table = args.next().ok_or("usage: .warm <table>")?;
+            let misses = cache_stats().misses;
+            let pages = warm_table(table)?;
+            writeln!(
+                out,
+                "Read {} page(s) of {} into the cache, {} of them from disk",
+                pages,
+                table,
+                cache_stats().misses - misses
+            )?;
+        }
+        ".checksum" => {
+            let table = args.next().ok_or("usage: .checksum <table>")?;
+            writeln!(out, "{:016x}", table_checksum(table)?)?;
+        }
+        ".timer" => {
+            let on = match args.next().map(str::to_lowercase).as_deref() {
+                Some("on") => true,
+                Some("off") => false,
+                _ => return Err("usage: .timer on|off".into()),
+            };
+            TIMER.store(on, Ordering::Relaxed);
+        }
+        ".output" => {
+            // results go to the file from now on, back to the terminal without one
+            let target = match args.next() {
+                None | Some("stdout") => Box::new(stdout()) as Box<dyn Write>,
+                Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            };
+            out.flush()?;
+            *out = target;
+        }
+        command => return Err(format!("unknown command {}", command).into()),
+    }
+    Ok(())
+}
+
 pub fn handle_err(sql: &str, err: &dyn Error) {
     eprintln!("Error: {}", err);
     if let Some(source) = err.source() {
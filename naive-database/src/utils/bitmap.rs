@@ -21,5 +21,5 @@ pub fn clear_bit_at(bitmap: &mut [u8], i: usize) {
 
 #[inline]
 pub fn iter_bits(bitmap: &[u8]) -> impl Iterator<Item = bool> + '_ {
-    (0..bitmap.len()).map(move |i| bit_at(bitmap, i))
+    (0..bitmap.len() * 8).map(move |i| bit_at(bitmap, i))
 }
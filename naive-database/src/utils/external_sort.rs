@@ -0,0 +1,127 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::atomic::{self, AtomicUsize},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::DBResult;
+
+/// Number of sorted runs ever written to disk
+pub static SPILLED_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorted items in a file of the temporary directory, which is removed along with it
+struct Run {
+    path: PathBuf,
+    len: usize,
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Stable sort of items pushed one at a time, keeping at most `capacity` of them in memory.
+/// Once that many are buffered they are sorted and written to a run in `dir`,
+/// and the runs are merged in the end
+pub struct ExternalSort<T, F> {
+    capacity: usize,
+    dir: PathBuf,
+    cmp: F,
+    buffer: Vec<T>,
+    runs: Vec<Run>,
+}
+
+impl<T, F> ExternalSort<T, F>
+where
+    T: Serialize + DeserializeOwned + 'static,
+    F: Fn(&T, &T) -> Ordering,
+{
+    pub fn new(capacity: usize, dir: PathBuf, cmp: F) -> Self {
+        Self {
+            capacity,
+            dir,
+            cmp,
+            buffer: vec![],
+            runs: vec![],
+        }
+    }
+
+    pub fn push(&mut self, item: T) -> DBResult<()> {
+        self.buffer.push(item);
+        if self.buffer.len() >= self.capacity {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> DBResult<()> {
+        static NEXT_RUN: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_RUN.fetch_add(1, atomic::Ordering::Relaxed);
+        // removed even if writing it fails
+        let run = Run {
+            path: self
+                .dir
+                .join(format!("naive-db-sort-{}-{}.run", process::id(), id)),
+            len: self.buffer.len(),
+        };
+        let mut writer = BufWriter::new(File::create(&run.path)?);
+        self.buffer.sort_by(&self.cmp);
+        for item in self.buffer.drain(..) {
+            bincode::serialize_into(&mut writer, &item)?;
+        }
+        writer.flush()?;
+        self.runs.push(run);
+        SPILLED_RUNS.fetch_add(1, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// All items in order, the runs and the items still buffered are merged,
+    /// taking the earliest pushed of equal items first
+    pub fn finish(mut self) -> DBResult<Vec<T>> {
+        self.buffer.sort_by(&self.cmp);
+        if self.runs.is_empty() {
+            return Ok(self.buffer);
+        }
+        let len = self.runs.iter().map(|run| run.len).sum::<usize>() + self.buffer.len();
+        let mut sources: Vec<Box<dyn Iterator<Item = DBResult<T>>>> = vec![];
+        for run in &self.runs {
+            let mut reader = BufReader::new(File::open(&run.path)?);
+            let items = (0..run.len).map(move |_| Ok(bincode::deserialize_from(&mut reader)?));
+            sources.push(Box::new(items));
+        }
+        sources.push(Box::new(mem::take(&mut self.buffer).into_iter().map(Ok)));
+
+        let mut heads = sources
+            .iter_mut()
+            .map(|source| source.next().transpose())
+            .collect::<DBResult<Vec<_>>>()?;
+        let mut ret = Vec::with_capacity(len);
+        loop {
+            let mut min: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                let head = match head {
+                    Some(head) => head,
+                    None => continue,
+                };
+                match min {
+                    Some(j) if (self.cmp)(head, heads[j].as_ref().unwrap()).is_ge() => {}
+                    _ => min = Some(i),
+                }
+            }
+            let i = match min {
+                Some(i) => i,
+                None => break,
+            };
+            let next = sources[i].next().transpose()?;
+            ret.extend(mem::replace(&mut heads[i], next));
+        }
+        Ok(ret)
+    }
+}
@@ -1,14 +1,11 @@
 #![allow(unused)]
 
-use std::{
-    fs::{self, DirEntry},
-    path::Path,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
 use crate::error::DBResult;
 
 pub mod bitmap;
+pub mod external_sort;
 pub mod lru;
 pub mod persistence;
 pub mod serial_cell;
@@ -16,19 +13,8 @@ pub mod table;
 
 pub use bitmap::*;
 use chrono::NaiveDate;
-use like::Like;
-
-pub fn iter_dir_by<T>(
-    dir: &Path,
-    mut action: impl FnMut(&DirEntry) -> Option<T>,
-) -> DBResult<impl Iterator<Item = T>> {
-    let iter = fs::read_dir(dir)?;
-    let iter = iter.filter_map(move |r| match r.ok() {
-        Some(d) => action(&d),
-        None => None,
-    });
-    Ok(iter)
-}
+use like::{ILike, Like};
+use naive_sql_parser::CompareOp;
 
 pub fn parse_date(s: &str) -> Option<NaiveDate> {
     let alternatives = ["%Y-%m-%d", "%Y/%m/%d"];
@@ -40,6 +26,30 @@ pub fn parse_date(s: &str) -> Option<NaiveDate> {
     None
 }
 
+/// Parse a FLOAT, a number too large for it is an error rather than infinity,
+/// but `inf` and `NaN` are still accepted as such
+pub fn parse_float(s: &str) -> DBResult<f32> {
+    let f: f32 = s.trim().parse()?;
+    let unsigned = s.trim().trim_start_matches(&['+', '-'][..]);
+    if f.is_infinite() && !unsigned.to_ascii_lowercase().starts_with("inf") {
+        return Err(format!("{} is out of the range of FLOAT", s).into());
+    }
+    Ok(f)
+}
+
+/// Whether `s` matches `pattern` by one of the LIKE operators, the parameter of
+/// `Like` and `ILike` is whether a backslash escapes `%` and `_`
+pub fn like_match(s: &str, op: CompareOp, pattern: &str) -> DBResult<bool> {
+    let ret = match op {
+        CompareOp::LIKE => Like::<true>::like(s, pattern)?,
+        CompareOp::NOTLIKE => Like::<true>::not_like(s, pattern)?,
+        CompareOp::ILIKE => ILike::<true>::ilike(s, pattern)?,
+        CompareOp::NOTILIKE => ILike::<true>::not_ilike(s, pattern)?,
+        _ => unreachable!(),
+    };
+    Ok(ret)
+}
+
 #[inline(always)]
 pub fn naive_timeit<T>(f: impl FnOnce() -> T) -> (T, Duration) {
     let now = Instant::now();
@@ -1,8 +1,8 @@
-use std::{fs, path::Path};
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{error::DBResult, filesystem::file_manager::fs_ensure_remove};
+use crate::{error::DBResult, filesystem::storage};
 
 pub trait Persistence
 where
@@ -13,20 +13,18 @@ where
     #[inline]
     fn store(&self, dir: &Path) -> DBResult<()> {
         let file = dir.join(self.filename());
-        let file = fs::OpenOptions::new().write(true).create(true).open(file)?;
-        bincode::serialize_into(file, self)?;
+        storage::write(&file, &bincode::serialize(self)?)?;
         Ok(())
     }
 
     #[inline]
     fn load(file: &Path) -> DBResult<Self> {
-        let file = fs::File::open(file)?;
-        Ok(bincode::deserialize_from(file)?)
+        Ok(bincode::deserialize(&storage::read(file)?)?)
     }
 
     #[inline]
     fn delete_self(self, dir: &Path) -> DBResult<()> {
-        fs_ensure_remove(&dir.join(self.filename()))?;
+        storage::ensure_remove(&dir.join(self.filename()))?;
         Ok(())
     }
 }
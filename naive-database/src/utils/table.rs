@@ -1,45 +1,55 @@
 use std::{
-    collections::HashSet,
     fmt::Display,
-    io::{stdout, BufWriter},
+    io::{self, Write},
 };
 
 use lazy_static::lazy_static;
-use prettytable::{format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR, Attr, Cell, Row, Table};
+use prettytable::{
+    format::{consts::FORMAT_NO_BORDER_LINE_SEPARATOR, Alignment},
+    Attr, Cell, Row, Table,
+};
 
 use regex::Regex;
 
 use crate::{
-    config::MAX_JOIN_TABLE,
-    dbms::database::get_table,
-    defines::{ColID, RowID, TableID},
+    config::SETTINGS,
     error::DBResult,
     record::{ColumnType, ColumnVal},
 };
 
+use super::parse_float;
+
 fn format_row<'a, T: Display + 'a>(row: impl Iterator<Item = &'a T>) -> Row {
     Row::new(row.map(|val| Cell::new(val.to_string().as_str())).collect())
 }
 
-fn format_data_row(row: &[Option<ColumnVal>]) -> Row {
-    lazy_static! {
-        static ref NULL: String = "NULL".to_owned();
-    };
+/// Numbers are right-aligned, text and dates left-aligned
+pub fn column_alignment(coltype: ColumnType) -> Alignment {
+    match coltype {
+        ColumnType::Int | ColumnType::Float => Alignment::RIGHT,
+        ColumnType::Char | ColumnType::Varchar | ColumnType::Date => Alignment::LEFT,
+    }
+}
+
+fn format_data_row(row: &[Option<ColumnVal>], coltypes: &[ColumnType]) -> Row {
+    let null = &SETTINGS.borrow().null_string;
 
     Row::new(
         row.iter()
-            .map(|val| match val {
-                Some(val) => Cell::new(&val.to_string()),
-                None => Cell::new(&NULL),
+            .zip(coltypes)
+            .map(|(val, &coltype)| match val {
+                Some(val) => Cell::new_align(&val.to_string(), column_alignment(coltype)),
+                None => Cell::new_align(null, column_alignment(coltype)),
             })
             .collect(),
     )
 }
 
 pub fn print_vec<'header, 'body>(
+    out: &mut dyn Write,
     header: impl Iterator<Item = &'header str>,
     body: impl Iterator<Item = &'body [&'body str]>,
-) {
+) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(*FORMAT_NO_BORDER_LINE_SEPARATOR);
 
@@ -53,33 +63,86 @@ pub fn print_vec<'header, 'body>(
     for row in body {
         table.add_row(format_row(row.iter()));
     }
-    let out = stdout();
-    let out = out.lock();
-    let mut out = BufWriter::new(out);
-    table.print(&mut out);
+    table.print(out)?;
+    Ok(())
 }
 
 pub fn print_data_row<'header, 'body>(
+    out: &mut dyn Write,
     header: impl Iterator<Item = &'header str>,
+    coltypes: &[ColumnType],
     body: impl Iterator<Item = &'body [Option<ColumnVal>]>,
-) {
+) -> io::Result<()> {
     let mut table = Table::new();
     table.set_format(*FORMAT_NO_BORDER_LINE_SEPARATOR);
 
     let header = Row::new(
         header
-            .map(|s| Cell::new(s).with_style(Attr::Bold))
+            .zip(coltypes)
+            .map(|(s, &coltype)| {
+                Cell::new_align(s, column_alignment(coltype)).with_style(Attr::Bold)
+            })
             .collect(),
     );
     table.set_titles(header);
 
     for row in body {
-        table.add_row(format_data_row(row));
+        table.add_row(format_data_row(row, coltypes));
     }
-    let out = stdout();
-    let out = out.lock();
-    let mut out = BufWriter::new(out);
-    table.print(&mut out);
+    table.print(out)?;
+    Ok(())
+}
+
+/// Print rows not stored in a table, column types are inferred from the values
+pub fn print_rows(
+    out: &mut dyn Write,
+    header: &[String],
+    body: &[Vec<Option<ColumnVal>>],
+) -> io::Result<()> {
+    if body.is_empty() {
+        return writeln!(out, "No data found");
+    }
+    let coltypes = (0..header.len())
+        .map(|i| {
+            body.iter()
+                .find_map(|row| row[i].as_ref().map(ColumnVal::coltype))
+                .unwrap_or(ColumnType::Varchar)
+        })
+        .collect::<Vec<_>>();
+    print_data_row(
+        out,
+        header.iter().map(String::as_str),
+        &coltypes,
+        body.iter().map(Vec::as_slice),
+    )?;
+    writeln!(out, "{} items in total", body.len())
+}
+
+/// Print rows of text cells, strings are shown without quotes
+pub fn print_text(
+    out: &mut dyn Write,
+    header: &[String],
+    body: &[Vec<Option<ColumnVal>>],
+) -> io::Result<()> {
+    if body.is_empty() {
+        return writeln!(out, "No data found");
+    }
+    let null = &SETTINGS.borrow().null_string;
+    let body = body
+        .iter()
+        .flatten()
+        .map(|val| match val {
+            Some(ColumnVal::Char(s)) | Some(ColumnVal::Varchar(s)) => s.clone(),
+            Some(val) => val.to_string(),
+            None => null.clone(),
+        })
+        .collect::<Vec<_>>();
+    let body = body.iter().map(String::as_str).collect::<Vec<_>>();
+    print_vec(
+        out,
+        header.iter().map(String::as_str),
+        body.chunks_exact(header.len()),
+    )
 }
 
 pub fn get_coltype(coltype: ColumnType, colsize: u8) -> String {
@@ -111,7 +174,7 @@ pub fn parse_colval(coltype: ColumnType, val: &str) -> DBResult<Option<ColumnVal
     } else {
         match coltype {
             ColumnType::Int => Int(val.parse()?),
-            ColumnType::Float => Float(val.parse()?),
+            ColumnType::Float => Float(parse_float(val)?),
             ColumnType::Char => Char(val.to_owned()),
             ColumnType::Varchar => Varchar(val.to_owned()),
             ColumnType::Date => Date(val.parse()?),
@@ -120,37 +183,3 @@ pub fn parse_colval(coltype: ColumnType, val: &str) -> DBResult<Option<ColumnVal
 
     Ok(Some(val))
 }
-
-pub fn print_join_table(
-    rows: HashSet<[RowID; MAX_JOIN_TABLE]>,
-    lid: TableID,
-    lcols: &[ColID],
-    rid: TableID,
-    rcols: &[ColID],
-) {
-    if rows.is_empty() {
-        println!("No data found");
-        return;
-    }
-    get_table(lid, |ltable| {
-        get_table(rid, |rtable| {
-            let header = lcols
-                .iter()
-                .map(|&lcol| ltable.meta.columns[lcol as usize].name.as_str())
-                .chain(
-                    rcols
-                        .iter()
-                        .map(|&rcol| rtable.meta.columns[rcol as usize].name.as_str()),
-                );
-            let mut body = Vec::with_capacity((lcols.len() + rcols.len()) * rows.len());
-            for &[lrow, rrow] in rows.iter() {
-                let ldata = ltable.select_cols(lrow, lcols.iter().copied()).unwrap();
-                body.extend(ldata);
-                let rdata = rtable.select_cols(rrow, rcols.iter().copied()).unwrap();
-                body.extend(rdata);
-            }
-            print_data_row(header, body.chunks_exact(lcols.len() + rcols.len()));
-            println!("{} items in total", rows.len());
-        })
-    })
-}
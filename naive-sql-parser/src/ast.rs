@@ -11,6 +11,7 @@ pub enum SqlStmt {
     DropTB(Box<DropTB>),
     DropIdx(Box<DropIdx>),
     Select(Box<Select>),
+    Union(Box<Union>),
     Insert(Box<Insert>),
     Update(Box<Update>),
     Delete(Box<Delete>),
@@ -18,6 +19,17 @@ pub enum SqlStmt {
     Show(Box<Show>),
     Desc(Box<Desc>),
     Alter(Box<Alter>),
+    Set(Box<Set>),
+    CheckConstraint(Box<CheckConstraint>),
+}
+
+/// A statement with the byte offsets where it starts and ends in the source,
+/// the end may take in whitespace before the `;` after it
+#[derive(Debug)]
+pub struct SpannedStmt {
+    pub start: usize,
+    pub stmt: SqlStmt,
+    pub end: usize,
 }
 
 #[derive(Debug)]
@@ -27,15 +39,24 @@ pub enum Alter {
     AddPrimary(AddPrimary),
     AddForeign(AddForeign),
     DropForeign(DropForeign),
+    RenameDB(RenameDB),
 }
 
 #[derive(Debug)]
-pub struct CreateDB(pub String);
+pub struct CreateDB {
+    pub name: String,
+    /// `IF NOT EXISTS`, an existing database is left as it is
+    pub if_not_exists: bool,
+}
 
 #[derive(Debug)]
 pub struct CreateTB {
     pub name: String,
     pub fields: Vec<CreateTBField>,
+    /// `IF NOT EXISTS`, an existing table is left as it is
+    pub if_not_exists: bool,
+    /// `CREATE TEMPORARY TABLE`, a table of the session dropped when the database is closed
+    pub temporary: bool,
 }
 
 #[derive(Debug)]
@@ -51,10 +72,26 @@ pub struct CreateIdx {
 }
 
 #[derive(Debug)]
-pub struct DropDB(pub String);
+pub struct DropDB {
+    pub name: String,
+    /// `IF EXISTS`, dropping a missing database does nothing
+    pub if_exists: bool,
+}
 
+/// `ALTER DATABASE old RENAME TO new`
 #[derive(Debug)]
-pub struct DropTB(pub String);
+pub struct RenameDB {
+    pub old: String,
+    pub new: String,
+}
+
+/// `DROP TABLE [IF EXISTS] name`, or `DROP TABLE LIKE 'pattern'` dropping the tables
+/// whose names match
+#[derive(Debug)]
+pub enum DropTB {
+    Name { name: String, if_exists: bool },
+    Like(String),
+}
 
 #[derive(Debug)]
 pub struct DropIdx {
@@ -74,6 +111,8 @@ pub struct AddForeign {
     pub cols: Vec<String>,
     pub ftable_name: String,
     pub fcols: Vec<String>,
+    pub on_delete: RefAction,
+    pub on_update: RefAction,
 }
 
 #[derive(Debug)]
@@ -84,14 +123,43 @@ pub struct DropForeign {
     pub fcols: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Select {
+    pub index_hint: Option<IndexHint>,
     pub selectors: Selectors,
-    pub from: Vec<String>,
+    pub from: Vec<FromItem>,
     pub condition: Option<CondExpr>,
-    pub group_by: Option<ColumnRef>,
-    pub limit: Option<i32>,
-    pub offset: Option<i32>,
+    pub group_by: Option<Expr>,
+    pub order_by: Vec<(Expr, SortOrder, Option<NullsOrder>)>,
+    pub limit: Option<i64>,
+    /// `LIMIT n WITH TIES`, also keeping rows sorted equal to the last one within the limit
+    pub with_ties: bool,
+    pub offset: Option<i64>,
+}
+
+/// `SELECT ... UNION [ALL] SELECT ...`, each `SELECT` is run on its own, with its own
+/// ORDER BY and LIMIT, and the rows of the right one follow those of the left one
+#[derive(Debug)]
+pub struct Union {
+    pub left: Select,
+    pub right: Select,
+    /// `UNION ALL`, which keeps duplicate rows
+    pub all: bool,
+}
+
+/// `/*+ INDEX(table column, ...) */`, forcing the index on the columns to be used
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexHint {
+    pub table: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FromItem {
+    /// A table and its alias, if given
+    Table(String, Option<String>),
+    /// `(SELECT ...) AS name`
+    Derived(Box<Select>, String),
 }
 
 #[derive(Debug)]
@@ -119,10 +187,36 @@ pub struct UseDB(pub String);
 
 #[derive(Debug)]
 pub enum Show {
-    Databases,
-    Tables,
+    /// Databases, or only those whose names match a LIKE pattern
+    Databases(Option<String>),
+    /// Tables, or only those whose names match a LIKE pattern
+    Tables(Option<String>),
+    /// `SHOW TABLE STATUS`, tables with their sizes and numbers of indices
+    TableStatus(Option<String>),
     Indices,
+    /// `SHOW CONFIG`, settings of the session and limits of the build
+    Config,
 }
 
 #[derive(Debug)]
 pub struct Desc(pub String);
+
+/// `CHECK CONSTRAINT ON table ...`, rows that would violate a constraint if it were added
+#[derive(Debug)]
+pub struct CheckConstraint {
+    pub table_name: String,
+    pub constraint: ProposedConstraint,
+}
+
+#[derive(Debug)]
+pub enum ProposedConstraint {
+    NotNull(String),
+    Unique(Vec<String>),
+    Check { colname: String, exprs: Vec<Expr> },
+}
+
+#[derive(Debug)]
+pub struct Set {
+    pub name: String,
+    pub value: String,
+}
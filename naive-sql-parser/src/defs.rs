@@ -2,7 +2,9 @@ use std::fmt::Display;
 
 use chrono::NaiveDate;
 
-#[derive(Debug, Clone, Copy)]
+use crate::ast::Select;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ColumnType {
     Int,
     Float,
@@ -11,12 +13,28 @@ pub enum ColumnType {
     Date,
 }
 
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ColumnType::*;
+        let coltype = match self {
+            Int => "INT",
+            Float => "FLOAT",
+            Char => "CHAR",
+            Varchar => "VARCHAR",
+            Date => "DATE",
+        };
+        write!(f, "{}", coltype)
+    }
+}
+
 #[derive(Debug)]
 pub struct Column {
     pub name: String,
     pub coltype: ColumnType,
     pub colsize: Option<u8>,
     pub notnull: bool,
+    /// Declared `NULL` or `DEFAULT NULL`, which columns are unless declared `NOT NULL`
+    pub nullable: bool,
     pub unique: bool,
     pub primary: bool,
     pub foreign: Option<(String, String)>,
@@ -40,10 +58,23 @@ pub enum TBConstraint {
         colname: Vec<String>,
         foreign_tb: String,
         foreign_col: Vec<String>,
+        on_delete: RefAction,
+        on_update: RefAction,
     },
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What happens to referencing rows when the referenced row is deleted or updated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefAction {
+    /// Reject the change while referencing rows exist
+    Restrict,
+    /// Delete or update the referencing rows along with the referenced one
+    Cascade,
+    /// Set the referencing columns to NULL, only on delete
+    SetNull,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CompareOp {
     EQ,
     NE,
@@ -53,31 +84,139 @@ pub enum CompareOp {
     LE,
     LIKE,
     NOTLIKE,
+    /// LIKE ignoring case
+    ILIKE,
+    NOTILIKE,
+    /// `<=>` or `IS NOT DISTINCT FROM`, unlike `=` it is never unknown: two NULLs are equal,
+    /// and NULL is not equal to any value
+    NULLSAFEEQ,
 }
 
 impl CompareOp {
+    /// The comparison with its operands swapped, `a < b` is `b > a`
     pub fn rev(self) -> Self {
         use CompareOp::*;
         match self {
             EQ => EQ,
             NE => NE,
+            GT => LT,
+            LT => GT,
+            GE => LE,
+            LE => GE,
+            LIKE => LIKE,
+            NOTLIKE => NOTLIKE,
+            ILIKE => ILIKE,
+            NOTILIKE => NOTILIKE,
+            NULLSAFEEQ => NULLSAFEEQ,
+        }
+    }
+
+    /// The comparison true exactly where this one is false, if neither operand is NULL
+    pub fn not(self) -> Self {
+        use CompareOp::*;
+        match self {
+            EQ => NE,
+            NE => EQ,
             GT => LE,
             LT => GE,
             GE => LT,
             LE => GT,
-            LIKE => LIKE,
-            NOTLIKE => NOTLIKE,
+            LIKE => NOTLIKE,
+            NOTLIKE => LIKE,
+            ILIKE => NOTILIKE,
+            NOTILIKE => ILIKE,
+            NULLSAFEEQ => NE,
         }
     }
 }
 
-#[derive(Debug)]
+/// Rewrite a LIKE pattern escaped by `escape` to one escaped by a backslash
+pub(crate) fn escape_pattern(pattern: &str, escape: &str) -> Result<String, &'static str> {
+    let mut chars = escape.chars();
+    let escape = match (chars.next(), chars.next()) {
+        (Some(escape), None) => escape,
+        _ => return Err("the escape of LIKE must be a single character"),
+    };
+    let mut ret = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == escape {
+            let escaped = chars
+                .next()
+                .ok_or("a LIKE pattern cannot end with its escape")?;
+            ret.push('\\');
+            ret.push(escaped);
+        } else if c == '\\' {
+            ret.push_str("\\\\");
+        } else {
+            ret.push(c);
+        }
+    }
+    Ok(ret)
+}
+
+/// `(a, b) IN ((1, 2), (3, 4))`, each tuple of the list should have as many items as `tuple`
+pub(crate) fn in_tuple(tuple: Vec<Expr>, list: Vec<Vec<Expr>>) -> Result<CalcExpr, &'static str> {
+    if list.iter().any(|item| item.len() != tuple.len()) {
+        return Err("tuples compared by IN should have the same number of items");
+    }
+    Ok(CalcExpr::InTuple(tuple, list))
+}
+
+/// `CASE WHEN cond THEN expr ... ELSE expr END`, literal results should be all numbers or all
+/// strings. Other results can only be checked on the values they give
+pub(crate) fn case(
+    whens: Vec<(CondExpr, Expr)>,
+    else_: Option<Expr>,
+) -> Result<Expr, &'static str> {
+    let is_number = |expr: &Expr| match expr {
+        Expr::IntLit(_) | Expr::FloatLit(_) => Some(true),
+        Expr::StringLit(_) => Some(false),
+        _ => None,
+    };
+    let mut kinds = whens
+        .iter()
+        .map(|(_, expr)| expr)
+        .chain(&else_)
+        .filter_map(is_number);
+    if let Some(first) = kinds.next() {
+        if kinds.any(|kind| kind != first) {
+            return Err("results of CASE should be all numbers or all strings");
+        }
+    }
+    Ok(Expr::Case {
+        whens,
+        else_: else_.map(Box::new),
+    })
+}
+
+impl Display for CompareOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use CompareOp::*;
+        let op = match self {
+            EQ => "=",
+            NE => "<>",
+            GT => ">",
+            LT => "<",
+            GE => ">=",
+            LE => "<=",
+            LIKE => "LIKE",
+            NOTLIKE => "NOT LIKE",
+            ILIKE => "ILIKE",
+            NOTILIKE => "NOT ILIKE",
+            NULLSAFEEQ => "<=>",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum LogicOp {
     AND,
     OR,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CondExpr {
     True,
     False,
@@ -86,14 +225,60 @@ pub enum CondExpr {
     Term(CalcExpr),
 }
 
-#[derive(Debug)]
+impl Display for CondExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CondExpr::True => write!(f, "TRUE"),
+            CondExpr::False => write!(f, "FALSE"),
+            CondExpr::Binary(lhs, op, rhs) => {
+                let op = match op {
+                    LogicOp::AND => "AND",
+                    LogicOp::OR => "OR",
+                };
+                write!(f, "({}) {} ({})", lhs, op, rhs)
+            }
+            CondExpr::Not(cond) => write!(f, "NOT ({})", cond),
+            CondExpr::Term(CalcExpr::Compare(lhs, op, rhs)) => write!(f, "{} {} {}", lhs, op, rhs),
+            CondExpr::Term(CalcExpr::IsNull(expr)) => write!(f, "{} IS NULL", expr),
+            CondExpr::Term(CalcExpr::InSelect(expr, _)) => write!(f, "{} IN (SELECT ...)", expr),
+            CondExpr::Term(CalcExpr::CompareSelect(lhs, op, _)) => {
+                write!(f, "{} {} (SELECT ...)", lhs, op)
+            }
+            CondExpr::Term(CalcExpr::Exists(_)) => write!(f, "EXISTS (SELECT ...)"),
+            CondExpr::Term(CalcExpr::In(expr, list)) => {
+                let list = list.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "{} IN ({})", expr, list.join(", "))
+            }
+            CondExpr::Term(CalcExpr::InTuple(tuple, list)) => {
+                let tuple_str = |tuple: &[Expr]| {
+                    let items = tuple.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                    format!("({})", items.join(", "))
+                };
+                let list = list.iter().map(|t| tuple_str(t)).collect::<Vec<_>>();
+                write!(f, "{} IN ({})", tuple_str(tuple), list.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CalcExpr {
     In(Box<Expr>, Vec<Expr>),
+    /// `(a, b) IN ((1, 2), (3, 4))`
+    InTuple(Vec<Expr>, Vec<Vec<Expr>>),
     Compare(Box<Expr>, CompareOp, Box<Expr>),
     IsNull(Box<Expr>),
+    /// `x IN (SELECT ...)`, the subquery selects one column
+    InSelect(Box<Expr>, Box<Select>),
+    /// `x > (SELECT ...)`, the subquery selects one column of at most one row,
+    /// which is NULL if there is none
+    CompareSelect(Box<Expr>, CompareOp, Box<Select>),
+    /// `EXISTS (SELECT ...)`, whether the subquery gives any row. Only uncorrelated
+    /// subqueries are supported, which cannot refer to columns of the outer query
+    Exists(Box<Select>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOp {
     ADD,
     SUB,
@@ -101,7 +286,19 @@ pub enum BinaryOp {
     DIV,
 }
 
-#[derive(Debug)]
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            BinaryOp::ADD => "+",
+            BinaryOp::SUB => "-",
+            BinaryOp::MUL => "*",
+            BinaryOp::DIV => "/",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Binary(Box<Expr>, BinaryOp, Box<Expr>),
     ColumnRef(ColumnRef),
@@ -109,9 +306,54 @@ pub enum Expr {
     FloatLit(f32),
     StringLit(String),
     Null,
+    /// `CASE WHEN cond THEN expr ... ELSE expr END`,
+    /// `CASE x WHEN val ...` is the same as `CASE WHEN x = val ...`
+    Case {
+        whens: Vec<(CondExpr, Expr)>,
+        else_: Option<Box<Expr>>,
+    },
+    /// A call of a scalar function, whose name is in upper case
+    Func(String, Vec<Expr>),
+    /// `CAST(expr AS type)`
+    Cast(Box<Expr>, ColumnType),
 }
 
-#[derive(Debug)]
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Binary(lhs, op, rhs) => {
+                // nested operations are parenthesized, so precedence is always explicit
+                let operand = |expr: &Expr| match expr {
+                    Expr::Binary(..) => format!("({})", expr),
+                    _ => expr.to_string(),
+                };
+                write!(f, "{} {} {}", operand(lhs), op, operand(rhs))
+            }
+            Expr::ColumnRef(colref) => write!(f, "{}", colref),
+            Expr::IntLit(i) => write!(f, "{}", i),
+            Expr::FloatLit(x) => write!(f, "{}", x),
+            Expr::StringLit(s) => write!(f, "'{}'", s),
+            Expr::Null => write!(f, "NULL"),
+            Expr::Case { whens, else_ } => {
+                write!(f, "CASE")?;
+                for (cond, expr) in whens {
+                    write!(f, " WHEN {} THEN {}", cond, expr)?;
+                }
+                if let Some(expr) = else_ {
+                    write!(f, " ELSE {}", expr)?;
+                }
+                write!(f, " END")
+            }
+            Expr::Func(name, args) => {
+                let args = args.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Cast(expr, coltype) => write!(f, "CAST({} AS {})", expr, coltype),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ColumnRef {
     Ident(String),
     Attr { table_name: String, column: String },
@@ -126,7 +368,21 @@ impl Display for ColumnRef {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    ASC,
+    DESC,
+}
+
+/// Where NULL goes in ORDER BY, by default it is greater than any value,
+/// i.e. last in ascending order and first in descending order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NullsOrder {
+    FIRST,
+    LAST,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Aggregator {
     COUNT,
     AVG,
@@ -135,14 +391,17 @@ pub enum Aggregator {
     SUM,
 }
 
-#[derive(Debug)]
+/// The last field of each selector is its optional alias (`AS name`)
+#[derive(Debug, Clone, PartialEq)]
 pub enum SingleSelector {
-    Single(ColumnRef),
-    Aggregate(Aggregator, ColumnRef),
-    CountAll,
+    Single(ColumnRef, Option<String>),
+    Aggregate(Aggregator, ColumnRef, Option<String>),
+    CountAll(Option<String>),
+    /// A computed column
+    Expr(Expr, Option<String>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Selectors {
     Part(Vec<SingleSelector>),
     All,
@@ -7,7 +7,9 @@ mod sql;
 pub use ast::*;
 pub use defs::*;
 pub use lalrpop_util::ParseError;
-pub use sql::{SingleSqlParser, SqlStmtsParser};
+pub use sql::{
+    identifierParser as IdentifierParser, SingleSqlParser, SpannedSqlStmtsParser, SqlStmtsParser,
+};
 
 #[cfg(test)]
 mod tests {
@@ -42,4 +44,15 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn statement_spans() {
+        let sqls = "USE db;\n  SELECT *\n  FROM t ; SHOW TABLES;";
+        let stmts = sql::SpannedSqlStmtsParser::new().parse(sqls).unwrap();
+        let spans = stmts
+            .iter()
+            .map(|stmt| sqls[stmt.start..stmt.end].trim_end())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, ["USE db", "SELECT *\n  FROM t", "SHOW TABLES"]);
+    }
 }
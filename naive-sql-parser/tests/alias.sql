@@ -0,0 +1,4 @@
+SELECT COUNT(*) AS n FROM t;
+SELECT a AS x, t.b AS y FROM t;
+SELECT MAX(a) AS top, MIN(t.a) FROM t WHERE b > 1;
+SELECT t.a AS x, u.a AS y FROM t, u WHERE t.a = u.a;
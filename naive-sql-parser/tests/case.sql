@@ -0,0 +1,4 @@
+SELECT name FROM task ORDER BY CASE status WHEN 'urgent' THEN 0 ELSE 1 END, name;
+SELECT CASE WHEN score >= 60 THEN 'pass' ELSE 'fail' END AS grade, COUNT(*) FROM exam GROUP BY CASE WHEN score >= 60 THEN 'pass' ELSE 'fail' END;
+SELECT name, score FROM exam ORDER BY score DESC, name ASC LIMIT 3;
+SELECT CASE WHEN a IS NULL THEN 0 WHEN a > 10 AND b < 2 THEN 1 END FROM t;
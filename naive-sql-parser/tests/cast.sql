@@ -0,0 +1,2 @@
+SELECT CAST(a AS FLOAT), cast('2022-01-02' as date) FROM t WHERE CAST(s AS INT) > 3;
+SELECT CAST(CAST(1.5 AS INT) AS VARCHAR);
@@ -0,0 +1,3 @@
+CHECK CONSTRAINT ON t NOT NULL (a);
+CHECK CONSTRAINT ON t UNIQUE (a, b);
+check constraint on t check (c in ('x', 'y'));
@@ -0,0 +1 @@
+CREATE TABLE t (a INT DEFAULT NULL, b INT NULL DEFAULT NULL, c VARCHAR(4) default null UNIQUE, d INT NOT NULL);
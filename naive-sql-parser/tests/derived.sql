@@ -0,0 +1,3 @@
+SELECT dept, n FROM (SELECT dept, COUNT(*) AS n FROM emp GROUP BY dept) AS sub WHERE n > 5;
+SELECT * FROM (SELECT a FROM t) s;
+SELECT sub.x FROM (SELECT a * 2 AS x FROM (SELECT a FROM t) AS inner_t) AS sub;
@@ -0,0 +1,3 @@
+DROP TABLE t;
+DROP TABLE LIKE 'tmp\_%';
+drop table like '%';
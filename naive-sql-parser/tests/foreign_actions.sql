@@ -0,0 +1,3 @@
+CREATE TABLE child (pid INT, FOREIGN KEY (pid) REFERENCES parent (id) ON DELETE SET NULL ON UPDATE CASCADE);
+CREATE TABLE grade (sid INT, FOREIGN KEY (sid) REFERENCES student (id) ON UPDATE RESTRICT ON DELETE CASCADE);
+ALTER TABLE child ADD CONSTRAINT FOREIGN KEY (pid) REFERENCES parent (id) ON DELETE SET NULL;
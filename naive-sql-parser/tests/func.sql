@@ -0,0 +1,3 @@
+SELECT UPPER(name), length(name) AS len FROM t WHERE LENGTH(name) > 5;
+SELECT SUBSTR(name, 2, 3) FROM t WHERE lower(name) = 'abc' ORDER BY Substr(name, 1);
+UPDATE t SET name = UPPER(name) WHERE id = 1;
@@ -0,0 +1,5 @@
+CREATE DATABASE IF NOT EXISTS db;
+CREATE TABLE IF NOT EXISTS t (a INT);
+create table if not exists `quoted` (a INT);
+DROP TABLE IF EXISTS t;
+DROP DATABASE IF EXISTS db;
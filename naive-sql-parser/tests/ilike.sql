@@ -0,0 +1,2 @@
+SELECT * FROM t WHERE name ILIKE 'ab%';
+SELECT * FROM t WHERE name NOT ILIKE '%x!_' ESCAPE '!';
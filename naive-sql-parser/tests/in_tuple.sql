@@ -0,0 +1,2 @@
+SELECT * FROM t WHERE (a, b) IN ((1, 2), (3, 4));
+SELECT * FROM t WHERE (a, b + 1) NOT IN ((1, 'x')) AND (a) IN (1, 2);
@@ -0,0 +1,3 @@
+SELECT /*+ INDEX(t a) */ * FROM t WHERE a = 1;
+SELECT /*+ index(t a, b) */ a, b FROM t WHERE a > 1 AND b < 2;
+SELECT /*+ INDEX(s id) */ t.a FROM t, s WHERE t.a = s.id;
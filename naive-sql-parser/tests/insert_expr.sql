@@ -0,0 +1,2 @@
+INSERT INTO t VALUES (1 + 2, UPPER('x'), CAST('2.5' AS FLOAT), 0 - 3 * 4, NULL);
+INSERT INTO t VALUES ((1), 'a'), (2 / 2, CASE WHEN 1 = 1 THEN 'b' END, 0.5, 1, NULL);
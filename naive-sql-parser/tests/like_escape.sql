@@ -0,0 +1,3 @@
+SELECT * FROM t WHERE name LIKE '100!%' ESCAPE '!';
+SELECT * FROM t WHERE name NOT LIKE 'a!_%' escape '!' AND code LIKE 'x%';
+SELECT * FROM t WHERE name LIKE code;
@@ -0,0 +1,3 @@
+SELECT * FROM t LIMIT 10 OFFSET 3000000000;
+SELECT * FROM t ORDER BY a LIMIT 2 WITH TIES;
+SELECT a FROM t ORDER BY a DESC LIMIT 2 with ties OFFSET 1;
@@ -0,0 +1,3 @@
+SELECT * FROM t WHERE a NOT IN (1, 2, NULL);
+SELECT * FROM t WHERE NOT (a IN (1, 2)) AND b IS NOT NULL;
+SELECT * FROM t WHERE name NOT LIKE 'a%' OR NOT (b > 3 AND a IS NULL);
@@ -0,0 +1,5 @@
+SELECT * FROM t WHERE a <=> NULL;
+SELECT * FROM t WHERE a <=> 1 AND b<=>c;
+SELECT * FROM t WHERE a IS NOT DISTINCT FROM b;
+SELECT * FROM t WHERE NOT a IS DISTINCT FROM NULL;
+SELECT * FROM t WHERE a <= 1 OR a >= 2;
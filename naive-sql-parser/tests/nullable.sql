@@ -0,0 +1 @@
+CREATE TABLE t (a INT NULL, b INT NOT NULL, c VARCHAR(4) NULL UNIQUE, d INT PRIMARY KEY);
@@ -0,0 +1,3 @@
+SELECT a, b FROM t ORDER BY a NULLS FIRST;
+SELECT a, b FROM t ORDER BY a DESC NULLS LAST, b nulls first LIMIT 3;
+SELECT first, last FROM names ORDER BY last, first DESC;
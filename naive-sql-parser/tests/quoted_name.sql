@@ -0,0 +1,11 @@
+CREATE TABLE `order` (`order` INT, "first name" VARCHAR(10), `select` INT PRIMARY KEY);
+CREATE TABLE "with space" (a INT, b INT, FOREIGN KEY ("a") REFERENCES `order` (`select`));
+INSERT INTO `order` VALUES (1, 'x', 2);
+SELECT `order`, "first name" AS "name", `order`.`select` FROM `order` WHERE "first name" = 'x';
+SELECT t."first name" FROM `order` AS t ORDER BY `order` DESC;
+UPDATE `order` SET "first name" = 'y' WHERE `order` = 1;
+CREATE INDEX ON `order` ("first name");
+ALTER TABLE "with space" ADD INDEX ("b");
+DROP INDEX "first name" ON `order`;
+CREATE DATABASE "db";
+USE `db`;
@@ -0,0 +1,2 @@
+ALTER DATABASE shop RENAME TO store;
+alter database a rename to b;
@@ -0,0 +1,5 @@
+SELECT 1;
+SELECT 1 + 2 * 3 AS seven;
+SELECT price * 1.1 FROM goods;
+SELECT name, (price - discount) / 2 AS half, qty FROM goods WHERE qty > 0;
+SELECT name AS n FROM goods;
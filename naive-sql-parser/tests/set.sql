@@ -0,0 +1,6 @@
+SET null_string = '(null)';
+set NULL_STRING = 'NULL';
+SET CONSTRAINTS DEFERRED;
+SET constraints = immediate;
+SET verbose_errors = on;
+SET verbose_errors = off;
@@ -0,0 +1,2 @@
+SHOW CONFIG;
+show config;
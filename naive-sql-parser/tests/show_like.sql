@@ -0,0 +1,5 @@
+SHOW TABLES LIKE 'order%';
+SHOW DATABASES like 'test\_%';
+SHOW TABLES;
+SHOW TABLE STATUS;
+show table status like 'order%';
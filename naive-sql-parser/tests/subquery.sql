@@ -0,0 +1,9 @@
+SELECT * FROM t WHERE id IN (SELECT tid FROM u);
+SELECT * FROM t WHERE id NOT IN (SELECT tid FROM u WHERE x > 1) AND a = 2;
+SELECT * FROM t WHERE x > (SELECT MAX(y) FROM u);
+DELETE FROM t WHERE x <= (SELECT MIN(y) FROM (SELECT y FROM u) AS v);
+SELECT * FROM t WHERE (x) = (SELECT y FROM u LIMIT 1);
+SELECT * FROM t WHERE EXISTS (SELECT * FROM u WHERE y > 1);
+SELECT * FROM t WHERE NOT EXISTS (SELECT 1) OR a = 1;
+DELETE FROM t WHERE EXISTS (SELECT * FROM u WHERE y > 1);
+UPDATE t SET a = 0 WHERE NOT EXISTS (SELECT 1 FROM u);
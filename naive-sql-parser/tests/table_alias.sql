@@ -0,0 +1,3 @@
+SELECT e.name, d.name FROM employee e, department AS d WHERE e.dept = d.id;
+SELECT COUNT(*) FROM employee AS e WHERE e.salary > 1000;
+SELECT * FROM employee e;
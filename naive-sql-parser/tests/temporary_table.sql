@@ -0,0 +1,3 @@
+CREATE TEMPORARY TABLE staging (a INT, b VARCHAR(10));
+create temporary table if not exists staging (a INT);
+CREATE TEMPORARY TABLE t (a INT, PRIMARY KEY (a));
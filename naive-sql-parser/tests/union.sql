@@ -0,0 +1,4 @@
+SELECT a FROM t UNION SELECT b FROM u;
+SELECT a, b FROM t WHERE a > 1 UNION ALL SELECT c, d FROM u ORDER BY c LIMIT 3;
+SELECT * FROM t AS x union all SELECT * FROM (SELECT a FROM u) AS y;
+SELECT all FROM t UNION SELECT 1;